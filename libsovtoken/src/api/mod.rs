@@ -12,21 +12,31 @@ use std::ffi::CString;
 use std::os::raw::c_char;
 
 use indy_sys;
+use serde_json;
 
 use logic::api_internals::{
     add_request_fees,
-    create_address
+    create_address,
+    discover_addresses,
+    manage_addresses
 };
 use logic::address;
+use logic::address_book;
 use logic::build_payment;
 use logic::config::{
     get_fees_config::GetFeesRequest,
     get_utxo_config:: *,
+    payment_address_config,
 };
+use logic::detached_signing;
 use logic::did::Did;
+use logic::fee_estimator;
 use logic::indy_sdk_api::crypto_api::CryptoSdk;
 use logic::indy_sdk_api::ledger;
+use logic::inspector;
+use logic::merkle;
 use logic::minting;
+use logic::multisig::PendingRequest;
 use logic::verify;
 use logic::parsers::{
     parse_get_utxo_response,
@@ -35,16 +45,22 @@ use logic::parsers::{
     parse_get_utxo_response::{ParseGetUtxoResponse},
     parse_payment_response::{ParsePaymentResponse, ParsePaymentReply, from_response},
     parse_response_with_fees_handler::{ParseResponseWithFees, ParseResponseWithFeesReply},
-    parse_get_txn_fees::{parse_fees_from_get_txn_fees_response, get_fees_state_proof_extractor}
+    parse_get_txn_fees::{self, ParseGetTxnFeesResponse, get_fees_state_proof_extractor}
 };
+use logic::parsers::common::UTXO;
+use logic::payment_uri::PaymentRequest;
 use logic::payments::CreatePaymentHandler;
+use logic::request_validator::{self, ValidationConfig};
 use logic::set_fees;
+use logic::state_proof::{self, NodeKeys, trie};
+use logic::state_proof::freshness::{self, FreshnessSettings};
 use logic::xfer_payload::XferPayload;
 
 use utils::constants::general::{JsonCallback, PAYMENT_METHOD_NAME, LEDGER_ID};
 use ErrorCode;
 use utils::constants::txn_types::{GET_FEES, GET_UTXO};
-use utils::ffi_support::{str_from_char_ptr, string_from_char_ptr, c_pointer_from_string};
+use utils::errors::{self, SovtokenError};
+use utils::ffi_support::{str_from_char_ptr, string_from_char_ptr, c_pointer_from_str, c_pointer_from_string};
 use utils::json_conversion::{JsonDeserialize, JsonSerialize};
 use utils::general::ResultExtension;
 use utils::callbacks::ClosureHandler;
@@ -67,7 +83,15 @@ use utils::constants::general::JsonI64Callback;
 /// config_str: payment address config as json:
 ///   {
 ///     seed: <str>, // allows deterministic creation of payment address
+///     label: <optional str>, // human readable name kept in the address book
+///     tags: <optional object>, // string tags kept in the address book, searchable with WQL
+///     masterSeed: <optional str>, // hex master seed, the key is derived along derivationPath (SLIP-0010)
+///     derivationPath: <optional str>, // hardened path like m/44'/0'/0', required with masterSeed or mnemonic
+///     mnemonic: <optional str>, // BIP-39 english mnemonic, its seed is the master seed
+///     passphrase: <optional str>, // ascii BIP-39 passphrase of the mnemonic
 ///   }
+///   an empty config_str is the default config, a config_str which doesn't
+///   parse fails with CommonInvalidStructure
 /// cb: description
 ///
 /// # Returns
@@ -83,6 +107,7 @@ pub extern "C" fn create_payment_address_handler(
     config_str: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
 
     trace!("api::create_payment_address_handler called");
     let (config, cb) = match create_address::deserialize_arguments(config_str, cb) {
@@ -90,14 +115,355 @@ pub extern "C" fn create_payment_address_handler(
         Err(e) => return e as i32
     };
 
-    let payment_closure = create_address::create_address_cb(command_handle, cb);
+    let payment_closure = create_address::record_address_cb(
+        wallet_handle,
+        &config,
+        create_address::create_address_cb(command_handle, cb)
+    );
 
     let handler = CreatePaymentHandler::new(CryptoSdk {});
-    let ec = handler.create_payment_address_async(wallet_handle, config, payment_closure);
+    let ec = handler.create_payment_address_async(wallet_handle, config.key, payment_closure);
     trace!("api::create_payment_address_handler << result: {:?}", ec);
     return ec as i32;
 }
 
+/**
+ * Lists the payment addresses recorded in the address book of a wallet.
+ *
+ * Addresses are recorded by [`create_payment_address_handler`].
+ *
+ * ## Returns
+ * ```JSON
+ * [
+ *      {
+ *          "address": <str: payment_address>,
+ *          "label": <optional str>,
+ *          "createdAt": <int: unix timestamp in seconds>,
+ *          "seedDerived": <bool: created from a seed>,
+ *          "tags": {<str>: <str>}
+ *      }
+ * ]
+ * ```
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_list_payment_addresses(
+    command_handle: i32,
+    wallet_handle: i32,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_list_payment_addresses called");
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let ec = address_book::search_addresses_async(wallet_handle, "{}", manage_addresses::address_book_cb(command_handle, cb));
+    trace!("api::sovtoken_list_payment_addresses << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+ * Searches the address book of a wallet.
+ *
+ * ## Parameters
+ * ### query_json
+ * WQL query over the tags of the addresses, for example
+ * ```JSON
+ * {"purpose": "savings"}
+ * ```
+ *
+ * ## Returns
+ * The matching addresses, like [`sovtoken_list_payment_addresses`].
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_search_payment_addresses(
+    command_handle: i32,
+    wallet_handle: i32,
+    query_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_search_payment_addresses called");
+    let (query_json, cb) = match manage_addresses::deserialize_search_arguments(query_json, cb) {
+        Ok(tup) => tup,
+        Err(e) => return e as i32
+    };
+
+    let ec = address_book::search_addresses_async(wallet_handle, &query_json, manage_addresses::address_book_cb(command_handle, cb));
+    trace!("api::sovtoken_search_payment_addresses << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+ * Gets the address book entry of a payment address.
+ *
+ * ## Returns
+ * An address, like the items of [`sovtoken_list_payment_addresses`].
+ *
+ * ## Errors
+ * WalletItemNotFound when the address is not in the address book
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_get_payment_address(
+    command_handle: i32,
+    wallet_handle: i32,
+    payment_address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_get_payment_address called");
+    let (payment_address, cb) = match manage_addresses::deserialize_get_arguments(payment_address, cb) {
+        Ok(tup) => tup,
+        Err(e) => return e as i32
+    };
+
+    let ec = address_book::get_address_async(wallet_handle, &payment_address, manage_addresses::address_book_cb(command_handle, cb));
+    trace!("api::sovtoken_get_payment_address << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+ * Changes the label and the tags of a payment address in the address book.
+ *
+ * ## Parameters
+ * ### update_json
+ * ```JSON
+ * {
+ *      "label": <optional str: new label>,
+ *      "tags": <optional object: replaces all the tags>
+ * }
+ * ```
+ *
+ * ## Returns
+ * The updated address, like the items of [`sovtoken_list_payment_addresses`].
+ *
+ * ## Errors
+ * WalletItemNotFound when the address is not in the address book
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_update_payment_address(
+    command_handle: i32,
+    wallet_handle: i32,
+    payment_address: *const c_char,
+    update_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_update_payment_address called");
+    let (payment_address, update, cb) = match manage_addresses::deserialize_update_arguments(payment_address, update_json, cb) {
+        Ok(tup) => tup,
+        Err(e) => return e as i32
+    };
+
+    let ec = address_book::update_address_async(wallet_handle, &payment_address, update, manage_addresses::address_book_cb(command_handle, cb));
+    trace!("api::sovtoken_update_payment_address << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+ * Generates a BIP-39 english mnemonic.
+ *
+ * The mnemonic can be given with a `derivationPath` to [`create_payment_address_handler`],
+ * all the addresses derived from it can be recreated from the mnemonic alone.
+ *
+ * ## Parameters
+ * ### word_count
+ * 12, 15, 18, 21 or 24
+ *
+ * ## Returns
+ * The words separated by spaces.
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_generate_mnemonic(
+    command_handle: i32,
+    word_count: u32,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_generate_mnemonic called >> word_count: {:?}", word_count);
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let mnemonic = match payment_address_config::generate_mnemonic(word_count as usize) {
+        Ok(mnemonic) => mnemonic,
+        Err(ec) => return ec as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(mnemonic));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_generate_mnemonic << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Recovers the payment addresses derived from a master seed into a wallet.
+ *
+ * The addresses `<accountPath>/<index>'` are derived from index 0 until `gapLimit`
+ * consecutive addresses are unused. An address is used when it has UTXOs, which
+ * are fetched with GET_UTXO requests submitted to `pool_handle`. An address whose
+ * outputs were all spent looks unused.
+ *
+ * The key of every used address is created in the wallet and the address is
+ * recorded in the address book with its derivation path. Addresses already in
+ * the wallet are kept.
+ *
+ * ## Parameters
+ * ### config_json
+ * ```JSON
+ * {
+ *      "masterSeed": <optional str>, // hex master seed
+ *      "mnemonic": <optional str>, // BIP-39 english mnemonic, instead of masterSeed
+ *      "passphrase": <optional str>, // ascii BIP-39 passphrase of the mnemonic
+ *      "accountPath": <str>, // hardened path like m/44'/0'/0'
+ *      "gapLimit": <optional int>, // 20 by default
+ * }
+ * ```
+ *
+ * ## Returns
+ * ```JSON
+ * [
+ *      {
+ *          "index": <int>,
+ *          "derivationPath": <str>,
+ *          "address": <str: payment_address>,
+ *          "utxos": {
+ *              "address": <str: payment_address>,
+ *              "utxos": [{"address": <str: unqualified address>, "seqNo": <int>, "amount": <int>}],
+ *              "balance": <int>,
+ *              "pages": <int: number of GET_UTXO requests>
+ *          }
+ *      }
+ * ]
+ * ```
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_discover_payment_addresses(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    config_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_discover_payment_addresses called >> wallet_handle: {:?}, pool_handle: {:?}", wallet_handle, pool_handle);
+    let (config, cb) = match discover_addresses::deserialize_arguments(config_json, cb) {
+        Ok(tup) => tup,
+        Err(ec) => return ec as i32
+    };
+
+    let res = match discover_addresses::discover_and_record(wallet_handle, pool_handle, config, discover_addresses::discover_addresses_cb(command_handle, cb)) {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+
+    trace!("api::sovtoken_discover_payment_addresses << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Writes a payment request as a URI.
+ *
+ * ## Parameters
+ * ### request_json
+ * ```JSON
+ * {
+ *      "address": <str: payment_address>,
+ *      "amount": <optional int>,
+ *      "ref": <optional str: reference of the payment>,
+ *      "extra": <optional object or str>
+ * }
+ * ```
+ *
+ * ## Returns
+ * `pay:sov:<address>?amount=<int>&ref=<str>&extra=<json>` with percent-encoded parameters.
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_build_payment_request_uri(
+    command_handle: i32,
+    request_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_build_payment_request_uri called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let uri = string_from_char_ptr(request_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|request_json| PaymentRequest::from_json(&request_json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+        .and_then(|request| request.to_uri());
+
+    let uri = match uri {
+        Ok(uri) => uri,
+        Err(ec) => return ec as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(uri));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_build_payment_request_uri << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Parses a payment request URI.
+ *
+ * ## Returns
+ * The payment request like the `request_json` of [`sovtoken_build_payment_request_uri`],
+ * with the arguments of [`build_payment_req_handler`] paying it when it has an amount:
+ * ```JSON
+ * {
+ *      "request": <object: payment request>,
+ *      "outputs": <optional array: outputs_json>,
+ *      "extra": <optional str: extra, holding the reference as "ref">
+ * }
+ * ```
+ *
+ * ## Errors
+ * CommonInvalidStructure when the address, its checksum or a parameter is invalid
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_parse_payment_request_uri(
+    command_handle: i32,
+    uri: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_parse_payment_request_uri called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let request = match string_from_char_ptr(uri).ok_or(ErrorCode::CommonInvalidStructure).and_then(|uri| uri.parse::<PaymentRequest>()) {
+        Ok(request) => request,
+        Err(ec) => return ec as i32
+    };
+
+    let (outputs, extra) = match request.amount {
+        Some(_) => match request.to_outputs() {
+            Ok((outputs, extra)) => (Some(outputs), extra.map(|extra| extra.to_string())),
+            Err(ec) => return ec as i32
+        },
+        None => (None, None)
+    };
+
+    let parsed = json!({
+        "request": request,
+        "outputs": outputs,
+        "extra": extra,
+    });
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(parsed.to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_parse_payment_request_uri << result: {:?}", res);
+    return res as i32;
+}
+
 /**
  * Add fees to a request.
  * 
@@ -192,6 +558,7 @@ pub extern "C" fn add_request_fees_handler(
     extra: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
 
     trace!("api::add_request_fees_handler called did (address) >> {:?}", secret!(&did));
     let (inputs, outputs, extra, request_json_map, cb) = match add_request_fees::deserialize_inputs(req_json, inputs_json, outputs_json, extra, cb) {
@@ -235,6 +602,121 @@ pub extern "C" fn add_request_fees_handler(
 }
 
 
+/**
+ * Prepares the fees of a request for signing outside of libsovtoken.
+ *
+ * First phase of [`add_request_fees_handler`] when the keys of the inputs are
+ * not in a wallet. The messages include the digest of `req_json`, so the request
+ * can't change before [`attach_request_fees_signatures_handler`].
+ *
+ * ## Parameters
+ * `req_json`, `inputs_json`, `outputs_json` and `extra` are the same as for [`add_request_fees_handler`].
+ *
+ * ## Returns
+ * The unsigned payload like [`prepare_payment_req_handler`], with the `txnDigest` of the request.
+ */
+#[no_mangle]
+pub extern "C" fn prepare_request_fees_handler(
+    command_handle: i32,
+    req_json: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::prepare_request_fees_handler called");
+    let (inputs, outputs, extra, request_json_map, cb) = match add_request_fees::deserialize_inputs(req_json, inputs_json, outputs_json, extra, cb) {
+        Ok(tup) => tup,
+        Err(error_code) => {
+            trace!("api::prepare_request_fees_handler << result: {:?}", error_code);
+            return error_code as i32;
+        }
+    };
+
+    let unsigned_json = detached_signing::prepare_fees(XferPayload::new(inputs, outputs, extra), &request_json_map)
+        .and_then(|unsigned| serde_json::to_string(&unsigned).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let unsigned_json = match unsigned_json {
+        Ok(unsigned_json) => unsigned_json,
+        Err(error_code) => {
+            trace!("api::prepare_request_fees_handler << result: {:?}", error_code);
+            return error_code as i32;
+        }
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(unsigned_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::prepare_request_fees_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Adds the fees to a request from a prepared payload and detached signatures.
+ *
+ * Second phase of [`prepare_request_fees_handler`], `req_json` has to be the
+ * request the fees were prepared for. `signatures_json` is the same as for
+ * [`attach_payment_signatures_handler`].
+ *
+ * ## Returns
+ * The request with fees, like [`add_request_fees_handler`].
+ *
+ * ## Errors
+ * CommonInvalidStructure when the request changed or an input has no valid signature
+ */
+#[no_mangle]
+pub extern "C" fn attach_request_fees_signatures_handler(
+    command_handle: i32,
+    req_json: *const c_char,
+    unsigned_payload_json: *const c_char,
+    signatures_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+    trace!("api::attach_request_fees_signatures_handler called");
+
+    let (req_json, unsigned_payload_json, signatures_json) = match (
+        string_from_char_ptr(req_json),
+        string_from_char_ptr(unsigned_payload_json),
+        string_from_char_ptr(signatures_json)
+    ) {
+        (Some(request), Some(unsigned), Some(signatures)) => (request, unsigned, signatures),
+        _ => {
+            error!("Failed to convert the arguments to strings");
+            trace!("api::attach_request_fees_signatures_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let request_json_map = match serde_json::from_str::<serde_json::Value>(&req_json) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => {
+            trace!("api::attach_request_fees_signatures_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let request_with_fees = detached_signing::deserialize_signed_arguments(&unsigned_payload_json, &signatures_json)
+        .and_then(|(unsigned, signatures)| detached_signing::attach_fees_signatures(&request_json_map, unsigned, &signatures));
+
+    let request_with_fees = match request_with_fees {
+        Ok(request_with_fees) => request_with_fees,
+        Err(error_code) => {
+            trace!("api::attach_request_fees_signatures_handler << result: {:?}", error_code);
+            return error_code as i32;
+        }
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(request_with_fees));
+
+    let res = ErrorCode::Success;
+    trace!("api::attach_request_fees_signatures_handler << result: {:?}", res);
+    return res as i32;
+}
+
+
 /// Parses inputted output fees section and returns it in utxo format
 ///
 ///
@@ -255,6 +737,7 @@ pub extern "C" fn parse_response_with_fees_handler(
     req_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
 
     trace!("api::parse_response_with_fees_handler called");
     check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
@@ -274,6 +757,11 @@ pub extern "C" fn parse_response_with_fees_handler(
 
     debug!("api::parse_response_with_fees_handler >> req_json: {:?}", resp_json_string);
 
+    if let Err(ec) = merkle::verify_write_reply_if_enabled(&resp_json_string) {
+        trace!("api::parse_response_with_fees_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
     let response: ParseResponseWithFees = match ParseResponseWithFees::from_json(&resp_json_string).map_err(map_err_err!()) {
         Ok(r) => r,
         Err(_) => return ErrorCode::CommonInvalidStructure as i32,
@@ -361,6 +849,7 @@ pub extern "C" fn build_payment_req_handler(
     extra: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
     trace!("api::build_payment_req_handler called >> submitter_did (address) {:?}", secret!(&submitter_did));
     let (inputs, outputs, extra, submitter_did, cb) =
         match build_payment::deserialize_inputs(inputs_json, outputs_json, extra, submitter_did, cb) {
@@ -387,26 +876,172 @@ pub extern "C" fn build_payment_req_handler(
     return ec as i32;
 }
 
-/// Parses inputted payment data and returns formatted UTXOs
-///
-///
-/// from tokens-interface.md/ParsePaymentResponseCB
-/// # Params
-/// command_handle: standard command handle
-/// resp_json: json. \For format see https://github.com/sovrin-foundation/libsovtoken/blob/master/doc/data_structures.md
-///
-/// # Returns
-/// utxo_json: json. For format see https://github.com/sovrin-foundation/libsovtoken/blob/master/doc/data_structures.md
-///
-/// # Errors
-/// CommonInvalidStructure when any of the inputs are invalid
-/// CommonInvalidState when any processing of inputs produces invalid results
+/**
+ * Prepares a payment request for signing outside of libsovtoken.
+ *
+ * First phase of [`build_payment_req_handler`] when the keys of the inputs are
+ * not in a wallet, for example on an air-gapped machine. Each input has to sign
+ * its `message` with ed25519, the signatures are then given to
+ * [`attach_payment_signatures_handler`].
+ *
+ * ## Parameters
+ * `inputs_json`, `outputs_json` and `extra` are the same as for [`build_payment_req_handler`].
+ *
+ * ## Returns
+ * ```JSON
+ * {
+ *      "payload": {
+ *          "inputs": [{"address": <str: unqualified address>, "seqNo": <int>}],
+ *          "outputs": [{"address": <str: unqualified address>, "amount": <int>}],
+ *          "extra": <optional object or str>,
+ *          "signatures": null
+ *      },
+ *      "taaAcceptance": <optional object>,
+ *      "signingInputs": [
+ *          {
+ *              "address": <str: payment_address>,
+ *              "seqNo": <int>,
+ *              "verkey": <str: verkey signing the message>,
+ *              "message": <str: exact message to sign>
+ *          }
+ *      ]
+ * }
+ * ```
+ */
+#[no_mangle]
+pub extern "C" fn prepare_payment_req_handler(
+    command_handle: i32,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::prepare_payment_req_handler called");
+    let (inputs, outputs, extra, _, cb) =
+        match build_payment::deserialize_inputs(inputs_json, outputs_json, extra, ::std::ptr::null(), cb) {
+            Ok(tup) => tup,
+            Err(error_code) => {
+                trace!("api::prepare_payment_req_handler << result: {:?}", error_code);
+                return error_code as i32;
+            }
+        };
+
+    let unsigned_json = detached_signing::prepare_transfer(XferPayload::new(inputs, outputs, extra))
+        .and_then(|unsigned| serde_json::to_string(&unsigned).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let unsigned_json = match unsigned_json {
+        Ok(unsigned_json) => unsigned_json,
+        Err(error_code) => {
+            trace!("api::prepare_payment_req_handler << result: {:?}", error_code);
+            return error_code as i32;
+        }
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(unsigned_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::prepare_payment_req_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Builds a payment request from a prepared payload and detached signatures.
+ *
+ * Second phase of [`prepare_payment_req_handler`]. Every input needs a valid
+ * signature of its `message`, the messages are rebuilt from the payload.
+ *
+ * ## Parameters
+ *
+ * ### submitter_did
+ * Optional, derived from the first input when null.
+ *
+ * ### unsigned_payload_json
+ * Output of [`prepare_payment_req_handler`].
+ *
+ * ### signatures_json
+ * Base58 signatures keyed by payment address, qualified or not.
+ * ```JSON
+ * {
+ *      <str: payment_address>: <str: signature> or [<str: signature>, ...]
+ * }
+ * ```
+ *
+ * ## Returns
+ * The payment request, like [`build_payment_req_handler`].
+ *
+ * ## Errors
+ * CommonInvalidStructure when an input has no valid signature
+ */
+#[no_mangle]
+pub extern "C" fn attach_payment_signatures_handler(
+    command_handle: i32,
+    submitter_did: *const c_char,
+    unsigned_payload_json: *const c_char,
+    signatures_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+    trace!("api::attach_payment_signatures_handler called >> submitter_did: {:?}", secret!(&submitter_did));
+
+    let (unsigned_payload_json, signatures_json) = match (string_from_char_ptr(unsigned_payload_json), string_from_char_ptr(signatures_json)) {
+        (Some(unsigned), Some(signatures)) => (unsigned, signatures),
+        _ => {
+            error!("Failed to convert the arguments to strings");
+            trace!("api::attach_payment_signatures_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let submitter_did = match Did::from_pointer(submitter_did).map(|did| did.validate()) {
+        Some(Ok(did)) => Some(did),
+        Some(Err(_)) => {
+            trace!("api::attach_payment_signatures_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+        None => None
+    };
+
+    let request_json = detached_signing::deserialize_signed_arguments(&unsigned_payload_json, &signatures_json)
+        .and_then(|(unsigned, signatures)| build_payment::build_payment_request_json(submitter_did, unsigned.attach_signatures(&signatures)));
+
+    let request_json = match request_json {
+        Ok(request_json) => request_json,
+        Err(error_code) => {
+            trace!("api::attach_payment_signatures_handler << result: {:?}", error_code);
+            return error_code as i32;
+        }
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(request_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::attach_payment_signatures_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/// Parses inputted payment data and returns formatted UTXOs
+///
+///
+/// from tokens-interface.md/ParsePaymentResponseCB
+/// # Params
+/// command_handle: standard command handle
+/// resp_json: json. \For format see https://github.com/sovrin-foundation/libsovtoken/blob/master/doc/data_structures.md
+///
+/// # Returns
+/// utxo_json: json. For format see https://github.com/sovrin-foundation/libsovtoken/blob/master/doc/data_structures.md
+///
+/// # Errors
+/// CommonInvalidStructure when any of the inputs are invalid
+/// CommonInvalidState when any processing of inputs produces invalid results
 #[no_mangle]
 pub extern "C" fn parse_payment_response_handler(
     command_handle: i32,
     resp_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
     trace!("api::parse_payment_response_handler called");
     check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
 
@@ -425,6 +1060,11 @@ pub extern "C" fn parse_payment_response_handler(
 
     debug!("api::parse_payment_response_handler >> resp_json: {:?}", &resp_json_string);
 
+    if let Err(ec) = merkle::verify_write_reply_if_enabled(&resp_json_string) {
+        trace!("api::parse_payment_response_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
     let response: ParsePaymentResponse = match ParsePaymentResponse::from_json(&resp_json_string)
         .map_err(map_err_err!()) {
         Ok(r) => r,
@@ -475,6 +1115,7 @@ pub extern "C" fn build_get_utxo_request_handler(command_handle: i32,
                                                  payment_address: *const c_char,
                                                  from: i64,
                                                  cb: JsonCallback) -> i32 {
+    errors::clear_current_error();
     trace!("api::build_get_utxo_request_handler called");
     let handle_result = api_result_handler!(< *const c_char >, command_handle, cb);
     let from: Option<i64> = if from == -1 { None } else {Some(from)};
@@ -511,16 +1152,19 @@ pub extern "C" fn build_get_utxo_request_handler(command_handle: i32,
 /// # Returns
 /// next: u64 (optional) - pointer to the next slice of payment sources
 /// utxo_json: json. For format see https://github.com/sovrin-foundation/libsovtoken/blob/master/doc/data_structures.md
+///   each source has a `proofTimestamp`, when the nodes signed the state proof, if the reply has one
 ///
 /// # Errors
 /// CommonInvalidStructure when any of the inputs are invalid
 /// CommonInvalidState when any processing of inputs produces invalid results
+/// LedgerSecurityError when the state proof isn't fresh enough, see sovtoken_set_proof_freshness
 #[no_mangle]
 pub extern "C" fn parse_get_utxo_response_handler(
     command_handle: i32,
     resp_json: *const c_char,
     cb: JsonI64Callback
 ) -> i32 {
+    errors::clear_current_error();
 
     trace!("api::parse_get_utxo_response_handler called");
     check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
@@ -544,9 +1188,15 @@ pub extern "C" fn parse_get_utxo_response_handler(
     let response: ParseGetUtxoResponse = match ParseGetUtxoResponse::from_json(&resp_json_string)
         .map_err(map_err_err!()) {
         Ok(r) => r,
-        Err(_) => return ErrorCode::CommonInvalidStructure as i32,
+        Err(err) => return errors::to_error_code(SovtokenError::invalid_structure("resp_json").with_source(err)) as i32,
     };
 
+    if let Err(stale) = parse_get_utxo_response::check_freshness(&response, freshness::freshness_settings().as_ref()) {
+        let ec = errors::to_error_code(SovtokenError::from(stale));
+        trace!("api::parse_get_utxo_response_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
     // here is where the magic happens--conversion from input structure to output structure
     // is handled in ParseGetUtxoReply::from_response
     let (sources, next) = match parse_get_utxo_response::from_response(response) {
@@ -600,6 +1250,7 @@ pub extern "C" fn build_set_txn_fees_handler(
     fees_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
 
     trace!("api::build_set_txn_fees_handler called >> wallet_handle {}", wallet_handle);
     let (did, set_fees, cb) = match set_fees::deserialize_inputs(
@@ -652,6 +1303,7 @@ pub extern "C" fn build_get_txn_fees_handler(
     submitter_did: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
 
     let handle_result = api_result_handler!(< *const c_char >, command_handle, cb);
     trace!("api::build_get_txn_fees_handler called");
@@ -697,8 +1349,10 @@ pub extern "C" fn build_get_txn_fees_handler(
 ///
 /// # Returns
 /// fees_json: JSON String. Structure of JSON available in libsovtoken/docs/data_structures.md
+///   with a `proofTimestamp`, when the nodes signed the state proof, if the reply has one
 ///
 /// # Errors
+/// LedgerSecurityError when the state proof isn't fresh enough, see sovtoken_set_proof_freshness
 ///
 #[no_mangle]
 pub extern "C" fn parse_get_txn_fees_response_handler(
@@ -706,6 +1360,7 @@ pub extern "C" fn parse_get_txn_fees_response_handler(
     resp_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
     check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
 
     trace!("api::parse_get_txn_fees_response_handler called");
@@ -724,8 +1379,23 @@ pub extern "C" fn parse_get_txn_fees_response_handler(
     debug!("api::parse_get_txn_fees_response_handler >> resp_json: {:?}", resp_json_string);
     debug!("Deserialized parse_get_txn_fees_response_handler arguments");
 
+    let fees_response = match ParseGetTxnFeesResponse::from_json(&resp_json_string).map_err(map_err_err!()) {
+        Ok(r) => r,
+        Err(err) => {
+            let ec = errors::to_error_code(SovtokenError::invalid_structure("resp_json").with_source(err));
+            trace!("api::parse_get_txn_fees_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    if let Err(stale) = parse_get_txn_fees::check_freshness(&fees_response, freshness::freshness_settings().as_ref()) {
+        let ec = errors::to_error_code(SovtokenError::from(stale));
+        trace!("api::parse_get_txn_fees_response_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
     let fees_json_obj =
-        match parse_fees_from_get_txn_fees_response(resp_json_string) {
+        match parse_get_txn_fees::fees_json(&fees_response) {
             Ok(s) => {
                 s
             },
@@ -744,6 +1414,79 @@ pub extern "C" fn parse_get_txn_fees_response_handler(
 }
 
 
+/// Estimates the fee a request has to pay, combining the amounts of a GET_FEES reply
+/// with the `metadata.fees` aliases of the auth rules in a GET_AUTH_RULE reply.
+///
+/// # Params
+/// command_handle: a standard command handle
+/// fees_json: JSON String. Output of parse_get_txn_fees_response_handler
+/// get_auth_rule_response_json: JSON String. Ledger reply of a GET_AUTH_RULE request
+/// request_json: JSON String. NYM, ATTRIB, SCHEMA, CRED_DEF, REVOC_REG_DEF or XFER_PUBLIC request
+/// signer_role: optional role of the request signer, any role is considered when null
+///
+/// # Returns
+/// fee_json: JSON String.
+///   {
+///     fee: <int>, // amount add_request_fees_handler has to cover
+///     alias: <optional str>, // fees alias the amount comes from
+///   }
+///
+/// # Errors
+/// CommonInvalidStructure when any of the inputs are invalid or no auth rule matches the request
+/// PaymentOperationNotSupportedError for other request types
+/// TransactionNotAllowed when none of the auth constraints can be satisfied
+#[no_mangle]
+pub extern "C" fn estimate_txn_fees_handler(
+    command_handle: i32,
+    fees_json: *const c_char,
+    get_auth_rule_response_json: *const c_char,
+    request_json: *const c_char,
+    signer_role: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    trace!("api::estimate_txn_fees_handler called");
+
+    let (fees_json, get_auth_rule_response_json, request_json) = match (
+        string_from_char_ptr(fees_json),
+        string_from_char_ptr(get_auth_rule_response_json),
+        string_from_char_ptr(request_json)
+    ) {
+        (Some(fees), Some(auth_rules), Some(request)) => (fees, auth_rules, request),
+        _ => {
+            error!("Failed to convert the arguments to strings");
+            trace!("api::estimate_txn_fees_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+    let signer_role = string_from_char_ptr(signer_role);
+
+    debug!("api::estimate_txn_fees_handler >> fees_json: {:?}, get_auth_rule_response_json: {:?}, request_json: {:?}, signer_role: {:?}",
+           fees_json, get_auth_rule_response_json, secret!(&request_json), signer_role);
+
+    let fee_json = match fee_estimator::estimate_fee_json(
+        &fees_json,
+        &get_auth_rule_response_json,
+        &request_json,
+        signer_role.as_ref().map(String::as_str)
+    ) {
+        Ok(fee_json) => fee_json,
+        Err(err) => {
+            trace!("api::estimate_txn_fees_handler << result: {:?}", err);
+            return err as i32;
+        }
+    };
+    info!("Estimated fee, result: {:?}", fee_json);
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(fee_json));
+
+    let res = ErrorCode::Success as i32;
+    trace!("api::estimate_txn_fees_handler << result: {:?}", res);
+    return res;
+}
+
 /**
  * Build a mint transaction request.
  * 
@@ -775,6 +1518,7 @@ pub extern "C" fn build_mint_txn_handler(
     cb: JsonCallback
 ) -> i32
 {
+    errors::clear_current_error();
     trace!("api::build_mint_txn_handle called >> wallet_handle {}", wallet_handle);
     let (did, outputs, extra, cb) = match minting::deserialize_inputs(
         submitter_did,
@@ -806,6 +1550,416 @@ pub extern "C" fn build_mint_txn_handler(
     return res as i32;
 }
 
+/**
+ * Exports a request of [`build_mint_txn_handler`] or [`build_set_txn_fees_handler`]
+ * for trustees on separate machines to sign.
+ *
+ * ## Parameters
+ * ### request_json
+ * The request, without signatures
+ * ### quorum
+ * Number of trustee signatures the request needs
+ *
+ * ## Returns
+ * The pending request, passed to the other `sovtoken_*_multisig_*` functions
+ * ```JSON
+ * {
+ *      "request": <object>,
+ *      "quorum": <int>,
+ *      "signatures": {<str: did>: {"verkey": <str>, "signature": <str>}}
+ * }
+ * ```
+ *
+ * [`build_mint_txn_handler`]: fn.build_mint_txn_handler.html
+ * [`build_set_txn_fees_handler`]: fn.build_set_txn_fees_handler.html
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_export_multisig_request(
+    command_handle: i32,
+    request_json: *const c_char,
+    quorum: u32,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_export_multisig_request called >> quorum: {}", quorum);
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let pending_json = str_from_char_ptr(request_json)
+        .ok_or_else(|| SovtokenError::invalid_structure("request_json"))
+        .and_then(|request_json| PendingRequest::new(request_json, quorum as usize))
+        .and_then(|pending| pending.to_json());
+
+    let pending_json = match pending_json {
+        Ok(pending_json) => pending_json,
+        Err(err) => return errors::to_error_code(err) as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(pending_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_export_multisig_request << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Describes a pending request for a trustee to check it before signing.
+ *
+ * ## Returns
+ * ```JSON
+ * {
+ *      "summary": <object: recipients and amounts of a mint, or the new fees>,
+ *      "description": <str: the summary, readable by humans>,
+ *      "signingMessage": <str: what the trustee signs with the key of their did>,
+ *      "quorum": {
+ *          "quorum": <int>,
+ *          "signedBy": [<str: did>],
+ *          "missing": <int: signatures still needed>
+ *      }
+ * }
+ * ```
+ *
+ * ## Errors
+ * CommonInvalidStructure when a signature of the pending request is not valid
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_inspect_multisig_request(
+    command_handle: i32,
+    pending_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_inspect_multisig_request called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let inspected = str_from_char_ptr(pending_json)
+        .ok_or_else(|| SovtokenError::invalid_structure("pending_json"))
+        .and_then(PendingRequest::from_json)
+        .and_then(|pending| {
+            let summary = pending.summary()?;
+            Ok(json!({
+                "description": summary.to_string(),
+                "summary": summary,
+                "signingMessage": pending.signing_message()?,
+                "quorum": pending.quorum_report(),
+            }))
+        });
+
+    let inspected = match inspected {
+        Ok(inspected) => inspected,
+        Err(err) => return errors::to_error_code(err) as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(inspected.to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_inspect_multisig_request << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Adds the signature of a trustee to a pending request.
+ *
+ * ## Parameters
+ * ### did
+ * DID of the trustee
+ * ### verkey
+ * Verkey of the DID, full or abbreviated
+ * ### signature
+ * base58 ed25519 signature of the `signingMessage` of [`sovtoken_inspect_multisig_request`]
+ *
+ * ## Returns
+ * The pending request with the signature
+ *
+ * ## Errors
+ * CommonInvalidStructure when the verkey is not the one of the DID or the signature is not valid
+ *
+ * [`sovtoken_inspect_multisig_request`]: fn.sovtoken_inspect_multisig_request.html
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_sign_multisig_request(
+    command_handle: i32,
+    pending_json: *const c_char,
+    did: *const c_char,
+    verkey: *const c_char,
+    signature: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_sign_multisig_request called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let (did, verkey, signature) = match (str_from_char_ptr(did), str_from_char_ptr(verkey), str_from_char_ptr(signature)) {
+        (Some(did), Some(verkey), Some(signature)) => (did, verkey, signature),
+        _ => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let pending_json = str_from_char_ptr(pending_json)
+        .ok_or_else(|| SovtokenError::invalid_structure("pending_json"))
+        .and_then(PendingRequest::from_json)
+        .and_then(|mut pending| {
+            pending.add_signature(did, verkey, signature)?;
+            pending.to_json()
+        });
+
+    let pending_json = match pending_json {
+        Ok(pending_json) => pending_json,
+        Err(err) => return errors::to_error_code(err) as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(pending_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_sign_multisig_request << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Combines the signatures of exports of the same pending request.
+ *
+ * ## Parameters
+ * ### pending_requests_json
+ * ```JSON
+ * [<object: pending request>]
+ * ```
+ *
+ * ## Returns
+ * When the quorum is reached `request` is the request to submit, with the
+ * signatures of the trustees.
+ * ```JSON
+ * {
+ *      "pending": <object: pending request with all the signatures>,
+ *      "quorum": {"quorum": <int>, "signedBy": [<str: did>], "missing": <int>},
+ *      "request": <optional str: the signed request>
+ * }
+ * ```
+ *
+ * ## Errors
+ * CommonInvalidStructure when the pending requests are not the same request
+ * or a signature is not valid
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_merge_multisig_requests(
+    command_handle: i32,
+    pending_requests_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_merge_multisig_requests called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let merged = str_from_char_ptr(pending_requests_json)
+        .ok_or_else(|| SovtokenError::invalid_structure("pending_requests_json"))
+        .and_then(|pending_requests_json| serde_json::from_str::<Vec<serde_json::Value>>(pending_requests_json)
+            .map_err(|err| SovtokenError::invalid_structure("pending_requests_json").with_source(err)))
+        .and_then(|pending_requests| {
+            let mut pending_requests = pending_requests.into_iter()
+                .map(|pending| PendingRequest::from_json(&pending.to_string()));
+            let mut merged = pending_requests.next()
+                .unwrap_or_else(|| Err(SovtokenError::invalid_structure("pending_requests_json").with_source("no pending request")))?;
+            for pending in pending_requests {
+                merged.merge(pending?)?;
+            }
+
+            let report = merged.quorum_report();
+            let request = if report.missing == 0 { Some(merged.clone().into_request()?) } else { None };
+            Ok(json!({
+                "pending": merged,
+                "quorum": report,
+                "request": request,
+            }))
+        });
+
+    let merged = match merged {
+        Ok(merged) => merged,
+        Err(err) => return errors::to_error_code(err) as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(merged.to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_merge_multisig_requests << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Decodes a request built by libsovtoken, to check it before signing or submitting it.
+ *
+ * ## Parameters
+ * ### request_json
+ * A MINT_PUBLIC, XFER_PUBLIC, SET_FEES, GET_UTXO or GET_FEES request, or a
+ * write request with fees added by [`add_request_fees_handler`]
+ * ### utxos_json
+ * Optional UTXOs giving the amounts of the inputs, like the receipts of [`parse_payment_response_handler`]
+ * ```JSON
+ * [
+ *      {
+ *          "recipient": <str: payment_address>,
+ *          "receipt": <str: txo>,
+ *          "amount": <int>,
+ *          "extra": <str>
+ *      }
+ * ]
+ * ```
+ *
+ * ## Returns
+ * ```JSON
+ * {
+ *      "summary": {
+ *          "txnType": <str>,
+ *          "kind": <"mint" | "transfer" | "setFees" | "getUtxo" | "getFees" | "withFees">,
+ *          "reqId": <optional int>,
+ *          "identifier": <optional str>,
+ *          "inputs": [{"txo": <str>, "address": <str>, "seqNo": <int>, "amount": <optional int>, "signed": <bool>}],
+ *          "outputs": [{"address": <str>, "amount": <int>}],
+ *          "recipients": [{"address": <str>, "amount": <int>}],
+ *          "change": <int>,
+ *          "totalIn": <optional int: none when the amount of an input is unknown>,
+ *          "totalOut": <int>,
+ *          "feePaid": <optional int>,
+ *          "fees": <optional object: the fees set by a SET_FEES>,
+ *          "address": <optional str: address of a GET_UTXO>,
+ *          "from": <optional int>,
+ *          "taaDigest": <optional str>,
+ *          "signers": [<str: payment address or did>],
+ *          "missingSigners": [<str: payment address>]
+ *      },
+ *      "description": <str: the summary, readable by humans>
+ * }
+ * ```
+ *
+ * [`add_request_fees_handler`]: fn.add_request_fees_handler.html
+ * [`parse_payment_response_handler`]: fn.parse_payment_response_handler.html
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_inspect_request(
+    command_handle: i32,
+    request_json: *const c_char,
+    utxos_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_inspect_request called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let utxos: Vec<UTXO> = match str_from_char_ptr(utxos_json).map(serde_json::from_str) {
+        Some(Ok(utxos)) => utxos,
+        Some(Err(err)) => return errors::to_error_code(SovtokenError::invalid_structure("utxos_json").with_source(err)) as i32,
+        None => Vec::new()
+    };
+
+    let inspection = str_from_char_ptr(request_json)
+        .ok_or_else(|| SovtokenError::invalid_structure("request_json"))
+        .and_then(|request_json| inspector::inspect_request(request_json, &utxos));
+
+    let inspection = match inspection {
+        Ok(inspection) => json!({
+            "description": inspection.to_string(),
+            "summary": inspection,
+        }),
+        Err(err) => return errors::to_error_code(err) as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(inspection.to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_inspect_request << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+ * Checks a signed payment or request with fees before it is submitted, so
+ * that a malformed or under-signed request isn't only found out when the pool
+ * rejects it.
+ *
+ * ## Parameters
+ * ### request_json
+ * A request of [`build_payment_req_handler`] or [`add_request_fees_handler`]
+ * ### config_json
+ * ```JSON
+ * {
+ *      "utxos": [
+ *          {
+ *              "recipient": <str: payment_address>,
+ *              "receipt": <str: txo>,
+ *              "amount": <int>,
+ *              "extra": <str>
+ *          }
+ *      ],
+ *      "fee": <optional int: fee of the request, 0 by default>,
+ *      "taaRequired": <optional bool: the ledger requires a TAA acceptance>,
+ *      "extra": <optional object: extra the fees of a request were signed with>
+ * }
+ * ```
+ *
+ * ## Returns
+ * ```JSON
+ * {
+ *      "txnType": <str>,
+ *      "totalIn": <int>,
+ *      "totalOut": <int>,
+ *      "fee": <int>
+ * }
+ * ```
+ *
+ * ## Errors
+ * - CommonInvalidStructure for duplicated inputs, amounts of 0, invalid signatures or a missing TAA acceptance
+ * - PaymentSourceDoesNotExistError when an input is not in `utxos`
+ * - PaymentInsufficientFundsError or PaymentExtraFundsError when the inputs are not the outputs plus the fee
+ *
+ * [`build_payment_req_handler`]: fn.build_payment_req_handler.html
+ * [`add_request_fees_handler`]: fn.add_request_fees_handler.html
+ */
+#[no_mangle]
+pub extern "C" fn sovtoken_validate_request(
+    command_handle: i32,
+    request_json: *const c_char,
+    config_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_validate_request called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let config: ValidationConfig = match str_from_char_ptr(config_json).map(serde_json::from_str) {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => return errors::to_error_code(SovtokenError::invalid_structure("config_json").with_source(err)) as i32,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let validated = str_from_char_ptr(request_json)
+        .ok_or_else(|| SovtokenError::invalid_structure("request_json"))
+        .and_then(|request_json| request_validator::validate_request(request_json, &config));
+
+    let validated = match validated {
+        Ok(validated) => json!(validated),
+        Err(err) => return errors::to_error_code(err) as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(validated.to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_validate_request << result: {:?}", res);
+    return res as i32;
+}
+
 /// Build a verify transaction request.
 ///
 /// # Parameters
@@ -823,6 +1977,7 @@ pub extern "C" fn build_verify_req_handler(
     txo: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
     trace!("api::build_verify_req called >> wallet_handle {}", wallet_handle);
 
     let (did, txo, cb) = match verify::deserialize(did, txo, cb) {
@@ -870,6 +2025,7 @@ pub extern "C" fn parse_verify_response_handler(
     resp_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    errors::clear_current_error();
     check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
 
     trace!("api::parse_verify_response_handler called");
@@ -908,6 +2064,7 @@ pub extern "C" fn parse_verify_response_handler(
 #[no_mangle]
 pub extern "C" fn get_utxo_state_proof_parser(reply_from_node: *const c_char,
                                               parsed_sp: *mut *const c_char) -> i32 {
+    errors::clear_current_error();
     trace!("Calling get_utxo_state_proof_parser.");
 
     check_useful_c_ptr!(reply_from_node, ErrorCode::CommonInvalidParam1 as i32);
@@ -922,6 +2079,7 @@ pub extern "C" fn get_utxo_state_proof_parser(reply_from_node: *const c_char,
 #[no_mangle]
 pub extern "C" fn get_fees_state_proof_parser(reply_from_node: *const c_char,
                                               parsed_sp: *mut *const c_char) -> i32 {
+    errors::clear_current_error();
     trace!("Calling get_fees_state_proof_parser.");
 
     check_useful_c_ptr!(reply_from_node, ErrorCode::CommonInvalidParam1 as i32);
@@ -935,6 +2093,7 @@ pub extern "C" fn get_fees_state_proof_parser(reply_from_node: *const c_char,
 
 #[no_mangle]
 pub extern fn free_parsed_state_proof(sp: *const c_char) -> i32 {
+    errors::clear_current_error();
     trace!("Calling free_parsed_state_proof.");
 
     check_useful_c_ptr!(sp, ErrorCode::CommonInvalidParam1 as i32);
@@ -946,6 +2105,184 @@ pub extern fn free_parsed_state_proof(sp: *const c_char) -> i32 {
     return ErrorCode::Success as i32;
 }
 
+/**
+    Sets how fresh the state proofs of the replies given to
+    [`parse_get_utxo_response_handler`] and [`parse_get_txn_fees_response_handler`]
+    have to be. Not checked by default.
+
+    # Params
+    freshness_json: null to not check the proofs, or
+    ```JSON
+    {
+        "maxProofAge": <int: seconds since the nodes signed the proof>,
+        "clockSkewTolerance": Optional<int: seconds, 0 by default>
+    }
+    ```
+
+    # Returns
+    ErrorCode::Success, CommonInvalidStructure when freshness_json is invalid
+
+    A handler given a stale proof, or one without timestamp, fails with
+    `LedgerSecurityError`.
+
+    The setting is process-wide: libindy calls the handlers with a fixed
+    signature, so it applies to the replies of every pool parsed on any thread
+    until it is changed again. The functions of [`client`] take the settings
+    per call instead.
+
+    [`client`]: ../client/index.html
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_set_proof_freshness(freshness_json: *const c_char) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_set_proof_freshness called");
+    let settings = match str_from_char_ptr(freshness_json) {
+        Some(json) => match FreshnessSettings::from_json(json) {
+            Ok(settings) => Some(settings),
+            Err(_) => return ErrorCode::CommonInvalidStructure as i32
+        },
+        None => None
+    };
+
+    trace!("api::sovtoken_set_proof_freshness >> settings: {:?}", settings);
+    freshness::set_freshness_settings(settings);
+    return ErrorCode::Success as i32;
+}
+
+/**
+    Verifies the BLS multi-signature of the state proof of a GET_UTXO or GET_FEES
+    reply, without going through the libindy pool.
+
+    # Params
+    command_handle: standard command handle
+    reply_json: the reply of a node
+    node_keys_json: the nodes trusted to sign
+    ```JSON
+    {
+        "nodes": {<str: node alias>: <str: base58 bls key>},
+        "quorum": Optional<int>,
+        "generator": Optional<str>
+    }
+    ```
+    cb: called with the signed value
+    ```JSON
+    {
+        "ledger_id": <int>,
+        "pool_state_root_hash": <str>,
+        "state_root_hash": <str>,
+        "timestamp": <int>,
+        "txn_root_hash": <str>
+    }
+    ```
+
+    # Returns
+    LedgerSecurityError when the state proof isn't signed by a quorum of the
+    nodes, LedgerInvalidTransaction when the reply has no state proof.
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_verify_state_proof_signature(
+    command_handle: i32,
+    reply_json: *const c_char,
+    node_keys_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_verify_state_proof_signature called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let reply_json = match str_from_char_ptr(reply_json) {
+        Some(reply_json) => reply_json,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let node_keys = match str_from_char_ptr(node_keys_json).map(NodeKeys::from_json) {
+        Some(Ok(node_keys)) => node_keys,
+        _ => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let value = match state_proof::verify_state_proof_signature(reply_json, &node_keys) {
+        Ok(value) => value,
+        Err(ec) => {
+            trace!("api::sovtoken_verify_state_proof_signature << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    let value = match value.to_json() {
+        Ok(value) => value,
+        Err(_) => return ErrorCode::CommonInvalidState as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(value));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_verify_state_proof_signature << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Verifies the `proof_nodes` of the state proof of a GET_UTXO or GET_FEES reply
+    against its `root_hash`, without going through the libindy pool.
+
+    # Params
+    command_handle: standard command handle
+    reply_json: the reply of a node
+    cb: called with the verified values, a `null` value when the proof shows the
+    key is absent
+    ```JSON
+    [
+        {
+            "key": <str: state key>,
+            "value": <str or null>
+        }
+    ]
+    ```
+
+    # Returns
+    LedgerInvalidTransaction when the outputs or fees of the reply aren't the ones
+    of the trie.
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_verify_state_proof_nodes(
+    command_handle: i32,
+    reply_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_verify_state_proof_nodes called");
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let reply_json = match str_from_char_ptr(reply_json) {
+        Some(reply_json) => reply_json,
+        None => return ErrorCode::CommonInvalidStructure as i32
+    };
+
+    let verified = match trie::verify_reply_state_proof(reply_json) {
+        Ok(verified) => verified,
+        Err(ec) => {
+            trace!("api::sovtoken_verify_state_proof_nodes << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    let verified = match verified.to_json() {
+        Ok(verified) => verified,
+        Err(_) => return ErrorCode::CommonInvalidState as i32
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(verified));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_verify_state_proof_nodes << result: {:?}", res);
+    return res as i32;
+}
+
 #[no_mangle]
 pub extern "C" fn sign_with_address_handler(
     command_handle: i32,
@@ -955,6 +2292,7 @@ pub extern "C" fn sign_with_address_handler(
     message_len: u32,
     cb: Option<ResponseSliceCB>
 ) -> i32 {
+    errors::clear_current_error();
     trace!("api::sign_with_address_handler called >> submitter_did (address) {:?}", secret!(&address));
 
     match _check_address_is_vk(address) {
@@ -1013,6 +2351,69 @@ fn _check_address_is_vk(address: *const c_char) -> Result<String, ErrorCode> {
     }
 }
 
+/**
+    Turns on or off the check of the audit paths of the replies given to
+    [`parse_payment_response_handler`] and [`parse_response_with_fees_handler`].
+
+    When on, a reply whose transaction doesn't hash to its `rootHash` fails with
+    `LedgerSecurityError`. Off by default.
+
+    The setting is process-wide, it applies to the replies parsed on every
+    thread until it is changed again.
+
+    # Returns
+    ErrorCode::Success
+*/
+#[no_mangle]
+pub extern fn sovtoken_set_audit_path_verification(enabled: bool) -> i32 {
+    errors::clear_current_error();
+    trace!("api::sovtoken_set_audit_path_verification >> enabled: {:?}", enabled);
+    merkle::set_audit_path_verification(enabled);
+    return ErrorCode::Success as i32;
+}
+
+/**
+    Gets the details of the last error of a libsovtoken function called on this
+    thread, like `indy_get_current_error` of libindy.
+
+    # Params
+    error_json_p: set to a null pointer without an error, or to
+    ```JSON
+    {
+        "errorCode": <int: the ErrorCode returned>,
+        "message": <str: why it failed>,
+        "field": Optional<str: the failing field>,
+        "value": Optional<str: the offending value>,
+        "source": Optional<str: the error it came from>,
+        "staleProof": Optional<object: see parse_get_utxo_response_handler>,
+        "rejection": Optional<{
+            "reason": <str: reason of the REJECT or REQNACK>,
+            "error": Optional<str: name of the exception, e.g. InsufficientFundsError>,
+            "spentUtxos": Optional<[str: txo or <address>:<seqNo>]>,
+            "requiredAmount": Optional<int>,
+            "providedAmount": Optional<int>,
+            "missingSignatures": Optional<{"provided": Optional<int>, "required": Optional<int>}>,
+            "taa": Optional<str: required|digestMismatch|mechanism|time|notExpected|other>,
+            "feeAlias": Optional<{"alias": Optional<str>}>
+        }>,
+        "amountOverflow": Optional<{"left": <int>, "right": <int>}: the sum which overflows>
+    }
+    ```
+    Every other libsovtoken function clears the error when it is called, so it
+    is the error of the last call on this thread. The string is owned by
+    libsovtoken and stays valid until the next call on this thread.
+*/
+#[no_mangle]
+pub extern fn sovtoken_get_current_error(error_json_p: *mut *const c_char) {
+    trace!("api::sovtoken_get_current_error >> error_json_p: {:?}", error_json_p);
+    if error_json_p.is_null() {
+        return;
+    }
+
+    unsafe { *error_json_p = errors::current_error_c_json(); }
+    trace!("api::sovtoken_get_current_error <<");
+}
+
 /**
     exported method indy-sdk will call for us to register our payment methods with indy-sdk
 
@@ -1024,6 +2425,7 @@ fn _check_address_is_vk(address: *const c_char) -> Result<String, ErrorCode> {
 */
 #[no_mangle]
 pub extern fn sovtoken_init() -> i32 {
+    errors::clear_current_error();
 
     if let Err(err) = ::utils::logger::SovtokenLogger::init() {
         return err as i32;
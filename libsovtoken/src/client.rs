@@ -0,0 +1,369 @@
+/*!
+    Rust API of libsovtoken
+
+    The handlers of [`api`] are for libindy: they take C strings and give their
+    results to callbacks. These functions do the same with Rust types, for
+    services linking `sovtoken` as a library.
+
+    The functions using a wallet block until libindy is done. Don't call them
+    from a libindy callback.
+
+    [`api`]: ../api/index.html
+*/
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::mpsc::channel;
+
+use serde_json;
+
+use {ErrorCode, IndyHandle};
+use logic::api_internals::add_request_fees;
+use logic::build_payment;
+use logic::config::get_fees_config::GetFeesRequest;
+use logic::config::get_utxo_config::GetUtxoOperationRequest;
+use logic::config::set_fees_config::{SetFees, SetFeesMap};
+use logic::did::Did;
+use logic::indy_sdk_api::crypto_api::CryptoSdk;
+use logic::indy_sdk_api::ledger::Ledger;
+use logic::input::Inputs;
+use logic::merkle;
+use logic::minting;
+use logic::output::Outputs;
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_txn_fees::{self, ParseGetTxnFeesResponse};
+use logic::parsers::parse_get_utxo_response::{self, ParseGetUtxoResponse, ParseGetUtxoReply};
+use logic::parsers::parse_payment_response::{self, ParsePaymentResponse, ParsePaymentReply};
+use logic::parsers::parse_response_with_fees_handler::{self, ParseResponseWithFees, ParseResponseWithFeesReply};
+use logic::parsers::parse_verify::{self, VerifyResult};
+use logic::state_proof::freshness::FreshnessSettings;
+use logic::type_aliases::TokenAmount;
+use logic::xfer_payload::{Extra, XferPayload};
+use utils::constants::general::LEDGER_ID;
+use utils::errors::SovtokenError;
+use utils::json_conversion::JsonDeserialize;
+
+const DEFAULT_GET_FEES_DID: &str = "LibsovtokenDid11111111";
+
+/**
+    Builds a signed XFER_PUBLIC request, like [`build_payment_req_handler`].
+
+    The keys of the inputs have to be in the wallet. The did is derived from the
+    first input when `submitter_did` is `None`.
+
+    [`build_payment_req_handler`]: ../api/fn.build_payment_req_handler.html
+*/
+pub fn build_payment_request(
+    wallet_handle: IndyHandle,
+    submitter_did: Option<&str>,
+    inputs: Inputs,
+    outputs: Outputs,
+    extra: Option<Extra>
+) -> Result<String, SovtokenError> {
+    trace!("client::build_payment_request >> inputs: {:?}, outputs: {:?}", secret!(&inputs), secret!(&outputs));
+    let did = validate_did(submitter_did)?;
+
+    let payload = XferPayload::new(inputs, outputs, extra);
+    let signed = wait_for(|cb| payload.sign_transfer(&CryptoSdk {}, wallet_handle, cb))?;
+
+    let res = build_payment::build_payment_request_json(did, signed).map_err(SovtokenError::from);
+    trace!("client::build_payment_request << result: {:?}", secret!(&res));
+    res
+}
+
+/**
+    Parses the reply to a XFER_PUBLIC request, like [`parse_payment_response_handler`].
+
+    [`parse_payment_response_handler`]: ../api/fn.parse_payment_response_handler.html
+*/
+pub fn parse_payment_response(resp_json: &str) -> Result<ParsePaymentReply, SovtokenError> {
+    merkle::verify_write_reply_if_enabled(resp_json)?;
+    let response = ParsePaymentResponse::from_json(resp_json)
+        .map_err(|err| SovtokenError::invalid_structure("resp_json").with_source(err))?;
+    parse_payment_response::from_response(response).map_err(SovtokenError::from)
+}
+
+/**
+    Adds signed fees to a request, like [`add_request_fees_handler`].
+
+    [`add_request_fees_handler`]: ../api/fn.add_request_fees_handler.html
+*/
+pub fn add_request_fees(
+    wallet_handle: IndyHandle,
+    request_json: &str,
+    inputs: Inputs,
+    outputs: Outputs,
+    extra: Option<Extra>
+) -> Result<String, SovtokenError> {
+    trace!("client::add_request_fees >> inputs: {:?}, outputs: {:?}", secret!(&inputs), secret!(&outputs));
+    let request: serde_json::Value = serde_json::from_str(request_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+    let request_json_map = request.as_object()
+        .ok_or(ErrorCode::CommonInvalidStructure)?
+        .to_owned();
+
+    add_request_fees::validate_type_not_transfer(&request_json_map)?;
+
+    let res = wait_for(|cb| add_request_fees::add_fees_to_request_and_serialize(wallet_handle, inputs, outputs, extra, request_json_map, cb))?;
+    trace!("client::add_request_fees << result: {:?}", secret!(&res));
+    res.map_err(SovtokenError::from)
+}
+
+/**
+    Parses the reply to a request with fees, like [`parse_response_with_fees_handler`].
+
+    `None` when the request had no fees.
+
+    [`parse_response_with_fees_handler`]: ../api/fn.parse_response_with_fees_handler.html
+*/
+pub fn parse_response_with_fees(resp_json: &str) -> Result<Option<ParseResponseWithFeesReply>, SovtokenError> {
+    merkle::verify_write_reply_if_enabled(resp_json)?;
+    let response = ParseResponseWithFees::from_json(resp_json)
+        .map_err(|err| SovtokenError::invalid_structure("resp_json").with_source(err))?;
+    parse_response_with_fees_handler::from_response(response).map_err(SovtokenError::from)
+}
+
+/**
+    Builds a MINT_PUBLIC request, like [`build_mint_txn_handler`].
+
+    The request still has to be signed by the trustees.
+
+    [`build_mint_txn_handler`]: ../api/fn.build_mint_txn_handler.html
+*/
+pub fn build_mint_request(submitter_did: Option<&str>, outputs: Outputs, extra: Option<String>) -> Result<String, SovtokenError> {
+    let did = validate_did(submitter_did)?;
+    minting::build_mint_request_json(did, outputs, extra).map_err(SovtokenError::from)
+}
+
+/**
+    Builds a SET_FEES request, like [`build_set_txn_fees_handler`].
+
+    [`build_set_txn_fees_handler`]: ../api/fn.build_set_txn_fees_handler.html
+*/
+pub fn build_set_fees_request(submitter_did: Option<&str>, fees: SetFeesMap) -> Result<String, SovtokenError> {
+    let did = validate_did(submitter_did)?;
+    let set_fees = SetFees::new(fees)
+        .validate()
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    set_fees.as_request(did)
+        .serialize_to_string()
+        .map_err(map_err_err!())
+        .map_err(|_| SovtokenError::from(ErrorCode::CommonInvalidState))
+}
+
+/**
+    Builds a GET_FEES request, like [`build_get_txn_fees_handler`].
+
+    [`build_get_txn_fees_handler`]: ../api/fn.build_get_txn_fees_handler.html
+*/
+pub fn build_get_fees_request(submitter_did: Option<&str>) -> Result<String, SovtokenError> {
+    let did = validate_did(submitter_did)?.unwrap_or(Did::new(DEFAULT_GET_FEES_DID.to_string()));
+
+    GetFeesRequest::new()
+        .as_request(Some(did))
+        .serialize_to_string()
+        .map_err(map_err_err!())
+        .map_err(|_| SovtokenError::from(ErrorCode::CommonInvalidState))
+}
+
+/**
+    Parses the reply to a GET_FEES request, like [`parse_get_txn_fees_response_handler`].
+
+    Fails with `LedgerSecurityError` when the state proof isn't as fresh as
+    `freshness` requires, not checked when it is `None`. See [`freshness`].
+
+    [`parse_get_txn_fees_response_handler`]: ../api/fn.parse_get_txn_fees_response_handler.html
+    [`freshness`]: ../logic/state_proof/freshness/index.html
+*/
+pub fn parse_get_fees_response(resp_json: &str, freshness: Option<&FreshnessSettings>) -> Result<HashMap<String, TokenAmount>, SovtokenError> {
+    let response = ParseGetTxnFeesResponse::from_json(resp_json)
+        .map_err(|err| SovtokenError::invalid_structure("resp_json").with_source(err))?;
+    parse_get_txn_fees::check_freshness(&response, freshness)?;
+    Ok(response.result.fees)
+}
+
+/**
+    Builds a GET_UTXO request, like [`build_get_utxo_request_handler`].
+
+    [`build_get_utxo_request_handler`]: ../api/fn.build_get_utxo_request_handler.html
+*/
+pub fn build_get_utxo_request(payment_address: &str, from: Option<i64>) -> Result<String, SovtokenError> {
+    GetUtxoOperationRequest::new(payment_address.to_string(), from)
+        .serialize_to_string()
+        .map_err(map_err_err!())
+        .map_err(|_| SovtokenError::from(ErrorCode::CommonInvalidStructure))
+}
+
+/**
+    Parses the reply to a GET_UTXO request, like [`parse_get_utxo_response_handler`].
+
+    Fails with `LedgerSecurityError` when the state proof isn't as fresh as
+    `freshness` requires, not checked when it is `None`.
+
+    [`parse_get_utxo_response_handler`]: ../api/fn.parse_get_utxo_response_handler.html
+*/
+pub fn parse_get_utxo_response(resp_json: &str, freshness: Option<&FreshnessSettings>) -> Result<ParseGetUtxoReply, SovtokenError> {
+    let response = ParseGetUtxoResponse::from_json(resp_json)
+        .map_err(|err| SovtokenError::invalid_structure("resp_json").with_source(err))?;
+    parse_get_utxo_response::check_freshness(&response, freshness)?;
+    parse_get_utxo_response::from_response(response).map_err(SovtokenError::from)
+}
+
+/**
+    Builds the GET_TXN request of the transaction of a txo, like [`build_verify_req_handler`].
+
+    [`build_verify_req_handler`]: ../api/fn.build_verify_req_handler.html
+*/
+pub fn build_verify_request(submitter_did: Option<&str>, txo: &str) -> Result<String, SovtokenError> {
+    let did = validate_did(submitter_did)?.map(String::from);
+    let txo = TXO::from_libindy_string(txo)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    Ledger::build_get_txn_request(did.as_ref().map(String::as_str), Some(LEDGER_ID), txo.seq_no as i32)
+        .map_err(SovtokenError::from)
+}
+
+/**
+    Parses the reply to a GET_TXN request, like [`parse_verify_response_handler`].
+
+    [`parse_verify_response_handler`]: ../api/fn.parse_verify_response_handler.html
+*/
+pub fn parse_verify_response(resp_json: &str) -> Result<VerifyResult, SovtokenError> {
+    parse_verify::parse_verify(resp_json).map_err(SovtokenError::from)
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn validate_did(did: Option<&str>) -> Result<Option<Did>, SovtokenError> {
+    match did {
+        Some(did) => Did::new(did.to_string())
+            .validate()
+            .map(Some)
+            .map_err(|err| SovtokenError::invalid_structure("submitter_did").with_value(did).with_source(format!("{:?}", err))),
+        None => Ok(None)
+    }
+}
+
+/*
+    Calls an asynchronous function of libsovtoken and waits for its callback.
+*/
+fn wait_for<T, F>(start: F) -> Result<T, ErrorCode>
+    where T: Send + 'static,
+          F: FnOnce(Box<Fn(T) + Send + Sync>) -> Result<(), ErrorCode>
+{
+    let (sender, receiver) = channel();
+    let sender = Mutex::new(sender);
+
+    start(Box::new(move |result| {
+        if let Ok(sender) = sender.lock() {
+            let _ = sender.send(result);
+        }
+    }))?;
+
+    receiver.recv()
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonIOError)
+}
+
+
+#[cfg(test)]
+mod client_tests {
+    use super::*;
+    use logic::output::Output;
+    use utils::constants::txn_types::{GET_FEES, GET_UTXO, MINT_PUBLIC, SET_FEES};
+
+    const DID: &str = "Th7MpTaRZVRYnPiabds81Y";
+    const ADDRESS: &str = "pay:sov:2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt";
+
+    #[test]
+    fn build_mint_request_of_outputs() {
+        let outputs = vec![Output::new(ADDRESS.to_string(), 10)];
+        let request: serde_json::Value = serde_json::from_str(&build_mint_request(Some(DID), outputs, None).unwrap()).unwrap();
+
+        assert_eq!(request["identifier"], json!(DID));
+        assert_eq!(request["operation"]["type"], json!(MINT_PUBLIC));
+        assert_eq!(request["operation"]["outputs"][0]["address"], json!("2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt"));
+    }
+
+    #[test]
+    fn build_mint_request_with_invalid_did() {
+        let outputs = vec![Output::new(ADDRESS.to_string(), 10)];
+        let error = build_mint_request(Some("not a did"), outputs, None).unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::CommonInvalidStructure);
+        assert!(error.to_error_json().contains("\"field\":\"submitter_did\""));
+    }
+
+    #[test]
+    fn build_set_fees_request_of_fees() {
+        let mut fees = SetFeesMap::new();
+        fees.insert("1".to_string(), TokenAmount::new(4));
+        let request: serde_json::Value = serde_json::from_str(&build_set_fees_request(Some(DID), fees).unwrap()).unwrap();
+
+        assert_eq!(request["operation"]["type"], json!(SET_FEES));
+        assert_eq!(request["operation"]["fees"], json!({"1": 4}));
+    }
+
+    #[test]
+    fn build_get_fees_request_with_default_did() {
+        let request: serde_json::Value = serde_json::from_str(&build_get_fees_request(None).unwrap()).unwrap();
+
+        assert_eq!(request["identifier"], json!(DEFAULT_GET_FEES_DID));
+        assert_eq!(request["operation"]["type"], json!(GET_FEES));
+    }
+
+    #[test]
+    fn build_get_utxo_request_of_address() {
+        let request: serde_json::Value = serde_json::from_str(&build_get_utxo_request(ADDRESS, Some(5)).unwrap()).unwrap();
+
+        assert_eq!(request["operation"]["type"], json!(GET_UTXO));
+        assert_eq!(request["operation"]["from"], json!(5));
+    }
+
+    #[test]
+    fn parse_get_fees_response_of_reply() {
+        let reply = json!({
+            "op": "REPLY",
+            "result": {
+                "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+                "reqId": 47660,
+                "type": "20001",
+                "fees": {"1": 4, "10001": 8}
+            }
+        });
+        let fees = parse_get_fees_response(&reply.to_string(), None).unwrap();
+
+        assert_eq!(fees.get("10001"), Some(&TokenAmount::new(8)));
+    }
+
+    #[test]
+    fn parse_get_fees_response_without_proof_timestamp() {
+        let reply = json!({
+            "op": "REPLY",
+            "result": {
+                "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+                "reqId": 47660,
+                "type": "20001",
+                "fees": {"1": 4, "10001": 8}
+            }
+        });
+        let freshness = FreshnessSettings { max_proof_age: 60, clock_skew_tolerance: 0 };
+        let error = parse_get_fees_response(&reply.to_string(), Some(&freshness)).unwrap_err();
+
+        assert_eq!(error.error_code(), ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn parse_get_utxo_response_of_invalid_json() {
+        let error = parse_get_utxo_response("{", None).unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::CommonInvalidStructure);
+    }
+}
@@ -9,12 +9,14 @@
 // ------------------------------------------
 extern crate base64;
 extern crate bs58;
+extern crate ed25519_dalek;
 extern crate hex;
 extern crate libc;
 extern crate openssl;
 extern crate rand;
 extern crate serde;
 extern crate sha2;
+extern crate sha3;
 extern crate time;
 // ------------------------------------------
 // crates from crate.io etc that require macro
@@ -32,6 +34,7 @@ extern crate time;
 // evernym/sovrin crates
 // ------------------------------------------
 
+extern crate indy_crypto;                   // bls multi signatures of state proofs
 extern crate indy_sys;                      // lib-sdk project
 extern crate indyrs as indy;
 extern crate core;                      // lib-sdk rust wrapper to get ErrorCodes
@@ -44,6 +47,7 @@ extern crate core;                      // lib-sdk rust wrapper to get ErrorCode
 #[macro_use]
 pub mod utils;
 pub mod api;
+pub mod client;
 pub mod logic;
 pub mod libraries;
 
@@ -0,0 +1,426 @@
+/*!
+    Address book of the payment addresses created in a wallet
+
+    Each address created through [`create_payment_address_handler`] gets a
+    non-secret wallet record of type [`ADDRESS_RECORD_TYPE`], its id being the
+    payment address. The record value holds the label, the creation time and
+    whether the key came from a seed. The tags are the ones of the
+    [`CreatePaymentAddressConfig`], they can be searched with WQL.
+
+    All the functions are asynchronous: they may be called from libindy callbacks,
+    where waiting on another libindy call would dead lock.
+
+    [`create_payment_address_handler`]: ../../api/fn.create_payment_address_handler.html
+    [`ADDRESS_RECORD_TYPE`]: constant.ADDRESS_RECORD_TYPE.html
+    [`CreatePaymentAddressConfig`]: ../config/payment_address_config/struct.CreatePaymentAddressConfig.html
+*/
+
+use serde_json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use time;
+
+use {ErrorCode, IndyHandle};
+use logic::address;
+use logic::config::payment_address_config::CreatePaymentAddressConfig;
+use logic::indy_sdk_api::non_secrets::{WalletRecord, WalletSearch};
+use utils::json_conversion::{JsonDeserialize, JsonSerialize};
+
+pub const ADDRESS_RECORD_TYPE: &str = "sovtoken_payment_address";
+
+/**
+    Number of records fetched at once when searching.
+*/
+pub const SEARCH_BATCH_SIZE: usize = 100;
+
+const RECORD_OPTIONS: &str = r#"{"retrieveType": false, "retrieveValue": true, "retrieveTags": true}"#;
+const SEARCH_OPTIONS: &str = r#"{"retrieveRecords": true, "retrieveTotalCount": false, "retrieveType": false, "retrieveValue": true, "retrieveTags": true}"#;
+
+/**
+    Metadata of a payment address
+
+    `created_at` is a unix timestamp in seconds.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressRecord {
+    pub address: String,
+    pub label: Option<String>,
+    pub created_at: u64,
+    pub seed_derived: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<String>,
+    pub tags: HashMap<String, String>,
+}
+
+/**
+    Changes made by [`update_address_async`], absent fields are left as they are.
+
+    [`update_address_async`]: fn.update_address_async.html
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressRecordUpdate {
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub tags: Option<HashMap<String, String>>,
+}
+
+/*
+    What is stored in the record value, the address is the record id
+    and the tags are the record tags.
+*/
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AddressRecordValue {
+    label: Option<String>,
+    created_at: u64,
+    seed_derived: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    derivation_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct WalletRecordJson {
+    id: String,
+    value: Option<String>,
+    tags: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct WalletSearchRecordsJson {
+    records: Option<Vec<WalletRecordJson>>,
+}
+
+impl AddressRecord {
+    pub fn new(address: String, label: Option<String>, seed_derived: bool, tags: HashMap<String, String>) -> AddressRecord {
+        AddressRecord {
+            address,
+            label,
+            created_at: time::get_time().sec as u64,
+            seed_derived,
+            derivation_path: None,
+            tags,
+        }
+    }
+
+    /**
+        Record of an address created with `config`
+    */
+    pub fn from_config(address: String, config: &CreatePaymentAddressConfig) -> AddressRecord {
+        let mut record = AddressRecord::new(address, config.label.clone(), !config.key.seed.is_empty(), config.tags.clone());
+        record.derivation_path = config.derivation_path.clone();
+        record
+    }
+
+    fn value_json(&self) -> Result<String, ErrorCode> {
+        AddressRecordValue {
+            label: self.label.clone(),
+            created_at: self.created_at,
+            seed_derived: self.seed_derived,
+            derivation_path: self.derivation_path.clone(),
+        }.to_json().map_err(|_| ErrorCode::CommonInvalidState)
+    }
+
+    fn tags_json(&self) -> Result<String, ErrorCode> {
+        self.tags.to_json().map_err(|_| ErrorCode::CommonInvalidState)
+    }
+
+    fn from_wallet_record(record: WalletRecordJson) -> Result<AddressRecord, ErrorCode> {
+        let value = record.value.ok_or(ErrorCode::CommonInvalidStructure)?;
+        let value = AddressRecordValue::from_json(&value)
+            .map_err(map_err_err!())
+            .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+        Ok(AddressRecord {
+            address: record.id,
+            label: value.label,
+            created_at: value.created_at,
+            seed_derived: value.seed_derived,
+            derivation_path: value.derivation_path,
+            tags: record.tags.unwrap_or_default(),
+        })
+    }
+}
+
+/**
+    Stores the record of a new address.
+*/
+pub fn add_address_async<F: 'static>(wallet_handle: IndyHandle, record: &AddressRecord, mut cb: F) -> ErrorCode
+    where F: FnMut(Result<(), ErrorCode>) + Send
+{
+    trace!("logic::address_book::add_address_async >> wallet_handle: {:?}, record: {:?}", wallet_handle, secret!(record));
+    let (value, tags) = match (record.value_json(), record.tags_json()) {
+        (Ok(value), Ok(tags)) => (value, tags),
+        (Err(err), _) | (_, Err(err)) => return err
+    };
+
+    WalletRecord::add_async(wallet_handle, ADDRESS_RECORD_TYPE, &record.address, &value, Some(&tags), move |err| {
+        cb(to_result(err))
+    })
+}
+
+/**
+    Gets the record of `payment_address`.
+
+    Errors with `WalletItemNotFound` when the address has no record.
+*/
+pub fn get_address_async<F: 'static>(wallet_handle: IndyHandle, payment_address: &str, mut cb: F) -> ErrorCode
+    where F: FnMut(Result<AddressRecord, ErrorCode>) + Send
+{
+    trace!("logic::address_book::get_address_async >> wallet_handle: {:?}, payment_address: {:?}", wallet_handle, secret!(payment_address));
+    if let Err(err) = address::validate_address(payment_address) {
+        return err;
+    }
+
+    WalletRecord::get_async(wallet_handle, ADDRESS_RECORD_TYPE, payment_address, RECORD_OPTIONS, move |err, record_json| {
+        let res = to_result(err).and_then(|_| {
+            let record = WalletRecordJson::from_json(&record_json)
+                .map_err(map_err_err!())
+                .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+            AddressRecord::from_wallet_record(record)
+        });
+        cb(res)
+    })
+}
+
+/**
+    Gets the records whose tags match the WQL `query_json`, `{}` lists all of them.
+*/
+pub fn search_addresses_async<F: 'static>(wallet_handle: IndyHandle, query_json: &str, cb: F) -> ErrorCode
+    where F: FnMut(Result<Vec<AddressRecord>, ErrorCode>) + Send
+{
+    trace!("logic::address_book::search_addresses_async >> wallet_handle: {:?}, query_json: {:?}", wallet_handle, query_json);
+    if let Err(_) = serde_json::from_str::<serde_json::Value>(query_json) {
+        return ErrorCode::CommonInvalidStructure;
+    }
+
+    let cb: SharedCallback<Vec<AddressRecord>> = Arc::new(Mutex::new(Some(Box::new(cb))));
+    WalletSearch::open_async(wallet_handle, ADDRESS_RECORD_TYPE, query_json, SEARCH_OPTIONS, move |err, search_handle| {
+        if err != ErrorCode::Success {
+            return call_once(&cb, Err(err));
+        }
+
+        fetch_records(wallet_handle, search_handle, vec![], cb.clone());
+    })
+}
+
+/**
+    Changes the label and/or the tags of the record of `payment_address`, gives the updated record.
+*/
+pub fn update_address_async<F: 'static>(wallet_handle: IndyHandle, payment_address: &str, update: AddressRecordUpdate, cb: F) -> ErrorCode
+    where F: FnMut(Result<AddressRecord, ErrorCode>) + Send
+{
+    trace!("logic::address_book::update_address_async >> wallet_handle: {:?}, payment_address: {:?}, update: {:?}", wallet_handle, secret!(payment_address), update);
+    let cb: SharedCallback<AddressRecord> = Arc::new(Mutex::new(Some(Box::new(cb))));
+
+    get_address_async(wallet_handle, payment_address, move |record| {
+        let mut record = match record {
+            Ok(record) => record,
+            Err(err) => return call_once(&cb, Err(err))
+        };
+
+        if let Some(ref label) = update.label {
+            record.label = Some(label.clone());
+        }
+        if let Some(ref tags) = update.tags {
+            record.tags = tags.clone();
+        }
+
+        let (value, tags) = match (record.value_json(), record.tags_json()) {
+            (Ok(value), Ok(tags)) => (value, tags),
+            (Err(err), _) | (_, Err(err)) => return call_once(&cb, Err(err))
+        };
+
+        let address = record.address.clone();
+        let value_cb = cb.clone();
+        let ec = WalletRecord::update_value_async(wallet_handle, ADDRESS_RECORD_TYPE, &address.clone(), &value, move |err| {
+            if err != ErrorCode::Success {
+                return call_once(&value_cb, Err(err));
+            }
+
+            let record = record.clone();
+            let tags_cb = value_cb.clone();
+            let ec = WalletRecord::update_tags_async(wallet_handle, ADDRESS_RECORD_TYPE, &address, &tags, move |err| {
+                call_once(&tags_cb, to_result(err).map(|_| record.clone()));
+            });
+
+            if ec != ErrorCode::Success {
+                error!("Failed to update the tags of an address record: {:?}", ec);
+                call_once(&value_cb, Err(ec));
+            }
+        });
+
+        if ec != ErrorCode::Success {
+            error!("Failed to update an address record: {:?}", ec);
+            call_once(&cb, Err(ec));
+        }
+    })
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    Callback shared between a libindy call and its synchronous error path,
+    libindy drops the closure of a call failing before it is queued.
+*/
+type SharedCallback<T> = Arc<Mutex<Option<Box<FnMut(Result<T, ErrorCode>) + Send>>>>;
+
+fn call_once<T>(cb: &SharedCallback<T>, result: Result<T, ErrorCode>) {
+    if let Some(mut cb) = cb.lock().unwrap().take() {
+        cb(result);
+    }
+}
+
+fn to_result(err: ErrorCode) -> Result<(), ErrorCode> {
+    if err == ErrorCode::Success { Ok(()) } else { Err(err) }
+}
+
+fn parse_records(records_json: &str) -> Result<Vec<AddressRecord>, ErrorCode> {
+    let records = WalletSearchRecordsJson::from_json(records_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    records.records.unwrap_or_default()
+        .into_iter()
+        .map(AddressRecord::from_wallet_record)
+        .collect()
+}
+
+/*
+    Fetches batches until the search is exhausted, then closes the search.
+*/
+fn fetch_records(
+    wallet_handle: IndyHandle,
+    search_handle: IndyHandle,
+    mut found: Vec<AddressRecord>,
+    cb: SharedCallback<Vec<AddressRecord>>
+) {
+    let fetch_cb = cb.clone();
+    let ec = WalletSearch::fetch_next_records_async(wallet_handle, search_handle, SEARCH_BATCH_SIZE, move |err, records_json| {
+        let batch = to_result(err).and_then(|_| parse_records(&records_json));
+        match batch {
+            Ok(ref batch) if batch.len() == SEARCH_BATCH_SIZE => {
+                found.extend(batch.iter().cloned());
+                fetch_records(wallet_handle, search_handle, found.split_off(0), fetch_cb.clone());
+            }
+            Ok(batch) => {
+                found.extend(batch);
+                close_search(search_handle, Ok(found.split_off(0)), fetch_cb.clone());
+            }
+            Err(err) => close_search(search_handle, Err(err), fetch_cb.clone())
+        }
+    });
+
+    if ec != ErrorCode::Success {
+        error!("Failed to fetch address records: {:?}", ec);
+        close_search(search_handle, Err(ec), cb);
+    }
+}
+
+fn close_search(
+    search_handle: IndyHandle,
+    result: Result<Vec<AddressRecord>, ErrorCode>,
+    cb: SharedCallback<Vec<AddressRecord>>
+) {
+    let close_cb = cb.clone();
+    let mut result = Some(result);
+    let ec = WalletSearch::close_async(search_handle, move |err| {
+        if err != ErrorCode::Success {
+            warn!("Failed to close wallet search: {:?}", err);
+        }
+        if let Some(result) = result.take() {
+            call_once(&close_cb, result);
+        }
+    });
+
+    if ec != ErrorCode::Success {
+        error!("Failed to close wallet search: {:?}", ec);
+        call_once(&cb, Err(ec));
+    }
+}
+
+
+#[cfg(test)]
+mod address_book_tests {
+    use super::*;
+    use logic::config::payment_address_config::PaymentAddressConfig;
+
+    #[test]
+    fn record_from_config() {
+        let mut tags = HashMap::new();
+        tags.insert("purpose".to_string(), "savings".to_string());
+        let config = CreatePaymentAddressConfig {
+            key: PaymentAddressConfig { seed: "00000000000000000000000000000My1".to_string() },
+            label: Some("savings".to_string()),
+            tags: tags.clone(),
+            ..Default::default()
+        };
+
+        let record = AddressRecord::from_config("pay:sov:address".to_string(), &config);
+
+        assert_eq!(record.address, "pay:sov:address");
+        assert_eq!(record.label, Some("savings".to_string()));
+        assert!(record.seed_derived);
+        assert_eq!(record.tags, tags);
+        assert!(record.created_at > 0);
+    }
+
+    #[test]
+    fn record_from_config_without_seed() {
+        let record = AddressRecord::from_config("pay:sov:address".to_string(), &Default::default());
+
+        assert_eq!(record.label, None);
+        assert!(!record.seed_derived);
+        assert!(record.tags.is_empty());
+    }
+
+    #[test]
+    fn record_roundtrip_through_wallet_record() {
+        let mut tags = HashMap::new();
+        tags.insert("~group".to_string(), "a".to_string());
+        let record = AddressRecord::new("pay:sov:address".to_string(), Some("label".to_string()), false, tags);
+
+        let wallet_record = WalletRecordJson {
+            id: record.address.clone(),
+            value: Some(record.value_json().unwrap()),
+            tags: Some(serde_json::from_str(&record.tags_json().unwrap()).unwrap()),
+        };
+
+        assert_eq!(AddressRecord::from_wallet_record(wallet_record).unwrap(), record);
+    }
+
+    #[test]
+    fn parse_search_records() {
+        let records_json = json!({
+            "records": [
+                {"id": "pay:sov:a", "value": r#"{"label":"a","createdAt":1,"seedDerived":true}"#, "tags": {"k": "v"}},
+                {"id": "pay:sov:b", "value": r#"{"label":null,"createdAt":2,"seedDerived":false}"#, "tags": {}}
+            ]
+        }).to_string();
+
+        let records = parse_records(&records_json).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].label, Some("a".to_string()));
+        assert_eq!(records[0].tags.get("k"), Some(&"v".to_string()));
+        assert_eq!(records[1].created_at, 2);
+        assert!(!records[1].seed_derived);
+    }
+
+    #[test]
+    fn parse_search_without_records() {
+        assert!(parse_records(r#"{"records": null}"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_record_without_value() {
+        let record = WalletRecordJson { id: "pay:sov:a".to_string(), value: None, tags: None };
+        assert_eq!(AddressRecord::from_wallet_record(record).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+}
@@ -109,6 +109,38 @@ pub fn add_fees_to_request_and_serialize(
     res
 }
 
+/**
+ * Digest of the request the fee signatures cover
+ *
+ * Hex encoded sha256 of the request serialized with [`serialize_signature`].
+ *
+ * [`serialize_signature`]: ../../xfer_payload/fn.serialize_signature.html
+ */
+pub fn txn_digest(request_json_map: &SerdeMap) -> Result<String, ErrorCode> {
+    let txn_serialized = serialize_signature(request_json_map.clone().into())?;
+    let mut hasher = Sha256::default();
+    hasher.input(txn_serialized.as_bytes());
+    Ok(hasher.result().to_hex())
+}
+
+/**
+ * Adds the signed fees to a copy of the request
+ */
+pub fn add_signed_fees(request_json_map: &SerdeMap, fees: &XferPayload) -> SerdeMap {
+    let mut map = request_json_map.clone();
+    map.insert(FEES.to_string(), json!([fees.inputs, fees.outputs, fees.signatures]));
+    map
+}
+
+pub fn serialize_request_with_fees(request_json_map_with_fees: SerdeMap) -> Result<String, ErrorCode> {
+    trace!("fee_map: {:?}", secret!(&request_json_map_with_fees));
+    let serialized_request_with_fees = serde_json::to_string(&json!(request_json_map_with_fees))
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    trace!("Serialized request_with_fees");
+    
+    return Ok(serialized_request_with_fees);
+} 
+
 /**
 Creates a callback for when the signing is complete and fees are added.
 */
@@ -130,17 +162,12 @@ pub fn closure_cb_response(command_handle: i32, cb: JsonCallbackUnwrapped) -> im
 */
 
 fn add_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<Extra>, request_json_map: SerdeMap, cb: Box<Fn(Result<SerdeMap, ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
-    let txn_serialized = serialize_signature(request_json_map.clone().into())?;
-    let mut hasher = Sha256::default();
-    hasher.input(txn_serialized.as_bytes());
-    let txn_digest = Some(hasher.result().to_hex());
+    let txn_digest = Some(txn_digest(&request_json_map)?);
     signed_fees(wallet_handle, inputs, outputs, extra, &txn_digest, Box::new(move |fees| {
         trace!("Added fees to request_json.");
         match fees {
             Ok((fees, _)) => {
-                let mut map = request_json_map.clone();
-                map.insert(FEES.to_string(), json!([fees.inputs, fees.outputs, fees.signatures]));
-                cb(Ok(map.clone()));
+                cb(Ok(add_signed_fees(&request_json_map, &fees)));
             }
             Err(err) => {
                 cb(Err(err))
@@ -151,15 +178,6 @@ fn add_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<
     Ok(())
 }
 
-fn serialize_request_with_fees(request_json_map_with_fees: SerdeMap) -> Result<String, ErrorCode> {
-    trace!("fee_map: {:?}", secret!(&request_json_map_with_fees));
-    let serialized_request_with_fees = serde_json::to_string(&json!(request_json_map_with_fees))
-        .or(Err(ErrorCode::CommonInvalidStructure))?;
-    trace!("Serialized request_with_fees");
-    
-    return Ok(serialized_request_with_fees);
-} 
-
 fn signed_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<Extra>, txn_digest: &Option<String>, cb: Box<Fn(Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
     let fees = XferPayload::new(inputs, outputs, extra);
     fees.sign_fees(&CryptoSdk{}, wallet_handle, txn_digest, cb)?;
@@ -5,14 +5,17 @@ Logic for the [`create_payment_address_handler`].
 */
 
 use std::os::raw::c_char;
+use std::sync::Arc;
 
-use ErrorCode;
-use logic::config::payment_address_config::PaymentAddressConfig;
+use {ErrorCode, IndyHandle};
+use logic::address_book::{self, AddressRecord};
+use logic::config::payment_address_config::CreatePaymentAddressConfig;
+use logic::hd_derivation;
 use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
 use utils::ffi_support::{string_from_char_ptr, cstring_from_str, c_pointer_from_str};
 use utils::json_conversion::JsonDeserialize;
 
-type DeserializedArguments = (PaymentAddressConfig, JsonCallbackUnwrapped);
+type DeserializedArguments = (CreatePaymentAddressConfig, JsonCallbackUnwrapped);
 
 /**
 Deserialize the [`create_payment_address_handler`] arguments.
@@ -31,12 +34,17 @@ pub fn deserialize_arguments(
 
     debug!("api::create_payment_address_handler json_config_string >> {:?}", secret!(&json_config_string));
 
-    // TODO: Only continue when seed is missing, not on any error.
-    let config = PaymentAddressConfig::from_json(&json_config_string)
-        .map_err(map_err_trace!())
-        .unwrap_or(PaymentAddressConfig { seed: "".to_string() });
+    let config = if json_config_string.trim().is_empty() {
+        CreatePaymentAddressConfig::default()
+    } else {
+        CreatePaymentAddressConfig::from_json(&json_config_string)
+            .map_err(map_err_err!())
+            .map_err(|_| ErrorCode::CommonInvalidStructure)?
+    };
 
-    debug!("api::create_payment_address_handler PaymentAddressConfig >> {:?}", secret!(&config));
+    let config = hd_derivation::resolve_config(config)?;
+
+    debug!("api::create_payment_address_handler CreatePaymentAddressConfig >> {:?}", secret!(&config));
 
     Ok((config, cb))
 }
@@ -60,6 +68,42 @@ pub fn create_address_cb(command_handle: i32, cb: JsonCallbackUnwrapped) -> impl
     }
 }
 
+/**
+Wraps `cb` so a created address is recorded in the [`address_book`] first.
+
+Failing to record the address doesn't fail its creation, the key is already in
+the wallet, so `cb` still gets the address.
+
+[`address_book`]: ../../address_book/index.html
+*/
+pub fn record_address_cb<F: 'static>(wallet_handle: IndyHandle, config: &CreatePaymentAddressConfig, cb: F) -> impl FnMut(String, ErrorCode) + Send
+    where F: Fn(String, ErrorCode) + Send + Sync
+{
+    let config = config.clone();
+    let cb = Arc::new(cb);
+
+    move | payment_address: String, error_code: ErrorCode | {
+        if error_code != ErrorCode::Success {
+            return cb(payment_address, error_code);
+        }
+
+        let record = AddressRecord::from_config(payment_address.clone(), &config);
+        let record_cb = cb.clone();
+        let address = payment_address.clone();
+        let ec = address_book::add_address_async(wallet_handle, &record, move |res| {
+            if let Err(err) = res {
+                error!("failed to record payment address in the address book ErrorCode={:?}", err);
+            }
+            record_cb(address.clone(), ErrorCode::Success);
+        });
+
+        if ec != ErrorCode::Success {
+            error!("failed to record payment address in the address book ErrorCode={:?}", ec);
+            cb(payment_address, ErrorCode::Success);
+        }
+    }
+}
+
 #[cfg(test)]
 mod deserialize_arguments_test {
     use super::*;
@@ -112,6 +156,27 @@ mod deserialize_arguments_test {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_empty_config()
+    {
+        let config_pointer = c_pointer_from_str("");
+        let (config, _) = call_deserialize_arguments(Some(config_pointer), None).unwrap();
+        assert_eq!(config.key.seed, "");
+    }
+
+    #[test]
+    fn test_config_not_parsing()
+    {
+        let config_pointer = json_c_pointer!({
+            "seed": 5
+        });
+        let result = call_deserialize_arguments(Some(config_pointer), None);
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+
+        let result = call_deserialize_arguments(Some(c_pointer_from_str("{seed")), None);
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+    }
+
     #[test]
     fn test_config_with_seed()
     {
@@ -123,6 +188,28 @@ mod deserialize_arguments_test {
     }
 
 
+    #[test]
+    fn test_config_with_master_seed()
+    {
+        let config_pointer = json_c_pointer!({
+            "masterSeed": "000102030405060708090a0b0c0d0e0f",
+            "derivationPath": "m/0'/1'"
+        });
+        let (config, _) = call_deserialize_arguments(Some(config_pointer), None).unwrap();
+        assert_eq!(config.key.seed, "sdC61AS/Ndp4WmTKGsVLJhchHSd3aW+/+vII90auhPI=");
+        assert_eq!(config.master_seed, None);
+    }
+
+    #[test]
+    fn test_config_with_master_seed_without_path()
+    {
+        let config_pointer = json_c_pointer!({
+            "masterSeed": "000102030405060708090a0b0c0d0e0f"
+        });
+        let result = call_deserialize_arguments(Some(config_pointer), None);
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+    }
+
     #[test]
     fn test_valid_arguments()
     {
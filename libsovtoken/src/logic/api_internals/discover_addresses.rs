@@ -0,0 +1,250 @@
+/*!
+Logic for the [`sovtoken_discover_payment_addresses`].
+
+[`sovtoken_discover_payment_addresses`]: sovtoken::api::sovtoken_discover_payment_addresses
+*/
+
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
+
+use {ErrorCode, IndyHandle};
+use logic::address_book::{self, AddressRecord};
+use logic::config::payment_address_config::{CreatePaymentAddressConfig, PaymentAddressConfig};
+use logic::hd_derivation::{self, DiscoverCallback, DiscoveredAddress, ExtendedKey, DEFAULT_GAP_LIMIT};
+use logic::indy_sdk_api::crypto_api::{CryptoAPI, CryptoSdk};
+use logic::indy_sdk_api::ledger::Ledger;
+use logic::utxo_pager::SubmitAsync;
+use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
+use utils::ffi_support::{string_from_char_ptr, c_pointer_from_str, c_pointer_from_string};
+use utils::json_conversion::{JsonDeserialize, JsonSerialize};
+
+type DeserializedArguments = (DiscoverAddressesConfig, JsonCallbackUnwrapped);
+
+/**
+    The config json of [`sovtoken_discover_payment_addresses`]
+
+    The master seed is given as a hex `masterSeed`, or as a `mnemonic` with its
+    optional `passphrase`, like for [`create_payment_address_handler`].
+
+    [`sovtoken_discover_payment_addresses`]: ../../../api/fn.sovtoken_discover_payment_addresses.html
+    [`create_payment_address_handler`]: ../../../api/fn.create_payment_address_handler.html
+*/
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoverAddressesConfig {
+    #[serde(default)]
+    pub master_seed: Option<String>,
+    #[serde(default)]
+    pub mnemonic: Option<String>,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    pub account_path: String,
+    #[serde(default = "default_gap_limit")]
+    pub gap_limit: u32,
+}
+
+fn default_gap_limit() -> u32 {
+    DEFAULT_GAP_LIMIT
+}
+
+/**
+Deserialize the [`sovtoken_discover_payment_addresses`] arguments.
+
+[`sovtoken_discover_payment_addresses`]: sovtoken::api::sovtoken_discover_payment_addresses
+*/
+pub fn deserialize_arguments(
+    config_json: *const c_char,
+    cb: JsonCallback,
+) -> Result<DeserializedArguments, ErrorCode> {
+    let cb = cb.ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let config_json = string_from_char_ptr(config_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?;
+
+    let config = DiscoverAddressesConfig::from_json(&config_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    Ok((config, cb))
+}
+
+/**
+Finds the used addresses of the master seed of `config` on the ledger of
+`pool_handle`, and records them in the wallet.
+
+The key of every address is created in the wallet and the address is added to
+the [`address_book`] with its derivation path. An address which already is in
+the wallet is kept as it is, so discovery can be run again.
+
+Every libindy call is made from the callback of the previous one. `cb` is
+called once, unless an error is returned.
+
+[`address_book`]: ../../address_book/index.html
+*/
+pub fn discover_and_record(wallet_handle: IndyHandle, pool_handle: IndyHandle, config: DiscoverAddressesConfig, cb: DiscoverCallback) -> Result<(), ErrorCode> {
+    let master_seed = hd_derivation::master_seed_of(config.master_seed, config.mnemonic, config.passphrase)?
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?;
+    let master = ExtendedKey::master(&master_seed)?;
+
+    let submit: Arc<SubmitAsync> = Arc::new(move |request: String, cb: ResultCallback<String>| {
+        call_with_sync_error(cb, |mut cb| Ledger::submit_request_async(pool_handle, &request, move |err, reply| {
+            cb(to_result(err).map(|_| reply))
+        }));
+    });
+
+    let mut pending = Some(cb);
+    hd_derivation::discover_addresses(&master_seed, &config.account_path, config.gap_limit, submit, Box::new(move |discovered| {
+        let mut cb = match pending.take() {
+            Some(cb) => cb,
+            None => return
+        };
+
+        match discovered {
+            Ok(discovered) => record_addresses(wallet_handle, master.clone(), discovered, 0, cb),
+            Err(err) => cb(Err(err))
+        }
+    }))
+}
+
+/**
+Create a callback giving the json of the discovered addresses.
+*/
+pub fn discover_addresses_cb(command_handle: i32, cb: JsonCallbackUnwrapped) -> DiscoverCallback {
+    Box::new(move |res: Result<Vec<DiscoveredAddress>, ErrorCode>| {
+        let res = res.and_then(|discovered| discovered.to_json().map_err(map_err_err!()).map_err(|_| ErrorCode::CommonInvalidState));
+        trace!("api::sovtoken_discover_payment_addresses cb >> result: {:?}", secret!(&res));
+
+        match res {
+            Ok(json) => cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(json)),
+            Err(ec) => cb(command_handle, ec as i32, c_pointer_from_str(""))
+        };
+    })
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+type ResultCallback<T> = Box<FnMut(Result<T, ErrorCode>) + Send>;
+
+/*
+    Records the addresses from `position` on, one after the other.
+*/
+fn record_addresses(wallet_handle: IndyHandle, master: ExtendedKey, discovered: Vec<DiscoveredAddress>, position: usize, mut cb: DiscoverCallback) {
+    if position == discovered.len() {
+        return cb(Ok(discovered));
+    }
+
+    let config = match address_config(&master, &discovered[position]) {
+        Ok(config) => config,
+        Err(err) => return cb(Err(err))
+    };
+    let record = AddressRecord::from_config(discovered[position].address.clone(), &config);
+
+    let mut pending = Some((master, discovered, cb));
+    record_address(wallet_handle, config.key, record, Box::new(move |res| {
+        let (master, discovered, mut cb) = match pending.take() {
+            Some(pending) => pending,
+            None => return
+        };
+
+        match res {
+            Ok(()) => record_addresses(wallet_handle, master, discovered, position + 1, cb),
+            Err(err) => cb(Err(err))
+        }
+    }));
+}
+
+fn address_config(master: &ExtendedKey, discovered: &DiscoveredAddress) -> Result<CreatePaymentAddressConfig, ErrorCode> {
+    Ok(CreatePaymentAddressConfig {
+        key: PaymentAddressConfig { seed: master.derive(&discovered.derivation_path.parse()?)?.to_seed() },
+        derivation_path: Some(discovered.derivation_path.clone()),
+        ..Default::default()
+    })
+}
+
+fn record_address(wallet_handle: IndyHandle, key: PaymentAddressConfig, record: AddressRecord, cb: ResultCallback<()>) {
+    let mut pending = Some((record, cb));
+    call_with_sync_error(Box::new(move |res: Result<(), ErrorCode>| {
+        let (record, mut cb) = match pending.take() {
+            Some(pending) => pending,
+            None => return
+        };
+
+        if let Err(err) = ignore_existing(res) {
+            return cb(Err(err));
+        }
+
+        call_with_sync_error(Box::new(move |res| cb(ignore_existing(res))), |mut cb| {
+            address_book::add_address_async(wallet_handle, &record, move |res| cb(res))
+        });
+    }), |mut cb| CryptoSdk {}.indy_create_key_async(wallet_handle, key, move |err, _| cb(to_result(err))));
+}
+
+/*
+    Calls `call` with a callback forwarding to `cb`, and calls `cb` with the
+    error code `call` returns when it isn't Success: libindy drops the closure
+    of a call failing before it is queued. `cb` is called once.
+*/
+fn call_with_sync_error<T: 'static, F>(cb: ResultCallback<T>, call: F)
+    where F: FnOnce(ResultCallback<T>) -> ErrorCode
+{
+    let cb = Arc::new(Mutex::new(Some(cb)));
+    let async_cb = cb.clone();
+    let ec = call(Box::new(move |res| {
+        if let Some(mut cb) = async_cb.lock().unwrap().take() {
+            cb(res);
+        }
+    }));
+
+    if ec != ErrorCode::Success {
+        if let Some(mut cb) = cb.lock().unwrap().take() {
+            cb(Err(ec));
+        }
+    }
+}
+
+fn to_result(err: ErrorCode) -> Result<(), ErrorCode> {
+    if err == ErrorCode::Success { Ok(()) } else { Err(err) }
+}
+
+fn ignore_existing(res: Result<(), ErrorCode>) -> Result<(), ErrorCode> {
+    match res {
+        Err(ErrorCode::WalletItemAlreadyExists) => Ok(()),
+        res => res
+    }
+}
+
+#[cfg(test)]
+mod discover_addresses_tests {
+    use super::*;
+    use utils::test::default;
+
+    #[test]
+    fn deserialize_config_with_defaults() {
+        let config = DiscoverAddressesConfig::from_json(r#"{"masterSeed": "000102030405060708090a0b0c0d0e0f", "accountPath": "m/44'"}"#).unwrap();
+
+        assert_eq!(config.master_seed, Some("000102030405060708090a0b0c0d0e0f".to_string()));
+        assert_eq!(config.account_path, "m/44'");
+        assert_eq!(config.gap_limit, DEFAULT_GAP_LIMIT);
+    }
+
+    #[test]
+    fn deserialize_config_without_account_path() {
+        let config_json = json_c_pointer!({"masterSeed": "000102030405060708090a0b0c0d0e0f"});
+        let err = deserialize_arguments(config_json, Some(default::empty_callback_string)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn discover_without_master_seed() {
+        let config = DiscoverAddressesConfig::from_json(r#"{"accountPath": "m/44'"}"#).unwrap();
+        let err = discover_and_record(1, 1, config, Box::new(|_| panic!("discovery started without a master seed"))).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+}
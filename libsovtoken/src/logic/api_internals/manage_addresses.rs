@@ -0,0 +1,143 @@
+/*!
+Logic for the address book handlers, [`sovtoken_search_payment_addresses`],
+[`sovtoken_get_payment_address`] and [`sovtoken_update_payment_address`].
+
+[`sovtoken_search_payment_addresses`]: sovtoken::api::sovtoken_search_payment_addresses
+[`sovtoken_get_payment_address`]: sovtoken::api::sovtoken_get_payment_address
+[`sovtoken_update_payment_address`]: sovtoken::api::sovtoken_update_payment_address
+*/
+
+use serde::Serialize;
+use serde_json;
+use std::os::raw::c_char;
+
+use ErrorCode;
+use logic::address_book::AddressRecordUpdate;
+use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
+use utils::ffi_support::{string_from_char_ptr, c_pointer_from_string};
+use utils::json_conversion::JsonDeserialize;
+
+type DeserializedSearchArguments = (String, JsonCallbackUnwrapped);
+type DeserializedGetArguments = (String, JsonCallbackUnwrapped);
+type DeserializedUpdateArguments = (String, AddressRecordUpdate, JsonCallbackUnwrapped);
+
+/**
+Deserialize the [`sovtoken_search_payment_addresses`] arguments.
+
+[`sovtoken_search_payment_addresses`]: sovtoken::api::sovtoken_search_payment_addresses
+*/
+pub fn deserialize_search_arguments(
+    query_json: *const c_char,
+    cb: JsonCallback,
+) -> Result<DeserializedSearchArguments, ErrorCode> {
+    let cb = cb.ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let query_json = string_from_char_ptr(query_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?;
+
+    debug!("api::sovtoken_search_payment_addresses query_json >> {:?}", query_json);
+
+    Ok((query_json, cb))
+}
+
+/**
+Deserialize the [`sovtoken_get_payment_address`] arguments.
+
+[`sovtoken_get_payment_address`]: sovtoken::api::sovtoken_get_payment_address
+*/
+pub fn deserialize_get_arguments(
+    payment_address: *const c_char,
+    cb: JsonCallback,
+) -> Result<DeserializedGetArguments, ErrorCode> {
+    let cb = cb.ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let payment_address = string_from_char_ptr(payment_address)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?;
+
+    debug!("api::sovtoken_get_payment_address payment_address >> {:?}", secret!(&payment_address));
+
+    Ok((payment_address, cb))
+}
+
+/**
+Deserialize the [`sovtoken_update_payment_address`] arguments.
+
+[`sovtoken_update_payment_address`]: sovtoken::api::sovtoken_update_payment_address
+*/
+pub fn deserialize_update_arguments(
+    payment_address: *const c_char,
+    update_json: *const c_char,
+    cb: JsonCallback,
+) -> Result<DeserializedUpdateArguments, ErrorCode> {
+    let cb = cb.ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let payment_address = string_from_char_ptr(payment_address)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?;
+
+    let update_json = string_from_char_ptr(update_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?;
+
+    let update = AddressRecordUpdate::from_json(&update_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    debug!("api::sovtoken_update_payment_address payment_address >> {:?}, update >> {:?}", secret!(&payment_address), update);
+
+    Ok((payment_address, update, cb))
+}
+
+/**
+Create a callback giving the json of an address book result.
+*/
+pub fn address_book_cb<T: Serialize>(command_handle: i32, cb: JsonCallbackUnwrapped) -> impl FnMut(Result<T, ErrorCode>) + Send {
+    move |res: Result<T, ErrorCode>| {
+        let res = res.and_then(|records| serde_json::to_string(&records).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+        match res {
+            Ok(json) => cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(json)),
+            Err(ec) => cb(command_handle, ec as i32, c_pointer_from_string("".to_string())),
+        };
+    }
+}
+
+
+#[cfg(test)]
+mod deserialize_arguments_test {
+    use super::*;
+    use std::ptr;
+    use utils::test::default;
+
+    fn address() -> *const c_char {
+        c_pointer_from_string("pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7".to_string())
+    }
+
+    #[test]
+    fn test_search_with_null_query() {
+        let result = deserialize_search_arguments(ptr::null(), Some(default::empty_callback_string));
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+    }
+
+    #[test]
+    fn test_get_with_empty_callback() {
+        let result = deserialize_get_arguments(address(), None);
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+    }
+
+    #[test]
+    fn test_update_with_invalid_json() {
+        let update_json = json_c_pointer!({"label": 5});
+        let result = deserialize_update_arguments(address(), update_json, Some(default::empty_callback_string));
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+    }
+
+    #[test]
+    fn test_update_valid() {
+        let update_json = json_c_pointer!({"label": "savings"});
+        let (_, update, _) = deserialize_update_arguments(address(), update_json, Some(default::empty_callback_string)).unwrap();
+        assert_eq!(update.label, Some("savings".to_string()));
+        assert_eq!(update.tags, None);
+    }
+}
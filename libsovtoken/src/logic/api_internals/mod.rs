@@ -5,4 +5,6 @@ Contains logic used in the [`api`].
 */
 
 pub mod add_request_fees;
-pub mod create_address;
\ No newline at end of file
+pub mod create_address;
+pub mod discover_addresses;
+pub mod manage_addresses;
@@ -10,7 +10,7 @@ use logic::xfer_payload::{XferPayload, Extra};
 use utils::base58::{IntoBase58, FromBase58};
 use utils::txn_author_agreement::TaaAcceptance;
 use ErrorCode;
-use utils::ffi_support::{string_from_char_ptr, c_pointer_from_str};
+use utils::ffi_support::{string_from_char_ptr, c_pointer_from_str, c_pointer_from_string};
 use logic::did::Did;
 
 
@@ -77,10 +77,15 @@ pub fn handle_signing(
     cb(command_handle, error_code as i32, pointer);
 }
 
-fn build_payment_request_pointer(
+/**
+ * Builds the XFER_PUBLIC request json from a signed payload
+ *
+ * When no `identifier` is given the did is derived from the first input.
+ */
+pub fn build_payment_request_json(
     identifier: Option<Did>,
     result: Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>,
-) -> Result<*const c_char, ErrorCode> {
+) -> Result<String, ErrorCode> {
     let (signed_payload, taa_acceptance) = result?;
     debug!("Signed payload >>> {:?}", secret!(&signed_payload));
 
@@ -106,13 +111,27 @@ fn build_payment_request_pointer(
     debug!("payment_request >>> {:?}", payment_request);
 
     return payment_request
-        .serialize_to_pointer()
+        .serialize_to_string()
         .map_err(|e| {
             map_err_err!()(e);
             return ErrorCode::CommonInvalidState;
         });
 }
 
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn build_payment_request_pointer(
+    identifier: Option<Did>,
+    result: Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>,
+) -> Result<*const c_char, ErrorCode> {
+    build_payment_request_json(identifier, result)
+        .map(c_pointer_from_string)
+}
+
 #[cfg(test)]
 mod test_deserialize_inputs {
 
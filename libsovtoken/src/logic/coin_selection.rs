@@ -0,0 +1,388 @@
+/*!
+    Coin selection
+
+    Picks which unspent outputs ([`UTXOInner`] as returned by a GET_UTXO reply)
+    are spent to cover a target amount plus a fee, and builds the change
+    [`Output`] which sends the surplus back to a chosen address.
+
+    [`UTXOInner`]: ../parsers/parse_get_utxo_response/struct.UTXOInner.html
+    [`Output`]: ../output/struct.Output.html
+*/
+
+use std::collections::HashSet;
+
+use ErrorCode;
+use logic::address;
+use logic::input::{Input, Inputs};
+use logic::output::{Output, Outputs};
+use logic::parsers::parse_get_utxo_response::UTXOInner;
+use logic::type_aliases::TokenAmount;
+
+/**
+    Upper bound of the branches visited by [`SelectionStrategy::BranchAndBound`]
+    before it gives up on finding an exact match.
+*/
+pub const BRANCH_AND_BOUND_MAX_TRIES: usize = 100_000;
+
+/**
+    How the inputs are picked from the available utxos.
+
+    * `LargestFirst` spends the biggest utxos first, using as few inputs as possible.
+    * `SmallestFirst` spends the smallest utxos first, consolidating dust.
+    * `BranchAndBound` searches for a set of utxos matching the target plus the
+      fee exactly so no change output is needed. Falls back to `LargestFirst`
+      when there is no exact match.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    LargestFirst,
+    SmallestFirst,
+    BranchAndBound,
+}
+
+/**
+    Result of a coin selection.
+
+    `inputs` are fully qualified and can be handed to the payment and fees
+    handlers as they are. `change` is `None` when the selected inputs match the
+    target plus the fee exactly.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinSelection {
+    pub inputs: Inputs,
+    pub selected_amount: TokenAmount,
+    pub change: Option<Output>,
+}
+
+/**
+    Selects utxos covering `target + fee` and computes the change sent to
+    `change_address` (a fully qualified payment address).
+
+    Zero amount and duplicated utxos are ignored.
+
+    # Errors
+    * `PaymentInsufficientFundsError` when the utxos do not cover `target + fee`
+    * `CommonInvalidStructure` when the change address or a utxo address is invalid,
+      or when the amounts overflow
+*/
+pub fn select_coins(
+    utxos: &[UTXOInner],
+    target: TokenAmount,
+    fee: TokenAmount,
+    change_address: &str,
+    strategy: SelectionStrategy
+) -> Result<CoinSelection, ErrorCode> {
+    trace!("logic::coin_selection::select_coins >> utxos: {:?}, target: {:?}, fee: {:?}, strategy: {:?}", secret!(utxos), target, fee, strategy);
+
+    address::validate_address(change_address)?;
+
+    let required = target.try_add(fee)?;
+
+    let candidates = spendable_utxos(utxos);
+
+    let available = TokenAmount::try_sum(candidates.iter().map(|utxo| utxo.amount))?;
+
+    if available < required {
+        error!("Insufficient funds, available: {}, required: {}", available, required);
+        return Err(ErrorCode::PaymentInsufficientFundsError);
+    }
+
+    let selected = match strategy {
+        SelectionStrategy::LargestFirst => accumulate(sorted_by_amount(candidates, true), required),
+        SelectionStrategy::SmallestFirst => accumulate(sorted_by_amount(candidates, false), required),
+        SelectionStrategy::BranchAndBound => {
+            let sorted = sorted_by_amount(candidates, true);
+            match branch_and_bound(&sorted, required) {
+                Some(exact) => exact,
+                None => {
+                    debug!("No exact match found, falling back to largest first");
+                    accumulate(sorted, required)
+                }
+            }
+        }
+    };
+
+    let res = build_selection(selected, required, change_address);
+
+    trace!("logic::coin_selection::select_coins << result: {:?}", secret!(&res));
+    res
+}
+
+/**
+    Same as [`select_coins`] but the target is the sum of `outputs`.
+
+    Returns the selected inputs and `outputs` with the change output appended
+    when there is one.
+
+    [`select_coins`]: fn.select_coins.html
+*/
+pub fn select_coins_for_outputs(
+    utxos: &[UTXOInner],
+    outputs: Outputs,
+    fee: TokenAmount,
+    change_address: &str,
+    strategy: SelectionStrategy
+) -> Result<(Inputs, Outputs), ErrorCode> {
+    let target = TokenAmount::try_sum(outputs.iter().map(|output| output.amount))?;
+
+    let selection = select_coins(utxos, target, fee, change_address, strategy)?;
+
+    let mut outputs = outputs;
+    if let Some(change) = selection.change {
+        outputs.push(change);
+    }
+
+    Ok((selection.inputs, outputs))
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn spendable_utxos(utxos: &[UTXOInner]) -> Vec<&UTXOInner> {
+    let mut seen = HashSet::new();
+    utxos.iter()
+        .filter(|utxo| !utxo.amount.is_zero())
+        .filter(|utxo| seen.insert((utxo.address.clone(), utxo.seq_no)))
+        .collect()
+}
+
+/*
+    Ties are broken by seq_no so the selection is deterministic.
+*/
+fn sorted_by_amount(mut utxos: Vec<&UTXOInner>, descending: bool) -> Vec<&UTXOInner> {
+    utxos.sort_by(|a, b| {
+        let by_amount = if descending { b.amount.cmp(&a.amount) } else { a.amount.cmp(&b.amount) };
+        by_amount.then(a.seq_no.cmp(&b.seq_no))
+    });
+    utxos
+}
+
+/*
+    The caller has checked that the utxos cover `required`, so the sum can't overflow
+    before reaching it.
+*/
+fn accumulate(sorted: Vec<&UTXOInner>, required: TokenAmount) -> Vec<&UTXOInner> {
+    let mut selected = vec![];
+    let mut total = TokenAmount::ZERO;
+
+    for utxo in sorted {
+        if total >= required && !selected.is_empty() {
+            break;
+        }
+        total = total.saturating_add(utxo.amount);
+        selected.push(utxo);
+    }
+
+    selected
+}
+
+fn branch_and_bound<'a>(sorted_desc: &[&'a UTXOInner], required: TokenAmount) -> Option<Vec<&'a UTXOInner>> {
+    let len = sorted_desc.len();
+
+    // remaining[i] is the sum of the utxos from i to the end.
+    let mut remaining: Vec<TokenAmount> = vec![TokenAmount::ZERO; len + 1];
+    for i in (0..len).rev() {
+        remaining[i] = remaining[i + 1].saturating_add(sorted_desc[i].amount);
+    }
+
+    let mut selected = vec![];
+    let mut tries = 0;
+
+    if search_exact_match(sorted_desc, &remaining, 0, TokenAmount::ZERO, required, &mut selected, &mut tries) {
+        Some(selected.into_iter().map(|i| sorted_desc[i]).collect())
+    } else {
+        None
+    }
+}
+
+fn search_exact_match(
+    utxos: &[&UTXOInner],
+    remaining: &[TokenAmount],
+    index: usize,
+    current: TokenAmount,
+    required: TokenAmount,
+    selected: &mut Vec<usize>,
+    tries: &mut usize
+) -> bool {
+    if current == required && !selected.is_empty() {
+        return true;
+    }
+
+    if index >= utxos.len() || *tries >= BRANCH_AND_BOUND_MAX_TRIES {
+        return false;
+    }
+    *tries += 1;
+
+    if current.saturating_add(remaining[index]) < required {
+        return false;
+    }
+
+    if let Some(with_current) = current.checked_add(utxos[index].amount) {
+        if with_current <= required {
+            selected.push(index);
+            if search_exact_match(utxos, remaining, index + 1, with_current, required, selected, tries) {
+                return true;
+            }
+            selected.pop();
+        }
+    }
+
+    search_exact_match(utxos, remaining, index + 1, current, required, selected, tries)
+}
+
+fn build_selection(selected: Vec<&UTXOInner>, required: TokenAmount, change_address: &str) -> Result<CoinSelection, ErrorCode> {
+    let mut inputs: Inputs = Vec::with_capacity(selected.len());
+    let mut selected_amount = TokenAmount::ZERO;
+
+    for utxo in selected {
+        let address = address::address_from_unqualified_address(&utxo.address)?;
+        inputs.push(Input::new(address, utxo.seq_no));
+        selected_amount = selected_amount.try_add(utxo.amount)?;
+    }
+
+    let change_amount = selected_amount.checked_sub(required).ok_or(ErrorCode::CommonInvalidState)?;
+    let change = if !change_amount.is_zero() {
+        Some(Output::new(change_address.to_string(), change_amount))
+    } else {
+        None
+    };
+
+    Ok(CoinSelection { inputs, selected_amount, change })
+}
+
+
+#[cfg(test)]
+mod coin_selection_tests {
+    use super::*;
+    use logic::address::address_tests::gen_random_base58_address;
+
+    fn utxo(address: &str, seq_no: u64, amount: u64) -> UTXOInner {
+        UTXOInner { address: address.to_string(), seq_no, amount: TokenAmount::new(amount) }
+    }
+
+    fn change_address() -> String {
+        address::add_qualifer_to_address(&gen_random_base58_address())
+    }
+
+    fn selected_seq_nos(selection: &CoinSelection) -> Vec<u64> {
+        selection.inputs.iter().map(|input| input.seq_no).collect()
+    }
+
+    fn utxos(amounts: &[u64]) -> Vec<UTXOInner> {
+        let address = gen_random_base58_address();
+        amounts.iter()
+            .enumerate()
+            .map(|(i, amount)| utxo(&address, i as u64 + 1, *amount))
+            .collect()
+    }
+
+    #[test]
+    fn select_largest_first() {
+        let utxos = utxos(&[5, 30, 10, 20]);
+        let change_address = change_address();
+
+        let selection = select_coins(&utxos, TokenAmount::new(35), TokenAmount::new(5), &change_address, SelectionStrategy::LargestFirst).unwrap();
+
+        assert_eq!(selected_seq_nos(&selection), vec![2, 4]);
+        assert_eq!(selection.selected_amount, 50);
+        assert_eq!(selection.change, Some(Output::new(change_address, 10)));
+    }
+
+    #[test]
+    fn select_smallest_first() {
+        let utxos = utxos(&[5, 30, 10, 20]);
+        let change_address = change_address();
+
+        let selection = select_coins(&utxos, TokenAmount::new(12), TokenAmount::new(2), &change_address, SelectionStrategy::SmallestFirst).unwrap();
+
+        assert_eq!(selected_seq_nos(&selection), vec![1, 3]);
+        assert_eq!(selection.change, Some(Output::new(change_address, 1)));
+    }
+
+    #[test]
+    fn select_branch_and_bound_exact_match() {
+        let utxos = utxos(&[7, 30, 11, 20, 4]);
+        let change_address = change_address();
+
+        let selection = select_coins(&utxos, TokenAmount::new(22), TokenAmount::new(2), &change_address, SelectionStrategy::BranchAndBound).unwrap();
+
+        assert_eq!(selection.selected_amount, 24);
+        assert_eq!(selection.change, None);
+        assert_eq!(selected_seq_nos(&selection), vec![4, 5]);
+    }
+
+    #[test]
+    fn select_branch_and_bound_falls_back_to_largest_first() {
+        let utxos = utxos(&[10, 10, 10]);
+        let change_address = change_address();
+
+        let selection = select_coins(&utxos, TokenAmount::new(14), TokenAmount::new(1), &change_address, SelectionStrategy::BranchAndBound).unwrap();
+
+        assert_eq!(selected_seq_nos(&selection), vec![1, 2]);
+        assert_eq!(selection.change, Some(Output::new(change_address, 5)));
+    }
+
+    #[test]
+    fn select_insufficient_funds() {
+        let utxos = utxos(&[10, 10]);
+        let change_address = change_address();
+
+        let err = select_coins(&utxos, TokenAmount::new(20), TokenAmount::new(1), &change_address, SelectionStrategy::LargestFirst).unwrap_err();
+        assert_eq!(err, ErrorCode::PaymentInsufficientFundsError);
+    }
+
+    #[test]
+    fn select_ignores_duplicates_and_zero_amounts() {
+        let mut utxos = utxos(&[10, 0]);
+        let duplicate = utxo(&utxos[0].address, 1, 10);
+        utxos.push(duplicate);
+        let change_address = change_address();
+
+        let err = select_coins(&utxos, TokenAmount::new(15), TokenAmount::ZERO, &change_address, SelectionStrategy::SmallestFirst).unwrap_err();
+        assert_eq!(err, ErrorCode::PaymentInsufficientFundsError);
+    }
+
+    #[test]
+    fn select_inputs_are_qualified() {
+        let utxos = utxos(&[10]);
+        let change_address = change_address();
+
+        let selection = select_coins(&utxos, TokenAmount::new(10), TokenAmount::ZERO, &change_address, SelectionStrategy::LargestFirst).unwrap();
+
+        assert_eq!(selection.inputs, vec![Input::new(address::add_qualifer_to_address(&utxos[0].address), 1)]);
+    }
+
+    #[test]
+    fn select_invalid_change_address() {
+        let utxos = utxos(&[10]);
+
+        let err = select_coins(&utxos, TokenAmount::new(5), TokenAmount::ZERO, "pay:sov:invalid", SelectionStrategy::LargestFirst).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn select_amount_overflow() {
+        let utxos = utxos(&[10]);
+        let change_address = change_address();
+
+        let err = select_coins(&utxos, TokenAmount::MAX, TokenAmount::new(1), &change_address, SelectionStrategy::LargestFirst).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn select_for_outputs_appends_change() {
+        let utxos = utxos(&[10, 25]);
+        let change_address = change_address();
+        let recipient = address::add_qualifer_to_address(&gen_random_base58_address());
+        let outputs = vec![Output::new(recipient.clone(), 20)];
+
+        let (inputs, outputs) = select_coins_for_outputs(&utxos, outputs, TokenAmount::new(2), &change_address, SelectionStrategy::LargestFirst).unwrap();
+
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].seq_no, 2);
+        assert_eq!(outputs, vec![Output::new(recipient, 20), Output::new(change_address, 3)]);
+    }
+}
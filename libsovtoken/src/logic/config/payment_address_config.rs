@@ -1,10 +1,35 @@
 //!  defines structure and implementation for PaymentAddressConfig which is used
 //! for generating payment addresses
 
+use base64;
+use hex::FromHex;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
 use serde_json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::ffi::CString;
+use ErrorCode;
 use utils::ffi_support::cstring_from_str;
 use utils::json_conversion::JsonSerialize;
+use utils::random::rand_bytes;
+
+pub const SEED_LEN: usize = 32;
+
+/**
+    Length of the seed of a BIP-39 mnemonic, it is the master seed of [`hd_derivation`].
+
+    [`hd_derivation`]: ../../hd_derivation/index.html
+*/
+pub const MNEMONIC_SEED_LEN: usize = 64;
+pub const MNEMONIC_PBKDF2_ROUNDS: usize = 2048;
+pub const MNEMONIC_WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+
+const MNEMONIC_BITS_PER_WORD: usize = 11;
+
+lazy_static! {
+    static ref MNEMONIC_WORDS: Vec<&'static str> = include_str!("bip39_english.txt").lines().collect();
+}
 
 /**
      The config structure maps to the config json structure
@@ -13,11 +38,44 @@ use utils::json_conversion::JsonSerialize;
      The seed should be 32 bytes, thats what libsodium requires. Seed can be optional, in that case libsodium generates a random 32 byte seed
 
 */
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct PaymentAddressConfig {
+    #[serde(default)]
     pub seed : String,
 }
 
+/**
+     The config json of [`create_payment_address_handler`], a [`PaymentAddressConfig`]
+     with optional fields which aren't passed to libindy.
+
+     `label` and `tags` go to the address book record of the address.
+
+     `masterSeed` and `derivationPath` replace the seed by a key derived from a
+     master seed (see [`hd_derivation`]). `mnemonic` and its optional
+     `passphrase` can be given instead of `masterSeed`.
+
+     [`create_payment_address_handler`]: ../../../api/fn.create_payment_address_handler.html
+     [`PaymentAddressConfig`]: struct.PaymentAddressConfig.html
+     [`hd_derivation`]: ../../hd_derivation/index.html
+*/
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct CreatePaymentAddressConfig {
+    #[serde(flatten)]
+    pub key: PaymentAddressConfig,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    #[serde(default, rename = "masterSeed")]
+    pub master_seed: Option<String>,
+    #[serde(default, rename = "derivationPath")]
+    pub derivation_path: Option<String>,
+    #[serde(default)]
+    pub mnemonic: Option<String>,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
 impl PaymentAddressConfig {
 
     /**
@@ -27,4 +85,259 @@ impl PaymentAddressConfig {
         let serialized = JsonSerialize::to_json(&self)?;
         return Ok(cstring_from_str(serialized));
     }
+
+    /**
+        Converts the seed to the 32 bytes of an ed25519 seed, the same way libindy does.
+
+        * a 32 bytes string is used as it is
+        * a string ending with `=` is decoded as base64
+        * a 64 chars string is decoded as hex
+
+        Returns `None` when the seed is empty.
+    */
+    pub fn seed_bytes(&self) -> Result<Option<Vec<u8>>, ErrorCode> {
+        if self.seed.is_empty() {
+            return Ok(None);
+        }
+
+        let bytes = if self.seed.as_bytes().len() == SEED_LEN {
+            self.seed.as_bytes().to_vec()
+        } else if self.seed.ends_with('=') {
+            base64::decode(&self.seed)
+                .map_err(map_err_err!())
+                .map_err(|_| ErrorCode::CommonInvalidStructure)?
+        } else if self.seed.as_bytes().len() == SEED_LEN * 2 {
+            Vec::<u8>::from_hex(&self.seed)
+                .map_err(map_err_err!())
+                .map_err(|_| ErrorCode::CommonInvalidStructure)?
+        } else {
+            error!("Trying to use invalid seed");
+            return Err(ErrorCode::CommonInvalidStructure);
+        };
+
+        if bytes.len() != SEED_LEN {
+            error!("Invalid seed length, expected {:?}, real {:?}", SEED_LEN, bytes.len());
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        Ok(Some(bytes))
+    }
+}
+
+/**
+    Generates a BIP-39 mnemonic of `word_count` english words from random entropy.
+
+    `word_count` is one of [`MNEMONIC_WORD_COUNTS`].
+
+    [`MNEMONIC_WORD_COUNTS`]: constant.MNEMONIC_WORD_COUNTS.html
+*/
+pub fn generate_mnemonic(word_count: usize) -> Result<String, ErrorCode> {
+    if !MNEMONIC_WORD_COUNTS.contains(&word_count) {
+        error!("Invalid mnemonic word count {:?}", word_count);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    Ok(mnemonic_from_entropy(&rand_bytes(word_count * 4 / 3)))
+}
+
+/**
+    Encodes `entropy` (16 to 32 bytes, a multiple of 4) as a BIP-39 mnemonic.
+*/
+pub fn mnemonic_from_entropy(entropy: &[u8]) -> String {
+    let checksum_bits = entropy.len() / 4;
+    let mut bits: Vec<bool> = bytes_to_bits(entropy);
+    bits.extend(bytes_to_bits(&sha256(entropy)).into_iter().take(checksum_bits));
+
+    bits.chunks(MNEMONIC_BITS_PER_WORD)
+        .map(|word_bits| MNEMONIC_WORDS[bits_to_index(word_bits)])
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/**
+    Checks the words and the checksum of a BIP-39 mnemonic, returns its entropy.
+
+    Errors with `CommonInvalidStructure` when a word isn't in the english list,
+    the number of words is wrong or the checksum doesn't match.
+*/
+pub fn validate_mnemonic(mnemonic: &str) -> Result<Vec<u8>, ErrorCode> {
+    let words: Vec<&str> = mnemonic.split_whitespace().collect();
+    if !MNEMONIC_WORD_COUNTS.contains(&words.len()) {
+        error!("Invalid mnemonic word count {:?}", words.len());
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let mut bits: Vec<bool> = Vec::with_capacity(words.len() * MNEMONIC_BITS_PER_WORD);
+    for word in &words {
+        let index = MNEMONIC_WORDS.binary_search(word)
+            .map_err(|_| ErrorCode::CommonInvalidStructure)
+            .map_err(map_err_err!())?;
+        for i in (0..MNEMONIC_BITS_PER_WORD).rev() {
+            bits.push((index >> i) & 1 == 1);
+        }
+    }
+
+    let checksum_bits = bits.len() / 33;
+    let entropy_bits = bits.len() - checksum_bits;
+    let entropy: Vec<u8> = bits[..entropy_bits].chunks(8).map(|byte| bits_to_index(byte) as u8).collect();
+
+    let expected_checksum = bytes_to_bits(&sha256(&entropy));
+    if bits[entropy_bits..] != expected_checksum[..checksum_bits] {
+        error!("Invalid mnemonic checksum");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    Ok(entropy)
+}
+
+/**
+    Converts a BIP-39 mnemonic and an optional passphrase to a 64 bytes seed.
+
+    The mnemonic is validated first. The passphrase has to be ascii: other
+    characters would need the NFKD normalization of BIP-39, an unnormalized
+    passphrase would silently give another seed than other wallets.
+*/
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: Option<&str>) -> Result<Vec<u8>, ErrorCode> {
+    validate_mnemonic(mnemonic)?;
+
+    let passphrase = passphrase.unwrap_or("");
+    if !passphrase.is_ascii() {
+        error!("Only ascii mnemonic passphrases are supported");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let mnemonic = mnemonic.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let salt = format!("mnemonic{}", passphrase);
+
+    let mut seed = vec![0u8; MNEMONIC_SEED_LEN];
+    pbkdf2_hmac(mnemonic.as_bytes(), salt.as_bytes(), MNEMONIC_PBKDF2_ROUNDS, MessageDigest::sha512(), &mut seed)
+        .map_err(|_| ErrorCode::CommonInvalidState)?;
+
+    Ok(seed)
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::default();
+    hasher.input(data);
+    hasher.result().to_vec()
+}
+
+fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes.iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (*byte >> i) & 1 == 1))
+        .collect()
+}
+
+fn bits_to_index(bits: &[bool]) -> usize {
+    bits.iter().fold(0, |index, bit| (index << 1) | (*bit as usize))
+}
+
+
+#[cfg(test)]
+mod payment_address_config_tests {
+    use super::*;
+
+    fn seed_bytes(seed: &str) -> Result<Option<Vec<u8>>, ErrorCode> {
+        PaymentAddressConfig { seed: seed.to_string() }.seed_bytes()
+    }
+
+    #[test]
+    fn seed_bytes_empty() {
+        assert_eq!(seed_bytes("").unwrap(), None);
+    }
+
+    #[test]
+    fn seed_bytes_raw() {
+        let seed = "00000000000000000000000000000My1";
+        assert_eq!(seed_bytes(seed).unwrap(), Some(seed.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn seed_bytes_base64() {
+        let seed = base64::encode(&[7u8; SEED_LEN]);
+        assert_eq!(seed_bytes(&seed).unwrap(), Some(vec![7u8; SEED_LEN]));
+    }
+
+    #[test]
+    fn seed_bytes_hex() {
+        let seed = "0101010101010101010101010101010101010101010101010101010101010101";
+        assert_eq!(seed_bytes(seed).unwrap(), Some(vec![1u8; SEED_LEN]));
+    }
+
+    const ZERO_MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn mnemonic_wordlist() {
+        assert_eq!(MNEMONIC_WORDS.len(), 2048);
+        assert_eq!(MNEMONIC_WORDS[0], "abandon");
+        assert_eq!(MNEMONIC_WORDS[2047], "zoo");
+    }
+
+    #[test]
+    fn mnemonic_from_entropy_test_vectors() {
+        assert_eq!(mnemonic_from_entropy(&[0u8; 16]), ZERO_MNEMONIC);
+        assert_eq!(mnemonic_from_entropy(&[0x7f; 16]), "legal winner thank year wave sausage worth useful legal winner thank yellow");
+        assert_eq!(mnemonic_from_entropy(&[0x80; 16]), "letter advice cage absurd amount doctor acoustic avoid letter advice cage above");
+        assert_eq!(mnemonic_from_entropy(&[0xff; 16]), "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong");
+    }
+
+    #[test]
+    fn mnemonic_to_seed_test_vector() {
+        let seed = mnemonic_to_seed(ZERO_MNEMONIC, Some("TREZOR")).unwrap();
+        let expected = Vec::<u8>::from_hex("c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04").unwrap();
+        assert_eq!(seed, expected);
+    }
+
+    #[test]
+    fn mnemonic_to_seed_without_passphrase() {
+        let seed = mnemonic_to_seed(ZERO_MNEMONIC, None).unwrap();
+        let expected = Vec::<u8>::from_hex("5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc19a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4").unwrap();
+        assert_eq!(seed, expected);
+        assert_eq!(mnemonic_to_seed(&format!("  {}\n", ZERO_MNEMONIC.replace(" ", "  ")), None).unwrap(), expected);
+    }
+
+    #[test]
+    fn mnemonic_to_seed_non_ascii_passphrase() {
+        assert_eq!(mnemonic_to_seed(ZERO_MNEMONIC, Some("caf\u{e9}")).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn validate_mnemonic_returns_entropy() {
+        assert_eq!(validate_mnemonic(ZERO_MNEMONIC).unwrap(), vec![0u8; 16]);
+        assert_eq!(validate_mnemonic("zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong").unwrap(), vec![0xff; 16]);
+    }
+
+    #[test]
+    fn validate_mnemonic_invalid() {
+        // bad checksum
+        assert_eq!(validate_mnemonic(&ZERO_MNEMONIC.replace("about", "above")).unwrap_err(), ErrorCode::CommonInvalidStructure);
+        // unknown word
+        assert_eq!(validate_mnemonic(&ZERO_MNEMONIC.replace("about", "aboot")).unwrap_err(), ErrorCode::CommonInvalidStructure);
+        // wrong word count
+        assert_eq!(validate_mnemonic("abandon abandon about").unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn generate_mnemonic_is_valid() {
+        for word_count in MNEMONIC_WORD_COUNTS.iter() {
+            let mnemonic = generate_mnemonic(*word_count).unwrap();
+            assert_eq!(mnemonic.split(' ').count(), *word_count);
+            assert_eq!(validate_mnemonic(&mnemonic).unwrap().len(), word_count * 4 / 3);
+        }
+        assert_eq!(generate_mnemonic(13).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn seed_bytes_invalid() {
+        assert_eq!(seed_bytes("short").unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(seed_bytes(&base64::encode(&[7u8; 16])).unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(seed_bytes(&"zz".repeat(SEED_LEN)).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
 }
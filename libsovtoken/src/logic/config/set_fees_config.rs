@@ -19,9 +19,10 @@ use logic::type_aliases::TokenAmount;
     ## Example
     ```
         use sovtoken::logic::config::set_fees_config::SetFeesMap;
+        use sovtoken::logic::type_aliases::TokenAmount;
         use std::collections::HashMap;
         let mut set_fees_map : SetFeesMap = HashMap::new();
-        set_fees_map.insert(String::from("1002"), 10);
+        set_fees_map.insert(String::from("1002"), TokenAmount::new(10));
     ```
 */
 pub type SetFeesMap = HashMap<String, TokenAmount>;
@@ -35,14 +36,15 @@ pub type SetFeesMap = HashMap<String, TokenAmount>;
         use std::collections::HashMap;
         use sovtoken::utils::constants::txn_types;
         use sovtoken::logic::did::Did;
+        use sovtoken::logic::type_aliases::TokenAmount;
         use sovtoken::logic::config::set_fees_config::{
             SetFees,
             SetFeesError,
         };
 
         let mut fees = HashMap::new();
-        fees.insert(String::from(txn_types::XFER_PUBLIC), 10);
-        fees.insert(String::from("15"), 3);
+        fees.insert(String::from(txn_types::XFER_PUBLIC), TokenAmount::new(10));
+        fees.insert(String::from("15"), TokenAmount::new(3));
         let identifier = String::from("hgrhyNXqW4KNTz4wwiV8v");
         let did = Did::new(identifier).validate().unwrap();
         let set_fees = SetFees::new(fees).validate().unwrap();
@@ -113,14 +115,15 @@ impl SetFees {
         ```
             use std::collections::HashMap;
             use sovtoken::utils::constants::txn_types;
+            use sovtoken::logic::type_aliases::TokenAmount;
             use sovtoken::logic::config::set_fees_config::{
                 SetFees,
                 SetFeesError,
             };
 
             let mut fees = HashMap::new();
-            fees.insert(String::from(txn_types::XFER_PUBLIC), 10);
-            fees.insert(String::from("15"), 3);
+            fees.insert(String::from(txn_types::XFER_PUBLIC), TokenAmount::new(10));
+            fees.insert(String::from("15"), TokenAmount::new(3));
             let set_fees = SetFees::new(fees);
             let validated = set_fees.validate();
 
@@ -0,0 +1,353 @@
+/*!
+    UTXO consolidation
+
+    Addresses receiving many small payments end up with more utxos than fit in
+    one XFER_PUBLIC request. A consolidation plan merges them in batches of at
+    most `max_inputs` inputs, each batch paying its fee and sending the rest to
+    a single output.
+
+    Each batch takes the largest utxo left and fills up with the smallest ones,
+    so dust is merged with a utxo able to pay its fee instead of being left in
+    batches of its own. The plan is deterministic: utxos are ordered by amount,
+    then seq_no, so the same utxo list always gives the same requests.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use {ErrorCode, IndyHandle};
+use logic::address;
+use logic::build_payment::build_payment_request_json;
+use logic::did::Did;
+use logic::fee_estimator::{AuthActionKey, estimate_fee_for_action};
+use logic::indy_sdk_api::crypto_api::CryptoAPI;
+use logic::input::Input;
+use logic::output::Output;
+use logic::parsers::parse_get_auth_rule_response::AuthRule;
+use logic::parsers::parse_get_utxo_response::UTXOInner;
+use logic::type_aliases::TokenAmount;
+use logic::xfer_payload::XferPayload;
+use utils::constants::txn_types::XFER_PUBLIC;
+
+/**
+    Transfers merging the utxos, and the utxos left out.
+
+    `skipped` holds the utxos of batches worth no more than the fee even with
+    the largest utxo left, and a single utxo already at the destination.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsolidationPlan {
+    pub batches: Vec<XferPayload>,
+    pub skipped: Vec<UTXOInner>,
+}
+
+/**
+    Plans the consolidation of `utxos` into `destination` (a fully qualified
+    payment address).
+
+    Zero amount and duplicated utxos are ignored. `fee` is paid by each batch,
+    see [`transfer_fee`].
+
+    # Errors
+    * `CommonInvalidStructure` when `max_inputs` is 0, an address is invalid
+      or the amounts overflow
+
+    [`transfer_fee`]: fn.transfer_fee.html
+*/
+pub fn plan_consolidation(
+    utxos: &[UTXOInner],
+    destination: &str,
+    max_inputs: usize,
+    fee: TokenAmount
+) -> Result<ConsolidationPlan, ErrorCode> {
+    trace!("logic::consolidation::plan_consolidation >> utxos: {:?}, destination: {:?}, max_inputs: {:?}, fee: {:?}", secret!(utxos), secret!(destination), max_inputs, fee);
+
+    if max_inputs == 0 {
+        error!("A consolidation batch needs at least one input");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    let destination_unqualified = address::unqualified_address_from_address(destination)?;
+
+    let mut seen = HashSet::new();
+    let mut candidates: Vec<&UTXOInner> = utxos.iter()
+        .filter(|utxo| !utxo.amount.is_zero())
+        .filter(|utxo| seen.insert((utxo.address.clone(), utxo.seq_no)))
+        .collect();
+    candidates.sort_by(|a, b| b.amount.cmp(&a.amount)
+        .then(a.seq_no.cmp(&b.seq_no))
+        .then(a.address.cmp(&b.address)));
+
+    let mut plan = ConsolidationPlan { batches: vec![], skipped: vec![] };
+
+    let (mut largest, mut smallest) = (0, candidates.len());
+    while largest < smallest {
+        let mut chunk = vec![candidates[largest]];
+        largest += 1;
+        while chunk.len() < max_inputs && largest < smallest {
+            smallest -= 1;
+            chunk.push(candidates[smallest]);
+        }
+        chunk.sort_by(|a, b| a.seq_no.cmp(&b.seq_no).then(a.address.cmp(&b.address)));
+
+        let total = TokenAmount::try_sum(chunk.iter().map(|utxo| utxo.amount))?;
+
+        let already_consolidated = chunk.len() == 1 && chunk[0].address == destination_unqualified;
+        if total <= fee || already_consolidated {
+            debug!("Skipping batch of {} utxos worth {}", chunk.len(), total);
+            plan.skipped.extend(chunk.iter().map(|utxo| (*utxo).clone()));
+            continue;
+        }
+
+        let mut inputs = Vec::with_capacity(chunk.len());
+        for utxo in &chunk {
+            inputs.push(Input::new(address::address_from_unqualified_address(&utxo.address)?, utxo.seq_no));
+        }
+        let change = total.checked_sub(fee).ok_or(ErrorCode::CommonInvalidState)?;
+        let outputs = vec![Output::new(destination.to_string(), change)];
+
+        plan.batches.push(XferPayload::new(inputs, outputs, None));
+    }
+
+    trace!("logic::consolidation::plan_consolidation << result: {:?}", secret!(&plan));
+    Ok(plan)
+}
+
+/**
+    The fee of a XFER_PUBLIC request.
+
+    `fees` is the alias to amount map of a GET_FEES reply and `auth_rules`
+    the rules of a GET_AUTH_RULE reply.
+*/
+pub fn transfer_fee(fees: &HashMap<String, TokenAmount>, auth_rules: &[AuthRule]) -> Result<TokenAmount, ErrorCode> {
+    let action = AuthActionKey::add(XFER_PUBLIC, "*", "*");
+    estimate_fee_for_action(fees, auth_rules, &action, None).map(|estimate| estimate.fee)
+}
+
+/**
+    Signs the batches of a plan and builds their XFER_PUBLIC requests.
+
+    `cb` is called once, with the request jsons in the order of the batches or
+    with the first error.
+*/
+pub fn build_consolidation_requests<A: CryptoAPI>(
+    crypto_api: &A,
+    wallet_handle: IndyHandle,
+    identifier: Option<Did>,
+    plan: ConsolidationPlan,
+    cb: Box<Fn(Result<Vec<String>, ErrorCode>) + Send + Sync>
+) -> Result<(), ErrorCode> {
+    trace!("logic::consolidation::build_consolidation_requests >> wallet_handle: {:?}, batches: {:?}", wallet_handle, plan.batches.len());
+
+    let batch_count = plan.batches.len();
+    if batch_count == 0 {
+        cb(Ok(vec![]));
+        return Ok(());
+    }
+
+    let requests: Arc<Mutex<(Vec<Option<String>>, bool)>> = Arc::new(Mutex::new((vec![None; batch_count], false)));
+    let cb = Arc::new(cb);
+
+    for (index, batch) in plan.batches.into_iter().enumerate() {
+        let batch_requests = requests.clone();
+        let cb = cb.clone();
+        let identifier = identifier.clone();
+
+        let signed = batch.sign_transfer(crypto_api, wallet_handle, Box::new(move |result| {
+            let mut requests = batch_requests.lock().unwrap();
+            if requests.1 {
+                return;
+            }
+
+            match build_payment_request_json(identifier.clone(), result) {
+                Ok(request) => {
+                    requests.0[index] = Some(request);
+                    if requests.0.iter().all(Option::is_some) {
+                        requests.1 = true;
+                        cb(Ok(requests.0.iter().filter_map(Clone::clone).collect()));
+                    }
+                }
+                Err(err) => {
+                    error!("Failed to build consolidation request {}: {:?}", index, err);
+                    requests.1 = true;
+                    cb(Err(err));
+                }
+            }
+        }));
+
+        if let Err(err) = signed {
+            requests.lock().unwrap().1 = true;
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod consolidation_tests {
+    use super::*;
+    use logic::address::address_tests::gen_random_base58_address;
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use logic::in_memory_crypto::InMemoryCrypto;
+    use logic::parsers::parse_get_auth_rule_response::{ConstraintMetadata, Constraint, RoleConstraint};
+    use serde_json;
+    use std::sync::mpsc::channel;
+
+    fn utxo(address: &str, seq_no: u64, amount: u64) -> UTXOInner {
+        UTXOInner { address: address.to_string(), seq_no, amount: TokenAmount::new(amount) }
+    }
+
+    fn destination() -> (String, String) {
+        let unqualified = gen_random_base58_address();
+        (address::address_from_unqualified_address(&unqualified).unwrap(), unqualified)
+    }
+
+    fn seq_nos(payload: &XferPayload) -> Vec<u64> {
+        payload.inputs.iter().map(|input| input.seq_no).collect()
+    }
+
+    #[test]
+    fn plan_pairs_largest_with_smallest() {
+        let (destination, _) = destination();
+        let source = gen_random_base58_address();
+        let utxos = vec![
+            utxo(&source, 5, 10), utxo(&source, 1, 10), utxo(&source, 3, 10),
+            utxo(&source, 2, 10), utxo(&source, 4, 10),
+        ];
+
+        let plan = plan_consolidation(&utxos, &destination, 2, TokenAmount::new(1)).unwrap();
+
+        assert_eq!(plan.batches.len(), 3);
+        assert_eq!(seq_nos(&plan.batches[0]), vec![1, 5]);
+        assert_eq!(seq_nos(&plan.batches[1]), vec![2, 4]);
+        assert_eq!(seq_nos(&plan.batches[2]), vec![3]);
+        assert_eq!(plan.batches[0].outputs, vec![Output::new(destination.clone(), 19)]);
+        assert_eq!(plan.batches[2].outputs, vec![Output::new(destination.clone(), 9)]);
+        assert_eq!(plan.batches[0].inputs[0].address, address::address_from_unqualified_address(&source).unwrap());
+        assert!(plan.skipped.is_empty());
+    }
+
+    #[test]
+    fn plan_merges_dust_with_larger_utxos() {
+        let (destination, _) = destination();
+        let source = gen_random_base58_address();
+        let utxos = vec![utxo(&source, 1, 2), utxo(&source, 2, 3), utxo(&source, 3, 10), utxo(&source, 4, 10)];
+
+        let plan = plan_consolidation(&utxos, &destination, 2, TokenAmount::new(5)).unwrap();
+
+        assert_eq!(plan.batches.len(), 2);
+        assert_eq!(seq_nos(&plan.batches[0]), vec![1, 3]);
+        assert_eq!(plan.batches[0].outputs[0].amount, 7);
+        assert_eq!(seq_nos(&plan.batches[1]), vec![2, 4]);
+        assert_eq!(plan.batches[1].outputs[0].amount, 8);
+        assert!(plan.skipped.is_empty());
+    }
+
+    #[test]
+    fn plan_skips_dust_not_covering_fee() {
+        let (destination, _) = destination();
+        let source = gen_random_base58_address();
+        let utxos = vec![utxo(&source, 1, 10), utxo(&source, 2, 1), utxo(&source, 3, 1), utxo(&source, 4, 1)];
+
+        let plan = plan_consolidation(&utxos, &destination, 2, TokenAmount::new(5)).unwrap();
+
+        assert_eq!(plan.batches.len(), 1);
+        assert_eq!(seq_nos(&plan.batches[0]), vec![1, 4]);
+        assert_eq!(plan.batches[0].outputs[0].amount, 6);
+        assert_eq!(plan.skipped, vec![utxo(&source, 2, 1), utxo(&source, 3, 1)]);
+    }
+
+    #[test]
+    fn plan_skips_single_utxo_at_destination() {
+        let (destination, unqualified) = destination();
+        let source = gen_random_base58_address();
+        let utxos = vec![utxo(&source, 1, 10), utxo(&unqualified, 3, 30)];
+
+        let plan = plan_consolidation(&utxos, &destination, 1, TokenAmount::ZERO).unwrap();
+
+        assert_eq!(plan.batches.len(), 1);
+        assert_eq!(plan.skipped, vec![utxo(&unqualified, 3, 30)]);
+    }
+
+    #[test]
+    fn plan_ignores_duplicates_and_zero_amounts() {
+        let (destination, _) = destination();
+        let source = gen_random_base58_address();
+        let utxos = vec![utxo(&source, 1, 10), utxo(&source, 1, 10), utxo(&source, 2, 0), utxo(&source, 3, 10)];
+
+        let plan = plan_consolidation(&utxos, &destination, 10, TokenAmount::ZERO).unwrap();
+
+        assert_eq!(plan.batches.len(), 1);
+        assert_eq!(seq_nos(&plan.batches[0]), vec![1, 3]);
+    }
+
+    #[test]
+    fn plan_is_deterministic() {
+        let (destination, _) = destination();
+        let source = gen_random_base58_address();
+        let mut utxos: Vec<UTXOInner> = (1..20).map(|seq_no| utxo(&source, seq_no, seq_no * 3)).collect();
+
+        let plan = plan_consolidation(&utxos, &destination, 4, TokenAmount::new(2)).unwrap();
+        utxos.reverse();
+        assert_eq!(plan, plan_consolidation(&utxos, &destination, 4, TokenAmount::new(2)).unwrap());
+    }
+
+    #[test]
+    fn plan_with_zero_max_inputs() {
+        let (destination, _) = destination();
+        let err = plan_consolidation(&[], &destination, 0, TokenAmount::ZERO).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn plan_with_invalid_destination() {
+        let err = plan_consolidation(&[], "pay:sov:invalid", 2, TokenAmount::ZERO).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn transfer_fee_from_auth_rule() {
+        let mut fees = HashMap::new();
+        fees.insert("xfer".to_string(), TokenAmount::new(7));
+        let rules = vec![AuthRule {
+            auth_type: XFER_PUBLIC.to_string(),
+            auth_action: "ADD".to_string(),
+            field: "*".to_string(),
+            old_value: None,
+            new_value: Some("*".to_string()),
+            constraint: Constraint::Role(RoleConstraint {
+                role: Some("*".to_string()),
+                sig_count: 1,
+                need_to_be_owner: false,
+                off_ledger_signature: false,
+                metadata: Some(ConstraintMetadata { fees: Some("xfer".to_string()) }),
+            }),
+        }];
+
+        assert_eq!(transfer_fee(&fees, &rules).unwrap(), 7);
+    }
+
+    #[test]
+    fn build_requests_for_plan() {
+        let crypto = InMemoryCrypto::new();
+        let verkey = crypto.create_key(&PaymentAddressConfig { seed: "00000000000000000000000000000My1".to_string() }).unwrap();
+        let source = address::unqualified_address_from_verkey(&verkey).unwrap();
+        let (destination, _) = destination();
+        let utxos: Vec<UTXOInner> = (1..6).map(|seq_no| utxo(&source, seq_no, 10)).collect();
+        let plan = plan_consolidation(&utxos, &destination, 2, TokenAmount::new(1)).unwrap();
+
+        let (sender, receiver) = channel();
+        let sender = Mutex::new(sender);
+        build_consolidation_requests(&crypto, 1, None, plan, Box::new(move |result| {
+            sender.lock().unwrap().send(result).unwrap();
+        })).unwrap();
+        let requests = receiver.recv().unwrap().unwrap();
+
+        assert_eq!(requests.len(), 3);
+        let last: serde_json::Value = serde_json::from_str(&requests[2]).unwrap();
+        assert_eq!(last["operation"]["type"], json!(XFER_PUBLIC));
+        assert_eq!(last["operation"]["inputs"][0]["seqNo"], json!(3));
+        assert_eq!(last["operation"]["signatures"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,392 @@
+/*!
+ * Signing without the keys being available to libsovtoken
+ *
+ * [`prepare_transfer`] and [`prepare_fees`] return an [`UnsignedPayload`]
+ * holding the exact message each [`Input`] has to sign, txn_digest and TAA
+ * acceptance included. The messages can be signed with ed25519 on an air-gapped
+ * machine. [`UnsignedPayload::attach_signatures`] then checks the detached
+ * signatures against the inputs and puts them in the payload.
+ *
+ * [`prepare_transfer`]: fn.prepare_transfer.html
+ * [`prepare_fees`]: fn.prepare_fees.html
+ * [`UnsignedPayload`]: struct.UnsignedPayload.html
+ * [`UnsignedPayload::attach_signatures`]: struct.UnsignedPayload.html#method.attach_signatures
+ * [`Input`]: ../input/struct.Input.html
+ */
+
+use serde_json;
+use std::collections::HashMap;
+
+use ErrorCode;
+use logic::address;
+use logic::api_internals::add_request_fees;
+use logic::in_memory_crypto::verify_signature;
+use logic::type_aliases::TxnSeqNo;
+use logic::xfer_payload::{XferPayload, signing_message};
+use utils::base58::{FromBase58, serialize_bytes};
+use utils::constants::general::PAYMENT_ADDRESS_QUALIFIER;
+use utils::txn_author_agreement::TaaAcceptance;
+
+type SerdeMap = serde_json::Map<String, serde_json::Value>;
+
+/**
+ * Detached signatures keyed by payment address, qualified or not
+ *
+ * An address spending several inputs maps to a list of signatures.
+ * ```JSON
+ * {
+ *      "pay:sov:<address>": <str: base58 signature>,
+ *      "<address>": [<str: base58 signature>, ...]
+ * }
+ * ```
+ */
+pub type SignaturesByAddress = HashMap<String, DetachedSignatures>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DetachedSignatures {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+/**
+ * Payload waiting for the signatures of its inputs
+ *
+ * ### Fields
+ * - `payload` the payload with unqualified addresses and without the TAA acceptance
+ * - `taa_acceptance` the TAA acceptance taken out of `extra`
+ * - `txn_digest` digest of the request the fees are added to
+ * - `signing_inputs` the message each input has to sign
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsignedPayload {
+    pub payload: XferPayload,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taa_acceptance: Option<TaaAcceptance>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub txn_digest: Option<String>,
+    pub signing_inputs: Vec<SigningInput>,
+}
+
+/**
+ * Message an input has to sign, with the verkey of the key signing it
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningInput {
+    pub address: String,
+    pub seq_no: TxnSeqNo,
+    pub verkey: String,
+    pub message: String,
+}
+
+impl UnsignedPayload {
+    fn new(payload: XferPayload, txn_digest: Option<String>) -> Result<UnsignedPayload, ErrorCode> {
+        let (payload, taa_acceptance) = payload.prepare()?;
+
+        let mut signing_inputs = Vec::with_capacity(payload.inputs.len());
+        for input in &payload.inputs {
+            signing_inputs.push(SigningInput {
+                address: address::address_from_unqualified_address(&input.address)?,
+                seq_no: input.seq_no,
+                verkey: address::verkey_from_unqualified_address(&input.address)?,
+                message: signing_message(input, &payload.outputs, &txn_digest, &payload.extra, &taa_acceptance)?,
+            });
+        }
+
+        Ok(UnsignedPayload { payload, taa_acceptance, txn_digest, signing_inputs })
+    }
+
+    /**
+     * Puts the detached signatures in the payload
+     *
+     * The messages are rebuilt from the payload, the `signing_inputs` are not trusted.
+     * Each input takes the signature of its address that verifies its message.
+     *
+     * Errors with `CommonInvalidStructure` when an address or signature is malformed
+     * or when an input has no valid signature.
+     */
+    pub fn attach_signatures(self, signatures: &SignaturesByAddress) -> Result<(XferPayload, Option<TaaAcceptance>), ErrorCode> {
+        trace!("logic::detached_signing::attach_signatures >> signatures: {:?}", signatures);
+        let signatures = decode_signatures(signatures)?;
+        let UnsignedPayload { mut payload, taa_acceptance, txn_digest, .. } = self;
+
+        let mut input_signatures = Vec::with_capacity(payload.inputs.len());
+        for input in &payload.inputs {
+            let verkey = address::verkey_from_unqualified_address(&input.address)?;
+            let message = signing_message(input, &payload.outputs, &txn_digest, &payload.extra, &taa_acceptance)?;
+
+            let mut found = None;
+            for signature in signatures.get(&input.address).into_iter().flat_map(|s| s.iter()) {
+                if verify_signature(&verkey, message.as_bytes(), signature)? {
+                    found = Some(serialize_bytes(signature));
+                    break;
+                }
+            }
+
+            match found {
+                Some(signature) => input_signatures.push(signature),
+                None => {
+                    error!("No valid signature for input {:?}", input);
+                    return Err(ErrorCode::CommonInvalidStructure);
+                }
+            }
+        }
+
+        payload.signatures = Some(input_signatures);
+
+        trace!("logic::detached_signing::attach_signatures << payload: {:?}", secret!(&payload));
+        Ok((payload, taa_acceptance))
+    }
+}
+
+/**
+ * Prepares the signing of a transfer
+ *
+ * Errors with `CommonInvalidStructure` when there are no inputs or outputs.
+ */
+pub fn prepare_transfer(payload: XferPayload) -> Result<UnsignedPayload, ErrorCode> {
+    trace!("logic::detached_signing::prepare_transfer >> payload: {:?}", secret!(&payload));
+    if payload.outputs.len() < 1 || payload.inputs.len() < 1 {
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let res = UnsignedPayload::new(payload, None);
+    trace!("logic::detached_signing::prepare_transfer << result: {:?}", res);
+    res
+}
+
+/**
+ * Prepares the signing of the fees of a request
+ *
+ * The messages cover the digest of `request_json_map`.
+ */
+pub fn prepare_fees(payload: XferPayload, request_json_map: &SerdeMap) -> Result<UnsignedPayload, ErrorCode> {
+    trace!("logic::detached_signing::prepare_fees >> payload: {:?}, request_json_map: {:?}", secret!(&payload), secret!(&request_json_map));
+    if payload.inputs.len() < 1 {
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    add_request_fees::validate_type_not_transfer(request_json_map)?;
+
+    let txn_digest = add_request_fees::txn_digest(request_json_map)?;
+    let res = UnsignedPayload::new(payload, Some(txn_digest));
+    trace!("logic::detached_signing::prepare_fees << result: {:?}", res);
+    res
+}
+
+/**
+ * Attaches the detached signatures of the fees and adds them to the request
+ *
+ * Errors with `CommonInvalidStructure` when the request is not the one the fees were prepared for.
+ */
+pub fn attach_fees_signatures(
+    request_json_map: &SerdeMap,
+    unsigned: UnsignedPayload,
+    signatures: &SignaturesByAddress
+) -> Result<String, ErrorCode> {
+    let txn_digest = add_request_fees::txn_digest(request_json_map)?;
+    if unsigned.txn_digest.as_ref() != Some(&txn_digest) {
+        error!("Fees were prepared for another request, digest: {:?}", unsigned.txn_digest);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let (fees, _) = unsigned.attach_signatures(signatures)?;
+    add_request_fees::serialize_request_with_fees(add_request_fees::add_signed_fees(request_json_map, &fees))
+}
+
+/**
+ * Deserializes an [`UnsignedPayload`] and the detached signatures of its inputs
+ *
+ * [`UnsignedPayload`]: struct.UnsignedPayload.html
+ */
+pub fn deserialize_signed_arguments(unsigned_payload_json: &str, signatures_json: &str) -> Result<(UnsignedPayload, SignaturesByAddress), ErrorCode> {
+    let unsigned: UnsignedPayload = serde_json::from_str(unsigned_payload_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    let signatures: SignaturesByAddress = serde_json::from_str(signatures_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    Ok((unsigned, signatures))
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn decode_signatures(signatures: &SignaturesByAddress) -> Result<HashMap<String, Vec<Vec<u8>>>, ErrorCode> {
+    let mut decoded: HashMap<String, Vec<Vec<u8>>> = HashMap::new();
+
+    for (addr, address_signatures) in signatures {
+        let unqualified = if addr.starts_with(PAYMENT_ADDRESS_QUALIFIER) {
+            address::unqualified_address_from_address(addr)?
+        } else {
+            address::verkey_from_unqualified_address(addr)?;
+            addr.to_string()
+        };
+
+        let address_signatures = match *address_signatures {
+            DetachedSignatures::Single(ref signature) => vec![signature.clone()],
+            DetachedSignatures::Multiple(ref signatures) => signatures.clone(),
+        };
+
+        let entry = decoded.entry(unqualified).or_insert_with(Vec::new);
+        for signature in address_signatures {
+            entry.push(signature.from_base58().map_err(|_| ErrorCode::CommonInvalidStructure)?);
+        }
+    }
+
+    Ok(decoded)
+}
+
+
+#[cfg(test)]
+mod detached_signing_tests {
+    use super::*;
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use logic::in_memory_crypto::InMemoryCrypto;
+    use logic::input::Input;
+    use logic::output::Output;
+    use logic::type_aliases::TokenAmount;
+    use logic::xfer_payload::Extra;
+
+    fn crypto_with_address(seed: &str) -> (InMemoryCrypto, String, String) {
+        let crypto = InMemoryCrypto::new();
+        let verkey = crypto.create_key(&PaymentAddressConfig { seed: seed.to_string() }).unwrap();
+        let address = address::qualified_address_from_verkey(&verkey).unwrap();
+        (crypto, verkey, address)
+    }
+
+    fn sign_all(crypto: &InMemoryCrypto, unsigned: &UnsignedPayload) -> SignaturesByAddress {
+        let mut signatures: HashMap<String, Vec<String>> = HashMap::new();
+        for input in &unsigned.signing_inputs {
+            let signature = crypto.sign(&input.verkey, input.message.as_bytes()).unwrap();
+            signatures.entry(input.address.clone()).or_insert_with(Vec::new).push(serialize_bytes(&signature));
+        }
+        signatures.into_iter().map(|(k, v)| (k, DetachedSignatures::Multiple(v))).collect()
+    }
+
+    fn payload(input_address: &str, extra: Option<Extra>) -> XferPayload {
+        let output_address = address::qualified_address_from_verkey(&address::address_tests::gen_random_base58_verkey()).unwrap();
+        XferPayload::new(
+            vec![Input::new(input_address.to_string(), 1), Input::new(input_address.to_string(), 2)],
+            vec![Output::new(output_address, 10)],
+            extra
+        )
+    }
+
+    #[test]
+    fn prepare_transfer_messages() {
+        let (_, verkey, address) = crypto_with_address("00000000000000000000000000000My1");
+        let taa = json!({"mechanism": "acceptance type 1", "taaDigest": "050e52a5", "time": 123456789});
+
+        let unsigned = prepare_transfer(payload(&address, Some(Extra(json!({"taaAcceptance": taa.clone()}))))).unwrap();
+
+        assert_eq!(unsigned.taa_acceptance, Some(taa.clone()));
+        assert_eq!(unsigned.payload.extra, None);
+        assert_eq!(unsigned.signing_inputs.len(), 2);
+        assert_eq!(unsigned.signing_inputs[1].address, address);
+        assert_eq!(unsigned.signing_inputs[1].seq_no, 2);
+        assert_eq!(unsigned.signing_inputs[1].verkey, verkey);
+
+        let input = unsigned.payload.inputs[1].clone();
+        let expected = ::logic::xfer_payload::serialize_signature(json!([[input], unsigned.payload.outputs, taa])).unwrap();
+        assert_eq!(unsigned.signing_inputs[1].message, expected);
+    }
+
+    #[test]
+    fn prepare_transfer_without_outputs() {
+        let (_, _, address) = crypto_with_address("00000000000000000000000000000My1");
+        let mut payload = payload(&address, None);
+        payload.outputs.clear();
+
+        assert_eq!(prepare_transfer(payload).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn attach_signatures_works() {
+        let (crypto, verkey, address) = crypto_with_address("00000000000000000000000000000My1");
+        let unsigned = prepare_transfer(payload(&address, None)).unwrap();
+        let messages: Vec<String> = unsigned.signing_inputs.iter().map(|i| i.message.clone()).collect();
+        let signatures = sign_all(&crypto, &unsigned);
+
+        let (signed, taa_acceptance) = unsigned.attach_signatures(&signatures).unwrap();
+
+        assert_eq!(taa_acceptance, None);
+        let signed_signatures = signed.signatures.unwrap();
+        assert_eq!(signed_signatures.len(), 2);
+        for (message, signature) in messages.iter().zip(signed_signatures.iter()) {
+            assert!(verify_signature(&verkey, message.as_bytes(), &signature.from_base58().unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn attach_signatures_keyed_by_unqualified_address() {
+        let (crypto, _, address) = crypto_with_address("00000000000000000000000000000My1");
+        let unsigned = prepare_transfer(payload(&address, None)).unwrap();
+        let signatures: SignaturesByAddress = sign_all(&crypto, &unsigned).into_iter()
+            .map(|(k, v)| (address::unqualified_address_from_address(&k).unwrap(), v))
+            .collect();
+
+        let (signed, _) = unsigned.attach_signatures(&signatures).unwrap();
+        assert_eq!(signed.signatures.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn attach_signatures_missing_signature() {
+        let (crypto, _, address) = crypto_with_address("00000000000000000000000000000My1");
+        let unsigned = prepare_transfer(payload(&address, None)).unwrap();
+        let signature = crypto.sign(&unsigned.signing_inputs[0].verkey, unsigned.signing_inputs[0].message.as_bytes()).unwrap();
+
+        let mut signatures = SignaturesByAddress::new();
+        signatures.insert(address, DetachedSignatures::Single(serialize_bytes(&signature)));
+
+        assert_eq!(unsigned.attach_signatures(&signatures).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn attach_signatures_rejects_tampered_payload() {
+        let (crypto, _, address) = crypto_with_address("00000000000000000000000000000My1");
+        let mut unsigned = prepare_transfer(payload(&address, None)).unwrap();
+        let signatures = sign_all(&crypto, &unsigned);
+
+        unsigned.payload.outputs[0].amount = TokenAmount::new(1000);
+
+        assert_eq!(unsigned.attach_signatures(&signatures).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn attach_fees_signatures_works() {
+        let (crypto, _, address) = crypto_with_address("00000000000000000000000000000My1");
+        let request = json!({"operation": {"type": "1", "dest": "V4SGRU86Z58d6TV7PBUe6f"}, "reqId": 1, "protocolVersion": 2});
+        let request = request.as_object().unwrap();
+
+        let unsigned = prepare_fees(payload(&address, None), request).unwrap();
+        assert!(unsigned.txn_digest.is_some());
+        let signatures = sign_all(&crypto, &unsigned);
+
+        let request_with_fees = attach_fees_signatures(request, unsigned.clone(), &signatures).unwrap();
+        let request_with_fees: serde_json::Value = serde_json::from_str(&request_with_fees).unwrap();
+        assert_eq!(request_with_fees["fees"][2].as_array().unwrap().len(), 2);
+
+        let mut other_request = request.clone();
+        other_request.insert("reqId".to_string(), json!(2));
+        assert_eq!(attach_fees_signatures(&other_request, unsigned, &signatures).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn deserialize_signed_arguments_roundtrip() {
+        let (_, _, address) = crypto_with_address("00000000000000000000000000000My1");
+        let unsigned = prepare_transfer(payload(&address, None)).unwrap();
+        let unsigned_json = serde_json::to_string(&unsigned).unwrap();
+        let signatures_json = format!(r#"{{"{}": "3Ntb", "other": ["1", "2"]}}"#, address);
+
+        let (deserialized, signatures) = deserialize_signed_arguments(&unsigned_json, &signatures_json).unwrap();
+        assert_eq!(deserialized, unsigned);
+        assert_eq!(signatures[&address], DetachedSignatures::Single("3Ntb".to_string()));
+        assert_eq!(signatures["other"], DetachedSignatures::Multiple(vec!["1".to_string(), "2".to_string()]));
+    }
+}
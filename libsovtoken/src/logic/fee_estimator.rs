@@ -0,0 +1,468 @@
+/*!
+    Estimates the fee a request has to pay.
+
+    The fee of an action is not in the GET_FEES reply directly. The auth rule of
+    the action names a fees alias in the `metadata.fees` of its constraints and the
+    GET_FEES reply maps that alias to an amount (see `doc/fees.md`).
+
+    ## Constraint evaluation
+    * `ROLE` costs the amount of its `metadata.fees` alias, or nothing without an alias.
+      It is only considered when the signer role matches (when a role is given).
+    * `OR` costs the cheapest of the branches which can be satisfied.
+    * `AND` requires all the branches, which must cost the same amount. The ledger
+      rejects the request otherwise.
+    * `FORBIDDEN` can't be satisfied.
+*/
+
+use std::collections::HashMap;
+
+use serde_json;
+
+use ErrorCode;
+use logic::parsers::parse_get_txn_fees;
+use logic::parsers::parse_get_auth_rule_response::{AuthRule, Constraint, RoleConstraint, parse_auth_rules_from_get_auth_rule_response};
+use logic::type_aliases::TokenAmount;
+use utils::json_conversion::JsonSerialize;
+use utils::constants::txn_types::{ATTRIB, CRED_DEF, NYM, REVOC_REG_DEF, SCHEMA, XFER_PUBLIC};
+
+const WILDCARD: &str = "*";
+const ADD_ACTION: &str = "ADD";
+const ROLE_FIELD: &str = "role";
+
+/**
+    Identifies the auth rule of an action, the same way the ledger does.
+
+    `old_value` is only used for `EDIT` actions.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuthActionKey {
+    pub auth_type: String,
+    pub auth_action: String,
+    pub field: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<String>,
+    #[serde(default)]
+    pub new_value: Option<String>,
+}
+
+impl AuthActionKey {
+    /**
+        The action of adding `field` with `new_value` by a request of `auth_type`.
+    */
+    pub fn add(auth_type: &str, field: &str, new_value: &str) -> AuthActionKey {
+        AuthActionKey {
+            auth_type: auth_type.to_string(),
+            auth_action: ADD_ACTION.to_string(),
+            field: field.to_string(),
+            old_value: None,
+            new_value: Some(new_value.to_string()),
+        }
+    }
+
+    /**
+        Derives the action a prospective request performs.
+
+        Requests are considered as adding a new entity, a NYM adds a
+        new identity with the role of the request. Use [`estimate_fee_for_action`]
+        for edits.
+
+        [`estimate_fee_for_action`]: fn.estimate_fee_for_action.html
+    */
+    pub fn from_request(request_json: &str) -> Result<AuthActionKey, ErrorCode> {
+        let request: serde_json::Value = serde_json::from_str(request_json)
+            .map_err(map_err_err!())
+            .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+        let operation = request.get("operation").ok_or(ErrorCode::CommonInvalidStructure)?;
+        let txn_type = operation.get("type")
+            .and_then(|txn_type| txn_type.as_str())
+            .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+        match txn_type {
+            NYM => {
+                let role = match operation.get(ROLE_FIELD) {
+                    None | Some(&serde_json::Value::Null) => "",
+                    Some(role) => role.as_str().ok_or(ErrorCode::CommonInvalidStructure)?
+                };
+                Ok(AuthActionKey::add(NYM, ROLE_FIELD, role))
+            },
+            ATTRIB | SCHEMA | CRED_DEF | REVOC_REG_DEF | XFER_PUBLIC => {
+                Ok(AuthActionKey::add(txn_type, WILDCARD, WILDCARD))
+            },
+            _ => {
+                error!("Fee estimation isn't supported for txn type {}", txn_type);
+                Err(ErrorCode::PaymentOperationNotSupportedError)
+            }
+        }
+    }
+}
+
+/**
+    The fee to pay and the alias it comes from. `alias` is `None` when the
+    action is free.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FeeEstimate {
+    pub fee: TokenAmount,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+}
+
+/**
+    Estimates the fee of a prospective request.
+
+    # Params
+    fees - the alias to amount map returned by parsing a GET_FEES reply
+    auth_rules - the rules from a GET_AUTH_RULE reply
+    request_json - NYM, ATTRIB, SCHEMA, CRED_DEF, REVOC_REG_DEF or XFER_PUBLIC request
+    signer_role - role of the request signer, `None` considers any role
+
+    # Errors
+    * `PaymentOperationNotSupportedError` for other request types
+    * `CommonInvalidStructure` when no auth rule matches the request
+    * `TransactionNotAllowed` when no constraint can be satisfied
+*/
+pub fn estimate_fee(
+    fees: &HashMap<String, TokenAmount>,
+    auth_rules: &[AuthRule],
+    request_json: &str,
+    signer_role: Option<&str>
+) -> Result<FeeEstimate, ErrorCode> {
+    let action = AuthActionKey::from_request(request_json)?;
+    estimate_fee_for_action(fees, auth_rules, &action, signer_role)
+}
+
+/**
+    Estimates the fee of an action, like [`estimate_fee`] for the actions
+    derived from no request, such as edits.
+
+    # Params
+    fees - the alias to amount map returned by parsing a GET_FEES reply
+    auth_rules - the rules from a GET_AUTH_RULE reply
+    action - the auth rule key of the action
+    signer_role - role of the request signer, `None` considers any role
+
+    # Errors
+    * `CommonInvalidStructure` when no auth rule matches the action
+    * `TransactionNotAllowed` when no constraint can be satisfied
+
+    [`estimate_fee`]: fn.estimate_fee.html
+*/
+pub fn estimate_fee_for_action(
+    fees: &HashMap<String, TokenAmount>,
+    auth_rules: &[AuthRule],
+    action: &AuthActionKey,
+    signer_role: Option<&str>
+) -> Result<FeeEstimate, ErrorCode> {
+    trace!("logic::fee_estimator::estimate_fee_for_action >> fees: {:?}, action: {:?}, signer_role: {:?}", fees, action, signer_role);
+
+    let rule = find_auth_rule(auth_rules, action)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?;
+    debug!("Found auth rule >>> {:?}", rule);
+
+    let res = evaluate_constraint(&rule.constraint, fees, signer_role)
+        .ok_or(ErrorCode::TransactionNotAllowed)
+        .map_err(map_err_err!());
+
+    trace!("logic::fee_estimator::estimate_fee_for_action << result: {:?}", res);
+    res
+}
+
+/**
+    [`estimate_fee`] from the json handed to `estimate_txn_fees_handler`.
+
+    `fees_json` is the output of `parse_get_txn_fees_response_handler`, its
+    `proofTimestamp` isn't an alias, and `get_auth_rule_response_json` the ledger
+    reply of a GET_AUTH_RULE request.
+    Returns the serialized [`FeeEstimate`].
+
+    [`estimate_fee`]: fn.estimate_fee.html
+    [`FeeEstimate`]: struct.FeeEstimate.html
+*/
+pub fn estimate_fee_json(
+    fees_json: &str,
+    get_auth_rule_response_json: &str,
+    request_json: &str,
+    signer_role: Option<&str>
+) -> Result<String, ErrorCode> {
+    let mut fees: HashMap<String, TokenAmount> = serde_json::from_str(fees_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+    fees.remove(parse_get_txn_fees::PROOF_TIMESTAMP);
+
+    let auth_rules = parse_auth_rules_from_get_auth_rule_response(get_auth_rule_response_json)?;
+
+    estimate_fee(&fees, &auth_rules, request_json, signer_role)?
+        .to_json()
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidState)
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    Picks the most specific rule, exact values win over wildcards.
+*/
+fn find_auth_rule<'a>(auth_rules: &'a [AuthRule], action: &AuthActionKey) -> Option<&'a AuthRule> {
+    auth_rules.iter()
+        .filter(|rule| rule.auth_type == action.auth_type && rule.auth_action == action.auth_action)
+        .filter_map(|rule| {
+            let field = match_value(Some(&rule.field), Some(&action.field))?;
+            let new_value = match_value(rule.new_value.as_ref(), action.new_value.as_ref())?;
+            let old_value = if action.auth_action == ADD_ACTION {
+                0
+            } else {
+                match_value(rule.old_value.as_ref(), action.old_value.as_ref())?
+            };
+            Some((field + new_value + old_value, rule))
+        })
+        .max_by_key(|&(specificity, _)| specificity)
+        .map(|(_, rule)| rule)
+}
+
+/*
+    None when the rule value doesn't match, else how specific the match is.
+*/
+fn match_value(rule_value: Option<&String>, value: Option<&String>) -> Option<u8> {
+    let rule_value = rule_value.map(|v| v.as_str()).unwrap_or("");
+    let value = value.map(|v| v.as_str()).unwrap_or("");
+
+    if rule_value == value {
+        Some(1)
+    } else if rule_value == WILDCARD {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+fn evaluate_constraint(
+    constraint: &Constraint,
+    fees: &HashMap<String, TokenAmount>,
+    signer_role: Option<&str>
+) -> Option<FeeEstimate> {
+    match *constraint {
+        Constraint::Role(ref role) => evaluate_role(role, fees, signer_role),
+        Constraint::Or(ref or) => {
+            or.auth_constraints.iter()
+                .filter_map(|c| evaluate_constraint(c, fees, signer_role))
+                .min_by_key(|estimate| estimate.fee)
+        },
+        Constraint::And(ref and) => {
+            let mut estimates = vec![];
+            for c in and.auth_constraints.iter() {
+                estimates.push(evaluate_constraint(c, fees, signer_role)?);
+            }
+
+            let first = estimates.first()?.clone();
+            if estimates.iter().any(|estimate| estimate.fee != first.fee) {
+                debug!("Branches of AND constraint have different fees {:?}", estimates);
+                return None;
+            }
+
+            let alias = estimates.into_iter().filter_map(|estimate| estimate.alias).next();
+            Some(FeeEstimate { fee: first.fee, alias })
+        },
+        Constraint::Forbidden(_) => None,
+    }
+}
+
+fn evaluate_role(
+    constraint: &RoleConstraint,
+    fees: &HashMap<String, TokenAmount>,
+    signer_role: Option<&str>
+) -> Option<FeeEstimate> {
+    let role = constraint.role.as_ref().map(|role| role.as_str()).unwrap_or("");
+    if let Some(signer_role) = signer_role {
+        if role != WILDCARD && role != signer_role {
+            return None;
+        }
+    }
+
+    let alias = constraint.metadata.as_ref().and_then(|metadata| metadata.fees.clone());
+    let fee = alias.as_ref()
+        .and_then(|alias| fees.get(alias))
+        .cloned()
+        .unwrap_or_default();
+
+    Some(FeeEstimate { fee, alias })
+}
+
+
+#[cfg(test)]
+mod fee_estimator_tests {
+    use super::*;
+
+    fn fees() -> HashMap<String, TokenAmount> {
+        let mut fees = HashMap::new();
+        fees.insert("add_new_steward".to_string(), TokenAmount::new(42));
+        fees.insert("steward_attrib".to_string(), TokenAmount::new(3));
+        fees.insert("trustee_attrib".to_string(), TokenAmount::new(5));
+        fees.insert("schema_a".to_string(), TokenAmount::new(7));
+        fees.insert("schema_b".to_string(), TokenAmount::new(7));
+        fees.insert("schema_c".to_string(), TokenAmount::new(8));
+        fees.insert("xfer".to_string(), TokenAmount::new(1));
+        fees
+    }
+
+    fn auth_rules() -> Vec<AuthRule> {
+        let response = json!({
+            "op": "REPLY",
+            "result": {
+                "type": "121",
+                "data": [
+                    {"auth_type": "1", "auth_action": "ADD", "field": "role", "new_value": "2", "constraint":
+                        {"constraint_id": "ROLE", "role": "0", "sig_count": 1, "need_to_be_owner": false, "metadata": {"fees": "add_new_steward"}}},
+                    {"auth_type": "1", "auth_action": "ADD", "field": "role", "new_value": "", "constraint":
+                        {"constraint_id": "ROLE", "role": "*", "sig_count": 1, "need_to_be_owner": false, "metadata": {}}},
+                    {"auth_type": "1", "auth_action": "ADD", "field": "role", "new_value": "0", "constraint":
+                        {"constraint_id": "FORBIDDEN"}},
+                    {"auth_type": "100", "auth_action": "ADD", "field": "*", "new_value": "*", "constraint":
+                        {"constraint_id": "OR", "auth_constraints": [
+                            {"constraint_id": "ROLE", "role": "0", "sig_count": 1, "metadata": {"fees": "trustee_attrib"}},
+                            {"constraint_id": "ROLE", "role": "2", "sig_count": 1, "metadata": {"fees": "steward_attrib"}}
+                        ]}},
+                    {"auth_type": "101", "auth_action": "ADD", "field": "*", "new_value": "*", "constraint":
+                        {"constraint_id": "AND", "auth_constraints": [
+                            {"constraint_id": "ROLE", "role": "0", "sig_count": 1, "metadata": {"fees": "schema_a"}},
+                            {"constraint_id": "ROLE", "role": "*", "sig_count": 1, "metadata": {"fees": "schema_b"}}
+                        ]}},
+                    {"auth_type": "102", "auth_action": "ADD", "field": "*", "new_value": "*", "constraint":
+                        {"constraint_id": "AND", "auth_constraints": [
+                            {"constraint_id": "ROLE", "role": "*", "sig_count": 1, "metadata": {"fees": "schema_a"}},
+                            {"constraint_id": "ROLE", "role": "*", "sig_count": 1, "metadata": {"fees": "schema_c"}}
+                        ]}},
+                    {"auth_type": "10001", "auth_action": "ADD", "field": "*", "new_value": "*", "constraint":
+                        {"constraint_id": "ROLE", "role": "*", "sig_count": 0, "metadata": {"fees": "xfer"}}}
+                ]
+            }
+        });
+        parse_auth_rules_from_get_auth_rule_response(&response.to_string()).unwrap()
+    }
+
+    fn request(operation: serde_json::Value) -> String {
+        json!({
+            "reqId": 1,
+            "identifier": "V4SGRU86Z58d6TV7PBUe6f",
+            "protocolVersion": 2,
+            "operation": operation
+        }).to_string()
+    }
+
+    #[test]
+    fn estimate_nym_steward() {
+        let request = request(json!({"type": "1", "dest": "VsKV7grR1BUE29mG2Fm2kX", "role": "2"}));
+        let estimate = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap();
+        assert_eq!(estimate, FeeEstimate { fee: TokenAmount::new(42), alias: Some("add_new_steward".to_string()) });
+    }
+
+    #[test]
+    fn estimate_nym_identity_owner_is_free() {
+        let request = request(json!({"type": "1", "dest": "VsKV7grR1BUE29mG2Fm2kX"}));
+        let estimate = estimate_fee(&fees(), &auth_rules(), &request, Some("101")).unwrap();
+        assert_eq!(estimate, FeeEstimate { fee: TokenAmount::ZERO, alias: None });
+    }
+
+    #[test]
+    fn estimate_nym_forbidden() {
+        let request = request(json!({"type": "1", "dest": "VsKV7grR1BUE29mG2Fm2kX", "role": "0"}));
+        let err = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap_err();
+        assert_eq!(err, ErrorCode::TransactionNotAllowed);
+    }
+
+    #[test]
+    fn estimate_or_takes_cheapest_branch() {
+        let request = request(json!({"type": "100", "dest": "VsKV7grR1BUE29mG2Fm2kX", "raw": "{}"}));
+        let estimate = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap();
+        assert_eq!(estimate, FeeEstimate { fee: TokenAmount::new(3), alias: Some("steward_attrib".to_string()) });
+    }
+
+    #[test]
+    fn estimate_or_with_signer_role() {
+        let request = request(json!({"type": "100", "dest": "VsKV7grR1BUE29mG2Fm2kX", "raw": "{}"}));
+        let estimate = estimate_fee(&fees(), &auth_rules(), &request, Some("0")).unwrap();
+        assert_eq!(estimate, FeeEstimate { fee: TokenAmount::new(5), alias: Some("trustee_attrib".to_string()) });
+
+        let err = estimate_fee(&fees(), &auth_rules(), &request, Some("101")).unwrap_err();
+        assert_eq!(err, ErrorCode::TransactionNotAllowed);
+    }
+
+    #[test]
+    fn estimate_and_with_equal_amounts() {
+        let request = request(json!({"type": "101", "data": {}}));
+        let estimate = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap();
+        assert_eq!(estimate, FeeEstimate { fee: TokenAmount::new(7), alias: Some("schema_a".to_string()) });
+    }
+
+    #[test]
+    fn estimate_and_with_different_amounts() {
+        let request = request(json!({"type": "102", "data": {}}));
+        let err = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap_err();
+        assert_eq!(err, ErrorCode::TransactionNotAllowed);
+    }
+
+    #[test]
+    fn estimate_xfer() {
+        let request = request(json!({"type": "10001", "inputs": [], "outputs": []}));
+        let estimate = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap();
+        assert_eq!(estimate, FeeEstimate { fee: TokenAmount::new(1), alias: Some("xfer".to_string()) });
+    }
+
+    #[test]
+    fn estimate_missing_alias_amount_is_free() {
+        let request = request(json!({"type": "10001", "inputs": [], "outputs": []}));
+        let estimate = estimate_fee(&HashMap::new(), &auth_rules(), &request, None).unwrap();
+        assert_eq!(estimate, FeeEstimate { fee: TokenAmount::ZERO, alias: Some("xfer".to_string()) });
+    }
+
+    #[test]
+    fn estimate_without_auth_rule() {
+        let request = request(json!({"type": "113", "data": {}}));
+        let err = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn estimate_unsupported_request() {
+        let request = request(json!({"type": "20000", "fees": {}}));
+        let err = estimate_fee(&fees(), &auth_rules(), &request, None).unwrap_err();
+        assert_eq!(err, ErrorCode::PaymentOperationNotSupportedError);
+    }
+
+    #[test]
+    fn estimate_from_json() {
+        let fees_json = json!({"add_new_steward": 42, "proofTimestamp": 1530059419}).to_string();
+        let auth_rule_response = json!({
+            "op": "REPLY",
+            "result": {"type": "121", "data": [
+                {"auth_type": "1", "auth_action": "ADD", "field": "role", "new_value": "2", "constraint":
+                    {"constraint_id": "ROLE", "role": "0", "sig_count": 1, "metadata": {"fees": "add_new_steward"}}}
+            ]}
+        }).to_string();
+        let request = request(json!({"type": "1", "dest": "VsKV7grR1BUE29mG2Fm2kX", "role": "2"}));
+
+        let estimate = estimate_fee_json(&fees_json, &auth_rule_response, &request, None).unwrap();
+        assert_eq!(estimate, r#"{"fee":42,"alias":"add_new_steward"}"#);
+    }
+
+    #[test]
+    fn estimate_prefers_exact_rule() {
+        let mut rules = auth_rules();
+        rules.push(AuthRule {
+            auth_type: "1".to_string(),
+            auth_action: "ADD".to_string(),
+            field: "role".to_string(),
+            old_value: None,
+            new_value: Some("*".to_string()),
+            constraint: Constraint::Forbidden(::logic::parsers::parse_get_auth_rule_response::ForbiddenConstraint {}),
+        });
+        let request = request(json!({"type": "1", "dest": "VsKV7grR1BUE29mG2Fm2kX", "role": "2"}));
+        let estimate = estimate_fee(&fees(), &rules, &request, None).unwrap();
+        assert_eq!(estimate.fee, 42);
+    }
+}
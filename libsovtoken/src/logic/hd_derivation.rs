@@ -0,0 +1,594 @@
+/*!
+ * All the addresses of a user come from one master seed: the key of an address
+ * is derived from the master seed along a path like `m/44'/0'/3'`. Ed25519 only
+ * has hardened derivation, so every index of a path has to be hardened.
+ *
+ * The derived key is given to libindy as the seed of a [`PaymentAddressConfig`],
+ * so the wallet holds a plain key and signing doesn't change.
+ *
+ * [`discover_addresses`] finds the addresses of a master seed which were used on
+ * the payment ledger, to recreate them in a fresh wallet.
+ *
+ * [`PaymentAddressConfig`]: ../config/payment_address_config/struct.PaymentAddressConfig.html
+ * [`discover_addresses`]: fn.discover_addresses.html
+ */
+
+use base64;
+use hex::{FromHex, ToHex};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ErrorCode;
+use logic::address;
+use logic::config::payment_address_config::{self, CreatePaymentAddressConfig, PaymentAddressConfig};
+use logic::in_memory_crypto::InMemoryCrypto;
+use logic::utxo_pager::{self, AddressUtxos, SubmitAsync};
+
+pub const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+/**
+ * Number of consecutive unused addresses after which [`discover_addresses`] stops.
+ *
+ * [`discover_addresses`]: fn.discover_addresses.html
+ */
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+pub const MASTER_SEED_MIN_LEN: usize = 16;
+pub const MASTER_SEED_MAX_LEN: usize = 64;
+
+const MASTER_HMAC_KEY: &[u8] = b"ed25519 seed";
+
+/**
+ * A private key with its chain code
+ */
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExtendedKey {
+    pub key: [u8; 32],
+    pub chain_code: [u8; 32],
+}
+
+/**
+ * A derivation path of hardened indexes, written `m/44'/0'/0'`
+ *
+ * The indexes are stored without the hardened offset.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(pub Vec<u32>);
+
+/**
+ * A used address found by [`discover_addresses`]
+ *
+ * The address is recreated in a wallet by creating a payment address with
+ * `{"masterSeed": <master seed>, "derivationPath": <derivation_path>}`, which
+ * [`sovtoken_discover_payment_addresses`] does.
+ *
+ * [`discover_addresses`]: fn.discover_addresses.html
+ * [`sovtoken_discover_payment_addresses`]: ../../api/fn.sovtoken_discover_payment_addresses.html
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredAddress {
+    pub index: u32,
+    pub derivation_path: String,
+    pub address: String,
+    pub utxos: AddressUtxos,
+}
+
+impl ExtendedKey {
+    /**
+     * The master key of a 16 to 64 bytes seed
+     */
+    pub fn master(seed: &[u8]) -> Result<ExtendedKey, ErrorCode> {
+        if seed.len() < MASTER_SEED_MIN_LEN || seed.len() > MASTER_SEED_MAX_LEN {
+            error!("Invalid master seed length {:?}", seed.len());
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        let hmac = hmac_sha512(MASTER_HMAC_KEY, &[seed])?;
+        Ok(ExtendedKey::from_hmac(&hmac))
+    }
+
+    /**
+     * The hardened child `index`, the hardened offset is added when missing.
+     */
+    pub fn child(&self, index: u32) -> Result<ExtendedKey, ErrorCode> {
+        let index = index | HARDENED_OFFSET;
+        let mut index_bytes = [0u8; 4];
+        index_bytes[0] = (index >> 24) as u8;
+        index_bytes[1] = (index >> 16) as u8;
+        index_bytes[2] = (index >> 8) as u8;
+        index_bytes[3] = index as u8;
+
+        let hmac = hmac_sha512(&self.chain_code, &[&[0u8], &self.key, &index_bytes])?;
+        Ok(ExtendedKey::from_hmac(&hmac))
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Result<ExtendedKey, ErrorCode> {
+        let mut key = self.clone();
+        for index in &path.0 {
+            key = key.child(*index)?;
+        }
+        Ok(key)
+    }
+
+    /**
+     * The key as the seed of a [`PaymentAddressConfig`]
+     *
+     * [`PaymentAddressConfig`]: ../config/payment_address_config/struct.PaymentAddressConfig.html
+     */
+    pub fn to_seed(&self) -> String {
+        base64::encode(&self.key)
+    }
+
+    fn from_hmac(hmac: &[u8]) -> ExtendedKey {
+        let mut key = [0u8; 32];
+        let mut chain_code = [0u8; 32];
+        key.copy_from_slice(&hmac[..32]);
+        chain_code.copy_from_slice(&hmac[32..64]);
+        ExtendedKey { key, chain_code }
+    }
+}
+
+impl fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ExtendedKey {{ key: {:?}, chain_code: {:?} }}", secret!(self.key.to_hex()), secret!(self.chain_code.to_hex()))
+    }
+}
+
+impl DerivationPath {
+    pub fn child(&self, index: u32) -> DerivationPath {
+        let mut path = self.0.clone();
+        path.push(index);
+        DerivationPath(path)
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = ErrorCode;
+
+    /**
+     * Parses `m/<index>'/...`, `h` and `H` are accepted for `'`.
+     *
+     * Errors with `CommonInvalidStructure` on an index which isn't hardened.
+     */
+    fn from_str(path: &str) -> Result<DerivationPath, ErrorCode> {
+        let mut parts = path.split('/');
+        if parts.next() != Some("m") {
+            error!("Derivation path {:?} doesn't start with m", path);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        let mut indexes = Vec::new();
+        for part in parts {
+            let index = if part.ends_with('\'') || part.ends_with('h') || part.ends_with('H') {
+                &part[..part.len() - 1]
+            } else {
+                error!("Derivation path {:?} has an index which isn't hardened", path);
+                return Err(ErrorCode::CommonInvalidStructure);
+            };
+
+            let index = index.parse::<u32>()
+                .map_err(map_err_err!())
+                .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+            if index >= HARDENED_OFFSET {
+                error!("Derivation path {:?} has an index out of range", path);
+                return Err(ErrorCode::CommonInvalidStructure);
+            }
+
+            indexes.push(index);
+        }
+
+        Ok(DerivationPath(indexes))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Decodes a hex master seed
+ */
+pub fn master_seed_from_hex(master_seed: &str) -> Result<Vec<u8>, ErrorCode> {
+    Vec::<u8>::from_hex(master_seed)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)
+}
+
+/**
+ * The master seed given as a hex `master_seed` or as a `mnemonic` with its
+ * optional `passphrase`, `None` when there is neither.
+ *
+ * Errors with `CommonInvalidStructure` when both are given.
+ */
+pub fn master_seed_of(master_seed: Option<String>, mnemonic: Option<String>, passphrase: Option<String>) -> Result<Option<Vec<u8>>, ErrorCode> {
+    match (master_seed, mnemonic) {
+        (None, None) => Ok(None),
+        (Some(master_seed), None) => master_seed_from_hex(&master_seed).map(Some),
+        (None, Some(mnemonic)) => payment_address_config::mnemonic_to_seed(&mnemonic, passphrase.as_ref().map(String::as_str)).map(Some),
+        (Some(_), Some(_)) => {
+            error!("Payment address config has both a master seed and a mnemonic");
+            Err(ErrorCode::CommonInvalidStructure)
+        }
+    }
+}
+
+/**
+ * Replaces the `masterSeed` or `mnemonic`, and the `derivationPath` of `config`
+ * by the seed of the derived key.
+ *
+ * The master seed of a mnemonic is its BIP-39 seed. A config without master seed
+ * nor mnemonic is returned as it is. Errors with `CommonInvalidStructure` when
+ * the config has more than one of seed, master seed and mnemonic, or no
+ * derivation path.
+ */
+pub fn resolve_config(mut config: CreatePaymentAddressConfig) -> Result<CreatePaymentAddressConfig, ErrorCode> {
+    let passphrase = config.passphrase.take();
+    let master_seed = match master_seed_of(config.master_seed.take(), config.mnemonic.take(), passphrase)? {
+        Some(master_seed) => master_seed,
+        None => return Ok(config)
+    };
+
+    if !config.key.seed.is_empty() {
+        error!("Payment address config has both a seed and a master seed");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let path = config.derivation_path.as_ref()
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?
+        .parse::<DerivationPath>()?;
+
+    let key = ExtendedKey::master(&master_seed)?.derive(&path)?;
+    config.key.seed = key.to_seed();
+    config.derivation_path = Some(path.to_string());
+
+    Ok(config)
+}
+
+/**
+ * The qualified payment address of the key at `path`
+ */
+pub fn derive_address(master: &ExtendedKey, path: &DerivationPath) -> Result<String, ErrorCode> {
+    let config = PaymentAddressConfig { seed: master.derive(path)?.to_seed() };
+    let verkey = InMemoryCrypto::new().create_key(&config)?;
+    address::qualified_address_from_verkey(&verkey)
+}
+
+/**
+ * Callback of [`discover_addresses`]
+ *
+ * [`discover_addresses`]: fn.discover_addresses.html
+ */
+pub type DiscoverCallback = Box<FnMut(Result<Vec<DiscoveredAddress>, ErrorCode>) + Send>;
+
+/**
+ * Finds the used addresses `<account_path>/<index>'` of a master seed.
+ *
+ * An address is used when it has UTXOs on the payment ledger, an address whose
+ * outputs were all spent looks unused. Indexes are scanned from 0 until
+ * `gap_limit` consecutive addresses are unused.
+ *
+ * The UTXOs of each address are fetched with GET_UTXO requests given to
+ * `submit`, like for [`fetch_all_utxos_async`]. `cb` is called once with the
+ * used addresses ascending by index, or with the first error.
+ *
+ * Errors with `CommonInvalidStructure` when `gap_limit` is 0 or the master seed
+ * or the path are invalid, `cb` isn't called then.
+ *
+ * [`fetch_all_utxos_async`]: ../utxo_pager/fn.fetch_all_utxos_async.html
+ */
+pub fn discover_addresses(master_seed: &[u8], account_path: &str, gap_limit: u32, submit: Arc<SubmitAsync>, cb: DiscoverCallback) -> Result<(), ErrorCode> {
+    trace!("logic::hd_derivation::discover_addresses >> account_path: {:?}, gap_limit: {:?}", account_path, gap_limit);
+    if gap_limit == 0 {
+        error!("Gap limit has to be positive");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let discovery = Discovery {
+        master: ExtendedKey::master(master_seed)?,
+        account_path: account_path.parse::<DerivationPath>()?,
+        gap_limit,
+        index: 0,
+        unused: 0,
+        discovered: Vec::new(),
+    };
+
+    discover_next(discovery, submit, cb);
+    Ok(())
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    State of a discovery between two addresses.
+*/
+struct Discovery {
+    master: ExtendedKey,
+    account_path: DerivationPath,
+    gap_limit: u32,
+    index: u32,
+    unused: u32,
+    discovered: Vec<DiscoveredAddress>,
+}
+
+fn discover_next(discovery: Discovery, submit: Arc<SubmitAsync>, mut cb: DiscoverCallback) {
+    if discovery.unused >= discovery.gap_limit {
+        trace!("logic::hd_derivation::discover_addresses << found: {:?}", discovery.discovered.len());
+        return cb(Ok(discovery.discovered));
+    }
+
+    if discovery.index >= HARDENED_OFFSET {
+        return cb(Err(ErrorCode::CommonInvalidStructure));
+    }
+
+    let address = match derive_address(&discovery.master, &discovery.account_path.child(discovery.index)) {
+        Ok(address) => address,
+        Err(err) => return cb(Err(err))
+    };
+
+    let next_submit = submit.clone();
+    let mut pending = Some((discovery, cb));
+    utxo_pager::fetch_all_utxos_async(&address, submit, Box::new(move |utxos| {
+        let (mut discovery, mut cb) = match pending.take() {
+            Some(pending) => pending,
+            None => return
+        };
+
+        let utxos = match utxos {
+            Ok(utxos) => utxos,
+            Err(err) => return cb(Err(err))
+        };
+
+        if utxos.utxos.is_empty() {
+            discovery.unused += 1;
+        } else {
+            discovery.unused = 0;
+            discovery.discovered.push(DiscoveredAddress {
+                index: discovery.index,
+                derivation_path: discovery.account_path.child(discovery.index).to_string(),
+                address: utxos.address.clone(),
+                utxos,
+            });
+        }
+
+        discovery.index += 1;
+        discover_next(discovery, next_submit.clone(), cb);
+    }));
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> Result<Vec<u8>, ErrorCode> {
+    let key = PKey::hmac(key).map_err(|_| ErrorCode::CommonInvalidState)?;
+    let mut signer = Signer::new(MessageDigest::sha512(), &key).map_err(|_| ErrorCode::CommonInvalidState)?;
+    for part in data {
+        signer.update(part).map_err(|_| ErrorCode::CommonInvalidState)?;
+    }
+    signer.sign_to_vec().map_err(|_| ErrorCode::CommonInvalidState)
+}
+
+
+#[cfg(test)]
+mod hd_derivation_tests {
+    use super::*;
+    use serde_json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use logic::address::verkey_from_unqualified_address;
+    use utils::base58::IntoBase58;
+
+    // SLIP-0010 test vector 1 for ed25519
+    const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+    fn master() -> ExtendedKey {
+        ExtendedKey::master(&master_seed_from_hex(SEED).unwrap()).unwrap()
+    }
+
+    fn path(path: &str) -> DerivationPath {
+        path.parse().unwrap()
+    }
+
+    fn empty_reply(request: &str) -> String {
+        let request: serde_json::Value = serde_json::from_str(request).unwrap();
+        json!({
+            "op": "REPLY",
+            "result": {
+                "type": "10002",
+                "address": request["operation"]["address"],
+                "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+                "reqId": 1,
+                "outputs": []
+            }
+        }).to_string()
+    }
+
+    #[test]
+    fn master_key_test_vector() {
+        let master = master();
+        assert_eq!(master.key.to_hex(), "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7");
+        assert_eq!(master.chain_code.to_hex(), "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb");
+    }
+
+    #[test]
+    fn child_key_test_vector() {
+        let key = master().derive(&path("m/0'/1'")).unwrap();
+        assert_eq!(key.key.to_hex(), "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2");
+        assert_eq!(key.chain_code.to_hex(), "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14");
+    }
+
+    #[test]
+    fn child_adds_hardened_offset() {
+        let master = master();
+        assert_eq!(master.child(0).unwrap(), master.child(HARDENED_OFFSET).unwrap());
+    }
+
+    #[test]
+    fn derive_address_test_vector() {
+        let address = derive_address(&master(), &path("m/0'/1'")).unwrap();
+        let unqualified = address::unqualified_address_from_address(&address).unwrap();
+        let verkey = verkey_from_unqualified_address(&unqualified).unwrap();
+
+        let expected = Vec::<u8>::from_hex("1932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187").unwrap();
+        assert_eq!(verkey, expected.into_base58());
+    }
+
+    #[test]
+    fn master_seed_invalid_length() {
+        assert_eq!(ExtendedKey::master(&[0u8; 15]).unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(ExtendedKey::master(&[0u8; 65]).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn parse_derivation_path() {
+        assert_eq!(path("m"), DerivationPath(vec![]));
+        assert_eq!(path("m/44'/0h/2H"), DerivationPath(vec![44, 0, 2]));
+        assert_eq!(path("m/44h/0'").to_string(), "m/44'/0'");
+    }
+
+    #[test]
+    fn parse_invalid_derivation_path() {
+        for invalid in &["", "44'/0'", "m/0", "m/0'/1", "m/x'", "m/2147483648'", "m//0'"] {
+            assert_eq!(invalid.parse::<DerivationPath>().unwrap_err(), ErrorCode::CommonInvalidStructure, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn resolve_config_derives_seed() {
+        let config = CreatePaymentAddressConfig {
+            master_seed: Some(SEED.to_string()),
+            derivation_path: Some("m/0h/1h".to_string()),
+            ..Default::default()
+        };
+
+        let config = resolve_config(config).unwrap();
+
+        assert_eq!(config.key.seed, master().derive(&path("m/0'/1'")).unwrap().to_seed());
+        assert_eq!(config.master_seed, None);
+        assert_eq!(config.derivation_path, Some("m/0'/1'".to_string()));
+    }
+
+    #[test]
+    fn resolve_config_from_mnemonic() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let config = CreatePaymentAddressConfig {
+            mnemonic: Some(mnemonic.to_string()),
+            passphrase: Some("TREZOR".to_string()),
+            derivation_path: Some("m/0'".to_string()),
+            ..Default::default()
+        };
+
+        let config = resolve_config(config).unwrap();
+
+        let master_seed = payment_address_config::mnemonic_to_seed(mnemonic, Some("TREZOR")).unwrap();
+        let expected = ExtendedKey::master(&master_seed).unwrap().derive(&path("m/0'")).unwrap();
+        assert_eq!(config.key.seed, expected.to_seed());
+        assert_eq!(config.mnemonic, None);
+        assert_eq!(config.passphrase, None);
+    }
+
+    #[test]
+    fn resolve_config_without_master_seed() {
+        let config = CreatePaymentAddressConfig {
+            key: PaymentAddressConfig { seed: "00000000000000000000000000000My1".to_string() },
+            ..Default::default()
+        };
+        assert_eq!(resolve_config(config).unwrap().key.seed, "00000000000000000000000000000My1");
+    }
+
+    #[test]
+    fn resolve_config_invalid() {
+        let with_seed = CreatePaymentAddressConfig {
+            key: PaymentAddressConfig { seed: "00000000000000000000000000000My1".to_string() },
+            master_seed: Some(SEED.to_string()),
+            derivation_path: Some("m/0'".to_string()),
+            ..Default::default()
+        };
+        let without_path = CreatePaymentAddressConfig { master_seed: Some(SEED.to_string()), ..Default::default() };
+        let with_mnemonic = CreatePaymentAddressConfig {
+            master_seed: Some(SEED.to_string()),
+            mnemonic: Some("zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong".to_string()),
+            derivation_path: Some("m/0'".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(resolve_config(with_seed).unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(resolve_config(without_path).unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(resolve_config(with_mnemonic).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    fn unqualified_address(index: u32) -> String {
+        let address = derive_address(&master(), &path("m/44'").child(index)).unwrap();
+        address::unqualified_address_from_address(&address).unwrap()
+    }
+
+    fn discover<F>(gap_limit: u32, ledger: F) -> Result<Vec<DiscoveredAddress>, ErrorCode>
+        where F: Fn(String) -> Result<String, ErrorCode> + Send + Sync + 'static
+    {
+        let submit: Arc<SubmitAsync> = Arc::new(move |request: String, mut cb: Box<FnMut(Result<String, ErrorCode>) + Send>| cb(ledger(request)));
+
+        let (sender, receiver) = channel();
+        discover_addresses(&master_seed_from_hex(SEED).unwrap(), "m/44'", gap_limit, submit, Box::new(move |res| sender.send(res).unwrap()))?;
+        receiver.recv().unwrap()
+    }
+
+    #[test]
+    fn discover_addresses_stops_at_gap_limit() {
+        let used = vec![unqualified_address(1), unqualified_address(4)];
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counted = requests.clone();
+        let discovered = discover(3, move |request| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            let mut reply: serde_json::Value = serde_json::from_str(&empty_reply(&request)).unwrap();
+            let address = reply["result"]["address"].as_str().unwrap().to_string();
+            if used.contains(&address) {
+                reply["result"]["outputs"] = json!([[address, 7, 10]]);
+            }
+            Ok(reply.to_string())
+        }).unwrap();
+
+        assert_eq!(discovered.iter().map(|d| d.index).collect::<Vec<u32>>(), vec![1, 4]);
+        assert_eq!(discovered[1].derivation_path, "m/44'/4'");
+        assert_eq!(discovered[1].utxos.balance, 10);
+        assert_eq!(requests.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn discover_addresses_nothing_used() {
+        let discovered = discover(2, |request| Ok(empty_reply(&request))).unwrap();
+        assert!(discovered.is_empty());
+    }
+
+    #[test]
+    fn discover_addresses_submit_error() {
+        let err = discover(2, |_| Err(ErrorCode::PoolLedgerTimeout)).unwrap_err();
+        assert_eq!(err, ErrorCode::PoolLedgerTimeout);
+    }
+
+    #[test]
+    fn discover_addresses_zero_gap_limit() {
+        let err = discover(0, |request| Ok(empty_reply(&request))).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn master_seed_of_mnemonic_or_hex() {
+        assert_eq!(master_seed_of(Some(SEED.to_string()), None, None).unwrap(), Some(master_seed_from_hex(SEED).unwrap()));
+        assert_eq!(master_seed_of(None, None, None).unwrap(), None);
+        assert_eq!(master_seed_of(Some(SEED.to_string()), Some("zoo".to_string()), None).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+}
@@ -0,0 +1,382 @@
+/*!
+ * The ledger only keeps the unspent outputs of an address. [`reconstruct_history`]
+ * starts from them and walks backwards: the transaction of an output credited
+ * the address, the inputs of that transaction which belong to the address are
+ * outputs of earlier transactions it spent, which are fetched in turn.
+ *
+ * The result is the chronological list of the MINT_PUBLIC, XFER_PUBLIC and fee
+ * transactions that led to the current UTXOs. Outputs which were spent without
+ * leaving change to the address can't be reached this way and are missing.
+ *
+ * [`reconstruct_history`]: fn.reconstruct_history.html
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ErrorCode;
+use logic::address;
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_utxo_response::UTXOInner;
+use logic::parsers::parse_verify::{ParseVerifyResponse, ParseVerifyResponseResultDataTxnData};
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use utils::constants::txn_types::{MINT_PUBLIC, XFER_PUBLIC};
+use utils::json_conversion::JsonDeserialize;
+
+/**
+ * The kind of a transaction of the payment ledger
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TxnKind {
+    Mint,
+    Transfer,
+    /// Fees paid for a transaction of another ledger
+    Fees,
+}
+
+/**
+ * A transaction which credited or debited the address
+ *
+ * ### Fields
+ * - `seq_no` seqNo of the transaction on the payment ledger
+ * - `txn_time` when the transaction was ordered, when the reply has it
+ * - `txn_type` type of the transaction, e.g. `10001` for XFER_PUBLIC
+ * - `kind` what the transaction was
+ * - `credit` tokens the transaction gave to the address
+ * - `debit` tokens of the address the transaction spent
+ * - `balance` the balance after the transaction
+ * - `received` the txos the transaction created for the address
+ * - `spent` the txos of the address the transaction spent
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub seq_no: TxnSeqNo,
+    pub txn_time: Option<u64>,
+    pub txn_type: Option<String>,
+    pub kind: TxnKind,
+    pub credit: TokenAmount,
+    pub debit: TokenAmount,
+    pub balance: TokenAmount,
+    pub received: Vec<String>,
+    pub spent: Vec<String>,
+}
+
+/**
+ * The history of an address, oldest transaction first
+ *
+ * ### Fields
+ * - `address` the qualified payment address
+ * - `entries` the transactions, ascending by seqNo
+ * - `balance` the balance after the last entry, the sum of the UTXOs
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressHistory {
+    pub address: String,
+    pub entries: Vec<HistoryEntry>,
+    pub balance: TokenAmount,
+}
+
+/**
+ * Reconstructs the history of `payment_address` from its `utxos`
+ *
+ * `fetch` returns the reply to a GET_TXN request of a seqNo on the payment
+ * ledger, see [`build_verify_req_handler`]. At most `max_transactions` are fetched.
+ *
+ * Errors with `CommonInvalidStructure` when a reply is malformed, is for another
+ * seqNo or doesn't have the outputs the address is known to have,
+ * `PaymentSourceDoesNotExistError` when a transaction isn't on the ledger and
+ * `CommonInvalidState` when more than `max_transactions` are needed.
+ *
+ * [`build_verify_req_handler`]: ../../api/fn.build_verify_req_handler.html
+ */
+pub fn reconstruct_history<F>(payment_address: &str, utxos: &[UTXOInner], max_transactions: usize, mut fetch: F) -> Result<AddressHistory, ErrorCode>
+    where F: FnMut(TxnSeqNo) -> Result<String, ErrorCode>
+{
+    trace!("logic::history::reconstruct_history >> payment_address: {:?}, utxos: {:?}", secret!(payment_address), secret!(utxos));
+    let unqualified_address = address::unqualified_address_from_address(payment_address)?;
+    let qualified_address = address::address_from_unqualified_address(&unqualified_address)?;
+
+    let mut txns: BTreeMap<TxnSeqNo, FetchedTxn> = BTreeMap::new();
+    let mut to_fetch: BTreeSet<TxnSeqNo> = utxos.iter().map(|utxo| utxo.seq_no).collect();
+
+    // newest first, the inputs of a transaction are always older
+    while let Some(seq_no) = to_fetch.iter().next_back().cloned() {
+        to_fetch.remove(&seq_no);
+        if txns.contains_key(&seq_no) {
+            continue;
+        }
+        if txns.len() >= max_transactions {
+            error!("History of {:?} needs more than {} transactions", secret!(payment_address), max_transactions);
+            return Err(ErrorCode::CommonInvalidState);
+        }
+
+        let txn = parse_txn(&fetch(seq_no)?, seq_no, &unqualified_address)?;
+        debug!("Fetched transaction {} >>> {:?}", seq_no, secret!(&txn));
+        to_fetch.extend(txn.spent.iter().cloned());
+        txns.insert(seq_no, txn);
+    }
+
+    for utxo in utxos {
+        if utxo.address != unqualified_address {
+            error!("UTXO {} is of {:?}", utxo.seq_no, secret!(&utxo.address));
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+        if txns[&utxo.seq_no].credit != utxo.amount {
+            error!("UTXO {} of {} tokens, its transaction gives {}", utxo.seq_no, utxo.amount, txns[&utxo.seq_no].credit);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    }
+
+    let mut entries: Vec<HistoryEntry> = Vec::with_capacity(txns.len());
+    let mut balance = TokenAmount::ZERO;
+    let mut all_spent: BTreeSet<TxnSeqNo> = BTreeSet::new();
+    for (seq_no, txn) in &txns {
+        let mut debit = TokenAmount::ZERO;
+        for spent in &txn.spent {
+            if txns[spent].credit.is_zero() || !all_spent.insert(*spent) {
+                error!("Transaction {} spends {} which is not an unspent output of the address", seq_no, spent);
+                return Err(ErrorCode::CommonInvalidStructure);
+            }
+            debit = debit.try_add(txns[spent].credit)?;
+        }
+
+        balance = balance.try_add(txn.credit)?
+            .checked_sub(debit)
+            .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+        entries.push(HistoryEntry {
+            seq_no: *seq_no,
+            txn_time: txn.txn_time,
+            txn_type: txn.txn_type.clone(),
+            kind: txn.kind,
+            credit: txn.credit,
+            debit,
+            balance,
+            received: if !txn.credit.is_zero() { vec![txo(&qualified_address, *seq_no)?] } else { vec![] },
+            spent: txn.spent.iter().map(|spent| txo(&qualified_address, *spent)).collect::<Result<Vec<String>, ErrorCode>>()?,
+        });
+    }
+
+    let res = AddressHistory { address: qualified_address, entries, balance };
+    trace!("logic::history::reconstruct_history << result: {:?}", secret!(&res));
+    Ok(res)
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    What a transaction did to the address
+*/
+#[derive(Debug)]
+struct FetchedTxn {
+    txn_time: Option<u64>,
+    txn_type: Option<String>,
+    kind: TxnKind,
+    credit: TokenAmount,
+    spent: Vec<TxnSeqNo>,
+}
+
+fn parse_txn(reply: &str, seq_no: TxnSeqNo, unqualified_address: &str) -> Result<FetchedTxn, ErrorCode> {
+    let reply = ParseVerifyResponse::from_json(reply)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    let data = reply.result
+        .ok_or(ErrorCode::CommonInvalidStructure)?
+        .data
+        .ok_or(ErrorCode::PaymentSourceDoesNotExistError)?;
+
+    let metadata = data.txn_metadata.ok_or(ErrorCode::CommonInvalidStructure)?;
+    if metadata.seq_no != seq_no {
+        error!("GET_TXN reply for {}, expected {}", metadata.seq_no, seq_no);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let txn = data.txn.ok_or(ErrorCode::CommonInvalidStructure)?;
+    let ParseVerifyResponseResultDataTxnData { inputs, outputs, .. } = txn.data.ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let mut credit = TokenAmount::ZERO;
+    for output in outputs.unwrap_or_default() {
+        if output.recipient == unqualified_address {
+            credit = credit.try_add(output.amount)?;
+        }
+    }
+
+    let spent: Vec<TxnSeqNo> = inputs.unwrap_or_default()
+        .into_iter()
+        .filter(|input| input.address == unqualified_address)
+        .map(|input| input.seq_no)
+        .collect();
+
+    if spent.iter().any(|input| *input >= seq_no) {
+        error!("Transaction {} spends a later output: {:?}", seq_no, spent);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let kind = match txn.txn_type.as_ref().map(String::as_str) {
+        Some(MINT_PUBLIC) => TxnKind::Mint,
+        Some(XFER_PUBLIC) => TxnKind::Transfer,
+        _ => TxnKind::Fees
+    };
+
+    Ok(FetchedTxn { txn_time: metadata.txn_time, txn_type: txn.txn_type, kind, credit, spent })
+}
+
+fn txo(address: &str, seq_no: TxnSeqNo) -> Result<String, ErrorCode> {
+    TXO { address: address.to_string(), seq_no }.to_libindy_string()
+}
+
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // (seqNo, type, inputs (address, seqNo), outputs (address, amount))
+    fn get_txn_reply(seq_no: TxnSeqNo, txn_type: &str, inputs: Vec<(&String, TxnSeqNo)>, outputs: Vec<(&String, u64)>) -> String {
+        let inputs: Vec<_> = inputs.into_iter().map(|(address, seq_no)| json!({"address": address, "seqNo": seq_no})).collect();
+        let outputs: Vec<_> = outputs.into_iter().map(|(address, amount)| json!({"address": address, "amount": amount})).collect();
+        json!({
+            "op": "REPLY",
+            "result": {
+                "seqNo": seq_no,
+                "type": "3",
+                "data": {
+                    "txn": {
+                        "type": txn_type,
+                        "data": {"inputs": inputs, "outputs": outputs}
+                    },
+                    "txnMetadata": {"seqNo": seq_no, "txnTime": 1_560_000_000 + seq_no}
+                }
+            }
+        }).to_string()
+    }
+
+    fn ledger(txns: HashMap<TxnSeqNo, String>) -> impl FnMut(TxnSeqNo) -> Result<String, ErrorCode> {
+        move |seq_no| Ok(txns.get(&seq_no).cloned().unwrap_or_else(|| json!({"op": "REPLY", "result": {"data": null}}).to_string()))
+    }
+
+    fn addresses() -> (String, String, String) {
+        let unqualified = address::address_tests::gen_random_base58_address();
+        let qualified = address::address_from_unqualified_address(&unqualified).unwrap();
+        (qualified, unqualified, address::address_tests::gen_random_base58_address())
+    }
+
+    fn utxo(address: &str, seq_no: TxnSeqNo, amount: u64) -> UTXOInner {
+        UTXOInner { address: address.to_string(), seq_no, amount: TokenAmount::new(amount) }
+    }
+
+    #[test]
+    fn history_of_mint_transfer_and_fees() {
+        let (qualified, ours, other) = addresses();
+        let mut txns = HashMap::new();
+        txns.insert(2, get_txn_reply(2, MINT_PUBLIC, vec![], vec![(&ours, 50), (&other, 10)]));
+        txns.insert(5, get_txn_reply(5, XFER_PUBLIC, vec![(&other, 2)], vec![(&ours, 10)]));
+        txns.insert(7, get_txn_reply(7, XFER_PUBLIC, vec![(&ours, 2), (&ours, 5)], vec![(&other, 25), (&ours, 35)]));
+        txns.insert(9, get_txn_reply(9, "10101", vec![(&ours, 7)], vec![(&ours, 34)]));
+
+        let history = reconstruct_history(&qualified, &[utxo(&ours, 9, 34)], 10, ledger(txns)).unwrap();
+
+        assert_eq!(history.address, qualified);
+        assert_eq!(history.balance, 34);
+        let summary: Vec<_> = history.entries.iter().map(|e| (e.seq_no, e.kind, e.credit.sovatoms(), e.debit.sovatoms(), e.balance.sovatoms())).collect();
+        assert_eq!(summary, vec![
+            (2, TxnKind::Mint, 50, 0, 50),
+            (5, TxnKind::Transfer, 10, 0, 60),
+            (7, TxnKind::Transfer, 35, 60, 35),
+            (9, TxnKind::Fees, 34, 35, 34),
+        ]);
+        assert_eq!(history.entries[0].txn_time, Some(1_560_000_002));
+        assert_eq!(history.entries[2].spent, vec![txo(&qualified, 2).unwrap(), txo(&qualified, 5).unwrap()]);
+        assert_eq!(history.entries[3].received, vec![txo(&qualified, 9).unwrap()]);
+    }
+
+    #[test]
+    fn history_of_several_utxos_fetches_once() {
+        let (qualified, ours, _) = addresses();
+        let mut txns = HashMap::new();
+        txns.insert(1, get_txn_reply(1, MINT_PUBLIC, vec![], vec![(&ours, 10)]));
+        txns.insert(3, get_txn_reply(3, MINT_PUBLIC, vec![], vec![(&ours, 5)]));
+
+        let mut fetched = vec![];
+        let mut fetch = ledger(txns);
+        let history = reconstruct_history(&qualified, &[utxo(&ours, 1, 10), utxo(&ours, 3, 5)], 10, |seq_no| {
+            fetched.push(seq_no);
+            fetch(seq_no)
+        }).unwrap();
+
+        assert_eq!(fetched, vec![3, 1]);
+        assert_eq!(history.balance, 15);
+        assert_eq!(history.entries.len(), 2);
+    }
+
+    #[test]
+    fn history_utxo_amount_mismatch() {
+        let (qualified, ours, _) = addresses();
+        let mut txns = HashMap::new();
+        txns.insert(1, get_txn_reply(1, MINT_PUBLIC, vec![], vec![(&ours, 10)]));
+
+        let err = reconstruct_history(&qualified, &[utxo(&ours, 1, 11)], 10, ledger(txns)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn history_reply_for_other_seq_no() {
+        let (qualified, ours, _) = addresses();
+        let mut txns = HashMap::new();
+        txns.insert(1, get_txn_reply(2, MINT_PUBLIC, vec![], vec![(&ours, 10)]));
+
+        let err = reconstruct_history(&qualified, &[utxo(&ours, 1, 10)], 10, ledger(txns)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn history_missing_transaction() {
+        let (qualified, ours, _) = addresses();
+        let mut txns = HashMap::new();
+        txns.insert(4, get_txn_reply(4, XFER_PUBLIC, vec![(&ours, 3)], vec![(&ours, 10)]));
+
+        let err = reconstruct_history(&qualified, &[utxo(&ours, 4, 10)], 10, ledger(txns)).unwrap_err();
+        assert_eq!(err, ErrorCode::PaymentSourceDoesNotExistError);
+    }
+
+    #[test]
+    fn history_too_many_transactions() {
+        let (qualified, ours, _) = addresses();
+        let mut txns = HashMap::new();
+        txns.insert(1, get_txn_reply(1, MINT_PUBLIC, vec![], vec![(&ours, 10)]));
+        txns.insert(2, get_txn_reply(2, XFER_PUBLIC, vec![(&ours, 1)], vec![(&ours, 10)]));
+
+        let err = reconstruct_history(&qualified, &[utxo(&ours, 2, 10)], 1, ledger(txns)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidState);
+    }
+
+    #[test]
+    fn history_output_spent_twice() {
+        let (qualified, ours, _) = addresses();
+        let mut txns = HashMap::new();
+        txns.insert(1, get_txn_reply(1, MINT_PUBLIC, vec![], vec![(&ours, 10)]));
+        txns.insert(2, get_txn_reply(2, XFER_PUBLIC, vec![(&ours, 1)], vec![(&ours, 10)]));
+        txns.insert(3, get_txn_reply(3, XFER_PUBLIC, vec![(&ours, 1)], vec![(&ours, 10)]));
+
+        let err = reconstruct_history(&qualified, &[utxo(&ours, 2, 10), utxo(&ours, 3, 10)], 10, ledger(txns)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn history_without_utxos() {
+        let (qualified, _, _) = addresses();
+
+        let history = reconstruct_history(&qualified, &[], 10, ledger(HashMap::new())).unwrap();
+
+        assert!(history.entries.is_empty());
+        assert_eq!(history.balance, 0);
+    }
+}
@@ -0,0 +1,215 @@
+/*!
+    [`CryptoAPI`] backed by ed25519 keys held in memory.
+
+    Signs payloads without an indy wallet, the wallet handles passed to it are ignored.
+    Keys are derived from the seed of a [`PaymentAddressConfig`] exactly like libindy
+    does, so the same seed gives the same payment address with both implementations.
+
+    Clones share the same keys.
+
+    [`CryptoAPI`]: ../indy_sdk_api/crypto_api/trait.CryptoAPI.html
+    [`PaymentAddressConfig`]: ../config/payment_address_config/struct.PaymentAddressConfig.html
+*/
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use {ErrorCode, IndyHandle};
+use logic::config::payment_address_config::{PaymentAddressConfig, SEED_LEN};
+use logic::indy_sdk_api::crypto_api::CryptoAPI;
+use utils::base58::{FromBase58, IntoBase58, serialize_bytes};
+use utils::random::rand_bytes;
+
+#[derive(Clone, Default)]
+pub struct InMemoryCrypto {
+    keys: Arc<Mutex<HashMap<String, Keypair>>>,
+}
+
+impl InMemoryCrypto {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /**
+        Creates a key from the seed of `config`, a random one when the seed is empty.
+
+        Returns the base58 verkey.
+    */
+    pub fn create_key(&self, config: &PaymentAddressConfig) -> Result<String, ErrorCode> {
+        let seed = match config.seed_bytes()? {
+            Some(seed) => seed,
+            None => rand_bytes(SEED_LEN)
+        };
+
+        let secret = SecretKey::from_bytes(&seed)
+            .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+        let public = PublicKey::from(&secret);
+        let verkey = public.as_bytes().into_base58();
+
+        self.keys.lock().unwrap().insert(verkey.clone(), Keypair { secret, public });
+
+        Ok(verkey)
+    }
+
+    pub fn contains_key(&self, verkey: &str) -> bool {
+        self.keys.lock().unwrap().contains_key(verkey)
+    }
+
+    /**
+        Signs `message` with the key of `verkey`.
+
+        Errors with `WalletItemNotFound` when the key is unknown.
+    */
+    pub fn sign(&self, verkey: &str, message: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+        let keys = self.keys.lock().unwrap();
+        let keypair = keys.get(verkey).ok_or(ErrorCode::WalletItemNotFound)?;
+        Ok(keypair.sign(message).to_bytes().to_vec())
+    }
+}
+
+/**
+    Verifies an ed25519 `signature` of `message` made by the key of the base58 `verkey`.
+*/
+pub fn verify_signature(verkey: &str, message: &[u8], signature: &[u8]) -> Result<bool, ErrorCode> {
+    let verkey = verkey.from_base58()
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+    let public = PublicKey::from_bytes(&verkey)
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+    let signature = match Signature::from_bytes(signature) {
+        Ok(signature) => signature,
+        Err(_) => return Ok(false)
+    };
+
+    Ok(public.verify(message, &signature).is_ok())
+}
+
+impl CryptoAPI for InMemoryCrypto {
+    fn indy_create_key(&self, _wallet_id: IndyHandle, config: PaymentAddressConfig) -> Result<String, ErrorCode> {
+        trace!("logic::in_memory_crypto::indy_create_key >>");
+        self.create_key(&config)
+    }
+
+    fn indy_create_key_async<F: 'static>(&self, _wallet_id: IndyHandle, config: PaymentAddressConfig, mut closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+        trace!("logic::in_memory_crypto::indy_create_key_async >>");
+        match self.create_key(&config) {
+            Ok(verkey) => closure(ErrorCode::Success, verkey),
+            Err(err) => closure(err, String::new())
+        }
+        ErrorCode::Success
+    }
+
+    fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(
+        &self,
+        _wallet_handle: IndyHandle,
+        verkey: String,
+        message: String,
+        mut cb: F
+    ) -> ErrorCode {
+        match self.sign(&verkey, message.as_bytes()) {
+            Ok(signature) => {
+                cb(Ok(serialize_bytes(&signature)));
+                ErrorCode::Success
+            },
+            Err(err) => err
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod in_memory_crypto_tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use logic::address;
+    use logic::input::Input;
+    use logic::output::Output;
+    use logic::payments::CreatePaymentHandler;
+    use logic::xfer_payload::{XferPayload, serialize_signature};
+
+    const SEED: &str = "00000000000000000000000000000My1";
+
+    fn config(seed: &str) -> PaymentAddressConfig {
+        PaymentAddressConfig { seed: seed.to_string() }
+    }
+
+    #[test]
+    fn create_key_with_seed_is_deterministic() {
+        let crypto = InMemoryCrypto::new();
+
+        let verkey = crypto.create_key(&config(SEED)).unwrap();
+        assert_eq!(verkey, "GjZWsBLgZCR18aL468JAT7w9CZRiBnpxUPPgyQxh4voa");
+        assert_eq!(verkey, InMemoryCrypto::new().create_key(&config(SEED)).unwrap());
+        assert!(crypto.contains_key(&verkey));
+    }
+
+    #[test]
+    fn create_key_without_seed() {
+        let crypto = InMemoryCrypto::new();
+
+        let verkey = crypto.create_key(&config("")).unwrap();
+        let other = crypto.create_key(&config("")).unwrap();
+
+        assert_ne!(verkey, other);
+        assert_eq!(verkey.from_base58().unwrap().len(), 32);
+    }
+
+    #[test]
+    fn create_key_with_invalid_seed() {
+        let err = InMemoryCrypto::new().create_key(&config("invalid")).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let crypto = InMemoryCrypto::new();
+        let verkey = crypto.create_key(&config(SEED)).unwrap();
+
+        let signature = crypto.sign(&verkey, b"message").unwrap();
+
+        assert!(verify_signature(&verkey, b"message", &signature).unwrap());
+        assert!(!verify_signature(&verkey, b"other message", &signature).unwrap());
+    }
+
+    #[test]
+    fn sign_with_unknown_key() {
+        let crypto = InMemoryCrypto::new();
+        let verkey = InMemoryCrypto::new().create_key(&config(SEED)).unwrap();
+
+        let err = crypto.sign(&verkey, b"message").unwrap_err();
+        assert_eq!(err, ErrorCode::WalletItemNotFound);
+    }
+
+    #[test]
+    fn create_payment_address_shares_keys() {
+        let crypto = InMemoryCrypto::new();
+        let handler = CreatePaymentHandler::new(crypto.clone());
+
+        let address = handler.create_payment_address(1, config(SEED)).unwrap();
+        let verkey = address::validate_address(&address).unwrap();
+
+        assert!(crypto.contains_key(&verkey));
+    }
+
+    #[test]
+    fn sign_transfer_payload() {
+        let crypto = InMemoryCrypto::new();
+        let verkey = crypto.create_key(&config(SEED)).unwrap();
+        let input_address = address::qualified_address_from_verkey(&verkey).unwrap();
+        let output_address = address::qualified_address_from_verkey(&crypto.create_key(&config("")).unwrap()).unwrap();
+
+        let inputs = vec![Input::new(input_address, 1)];
+        let outputs = vec![Output::new(output_address, 10)];
+
+        let (sender, receiver) = channel();
+        let sender = Mutex::new(sender);
+        let cb = move |result| { sender.lock().unwrap().send(result).unwrap(); };
+        XferPayload::new(inputs, outputs, None).sign_transfer(&crypto, 0, Box::new(cb)).unwrap();
+        let (payload, _) = receiver.recv().unwrap().unwrap();
+
+        let input = payload.inputs[0].clone();
+        let message = serialize_signature(json!([[input], payload.outputs])).unwrap();
+        let signature = payload.signatures.unwrap()[0].from_base58().unwrap();
+        assert!(verify_signature(&verkey, message.as_bytes(), &signature).unwrap());
+    }
+}
@@ -103,4 +103,25 @@ impl Ledger {
 
         ErrorCode::from(unsafe { ledger::indy_multi_sign_request(command_handle, wallet_handle, submitter_did.as_ptr(), request_json.as_ptr(), cb) })
     }
-}
\ No newline at end of file
+
+    /// Publishes request message to validator pool (no signing, unlike sign_and_submit_request).
+    ///
+    /// # Arguments
+    /// * `pool_handle` - pool handle (created by open_pool_ledger).
+    /// * `request_json` - Request data json.
+    /// * `closure` - The closure that is called when finished
+    ///
+    /// # Returns
+    /// Request result as json.
+    pub fn submit_request_async<F: 'static>(pool_handle: IndyHandle, request_json: &str, closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec_string(Box::new(closure));
+
+        Ledger::_submit_request(command_handle, pool_handle, request_json, cb)
+    }
+
+    fn _submit_request(command_handle: IndyHandle, pool_handle: IndyHandle, request_json: &str, cb: Option<ResponseStringCB>) -> ErrorCode {
+        let request_json = c_str!(request_json);
+
+        ErrorCode::from(unsafe { ledger::indy_submit_request(command_handle, pool_handle, request_json.as_ptr(), cb) })
+    }
+}
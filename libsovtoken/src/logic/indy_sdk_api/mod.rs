@@ -4,3 +4,4 @@ pub mod crypto_api;
 pub mod crypto;
 pub mod ledger;
 pub mod logger;
+pub mod non_secrets;
@@ -0,0 +1,210 @@
+use {ErrorCode, IndyHandle};
+
+use std::ffi::CString;
+
+use indy_sys::non_secrets;
+use indy_sys::{ResponseEmptyCB,
+               ResponseI32CB,
+               ResponseStringCB};
+
+use utils::results::ResultHandler;
+use utils::callbacks::ClosureHandler;
+
+pub struct WalletRecord {}
+
+impl WalletRecord {
+    /// Creates a new non-secret record in the wallet
+    /// # Arguments
+    /// * `wallet_handle` - wallet handle (created by Wallet::open)
+    /// * `xtype` - allows to separate different record types collections
+    /// * `id` - the id of record
+    /// * `value` - the value of record
+    /// * `tags_json` - the record tags used for search and storing meta information as json
+    /// # Returns
+    /// nothing, or the error of libindy
+    pub fn add(wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str, tags_json: Option<&str>) -> Result<(), ErrorCode> {
+        let (receiver, command_handle, cb) = ClosureHandler::cb_ec();
+
+        let err = WalletRecord::_add(command_handle, wallet_handle, xtype, id, value, tags_json, cb);
+
+        ResultHandler::empty(err, receiver)
+    }
+
+    /// Creates a new non-secret record in the wallet
+    /// # Arguments
+    /// * `wallet_handle` - wallet handle (created by Wallet::open)
+    /// * `xtype` - allows to separate different record types collections
+    /// * `id` - the id of record
+    /// * `value` - the value of record
+    /// * `tags_json` - the record tags used for search and storing meta information as json:
+    ///   {
+    ///     "tagName1": <str>, // string tag (will be stored encrypted)
+    ///     "~tagName2": <str>, // string tag (will be stored un-encrypted)
+    ///   }
+    /// * `closure` - The closure that is called when finished
+    /// # Returns
+    /// errorcode from calling ffi function
+    pub fn add_async<F: 'static>(wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str, tags_json: Option<&str>, closure: F) -> ErrorCode where F: FnMut(ErrorCode) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec(Box::new(closure));
+
+        WalletRecord::_add(command_handle, wallet_handle, xtype, id, value, tags_json, cb)
+    }
+
+    fn _add(command_handle: IndyHandle, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str, tags_json: Option<&str>, cb: Option<ResponseEmptyCB>) -> ErrorCode {
+        let xtype = c_str!(xtype);
+        let id = c_str!(id);
+        let value = c_str!(value);
+        let tags_json = opt_c_str_json!(tags_json);
+
+        ErrorCode::from(unsafe { non_secrets::indy_add_wallet_record(command_handle, wallet_handle, xtype.as_ptr(), id.as_ptr(), value.as_ptr(), tags_json.as_ptr(), cb) })
+    }
+
+    /// Updates the value of a non-secret wallet record
+    /// # Arguments
+    /// * `wallet_handle` - wallet handle (created by Wallet::open)
+    /// * `xtype` - type of the record
+    /// * `id` - the id of the record
+    /// * `value` - the new value of the record
+    /// * `closure` - The closure that is called when finished
+    /// # Returns
+    /// errorcode from calling ffi function
+    pub fn update_value_async<F: 'static>(wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str, closure: F) -> ErrorCode where F: FnMut(ErrorCode) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec(Box::new(closure));
+
+        WalletRecord::_update_value(command_handle, wallet_handle, xtype, id, value, cb)
+    }
+
+    fn _update_value(command_handle: IndyHandle, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str, cb: Option<ResponseEmptyCB>) -> ErrorCode {
+        let xtype = c_str!(xtype);
+        let id = c_str!(id);
+        let value = c_str!(value);
+
+        ErrorCode::from(unsafe { non_secrets::indy_update_wallet_record_value(command_handle, wallet_handle, xtype.as_ptr(), id.as_ptr(), value.as_ptr(), cb) })
+    }
+
+    /// Replaces the tags of a non-secret wallet record
+    /// # Arguments
+    /// * `wallet_handle` - wallet handle (created by Wallet::open)
+    /// * `xtype` - type of the record
+    /// * `id` - the id of the record
+    /// * `tags_json` - the new tags of the record, same format as for `add_async`
+    /// * `closure` - The closure that is called when finished
+    /// # Returns
+    /// errorcode from calling ffi function
+    pub fn update_tags_async<F: 'static>(wallet_handle: IndyHandle, xtype: &str, id: &str, tags_json: &str, closure: F) -> ErrorCode where F: FnMut(ErrorCode) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec(Box::new(closure));
+
+        WalletRecord::_update_tags(command_handle, wallet_handle, xtype, id, tags_json, cb)
+    }
+
+    fn _update_tags(command_handle: IndyHandle, wallet_handle: IndyHandle, xtype: &str, id: &str, tags_json: &str, cb: Option<ResponseEmptyCB>) -> ErrorCode {
+        let xtype = c_str!(xtype);
+        let id = c_str!(id);
+        let tags_json = c_str!(tags_json);
+
+        ErrorCode::from(unsafe { non_secrets::indy_update_wallet_record_tags(command_handle, wallet_handle, xtype.as_ptr(), id.as_ptr(), tags_json.as_ptr(), cb) })
+    }
+
+    /// Gets a non-secret wallet record by its id
+    /// # Arguments
+    /// * `wallet_handle` - wallet handle (created by Wallet::open)
+    /// * `xtype` - type of the record
+    /// * `id` - the id of the record
+    /// * `options_json` - what to retrieve:
+    ///   {
+    ///     retrieveType: (optional, false by default) Retrieve record type,
+    ///     retrieveValue: (optional, true by default) Retrieve record value,
+    ///     retrieveTags: (optional, false by default) Retrieve record tags
+    ///   }
+    /// * `closure` - The closure that is called when finished
+    /// # Returns
+    /// errorcode from calling ffi function. The closure receives the record json:
+    ///   {
+    ///     id: "Some id",
+    ///     type: "Some type", // present only if retrieveType set to true
+    ///     value: "Some value", // present only if retrieveValue set to true
+    ///     tags: <tags json>, // present only if retrieveTags set to true
+    ///   }
+    pub fn get_async<F: 'static>(wallet_handle: IndyHandle, xtype: &str, id: &str, options_json: &str, closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec_string(Box::new(closure));
+
+        WalletRecord::_get(command_handle, wallet_handle, xtype, id, options_json, cb)
+    }
+
+    fn _get(command_handle: IndyHandle, wallet_handle: IndyHandle, xtype: &str, id: &str, options_json: &str, cb: Option<ResponseStringCB>) -> ErrorCode {
+        let xtype = c_str!(xtype);
+        let id = c_str!(id);
+        let options_json = c_str!(options_json);
+
+        ErrorCode::from(unsafe { non_secrets::indy_get_wallet_record(command_handle, wallet_handle, xtype.as_ptr(), id.as_ptr(), options_json.as_ptr(), cb) })
+    }
+}
+
+pub struct WalletSearch {}
+
+impl WalletSearch {
+    /// Searches the non-secret wallet records of a type
+    /// # Arguments
+    /// * `wallet_handle` - wallet handle (created by Wallet::open)
+    /// * `xtype` - type of the records
+    /// * `query_json` - WQL query over the record tags, `{}` matches all the records
+    /// * `options_json` - what to retrieve:
+    ///   {
+    ///     retrieveRecords: (optional, true by default) If false only "counts" will be calculated,
+    ///     retrieveTotalCount: (optional, false by default) Calculate total count,
+    ///     retrieveType: (optional, false by default) Retrieve record type,
+    ///     retrieveValue: (optional, true by default) Retrieve record value,
+    ///     retrieveTags: (optional, false by default) Retrieve record tags,
+    ///   }
+    /// * `closure` - The closure that is called when finished
+    /// # Returns
+    /// errorcode from calling ffi function. The closure receives the search handle
+    pub fn open_async<F: 'static>(wallet_handle: IndyHandle, xtype: &str, query_json: &str, options_json: &str, closure: F) -> ErrorCode where F: FnMut(ErrorCode, IndyHandle) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec_i32(Box::new(closure));
+
+        WalletSearch::_open(command_handle, wallet_handle, xtype, query_json, options_json, cb)
+    }
+
+    fn _open(command_handle: IndyHandle, wallet_handle: IndyHandle, xtype: &str, query_json: &str, options_json: &str, cb: Option<ResponseI32CB>) -> ErrorCode {
+        let xtype = c_str!(xtype);
+        let query_json = c_str!(query_json);
+        let options_json = c_str!(options_json);
+
+        ErrorCode::from(unsafe { non_secrets::indy_open_wallet_search(command_handle, wallet_handle, xtype.as_ptr(), query_json.as_ptr(), options_json.as_ptr(), cb) })
+    }
+
+    /// Fetches the next records of a wallet search
+    /// # Arguments
+    /// * `wallet_handle` - wallet handle (created by Wallet::open)
+    /// * `search_handle` - handle returned by `open_async`
+    /// * `count` - the maximum number of records to fetch
+    /// * `closure` - The closure that is called when finished
+    /// # Returns
+    /// errorcode from calling ffi function. The closure receives the records json:
+    ///   {
+    ///     totalCount: <int>, // present only if retrieveTotalCount set to true
+    ///     records: [{ // present only if retrieveRecords set to true
+    ///         id: "Some id",
+    ///         type: "Some type", // present only if retrieveType set to true
+    ///         value: "Some value", // present only if retrieveValue set to true
+    ///         tags: <tags json>, // present only if retrieveTags set to true
+    ///     }],
+    ///   }
+    pub fn fetch_next_records_async<F: 'static>(wallet_handle: IndyHandle, search_handle: IndyHandle, count: usize, closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec_string(Box::new(closure));
+
+        ErrorCode::from(unsafe { non_secrets::indy_fetch_wallet_search_next_records(command_handle, wallet_handle, search_handle, count, cb) })
+    }
+
+    /// Closes a wallet search, releasing its resources
+    /// # Arguments
+    /// * `search_handle` - handle returned by `open_async`
+    /// * `closure` - The closure that is called when finished
+    /// # Returns
+    /// errorcode from calling ffi function
+    pub fn close_async<F: 'static>(search_handle: IndyHandle, closure: F) -> ErrorCode where F: FnMut(ErrorCode) + Send {
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec(Box::new(closure));
+
+        ErrorCode::from(unsafe { non_secrets::indy_close_wallet_search(command_handle, search_handle, cb) })
+    }
+}
@@ -115,8 +115,8 @@ impl<'de> Deserialize<'de> for Input {
                 let mut address = None;
                 let mut seq_no = None;
 
-                while let Some(key) = map.next_key()? {
-                    match key {
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
                         "address" => { address = map.next_value()?; },
                         "seqNo" => { seq_no =  map.next_value()?; },
                         x => { return Err(de::Error::unknown_field(x, FIELDS)) }
@@ -202,6 +202,12 @@ mod input_tests {
         assert_valid_deserialize(json, input);
     }
 
+    #[test]
+    fn deserialize_input_object_from_value() {
+        let json = json!({"address": "pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7", "seqNo": 30});
+        assert_eq!(serde_json::from_value::<Input>(json).unwrap(), valid_input());
+    }
+
     #[test]
     fn serialize_input() {
         let input = Input::new(String::from("a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7"), 5);
@@ -0,0 +1,447 @@
+/*!
+ * [`inspect_request`] takes the json of a MINT_PUBLIC, XFER_PUBLIC, SET_FEES,
+ * GET_UTXO or GET_FEES request, or of any write request with the fees of
+ * `add_request_fees_handler`, and tells what it does before it is signed or
+ * confirmed: what is paid to whom, what comes back as change, the fee paid and
+ * who signed.
+ *
+ * Inputs carry no amount, the amounts of the inputs come from the UTXOs given
+ * to [`inspect_request`]. Without them the total in and the fee paid are unknown.
+ *
+ * [`inspect_request`]: fn.inspect_request.html
+ */
+
+use serde_json;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use logic::address;
+use logic::config::set_fees_config::{SetFees, SetFeesMap};
+use logic::input::Inputs;
+use logic::output::{Output, Outputs};
+use logic::parsers::common::{TXO, UTXO};
+use logic::type_aliases::{ReqId, TokenAmount, TxnSeqNo};
+use logic::xfer_payload::XferPayload;
+use utils::constants::txn_types::{GET_FEES, GET_UTXO, MINT_PUBLIC, SET_FEES, XFER_PUBLIC};
+use utils::errors::SovtokenError;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum RequestKind {
+    Mint,
+    Transfer,
+    SetFees,
+    GetUtxo,
+    GetFees,
+    /// Another write request with fees attached
+    WithFees,
+}
+
+/**
+ * An input of the request
+ *
+ * `amount` is known when the UTXO of the input was given.
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectedInput {
+    pub txo: String,
+    pub address: String,
+    pub seq_no: TxnSeqNo,
+    pub amount: Option<TokenAmount>,
+    pub signed: bool,
+}
+
+/**
+ * What a request does
+ *
+ * ### Fields
+ * - `txn_type` type of the operation, e.g. `10001`, or of the request the fees are added to
+ * - `kind` which of the requests of libsovtoken it is
+ * - `inputs` inputs of the payment or of the fees
+ * - `outputs` all outputs, with qualified addresses
+ * - `recipients` outputs to other addresses than the ones of the inputs
+ * - `change` sum of the outputs back to the addresses of the inputs
+ * - `total_in` sum of the inputs, when all their amounts are known
+ * - `total_out` sum of the outputs
+ * - `fee_paid` `total_in - total_out`, when `total_in` is known
+ * - `fees` the new fees of a SET_FEES, by alias
+ * - `address`, `from` the payment address and start of a GET_UTXO
+ * - `taa_digest` digest of the transaction author agreement accepted
+ * - `signers` addresses of the signed inputs, DIDs which signed
+ * - `missing_signers` addresses of the inputs without signature
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestInspection {
+    pub txn_type: String,
+    pub kind: RequestKind,
+    pub req_id: Option<ReqId>,
+    pub identifier: Option<String>,
+    pub inputs: Vec<InspectedInput>,
+    pub outputs: Outputs,
+    pub recipients: Outputs,
+    pub change: TokenAmount,
+    pub total_in: Option<TokenAmount>,
+    pub total_out: TokenAmount,
+    pub fee_paid: Option<TokenAmount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fees: Option<BTreeMap<String, TokenAmount>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<i64>,
+    pub taa_digest: Option<String>,
+    pub signers: Vec<String>,
+    pub missing_signers: Vec<String>,
+}
+
+/**
+ * Decodes `request_json`, taking the amounts of its inputs from `utxos`
+ *
+ * Errors with `CommonInvalidStructure` and the failing field when the request
+ * isn't one built by libsovtoken, or when its outputs overflow.
+ */
+pub fn inspect_request(request_json: &str, utxos: &[UTXO]) -> Result<RequestInspection, SovtokenError> {
+    trace!("logic::inspector::inspect_request >> request_json: {:?}, utxos: {:?}", secret!(request_json), secret!(utxos));
+    let request: serde_json::Value = serde_json::from_str(request_json)
+        .map_err(|err| SovtokenError::invalid_structure("request_json").with_source(err))?;
+    let operation = &request["operation"];
+
+    let txn_type = operation["type"].as_str()
+        .ok_or_else(|| SovtokenError::invalid_structure("operation.type").with_value(&operation["type"]))?;
+
+    let mut inspection = RequestInspection {
+        txn_type: txn_type.to_string(),
+        kind: RequestKind::WithFees,
+        req_id: request["reqId"].as_u64(),
+        identifier: request["identifier"].as_str().map(String::from),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        recipients: Vec::new(),
+        change: TokenAmount::ZERO,
+        total_in: None,
+        total_out: TokenAmount::ZERO,
+        fee_paid: None,
+        fees: None,
+        address: None,
+        from: None,
+        taa_digest: request["taaAcceptance"]["taaDigest"].as_str().map(String::from),
+        signers: trustee_signers(&request),
+        missing_signers: Vec::new(),
+    };
+
+    let payment: Option<XferPayload> = match txn_type {
+        MINT_PUBLIC => {
+            inspection.kind = RequestKind::Mint;
+            let outputs: Outputs = serde_json::from_value(operation["outputs"].clone())
+                .map_err(|err| SovtokenError::invalid_structure("operation.outputs").with_source(err))?;
+            Some(XferPayload::new(Vec::new(), outputs, None))
+        }
+        XFER_PUBLIC => {
+            inspection.kind = RequestKind::Transfer;
+            let payload: XferPayload = serde_json::from_value(operation.clone())
+                .map_err(|err| SovtokenError::invalid_structure("operation").with_source(err))?;
+            Some(payload)
+        }
+        SET_FEES => {
+            inspection.kind = RequestKind::SetFees;
+            let fees: SetFeesMap = serde_json::from_value(operation["fees"].clone())
+                .map_err(|err| SovtokenError::invalid_structure("operation.fees").with_source(err))?;
+            let set_fees = SetFees::new(fees).validate()
+                .map_err(|err| SovtokenError::invalid_structure("operation.fees").with_source(format!("{:?}", err)))?;
+            inspection.fees = Some(set_fees.fees.into_iter().collect());
+            None
+        }
+        GET_UTXO => {
+            inspection.kind = RequestKind::GetUtxo;
+            let address = operation["address"].as_str()
+                .ok_or_else(|| SovtokenError::invalid_structure("operation.address").with_value(&operation["address"]))?;
+            inspection.address = Some(address::address_from_unqualified_address(address)?);
+            inspection.from = operation["from"].as_i64();
+            None
+        }
+        GET_FEES => {
+            inspection.kind = RequestKind::GetFees;
+            None
+        }
+        _ => None
+    };
+
+    let payment = match (payment, request.get("fees")) {
+        (Some(_), Some(_)) => return Err(SovtokenError::invalid_structure("fees")
+            .with_source(format!("fees are paid by the inputs of a {} request", txn_type))),
+        (Some(payment), None) => Some(payment),
+        (None, Some(fees)) => {
+            let (inputs, outputs, signatures): (Inputs, Outputs, Option<Vec<String>>) = serde_json::from_value(fees.clone())
+                .map_err(|err| SovtokenError::invalid_structure("fees").with_source(err))?;
+            Some(XferPayload { inputs, outputs, extra: None, signatures })
+        }
+        (None, None) if inspection.kind == RequestKind::WithFees => return Err(SovtokenError::invalid_structure("operation.type")
+            .with_value(txn_type)
+            .with_source("neither a request of libsovtoken nor a request with fees")),
+        (None, None) => None
+    };
+
+    if let Some(payment) = payment {
+        add_payment(&mut inspection, payment, utxos)?;
+    }
+
+    trace!("logic::inspector::inspect_request << result: {:?}", secret!(&inspection));
+    Ok(inspection)
+}
+
+impl fmt::Display for RequestInspection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let amount = |amount: Option<TokenAmount>| amount.map_or("unknown".to_string(), |amount| amount.to_string());
+
+        write!(f, "{:?} request ({})", self.kind, self.txn_type)?;
+        if let Some(req_id) = self.req_id {
+            write!(f, " {}", req_id)?;
+        }
+
+        for input in &self.inputs {
+            write!(f, "\n  spends {} of {}", amount(input.amount), input.txo)?;
+        }
+        for output in &self.recipients {
+            write!(f, "\n  pays {} to {}", output.amount, output.recipient)?;
+        }
+        if !self.inputs.is_empty() {
+            write!(f, "\n  change: {}\n  fee paid: {}", self.change, amount(self.fee_paid))?;
+        }
+        if let Some(ref fees) = self.fees {
+            for (alias, fee) in fees {
+                write!(f, "\n  sets the fee of {} to {}", alias, fee)?;
+            }
+        }
+        if let Some(ref address) = self.address {
+            write!(f, "\n  UTXOs of {}", address)?;
+            if let Some(from) = self.from {
+                write!(f, " from {}", from)?;
+            }
+        }
+        if let Some(ref taa_digest) = self.taa_digest {
+            write!(f, "\n  accepts the TAA {}", taa_digest)?;
+        }
+        if !self.signers.is_empty() {
+            write!(f, "\n  signed by {}", self.signers.join(", "))?;
+        }
+        if !self.missing_signers.is_empty() {
+            write!(f, "\n  not signed by {}", self.missing_signers.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn add_payment(inspection: &mut RequestInspection, payment: XferPayload, utxos: &[UTXO]) -> Result<(), SovtokenError> {
+    let mut amounts: BTreeMap<(String, TxnSeqNo), TokenAmount> = BTreeMap::new();
+    for utxo in utxos {
+        let txo = TXO::from_libindy_string(&utxo.receipt)
+            .map_err(|err| SovtokenError::invalid_structure("utxos.receipt").with_value(&utxo.receipt).with_source(err))?;
+        amounts.insert((address::unqualified_address_from_address(&txo.address)?, txo.seq_no), utxo.amount);
+    }
+
+    let signatures = payment.signatures.unwrap_or_default();
+    let mut total_in = Some(TokenAmount::ZERO);
+    for (i, input) in payment.inputs.iter().enumerate() {
+        let qualified = address::address_from_unqualified_address(&input.address)?;
+        let amount = amounts.get(&(input.address.clone(), input.seq_no)).cloned();
+        let signed = signatures.get(i).map_or(false, |signature| !signature.is_empty());
+
+        total_in = match (total_in, amount) {
+            (Some(sum), Some(amount)) => Some(sum.try_add(amount)?),
+            _ => None
+        };
+
+        let signers = if signed { &mut inspection.signers } else { &mut inspection.missing_signers };
+        if !signers.contains(&qualified) {
+            signers.push(qualified.clone());
+        }
+
+        inspection.inputs.push(InspectedInput {
+            txo: TXO { address: qualified.clone(), seq_no: input.seq_no }.to_libindy_string()?,
+            address: qualified,
+            seq_no: input.seq_no,
+            amount,
+            signed,
+        });
+    }
+    let signers = &inspection.signers;
+    inspection.missing_signers.retain(|address| !signers.contains(address));
+
+    for output in payment.outputs {
+        let change = payment.inputs.iter().any(|input| input.address == output.recipient);
+        let output = Output::new(address::address_from_unqualified_address(&output.recipient)?, output.amount);
+
+        inspection.total_out = inspection.total_out.try_add(output.amount)?;
+        if change {
+            inspection.change = inspection.change.try_add(output.amount)?;
+        } else {
+            inspection.recipients.push(output.clone());
+        }
+        inspection.outputs.push(output);
+    }
+
+    if !payment.inputs.is_empty() {
+        inspection.total_in = total_in;
+        inspection.fee_paid = total_in.and_then(|total_in| total_in.checked_sub(inspection.total_out));
+    }
+    Ok(())
+}
+
+/*
+    DIDs which signed the request itself, as libindy signs it: `signature` by
+    the identifier, `signatures` by DID.
+*/
+fn trustee_signers(request: &serde_json::Value) -> Vec<String> {
+    let mut signers: Vec<String> = request["signatures"].as_object()
+        .map(|signatures| signatures.keys().cloned().collect())
+        .unwrap_or_default();
+
+    if let (Some(_), Some(identifier)) = (request["signature"].as_str(), request["identifier"].as_str()) {
+        if !signers.iter().any(|signer| signer == identifier) {
+            signers.insert(0, identifier.to_string());
+        }
+    }
+    signers
+}
+
+
+#[cfg(test)]
+mod inspector_tests {
+    use super::*;
+    use logic::input::Input;
+    use ErrorCode;
+
+    const SENDER: &str = "dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q";
+    const RECIPIENT: &str = "2mVXsXyVADzSDw88RAojPpdgxLPQyC1oJUqkrLeU5AdfEq2PmC";
+
+    fn qualified(address: &str) -> String {
+        address::address_from_unqualified_address(address).unwrap()
+    }
+
+    fn utxo(address: &str, seq_no: TxnSeqNo, amount: u64) -> UTXO {
+        UTXO {
+            recipient: qualified(address),
+            receipt: TXO { address: qualified(address), seq_no }.to_libindy_string().unwrap(),
+            amount: TokenAmount::new(amount),
+            extra: String::new(),
+        }
+    }
+
+    fn payment_request() -> String {
+        let payload = XferPayload {
+            inputs: vec![Input::new(SENDER.to_string(), 1), Input::new(SENDER.to_string(), 2)],
+            outputs: vec![Output::new(RECIPIENT.to_string(), 20), Output::new(SENDER.to_string(), 8)],
+            extra: None,
+            signatures: Some(vec!["sig1".to_string(), "sig2".to_string()]),
+        };
+        let mut operation = json!(payload);
+        operation["type"] = json!(XFER_PUBLIC);
+        json!({
+            "operation": operation,
+            "reqId": 42,
+            "identifier": "V4SGRU86Z58d6TV7PBUe6f",
+            "protocolVersion": 2,
+            "taaAcceptance": {"taaDigest": "abc", "mechanism": "on_file", "time": 1_560_000_000},
+        }).to_string()
+    }
+
+    #[test]
+    fn inspect_payment() {
+        let inspection = inspect_request(&payment_request(), &[utxo(SENDER, 1, 20), utxo(SENDER, 2, 10)]).unwrap();
+
+        assert_eq!(inspection.kind, RequestKind::Transfer);
+        assert_eq!(inspection.req_id, Some(42));
+        assert_eq!(inspection.total_in, Some(TokenAmount::new(30)));
+        assert_eq!(inspection.total_out, 28);
+        assert_eq!(inspection.fee_paid, Some(TokenAmount::new(2)));
+        assert_eq!(inspection.change, 8);
+        assert_eq!(inspection.recipients, vec![Output::new(qualified(RECIPIENT), 20)]);
+        assert_eq!(inspection.taa_digest, Some("abc".to_string()));
+        assert_eq!(inspection.signers, vec![qualified(SENDER)]);
+        assert!(inspection.missing_signers.is_empty());
+        assert!(inspection.to_string().contains(&format!("pays 20 to {}", qualified(RECIPIENT))));
+    }
+
+    #[test]
+    fn inspect_payment_without_utxos() {
+        let inspection = inspect_request(&payment_request(), &[utxo(SENDER, 1, 20)]).unwrap();
+
+        assert_eq!(inspection.inputs[0].amount, Some(TokenAmount::new(20)));
+        assert_eq!(inspection.inputs[1].amount, None);
+        assert_eq!(inspection.total_in, None);
+        assert_eq!(inspection.fee_paid, None);
+        assert!(inspection.to_string().contains("fee paid: unknown"));
+    }
+
+    #[test]
+    fn inspect_request_with_fees() {
+        let request = json!({
+            "operation": {"type": "1", "dest": "V4SGRU86Z58d6TV7PBUe6f"},
+            "reqId": 7,
+            "identifier": "V4SGRU86Z58d6TV7PBUe6f",
+            "signature": "sig",
+            "fees": [[{"address": SENDER, "seqNo": 3}], [{"address": SENDER, "amount": 9}], [""]],
+        }).to_string();
+
+        let inspection = inspect_request(&request, &[utxo(SENDER, 3, 10)]).unwrap();
+
+        assert_eq!(inspection.kind, RequestKind::WithFees);
+        assert_eq!(inspection.txn_type, "1");
+        assert_eq!(inspection.fee_paid, Some(TokenAmount::new(1)));
+        assert_eq!(inspection.change, 9);
+        assert!(inspection.recipients.is_empty());
+        assert_eq!(inspection.signers, vec!["V4SGRU86Z58d6TV7PBUe6f".to_string()]);
+        assert_eq!(inspection.missing_signers, vec![qualified(SENDER)]);
+    }
+
+    #[test]
+    fn inspect_mint() {
+        let request = json!({
+            "operation": {"type": MINT_PUBLIC, "outputs": [{"address": RECIPIENT, "amount": 10}, {"address": SENDER, "amount": 5}]},
+            "reqId": 7,
+            "signatures": {"V4SGRU86Z58d6TV7PBUe6f": "sig"},
+        }).to_string();
+
+        let inspection = inspect_request(&request, &[]).unwrap();
+
+        assert_eq!(inspection.kind, RequestKind::Mint);
+        assert_eq!(inspection.total_out, 15);
+        assert_eq!(inspection.recipients.len(), 2);
+        assert_eq!(inspection.total_in, None);
+        assert_eq!(inspection.signers, vec!["V4SGRU86Z58d6TV7PBUe6f".to_string()]);
+    }
+
+    #[test]
+    fn inspect_set_fees_and_getters() {
+        let set_fees = json!({"operation": {"type": SET_FEES, "fees": {"1": 4, "10001": 2}}, "reqId": 7}).to_string();
+        let get_utxo = json!({"operation": {"type": GET_UTXO, "address": SENDER, "from": 3}, "reqId": 8}).to_string();
+        let get_fees = json!({"operation": {"type": GET_FEES}, "reqId": 9}).to_string();
+
+        let inspection = inspect_request(&set_fees, &[]).unwrap();
+        assert_eq!(inspection.fees.unwrap().get("10001"), Some(&TokenAmount::new(2)));
+
+        let inspection = inspect_request(&get_utxo, &[]).unwrap();
+        assert_eq!(inspection.address, Some(qualified(SENDER)));
+        assert_eq!(inspection.from, Some(3));
+
+        assert_eq!(inspect_request(&get_fees, &[]).unwrap().kind, RequestKind::GetFees);
+    }
+
+    #[test]
+    fn inspect_unknown_request() {
+        let request = json!({"operation": {"type": "1"}, "reqId": 7}).to_string();
+
+        let err = inspect_request(&request, &[]).unwrap_err();
+
+        assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure);
+    }
+}
@@ -0,0 +1,482 @@
+/*!
+    Audit path verification of write replies
+
+    A node replying to a write request gives the `rootHash` of the ledger and the
+    `auditPath` of the transaction. The leaf of the transaction is the msgpack
+    serialization (with sorted keys) of the reply `result` without `rootHash`,
+    `auditPath` and the `fees`, which is another transaction with its own proof.
+    Leaves and nodes are hashed like [RFC 6962], see [`Hash`].
+
+    The reply doesn't say how big the ledger was, so the ledger sizes leading to
+    different audit paths are all tried. A forged reply still can't match: it
+    would need a path hashing to `rootHash`.
+
+    The verification is off by default, see [`set_audit_path_verification`]. A
+    transaction whose audit path doesn't match fails with `LedgerSecurityError`.
+
+    [RFC 6962]: https://tools.ietf.org/html/rfc6962#section-2.1
+    [`Hash`]: ../hash/struct.Hash.html
+    [`set_audit_path_verification`]: fn.set_audit_path_verification.html
+*/
+
+use serde_json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ErrorCode;
+use logic::hash::{Hash, HASH_OUTPUT_LEN};
+use utils::base58::FromBase58;
+
+pub const ROOT_HASH: &str = "rootHash";
+pub const AUDIT_PATH: &str = "auditPath";
+pub const FEES: &str = "fees";
+
+static AUDIT_PATH_VERIFICATION: AtomicBool = AtomicBool::new(false);
+
+/**
+    Turns on or off the verification of the write replies given to
+    `parse_payment_response_handler` and `parse_response_with_fees_handler`.
+
+    The setting is process-wide: it applies to the replies parsed on every
+    thread, for every pool, until it is changed again. The handlers are called
+    by libindy with a fixed signature, so it can't be given per call.
+*/
+pub fn set_audit_path_verification(enabled: bool) {
+    AUDIT_PATH_VERIFICATION.store(enabled, Ordering::SeqCst);
+}
+
+pub fn audit_path_verification_enabled() -> bool {
+    AUDIT_PATH_VERIFICATION.load(Ordering::SeqCst)
+}
+
+/**
+    Verifies the reply to a write request when the verification is on.
+*/
+pub fn verify_write_reply_if_enabled(reply_json: &str) -> Result<(), ErrorCode> {
+    if audit_path_verification_enabled() {
+        verify_write_reply(reply_json)
+    } else {
+        Ok(())
+    }
+}
+
+/**
+    Checks that the transaction of a write reply, and its fees transaction, are
+    in the ledger of their `rootHash`.
+
+    Replies which aren't a `REPLY` are left to the parsers. Errors with
+    `CommonInvalidStructure` when the reply has no proof and with
+    `LedgerSecurityError` when the proof doesn't match.
+*/
+pub fn verify_write_reply(reply_json: &str) -> Result<(), ErrorCode> {
+    let reply: serde_json::Value = serde_json::from_str(reply_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    if reply["op"] != json!("REPLY") {
+        return Ok(());
+    }
+
+    let mut result = reply["result"].clone();
+    let fees = result.as_object_mut()
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?
+        .remove(FEES);
+
+    verify_txn(result)?;
+    match fees {
+        Some(serde_json::Value::Null) | None => Ok(()),
+        Some(fees) => verify_txn(fees)
+    }
+}
+
+/**
+    Checks that a ledger transaction with its `rootHash` and `auditPath`, like
+    a write reply `result`, is in the ledger.
+*/
+pub fn verify_txn(mut txn: serde_json::Value) -> Result<(), ErrorCode> {
+    let (root_hash, audit_path, seq_no) = {
+        let txn = txn.as_object_mut()
+            .ok_or(ErrorCode::CommonInvalidStructure)
+            .map_err(map_err_err!())?;
+
+        let root_hash = txn.remove(ROOT_HASH);
+        let audit_path = txn.remove(AUDIT_PATH);
+        let seq_no = txn.get("txnMetadata").and_then(|metadata| metadata["seqNo"].as_u64());
+        (root_hash, audit_path, seq_no)
+    };
+
+    let root_hash = root_hash.as_ref()
+        .and_then(|root_hash| root_hash.as_str())
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())
+        .and_then(decode_hash)?;
+    let audit_path = audit_path.as_ref()
+        .and_then(|audit_path| audit_path.as_array())
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .map_err(map_err_err!())?
+        .iter()
+        .map(|hash| hash.as_str().ok_or(ErrorCode::CommonInvalidStructure).and_then(decode_hash))
+        .collect::<Result<Vec<Vec<u8>>, ErrorCode>>()?;
+    let seq_no = match seq_no {
+        Some(seq_no) if seq_no > 0 => seq_no,
+        _ => {
+            error!("Transaction has no valid seqNo");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+
+    let leaf_hash = leaf_hash(&txn)?;
+    if verify_inclusion(&leaf_hash, seq_no - 1, &audit_path, &root_hash)? {
+        Ok(())
+    } else {
+        error!("Audit path of transaction {:?} doesn't match root hash", seq_no);
+        Err(ErrorCode::LedgerSecurityError)
+    }
+}
+
+/**
+    Hash of the ledger leaf of `txn`
+*/
+pub fn leaf_hash(txn: &serde_json::Value) -> Result<Vec<u8>, ErrorCode> {
+    Ok(Hash::hash_leaf(&msgpack_serialize(txn))?.to_vec())
+}
+
+/**
+    Checks the audit path of the leaf `leaf_index` (0 based) in a ledger of `tree_size` leaves.
+
+    This is the algorithm of RFC 9162, section 2.1.3.2.
+*/
+pub fn verify_audit_path(leaf_hash: &[u8], leaf_index: u64, tree_size: u64, audit_path: &[Vec<u8>], root_hash: &[u8]) -> Result<bool, ErrorCode> {
+    if leaf_index >= tree_size {
+        return Ok(false);
+    }
+
+    let mut fn_ = leaf_index;
+    let mut sn = tree_size - 1;
+    let mut r = leaf_hash.to_vec();
+
+    for p in audit_path {
+        if sn == 0 {
+            return Ok(false);
+        }
+
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = Hash::hash_nodes(p, &r)?.to_vec();
+            if fn_ & 1 == 0 {
+                while fn_ & 1 == 0 && fn_ != 0 {
+                    fn_ >>= 1;
+                    sn >>= 1;
+                }
+            }
+        } else {
+            r = Hash::hash_nodes(&r, p)?.to_vec();
+        }
+
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+
+    Ok(sn == 0 && r.as_slice() == root_hash)
+}
+
+/**
+    Checks the audit path of the leaf `leaf_index` against every ledger size
+    giving a different path.
+
+    Above `leaf_index`, a size only changes the path by the level where the
+    ancestor of the leaf becomes the last node of its level.
+*/
+pub fn verify_inclusion(leaf_hash: &[u8], leaf_index: u64, audit_path: &[Vec<u8>], root_hash: &[u8]) -> Result<bool, ErrorCode> {
+    for level in 0..63 {
+        let last_index = ((leaf_index >> level) << level) | ((1u64 << level) - 1);
+        if verify_audit_path(leaf_hash, leaf_index, last_index + 1, audit_path, root_hash)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/**
+    Msgpack serialization of a json value with the keys of objects sorted,
+    like the ledger serializes its transactions.
+*/
+pub fn msgpack_serialize(value: &serde_json::Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_msgpack(value, &mut bytes);
+    bytes
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn decode_hash(hash: &str) -> Result<Vec<u8>, ErrorCode> {
+    let hash = hash.from_base58()
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    if hash.len() != HASH_OUTPUT_LEN {
+        error!("Invalid hash length, expected {:?}, real {:?}", HASH_OUTPUT_LEN, hash.len());
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    Ok(hash)
+}
+
+fn write_length(bytes: &mut Vec<u8>, len: usize, fix: u8, fix_max: usize, markers: [u8; 3]) {
+    if len <= fix_max {
+        bytes.push(fix | len as u8);
+    } else if markers[0] != 0 && len <= 0xff {
+        bytes.push(markers[0]);
+        bytes.push(len as u8);
+    } else if len <= 0xffff {
+        bytes.push(markers[1]);
+        bytes.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        bytes.push(markers[2]);
+        bytes.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn write_msgpack(value: &serde_json::Value, bytes: &mut Vec<u8>) {
+    match *value {
+        serde_json::Value::Null => bytes.push(0xc0),
+        serde_json::Value::Bool(false) => bytes.push(0xc2),
+        serde_json::Value::Bool(true) => bytes.push(0xc3),
+        serde_json::Value::Number(ref number) => {
+            if let Some(n) = number.as_u64() {
+                write_unsigned(n, bytes);
+            } else if let Some(n) = number.as_i64() {
+                write_negative(n, bytes);
+            } else if let Some(n) = number.as_f64() {
+                bytes.push(0xcb);
+                bytes.extend_from_slice(&n.to_bits().to_be_bytes());
+            }
+        }
+        serde_json::Value::String(ref s) => {
+            write_length(bytes, s.len(), 0xa0, 31, [0xd9, 0xda, 0xdb]);
+            bytes.extend_from_slice(s.as_bytes());
+        }
+        serde_json::Value::Array(ref array) => {
+            write_length(bytes, array.len(), 0x90, 15, [0, 0xdc, 0xdd]);
+            for item in array {
+                write_msgpack(item, bytes);
+            }
+        }
+        serde_json::Value::Object(ref object) => {
+            let mut entries: Vec<(&String, &serde_json::Value)> = object.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+
+            write_length(bytes, entries.len(), 0x80, 15, [0, 0xde, 0xdf]);
+            for (key, value) in entries {
+                write_msgpack(&serde_json::Value::String(key.clone()), bytes);
+                write_msgpack(value, bytes);
+            }
+        }
+    }
+}
+
+fn write_unsigned(n: u64, bytes: &mut Vec<u8>) {
+    if n <= 0x7f {
+        bytes.push(n as u8);
+    } else if n <= 0xff {
+        bytes.push(0xcc);
+        bytes.push(n as u8);
+    } else if n <= 0xffff {
+        bytes.push(0xcd);
+        bytes.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= 0xffff_ffff {
+        bytes.push(0xce);
+        bytes.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        bytes.push(0xcf);
+        bytes.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+fn write_negative(n: i64, bytes: &mut Vec<u8>) {
+    if n >= -32 {
+        bytes.push(n as i8 as u8);
+    } else if n >= -0x80 {
+        bytes.push(0xd0);
+        bytes.push(n as i8 as u8);
+    } else if n >= -0x8000 {
+        bytes.push(0xd1);
+        bytes.extend_from_slice(&(n as i16).to_be_bytes());
+    } else if n >= -0x8000_0000 {
+        bytes.push(0xd2);
+        bytes.extend_from_slice(&(n as i32).to_be_bytes());
+    } else {
+        bytes.push(0xd3);
+        bytes.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+
+#[cfg(test)]
+mod merkle_tests {
+    use super::*;
+    use utils::base58::IntoBase58;
+
+    /*
+        Merkle tree hash and audit path of RFC 6962, section 2.1
+    */
+    fn tree_hash(leaves: &[Vec<u8>]) -> Vec<u8> {
+        if leaves.len() == 1 {
+            return leaves[0].clone();
+        }
+        let k = split(leaves.len());
+        Hash::hash_nodes(&tree_hash(&leaves[..k]), &tree_hash(&leaves[k..])).unwrap().to_vec()
+    }
+
+    fn tree_path(index: usize, leaves: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        if leaves.len() == 1 {
+            return vec![];
+        }
+        let k = split(leaves.len());
+        if index < k {
+            let mut path = tree_path(index, &leaves[..k]);
+            path.push(tree_hash(&leaves[k..]));
+            path
+        } else {
+            let mut path = tree_path(index - k, &leaves[k..]);
+            path.push(tree_hash(&leaves[..k]));
+            path
+        }
+    }
+
+    fn split(n: usize) -> usize {
+        let mut k = 1;
+        while k * 2 < n {
+            k *= 2;
+        }
+        k
+    }
+
+    fn leaves(n: usize) -> Vec<Vec<u8>> {
+        (0..n).map(|i| Hash::hash_leaf(&vec![i as u8]).unwrap().to_vec()).collect()
+    }
+
+    fn payment_txn(seq_no: u64, amount: u64) -> serde_json::Value {
+        json!({
+            "txn": {
+                "type": "10001",
+                "protocolVersion": 2,
+                "data": {
+                    "inputs": [{"address": "dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q", "seqNo": 1}],
+                    "outputs": [{"address": "2jS4PHWQJKcawRxdW6GVsjnZBa1ecGdCssn7KhWYJZGTXgL7Es", "amount": amount}],
+                    "extra": null
+                },
+                "metadata": {"digest": "228af6a0c773cbbd575bf4e16f9144c2eaa615fa81fdcc3d06b83e20a92e5989", "from": "6baBEYA94sAphWBA5efEsaA6X2wCdyaH7PXuBtv2H5S1", "reqId": 1529682415342024u64}
+            },
+            "reqSignature": {"type": "ED25519", "values": [{"from": "dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q", "value": "4k6Y9CQ9iTE2U7BshhSVCu7rGSBYXP5rSaqDjonuvVVK5gQt9ctEoyBFgoYfu8ozL2B4LWxaLV2SRzWF9N1CBkQ"}]},
+            "txnMetadata": {"seqNo": seq_no, "txnTime": 1529682415},
+            "ver": "1"
+        })
+    }
+
+    /*
+        The reply to `txn` in a ledger of `size` transactions
+    */
+    fn with_proof(mut txn: serde_json::Value, size: usize) -> serde_json::Value {
+        let index = txn["txnMetadata"]["seqNo"].as_u64().unwrap() as usize - 1;
+        let mut leaves = leaves(size);
+        leaves[index] = leaf_hash(&txn).unwrap();
+
+        txn[ROOT_HASH] = json!(tree_hash(&leaves).into_base58());
+        txn[AUDIT_PATH] = json!(tree_path(index, &leaves).iter().map(|hash| hash.into_base58()).collect::<Vec<String>>());
+        txn
+    }
+
+    fn reply(result: serde_json::Value) -> String {
+        json!({"op": "REPLY", "protocolVersion": 2, "result": result}).to_string()
+    }
+
+    #[test]
+    fn msgpack_sorts_keys() {
+        let value = json!({"b": [true, null, -1, 300, "xy"], "a": 1});
+        let expected = vec![0x82, 0xa1, 0x61, 0x01, 0xa1, 0x62, 0x95, 0xc3, 0xc0, 0xff, 0xcd, 0x01, 0x2c, 0xa2, 0x78, 0x79];
+        assert_eq!(msgpack_serialize(&value), expected);
+    }
+
+    #[test]
+    fn msgpack_integers() {
+        assert_eq!(msgpack_serialize(&json!(127)), vec![0x7f]);
+        assert_eq!(msgpack_serialize(&json!(128)), vec![0xcc, 0x80]);
+        assert_eq!(msgpack_serialize(&json!(65536)), vec![0xce, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(msgpack_serialize(&json!(1529682415342024u64)), vec![0xcf, 0x00, 0x05, 0x6f, 0x3c, 0xf0, 0xfc, 0x51, 0xc8]);
+        assert_eq!(msgpack_serialize(&json!(-33)), vec![0xd0, 0xdf]);
+        assert_eq!(msgpack_serialize(&json!(-129)), vec![0xd1, 0xff, 0x7f]);
+    }
+
+    #[test]
+    fn msgpack_long_string() {
+        let s = "a".repeat(32);
+        let bytes = msgpack_serialize(&json!(s));
+        assert_eq!(&bytes[..2], &[0xd9, 32]);
+        assert_eq!(bytes.len(), 34);
+    }
+
+    #[test]
+    fn verify_audit_path_all_leaves() {
+        for size in 1..20 {
+            let leaves = leaves(size);
+            let root = tree_hash(&leaves);
+            for index in 0..size {
+                let path = tree_path(index, &leaves);
+                assert!(verify_audit_path(&leaves[index], index as u64, size as u64, &path, &root).unwrap(), "{} {}", size, index);
+                assert!(verify_inclusion(&leaves[index], index as u64, &path, &root).unwrap(), "{} {}", size, index);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_audit_path_wrong_leaf() {
+        let leaves = leaves(7);
+        let root = tree_hash(&leaves);
+        let path = tree_path(3, &leaves);
+
+        assert!(!verify_inclusion(&leaves[4], 3, &path, &root).unwrap());
+        assert!(!verify_inclusion(&leaves[3], 2, &path, &root).unwrap());
+        assert!(!verify_audit_path(&leaves[3], 7, 7, &path, &root).unwrap());
+    }
+
+    #[test]
+    fn verify_write_reply_valid() {
+        let result = with_proof(payment_txn(5, 10), 9);
+        verify_write_reply(&reply(result)).unwrap();
+    }
+
+    #[test]
+    fn verify_write_reply_forged_amount() {
+        let mut result = with_proof(payment_txn(5, 10), 9);
+        result["txn"]["data"]["outputs"][0]["amount"] = json!(1000);
+
+        assert_eq!(verify_write_reply(&reply(result)).unwrap_err(), ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn verify_write_reply_with_fees() {
+        let mut result = with_proof(payment_txn(5, 10), 9);
+        result[FEES] = with_proof(payment_txn(2, 3), 2);
+        verify_write_reply(&reply(result.clone())).unwrap();
+
+        result[FEES]["txnMetadata"]["seqNo"] = json!(1);
+        assert_eq!(verify_write_reply(&reply(result)).unwrap_err(), ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn verify_write_reply_without_proof() {
+        let result = payment_txn(5, 10);
+        assert_eq!(verify_write_reply(&reply(result)).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn verify_write_reply_ignores_rejections() {
+        verify_write_reply(r#"{"op": "REJECT", "reason": "some reason"}"#).unwrap();
+    }
+}
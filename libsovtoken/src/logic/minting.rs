@@ -5,7 +5,7 @@ use logic::config::output_mint_config::MintRequest;
 use logic::did::Did;
 use serde_json;
 use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
-use utils::ffi_support::{string_from_char_ptr};
+use utils::ffi_support::{string_from_char_ptr, c_pointer_from_string};
 use logic::output::Outputs;
 
 type DeserializedArguments = (Option<Did>, Outputs, Option<String>, JsonCallbackUnwrapped);
@@ -45,9 +45,17 @@ pub fn deserialize_inputs(
 
 pub fn build_mint_request(
     did: Option<Did>,
-    mut outputs: Outputs,
+    outputs: Outputs,
     extra: Option<String>,
 ) -> Result<*const c_char, ErrorCode> {
+    build_mint_request_json(did, outputs, extra).map(c_pointer_from_string)
+}
+
+pub fn build_mint_request_json(
+    did: Option<Did>,
+    mut outputs: Outputs,
+    extra: Option<String>,
+) -> Result<String, ErrorCode> {
     trace!("logic::minting::build_mint_request >> did: {:?}, outputs: {:?}", secret!(&did), secret!(&outputs));
 
     for output in &mut outputs {
@@ -59,11 +67,11 @@ pub fn build_mint_request(
     let mint_request = MintRequest::from_config(outputs, did, extra);
     info!("Built a mint request >>> {:?}", secret!(&mint_request));
 
-    let ptr = mint_request.serialize_to_pointer()
+    let json = mint_request.serialize_to_string()
         .or(Err(ErrorCode::CommonInvalidStructure));
 
-    trace!("logic::minting::build_mint_request << res: {:?}", ptr);
-    ptr
+    trace!("logic::minting::build_mint_request << res: {:?}", secret!(&json));
+    json
 }
 
 #[cfg(test)]
@@ -5,19 +5,37 @@
 // KEEP THESE IN ALPHABETICAL ORDER
 
 pub mod address;
+pub mod address_book;
 pub mod api_internals;
 pub mod build_payment;
+pub mod coin_selection;
 pub mod config;
+pub mod consolidation;
+pub mod detached_signing;
 pub mod did;
+pub mod fee_estimator;
 pub mod hash;
+pub mod hd_derivation;
+pub mod history;
+pub mod in_memory_crypto;
 pub mod indy_sdk_api;
 pub mod input;
+pub mod inspector;
+pub mod merkle;
 pub mod output;
 pub mod minting;
+pub mod multisig;
+pub mod payment_uri;
 pub mod payments;
 pub mod parsers;
+pub mod provenance;
 pub mod request;
+pub mod request_validator;
 pub mod set_fees;
+pub mod state_proof;
+pub mod token_amount;
 pub mod type_aliases;
+pub mod units;
+pub mod utxo_pager;
 pub mod verify;
 pub mod xfer_payload;
@@ -0,0 +1,455 @@
+/*!
+ * A [`PendingRequest`] is the request of [`build_mint_txn_handler`] or
+ * [`build_set_txn_fees_handler`] with the signatures of the trustees collected
+ * so far and the quorum it needs. It is exported as json, so each trustee can
+ * import it, read its [`summary`], sign its [`signing_message`] with the key of
+ * their DID and export it again with [`add_signature`]. Partial signature sets
+ * are combined with [`merge`], and [`into_request`] gives the request to submit
+ * once the quorum is reached.
+ *
+ * Every signature is checked against the verkey of its DID when it is added,
+ * imported or merged.
+ *
+ * [`PendingRequest`]: struct.PendingRequest.html
+ * [`summary`]: struct.PendingRequest.html#method.summary
+ * [`signing_message`]: struct.PendingRequest.html#method.signing_message
+ * [`add_signature`]: struct.PendingRequest.html#method.add_signature
+ * [`merge`]: struct.PendingRequest.html#method.merge
+ * [`into_request`]: struct.PendingRequest.html#method.into_request
+ * [`build_mint_txn_handler`]: ../../api/fn.build_mint_txn_handler.html
+ * [`build_set_txn_fees_handler`]: ../../api/fn.build_set_txn_fees_handler.html
+ */
+
+use serde_json;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use logic::address;
+use logic::config::set_fees_config::SetFeesMap;
+use logic::did::Did;
+use logic::in_memory_crypto::verify_signature;
+use logic::output::Outputs;
+use logic::type_aliases::{ReqId, TokenAmount};
+use logic::xfer_payload::serialize_signature;
+use utils::base58::{FromBase58, IntoBase58};
+use utils::constants::txn_types::{MINT_PUBLIC, SET_FEES};
+use utils::errors::SovtokenError;
+
+/**
+ * Request waiting for the signatures of trustees
+ *
+ * ```JSON
+ * {
+ *      "request": <object: request of build_mint_txn_handler or build_set_txn_fees_handler>,
+ *      "quorum": <int: signatures the request needs>,
+ *      "signatures": {
+ *          <str: did>: {"verkey": <str: verkey of the did>, "signature": <str: base58 signature>},
+ *      }
+ * }
+ * ```
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingRequest {
+    pub request: serde_json::Value,
+    pub quorum: usize,
+    #[serde(default)]
+    pub signatures: BTreeMap<String, TrusteeSignature>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrusteeSignature {
+    pub verkey: String,
+    pub signature: String,
+}
+
+/**
+ * What a pending request does, for the trustees to check before signing
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum RequestSummary {
+    /// `outputs` with qualified addresses
+    #[serde(rename_all = "camelCase")]
+    Mint { req_id: ReqId, outputs: Outputs, total: TokenAmount, extra: Option<String> },
+    /// The new fees, by alias
+    #[serde(rename_all = "camelCase")]
+    SetFees { req_id: ReqId, fees: BTreeMap<String, TokenAmount> },
+}
+
+/**
+ * How far a pending request is from its quorum
+ *
+ * ### Fields
+ * - `quorum` signatures the request needs
+ * - `signed_by` DIDs which signed it
+ * - `missing` signatures still needed, 0 once the quorum is reached
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuorumReport {
+    pub quorum: usize,
+    pub signed_by: Vec<String>,
+    pub missing: usize,
+}
+
+impl PendingRequest {
+    /**
+     * Exports a MINT_PUBLIC or SET_FEES request for `quorum` trustees to sign
+     *
+     * Errors with `CommonInvalidStructure` for another type of request, a
+     * request already signed or a quorum of 0.
+     */
+    pub fn new(request_json: &str, quorum: usize) -> Result<PendingRequest, SovtokenError> {
+        trace!("logic::multisig::PendingRequest::new >> request_json: {:?}, quorum: {}", secret!(request_json), quorum);
+        let request: serde_json::Value = serde_json::from_str(request_json)
+            .map_err(|err| SovtokenError::invalid_structure("request_json").with_source(err))?;
+
+        if request.get("signature").is_some() || request.get("signatures").is_some() {
+            return Err(SovtokenError::invalid_structure("request_json.signatures")
+                .with_source("the request is already signed, it has to be exported before signing it"));
+        }
+        if quorum == 0 {
+            return Err(SovtokenError::invalid_structure("quorum").with_value(quorum));
+        }
+
+        let pending = PendingRequest { request, quorum, signatures: BTreeMap::new() };
+        pending.summary()?;
+
+        trace!("logic::multisig::PendingRequest::new << result: {:?}", secret!(&pending));
+        Ok(pending)
+    }
+
+    /**
+     * Imports a pending request exported by [`to_json`], checking its signatures
+     *
+     * [`to_json`]: #method.to_json
+     */
+    pub fn from_json(pending_json: &str) -> Result<PendingRequest, SovtokenError> {
+        let pending: PendingRequest = serde_json::from_str(pending_json)
+            .map_err(|err| SovtokenError::invalid_structure("pending_json").with_source(err))?;
+
+        if pending.quorum == 0 {
+            return Err(SovtokenError::invalid_structure("pending_json.quorum").with_value(pending.quorum));
+        }
+        pending.summary()?;
+
+        let message = pending.signing_message()?;
+        for (did, signature) in &pending.signatures {
+            check_signature(did, signature, &message)?;
+        }
+
+        Ok(pending)
+    }
+
+    pub fn to_json(&self) -> Result<String, SovtokenError> {
+        serde_json::to_string(self)
+            .map_err(|err| SovtokenError::invalid_state("can't serialize the pending request").with_source(err))
+    }
+
+    /**
+     * The message each trustee signs with the key of their DID, the same as
+     * libindy's `multi_sign_request` signs
+     */
+    pub fn signing_message(&self) -> Result<String, SovtokenError> {
+        Ok(serialize_signature(self.request.clone())?)
+    }
+
+    pub fn summary(&self) -> Result<RequestSummary, SovtokenError> {
+        let req_id = self.request["reqId"].as_u64()
+            .ok_or_else(|| SovtokenError::invalid_structure("request.reqId").with_value(&self.request["reqId"]))?;
+        let operation = &self.request["operation"];
+
+        match operation["type"].as_str() {
+            Some(MINT_PUBLIC) => {
+                let mut outputs: Outputs = serde_json::from_value(operation["outputs"].clone())
+                    .map_err(|err| SovtokenError::invalid_structure("request.operation.outputs").with_source(err))?;
+
+                let mut total = TokenAmount::ZERO;
+                for output in &mut outputs {
+                    output.recipient = address::address_from_unqualified_address(&output.recipient)?;
+                    total = total.try_add(output.amount)?;
+                }
+
+                let extra = operation["extra"].as_str().map(String::from);
+                Ok(RequestSummary::Mint { req_id, outputs, total, extra })
+            }
+            Some(SET_FEES) => {
+                let fees: SetFeesMap = serde_json::from_value(operation["fees"].clone())
+                    .map_err(|err| SovtokenError::invalid_structure("request.operation.fees").with_source(err))?;
+                Ok(RequestSummary::SetFees { req_id, fees: fees.into_iter().collect() })
+            }
+            _ => Err(SovtokenError::invalid_structure("request.operation.type").with_value(&operation["type"])
+                .with_source(format!("only {} and {} requests are signed by trustees", MINT_PUBLIC, SET_FEES)))
+        }
+    }
+
+    /**
+     * Adds the signature of `did` to the request
+     *
+     * `verkey` is the verkey of `did`, full or abbreviated. Errors with
+     * `CommonInvalidStructure` when the verkey isn't the one of `did` or the
+     * signature doesn't verify.
+     */
+    pub fn add_signature(&mut self, did: &str, verkey: &str, signature: &str) -> Result<(), SovtokenError> {
+        trace!("logic::multisig::PendingRequest::add_signature >> did: {:?}, verkey: {:?}", did, verkey);
+        let signature = TrusteeSignature { verkey: verkey.to_string(), signature: signature.to_string() };
+        check_signature(did, &signature, &self.signing_message()?)?;
+
+        self.signatures.insert(did.to_string(), signature);
+        Ok(())
+    }
+
+    /**
+     * Adds the signatures of another export of the same request
+     *
+     * Errors with `CommonInvalidStructure` when `other` is another request or
+     * asks for another quorum.
+     */
+    pub fn merge(&mut self, other: PendingRequest) -> Result<(), SovtokenError> {
+        if other.request != self.request {
+            return Err(SovtokenError::invalid_structure("request")
+                .with_value(other.request["reqId"].clone())
+                .with_source("the pending requests are not the same request"));
+        }
+        if other.quorum != self.quorum {
+            return Err(SovtokenError::invalid_structure("quorum").with_value(other.quorum)
+                .with_source(format!("the request needs a quorum of {}", self.quorum)));
+        }
+
+        let message = self.signing_message()?;
+        for (did, signature) in other.signatures {
+            check_signature(&did, &signature, &message)?;
+            self.signatures.insert(did, signature);
+        }
+        Ok(())
+    }
+
+    pub fn quorum_report(&self) -> QuorumReport {
+        QuorumReport {
+            quorum: self.quorum,
+            signed_by: self.signatures.keys().cloned().collect(),
+            missing: self.quorum.saturating_sub(self.signatures.len()),
+        }
+    }
+
+    /**
+     * The request with the signatures of the trustees, ready to be submitted
+     *
+     * Errors with `CommonInvalidState` until the quorum is reached.
+     */
+    pub fn into_request(self) -> Result<String, SovtokenError> {
+        let missing = self.quorum_report().missing;
+        if missing > 0 {
+            return Err(SovtokenError::invalid_state(&format!("{} more signatures are needed", missing)));
+        }
+
+        let mut request = self.request;
+        let signatures: serde_json::Map<String, serde_json::Value> = self.signatures.into_iter()
+            .map(|(did, signature)| (did, json!(signature.signature)))
+            .collect();
+        request["signatures"] = json!(signatures);
+
+        Ok(request.to_string())
+    }
+}
+
+impl fmt::Display for RequestSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestSummary::Mint { req_id, ref outputs, total, ref extra } => {
+                writeln!(f, "MINT_PUBLIC request {}", req_id)?;
+                for output in outputs {
+                    writeln!(f, "  {} to {}", output.amount, output.recipient)?;
+                }
+                write!(f, "  {} in total", total)?;
+                if let Some(ref extra) = *extra {
+                    write!(f, "\n  extra: {}", extra)?;
+                }
+                Ok(())
+            }
+            RequestSummary::SetFees { req_id, ref fees } => {
+                write!(f, "SET_FEES request {}", req_id)?;
+                for (alias, amount) in fees {
+                    write!(f, "\n  {}: {}", alias, amount)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn check_signature(did: &str, signature: &TrusteeSignature, message: &str) -> Result<(), SovtokenError> {
+    let did_error = || SovtokenError::invalid_structure("did").with_value(did);
+
+    Did::new(did.to_string()).validate()
+        .map_err(|err| did_error().with_source(format!("{:?}", err)))?;
+    let did_bytes = did.from_base58().map_err(|err| did_error().with_source(err))?;
+
+    let verkey = full_verkey(&did_bytes, &signature.verkey)?;
+    if verkey.from_base58().ok().map_or(true, |bytes| !bytes.starts_with(&did_bytes)) {
+        return Err(SovtokenError::invalid_structure("verkey").with_value(&signature.verkey)
+            .with_source(format!("not the verkey of {}", did)));
+    }
+
+    let signature_bytes = signature.signature.from_base58()
+        .map_err(|err| SovtokenError::invalid_structure("signature").with_value(&signature.signature).with_source(err))?;
+
+    if !verify_signature(&verkey, message.as_bytes(), &signature_bytes)? {
+        return Err(SovtokenError::invalid_structure("signature").with_value(&signature.signature)
+            .with_source(format!("not a signature of the request by {}", did)));
+    }
+    Ok(())
+}
+
+/*
+    An abbreviated verkey `~<16 bytes>` is the end of the verkey of which the
+    DID is the beginning.
+*/
+fn full_verkey(did_bytes: &[u8], verkey: &str) -> Result<String, SovtokenError> {
+    if verkey.starts_with('~') {
+        let mut bytes = did_bytes.to_vec();
+        bytes.extend((&verkey[1..]).from_base58()
+            .map_err(|err| SovtokenError::invalid_structure("verkey").with_value(verkey).with_source(err))?);
+        Ok(bytes.into_base58())
+    } else {
+        Ok(verkey.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod multisig_tests {
+    use super::*;
+    use ErrorCode;
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use logic::in_memory_crypto::InMemoryCrypto;
+    use logic::minting::build_mint_request_json;
+    use logic::output::Output;
+
+    const DID_LEN: usize = 16;
+
+    struct Trustee {
+        crypto: InMemoryCrypto,
+        did: String,
+        verkey: String,
+    }
+
+    fn trustee(seed: &str) -> Trustee {
+        let crypto = InMemoryCrypto::new();
+        let verkey = crypto.create_key(&PaymentAddressConfig { seed: seed.to_string() }).unwrap();
+        let did = verkey.from_base58().unwrap()[..DID_LEN].to_vec().into_base58();
+        Trustee { crypto, did, verkey }
+    }
+
+    fn sign(trustee: &Trustee, pending: &PendingRequest) -> String {
+        let message = pending.signing_message().unwrap();
+        trustee.crypto.sign(&trustee.verkey, message.as_bytes()).unwrap().into_base58()
+    }
+
+    fn mint_request() -> String {
+        let outputs = vec![
+            Output::new("pay:sov:dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q".to_string(), 10),
+            Output::new("pay:sov:2mVXsXyVADzSDw88RAojPpdgxLPQyC1oJUqkrLeU5AdfEq2PmC".to_string(), 22),
+        ];
+        build_mint_request_json(None, outputs, Some("first mint".to_string())).unwrap()
+    }
+
+    #[test]
+    fn summary_of_mint() {
+        let pending = PendingRequest::new(&mint_request(), 2).unwrap();
+
+        match pending.summary().unwrap() {
+            RequestSummary::Mint { outputs, total, extra, .. } => {
+                assert_eq!(outputs[0].recipient, "pay:sov:dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q");
+                assert_eq!(total, 32);
+                assert_eq!(extra, Some("first mint".to_string()));
+            }
+            summary => panic!("{:?}", summary)
+        }
+        assert!(pending.summary().unwrap().to_string().contains("22 to pay:sov:2mVXsXyVADzSDw88RAojPpdgxLPQyC1oJUqkrLeU5AdfEq2PmC"));
+    }
+
+    #[test]
+    fn summary_of_set_fees() {
+        let request = json!({"operation": {"type": SET_FEES, "fees": {"3": 6, "1": 4}}, "reqId": 7, "protocolVersion": 2}).to_string();
+        let summary = PendingRequest::new(&request, 1).unwrap().summary().unwrap();
+
+        assert_eq!(summary.to_string(), "SET_FEES request 7\n  1: 4\n  3: 6");
+    }
+
+    #[test]
+    fn new_rejects_other_requests() {
+        let xfer = json!({"operation": {"type": "10001"}, "reqId": 7}).to_string();
+        let signed = json!({"operation": {"type": SET_FEES, "fees": {}}, "reqId": 7, "signatures": {}}).to_string();
+
+        assert_eq!(PendingRequest::new(&xfer, 1).unwrap_err().error_code(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(PendingRequest::new(&signed, 1).unwrap_err().error_code(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(PendingRequest::new(&mint_request(), 0).unwrap_err().error_code(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn sign_export_merge_and_submit() {
+        let trustees = [trustee("000000000000000000000000Trustee1"), trustee("000000000000000000000000Trustee2")];
+        let exported = PendingRequest::new(&mint_request(), 2).unwrap().to_json().unwrap();
+
+        let mut first = PendingRequest::from_json(&exported).unwrap();
+        let signature = sign(&trustees[0], &first);
+        first.add_signature(&trustees[0].did, &trustees[0].verkey, &signature).unwrap();
+        assert_eq!(first.quorum_report(), QuorumReport { quorum: 2, signed_by: vec![trustees[0].did.clone()], missing: 1 });
+        assert_eq!(first.clone().into_request().unwrap_err().error_code(), ErrorCode::CommonInvalidState);
+
+        let mut second = PendingRequest::from_json(&exported).unwrap();
+        let signature = sign(&trustees[1], &second);
+        let abbreviated = format!("~{}", trustees[1].verkey.from_base58().unwrap()[DID_LEN..].to_vec().into_base58());
+        second.add_signature(&trustees[1].did, &abbreviated, &signature).unwrap();
+
+        let mut merged = PendingRequest::from_json(&first.to_json().unwrap()).unwrap();
+        merged.merge(PendingRequest::from_json(&second.to_json().unwrap()).unwrap()).unwrap();
+        assert_eq!(merged.quorum_report().missing, 0);
+
+        let request: serde_json::Value = serde_json::from_str(&merged.into_request().unwrap()).unwrap();
+        assert_eq!(request["signatures"].as_object().unwrap().len(), 2);
+        assert_eq!(request["operation"]["type"], json!(MINT_PUBLIC));
+    }
+
+    #[test]
+    fn add_signature_of_another_did() {
+        let trustees = [trustee("000000000000000000000000Trustee1"), trustee("000000000000000000000000Trustee2")];
+        let mut pending = PendingRequest::new(&mint_request(), 2).unwrap();
+        let signature = sign(&trustees[0], &pending);
+
+        let err = pending.add_signature(&trustees[1].did, &trustees[0].verkey, &signature).unwrap_err();
+
+        assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure);
+        assert!(pending.signatures.is_empty());
+    }
+
+    #[test]
+    fn import_with_a_forged_signature() {
+        let trustees = [trustee("000000000000000000000000Trustee1"), trustee("000000000000000000000000Trustee2")];
+        let mut pending = PendingRequest::new(&mint_request(), 2).unwrap();
+        let signature = sign(&trustees[0], &pending);
+        pending.signatures.insert(trustees[1].did.clone(), TrusteeSignature { verkey: trustees[1].verkey.clone(), signature });
+
+        let err = PendingRequest::from_json(&pending.to_json().unwrap()).unwrap_err();
+
+        assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn merge_another_request() {
+        let mut pending = PendingRequest::new(&mint_request(), 2).unwrap();
+        let other = PendingRequest::new(&mint_request().replace("first mint", "second mint"), 2).unwrap();
+
+        assert_eq!(pending.merge(other).unwrap_err().error_code(), ErrorCode::CommonInvalidStructure);
+    }
+}
@@ -5,6 +5,7 @@
 use serde::{de, Deserialize};
 use std::fmt;
 use logic::type_aliases::TokenAmount;
+use logic::units::DenominatedAmount;
 
 pub type Outputs = Vec<Output>;
 
@@ -52,6 +53,20 @@ pub struct OutputConfig {
     let output = Output::from_json(json);
     ```
 
+    ## Amount with a unit
+    The amount can also be a string with the symbol of a denomination, see
+    [`logic::units`]. It is serialized back in sovatoms.
+
+    ```
+    use sovtoken::utils::json_conversion::JsonDeserialize;
+    use sovtoken::logic::output::Output;
+    let json = r#"{"address": "pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7", "amount": "1.5 SOV"}"#;
+    let output = Output::from_json(json).unwrap();
+    assert_eq!(output.amount, 150_000_000);
+    ```
+
+    [`logic::units`]: ../units/index.html
+
     # Serialization
     When Output is serialized, it is always serialized as an array:
 
@@ -73,8 +88,8 @@ pub struct Output {
 }
 
 impl Output {
-    pub fn new(address: String, amount: TokenAmount) -> Output {
-        return Output { recipient: address, amount };
+    pub fn new<A: Into<TokenAmount>>(address: String, amount: A) -> Output {
+        return Output { recipient: address, amount: amount.into() };
     }
 }
 
@@ -91,13 +106,13 @@ impl<'de> Deserialize<'de> for Output {
 
             fn visit_map<V: de::MapAccess<'de>>(self, mut map: V) -> Result<Output, V::Error> {
                 let mut address = None;
-                let mut amount = None;
+                let mut amount: Option<TokenAmount> = None;
 
-                while let Some(key) = map.next_key()? {
-                    match key {
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
                         "recipient" => { address = map.next_value()?; },
                         "address" => { address = map.next_value()?; },
-                        "amount" => { amount =  map.next_value()?; },
+                        "amount" => { amount = map.next_value::<Option<DenominatedAmount>>()?.map(|amount| amount.0); },
                         x => { return Err(de::Error::unknown_field(x, FIELDS)) }
                     }
                 }
@@ -161,6 +176,15 @@ mod output_tests {
         assert_valid_deserialize(json, expected);
     }
 
+    #[test]
+    fn deserialize_output_from_value() {
+        let json = json!({
+            "address": "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7",
+            "amount": 10
+        });
+        assert_eq!(serde_json::from_value::<Output>(json).unwrap(), output());
+    }
+
     #[test]
     fn deserialize_invalid_output_object() {
         let json = json!({
@@ -169,6 +193,21 @@ mod output_tests {
         assert_invalid_deserialize(json, "missing field `amount`");
     }
 
+    #[test]
+    fn deserialize_output_with_unit() {
+        let json = json!({
+            "address": "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7",
+            "amount": "0.0000001 SOV"
+        });
+        assert_valid_deserialize(json, output());
+
+        let json = json!({
+            "address": "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7",
+            "amount": "10"
+        });
+        assert_invalid_deserialize(json, "invalid value: string \"10\"");
+    }
+
     #[test]
     fn serialize_valid_output_object() {
         let output = output();
@@ -2,6 +2,7 @@
 
 use ErrorCode;
 use libc::c_char;
+use utils::errors::SovtokenError;
 use utils::ffi_support::string_from_char_ptr;
 use utils::constants::txn_fields::{RESULT, STATE_PROOF};
 use std::str;
@@ -26,7 +27,7 @@ pub enum ResponseOperations {
 
     used by [`ParsePaymentReply`], [`ParseResponseWithFeesReply`]
 */
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct UTXO {
     pub recipient: String,
@@ -182,30 +183,28 @@ pub struct NumericalSuffixAscendingNoGapsData {
     pub multi_signature: serde_json::Value,
 }
 
-pub fn extract_result_and_state_proof_from_node_reply(reply_from_node: *const c_char) -> Result<(SJsonValue, StateProof), ErrorCode> {
-    // TODO: The following errors should have messages
+pub fn extract_result_and_state_proof_from_node_reply(reply_from_node: *const c_char) -> Result<(SJsonValue, StateProof), SovtokenError> {
     let reply = match string_from_char_ptr(reply_from_node) {
         Some(r) => r,
-        None => return Err(ErrorCode::CommonInvalidStructure)
+        None => return Err(SovtokenError::invalid_structure("reply_from_node").with_source("not a valid C string"))
     };
 
     let json_reply: SJsonValue = serde_json::from_str::<SJsonValue>(&reply)
-        .or(Err(ErrorCode::CommonInvalidStructure))?;
+        .map_err(|err| SovtokenError::invalid_structure("reply_from_node").with_value(&reply).with_source(err))?;
 
     let result: SJsonValue = match json_reply.get(RESULT) {
         Some(r) => r.clone(),
-        None => return Err(ErrorCode::CommonInvalidStructure)
+        None => return Err(SovtokenError::invalid_structure(RESULT).with_source("missing"))
     };
 
     let state_proof = match result.get(STATE_PROOF) {
         Some(sp) => sp.to_owned(),
-        None => return Err(ErrorCode::CommonInvalidStructure)
+        None => return Err(SovtokenError::invalid_structure(&format!("{}.{}", RESULT, STATE_PROOF)).with_source("missing"))
     };
 
-    match serde_json::from_value(state_proof) {
-        Ok(s) => Ok((result, s)),
-        Err(_) => Err(ErrorCode::CommonInvalidStructure)
-    }
+    serde_json::from_value(state_proof.clone())
+        .map(|s| (result, s))
+        .map_err(|err| SovtokenError::invalid_structure(&format!("{}.{}", RESULT, STATE_PROOF)).with_value(state_proof).with_source(err))
 }
 
 #[cfg(test)]
@@ -220,7 +219,7 @@ mod common_tests {
         let return_error = extract_result_and_state_proof_from_node_reply(
             json_str_ptr).unwrap_err();
 
-        assert_eq!(return_error, ErrorCode::CommonInvalidStructure);
+        assert_eq!(return_error.error_code(), ErrorCode::CommonInvalidStructure);
     }
 
     #[test]
@@ -249,6 +248,15 @@ mod common_tests {
         test_invalid_json(invalid_json);
     }
 
+    #[test]
+    fn test_extraction_with_result_absent_has_field() {
+        let json_str = CString::new(r#"{ "op" : "REPLY"}"#).unwrap();
+
+        let error = extract_result_and_state_proof_from_node_reply(json_str.as_ptr()).unwrap_err();
+
+        assert_eq!(error, SovtokenError::invalid_structure(RESULT).with_source("missing"));
+    }
+
     #[test]
     fn test_extraction_with_state_proof_absent() {
         let invalid_json = r#"{ "op" : "REPLY", "result": {"reqId": 83955, "type": "20001", "identifier": "6ouriXMZkLeHsuXrN1X1fd", "fees": {"1": 4, "10001": 8}}}"#;
@@ -2,9 +2,10 @@
 //! json for the API
 
 pub mod common;
+pub mod parse_get_auth_rule_response;
 pub mod parse_get_utxo_response;
 pub mod parse_get_txn_fees;
 pub mod parse_payment_response;
 pub mod parse_response_with_fees_handler;
-pub mod error_code_parser;
+pub mod rejection_parser;
 pub mod parse_verify;
@@ -0,0 +1,220 @@
+//! types used to parse the reply of a GET_AUTH_RULE request
+
+use ErrorCode;
+use logic::parsers::common::ResponseOperations;
+use logic::parsers::rejection_parser;
+use logic::type_aliases::ProtocolVersion;
+use utils::json_conversion::JsonDeserialize;
+
+/**
+    Structure for parsing the GET_AUTH_RULE reply
+
+    # parameters
+    op - the operation type received
+    protocol_version - the protocol version of the format of the transaction
+    result - the payload containing the requested auth rules
+    reason - why the request was rejected
+*/
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseGetAuthRuleResponse {
+    pub op: ResponseOperations,
+    pub protocol_version: Option<ProtocolVersion>,
+    pub result: Option<ParseGetAuthRuleResult>,
+    pub reason: Option<String>,
+}
+
+/**
+    Structure of the result value within the GET_AUTH_RULE reply
+
+    # parameters
+    txn_type - the type of the request, "121"
+    data - the auth rules, a single one when the request asked for a specific action
+*/
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ParseGetAuthRuleResult {
+    #[serde(rename = "type")]
+    pub txn_type: String,
+    pub data: Vec<AuthRule>,
+}
+
+/**
+    An auth rule as stored on the ledger
+
+    `old_value` is only present for `EDIT` actions.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuthRule {
+    pub auth_type: String,
+    pub auth_action: String,
+    pub field: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<String>,
+    #[serde(default)]
+    pub new_value: Option<String>,
+    pub constraint: Constraint,
+}
+
+/**
+    Auth constraint of an [`AuthRule`], tagged by its `constraint_id`
+
+    [`AuthRule`]: struct.AuthRule.html
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "constraint_id")]
+pub enum Constraint {
+    #[serde(rename = "ROLE")]
+    Role(RoleConstraint),
+    #[serde(rename = "AND")]
+    And(CombinationConstraint),
+    #[serde(rename = "OR")]
+    Or(CombinationConstraint),
+    #[serde(rename = "FORBIDDEN")]
+    Forbidden(ForbiddenConstraint),
+}
+
+/**
+    `ROLE` constraint, the leaf of a constraint tree
+
+    The fees alias the action costs is in `metadata.fees`.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoleConstraint {
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub sig_count: u32,
+    #[serde(default)]
+    pub need_to_be_owner: bool,
+    #[serde(default)]
+    pub off_ledger_signature: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ConstraintMetadata>,
+}
+
+/**
+    `AND` and `OR` constraints
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CombinationConstraint {
+    pub auth_constraints: Vec<Constraint>,
+}
+
+/**
+    `FORBIDDEN` constraint, nobody can perform the action
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ForbiddenConstraint {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConstraintMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<String>,
+}
+
+/**
+    Returns the auth rules of a GET_AUTH_RULE reply
+*/
+pub fn parse_auth_rules_from_get_auth_rule_response(response: &str) -> Result<Vec<AuthRule>, ErrorCode> {
+    trace!("logic::parsers::parse_auth_rules_from_get_auth_rule_response >> response: {:?}", response);
+    let response = ParseGetAuthRuleResponse::from_json(response)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    let res = match response.op {
+        ResponseOperations::REPLY => {
+            response.result
+                .map(|result| result.data)
+                .ok_or(ErrorCode::CommonInvalidStructure)
+        }
+        ResponseOperations::REQNACK | ResponseOperations::REJECT => {
+            let reason = response.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
+            Err(rejection_parser::error_code_from_reason(&reason))
+        }
+    };
+
+    trace!("logic::parsers::parse_auth_rules_from_get_auth_rule_response << result: {:?}", res);
+    res
+}
+
+
+#[cfg(test)]
+mod parse_get_auth_rule_response_tests {
+    use super::*;
+
+    const GET_AUTH_RULE_REPLY: &str = r#"{
+        "op": "REPLY",
+        "result": {
+            "type": "121",
+            "reqId": 15550536,
+            "identifier": "L5AD5g65TDQr1PPHHRoiGf",
+            "data": [
+                {
+                    "auth_type": "1",
+                    "auth_action": "ADD",
+                    "field": "role",
+                    "new_value": "2",
+                    "constraint": {
+                        "constraint_id": "OR",
+                        "auth_constraints": [
+                            {"constraint_id": "ROLE", "role": "0", "sig_count": 1, "need_to_be_owner": false, "metadata": {}},
+                            {"constraint_id": "ROLE", "role": "2", "sig_count": 5, "need_to_be_owner": false, "metadata": {"fees": "add_new_steward"}}
+                        ]
+                    }
+                },
+                {
+                    "auth_type": "1",
+                    "auth_action": "EDIT",
+                    "field": "verkey",
+                    "old_value": "*",
+                    "new_value": "*",
+                    "constraint": {"constraint_id": "FORBIDDEN"}
+                }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn parse_auth_rules() {
+        let rules = parse_auth_rules_from_get_auth_rule_response(GET_AUTH_RULE_REPLY).unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].auth_action, "ADD");
+        assert_eq!(rules[0].old_value, None);
+        assert_eq!(rules[0].new_value, Some("2".to_string()));
+
+        match rules[0].constraint {
+            Constraint::Or(ref or) => {
+                assert_eq!(or.auth_constraints.len(), 2);
+                match or.auth_constraints[1] {
+                    Constraint::Role(ref role) => {
+                        assert_eq!(role.role, Some("2".to_string()));
+                        assert_eq!(role.sig_count, 5);
+                        assert_eq!(role.metadata, Some(ConstraintMetadata { fees: Some("add_new_steward".to_string()) }));
+                    },
+                    ref c => panic!("Unexpected constraint {:?}", c)
+                }
+            },
+            ref c => panic!("Unexpected constraint {:?}", c)
+        }
+
+        assert_eq!(rules[1].old_value, Some("*".to_string()));
+        assert_eq!(rules[1].constraint, Constraint::Forbidden(ForbiddenConstraint {}));
+    }
+
+    #[test]
+    fn parse_auth_rules_rejected() {
+        let response = r#"{"op": "REJECT", "reason": "client request invalid: InvalidClientRequest()"}"#;
+        let err = parse_auth_rules_from_get_auth_rule_response(response).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn parse_auth_rules_unknown_constraint() {
+        let response = r#"{"op": "REPLY", "result": {"type": "121", "data": [
+            {"auth_type": "1", "auth_action": "ADD", "field": "role", "new_value": "2", "constraint": {"constraint_id": "UNKNOWN"}}
+        ]}}"#;
+        let err = parse_auth_rules_from_get_auth_rule_response(response).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+}
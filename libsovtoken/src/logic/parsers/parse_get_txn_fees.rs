@@ -10,11 +10,20 @@ use libc::c_char;
 use logic::parsers::common::{ResponseOperations, StateProof,
                              extract_result_and_state_proof_from_node_reply,
                              KeyValuesInSP, KeyValueSimpleData, ParsedSP};
+use utils::errors::{self, SovtokenError};
 use utils::json_conversion::JsonDeserialize;
 use utils::ffi_support::c_pointer_from_string;
 use utils::constants::{general::FEES_STATE_KEY, txn_fields::FEES};
 use logic::type_aliases::{ProtocolVersion, TokenAmount, ReqId};
 use logic::parsers::common::KeyValueSimpleDataVerificationType;
+use logic::state_proof::freshness::{self, FreshnessSettings, StaleProof};
+
+/**
+    Key of the state proof timestamp in the output of [`fees_json`].
+
+    [`fees_json`]: fn.fees_json.html
+*/
+pub const PROOF_TIMESTAMP: &str = "proofTimestamp";
 
 /**
     Structure for parsing GET_FEES request
@@ -61,20 +70,59 @@ pub fn parse_fees_from_get_txn_fees_response(response : String) -> Result<String
     trace!("logic::parsers::parse_fees_from_get_txn_fees_response >> response: {:?}", response);
     let fees_response : ParseGetTxnFeesResponse =
             ParseGetTxnFeesResponse::from_json(&response).map_err(map_err_err!())?;
-    let res = serde_json::to_string(&fees_response.result.fees).map_err(map_err_err!());
+    let res = fees_json(&fees_response);
     trace!("logic::parsers::parse_fees_from_get_txn_fees_response << result: {:?}", res);
     return res;
 }
 
+/**
+    The fees of a reply as `{<alias>: <amount>}`, with the time its state proof
+    was signed under [`PROOF_TIMESTAMP`].
+
+    [`PROOF_TIMESTAMP`]: constant.PROOF_TIMESTAMP.html
+*/
+pub fn fees_json(fees_response: &ParseGetTxnFeesResponse) -> Result<String, Error> {
+    let mut fees = serde_json::to_value(&fees_response.result.fees).map_err(map_err_err!())?;
+    let proof_timestamp = fees_response.result.state_proof.as_ref().and_then(freshness::proof_timestamp);
+    if let (Some(timestamp), Some(fees)) = (proof_timestamp, fees.as_object_mut()) {
+        fees.insert(PROOF_TIMESTAMP.to_string(), json!(timestamp));
+    }
+    serde_json::to_string(&fees).map_err(map_err_err!())
+}
+
+/**
+    Checks the state proof of a reply against `settings`, returns the time it
+    was signed.
+*/
+pub fn check_freshness(fees_response: &ParseGetTxnFeesResponse, settings: Option<&FreshnessSettings>) -> Result<Option<u64>, StaleProof> {
+    freshness::check_proof_freshness(fees_response.result.state_proof.as_ref(), settings)
+}
+
 pub fn get_fees_state_proof_extractor(reply_from_node: *const c_char, parsed_sp: *mut *const c_char) -> ErrorCode {
-    // TODO: The following errors should have logs
-    let (result, state_proof) = match extract_result_and_state_proof_from_node_reply(reply_from_node) {
-        Ok((r, s)) => (r, s),
-        Err(_) => return ErrorCode::CommonInvalidStructure
-    };
+    match get_fees_parsed_state_proof(reply_from_node) {
+        Ok(s) => {
+            trace!("JSON representation of ParsedSP for get fees {:?}", &s);
+            unsafe { *parsed_sp = c_pointer_from_string(s); }
+            ErrorCode::Success
+        },
+        Err(err) => {
+            error!("Could not extract the state proof of a get fees reply: {}", err);
+            errors::to_error_code(err)
+        }
+    }
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn get_fees_parsed_state_proof(reply_from_node: *const c_char) -> Result<String, SovtokenError> {
+    let (result, state_proof) = extract_result_and_state_proof_from_node_reply(reply_from_node)?;
     let fees = match result.get(FEES) {
         Some(f) => f.to_owned(),
-        None => return ErrorCode::CommonInvalidStructure
+        None => return Err(SovtokenError::invalid_structure(FEES).with_source("missing"))
     };
 
     // TODO: Make sure JSON serialisation preserves order
@@ -82,18 +130,12 @@ pub fn get_fees_state_proof_extractor(reply_from_node: *const c_char, parsed_sp:
         kvs: vec![(base64::encode(FEES_STATE_KEY), Some(fees.to_string()))],
         verification_type: KeyValueSimpleDataVerificationType::Simple
     });
-    let proof_nodes = match state_proof.proof_nodes {
-        Some(o) => o,
-        None => return ErrorCode::CommonInvalidStructure
-    };
-    let root_hash = match state_proof.root_hash {
-        Some(o) => o,
-        None => return ErrorCode::CommonInvalidStructure
-    };
-    let multi_signature = match state_proof.multi_signature {
-        Some(o) => o,
-        None => return ErrorCode::CommonInvalidStructure
-    };
+    let proof_nodes = state_proof.proof_nodes
+        .ok_or_else(|| SovtokenError::invalid_structure("state_proof.proof_nodes").with_source("missing"))?;
+    let root_hash = state_proof.root_hash
+        .ok_or_else(|| SovtokenError::invalid_structure("state_proof.root_hash").with_source("missing"))?;
+    let multi_signature = state_proof.multi_signature
+        .ok_or_else(|| SovtokenError::invalid_structure("state_proof.multi_signature").with_source("missing"))?;
 
     let sp = vec![ParsedSP {
         proof_nodes,
@@ -102,14 +144,8 @@ pub fn get_fees_state_proof_extractor(reply_from_node: *const c_char, parsed_sp:
         multi_signature,
     }];
 
-    match serde_json::to_string(&sp) {
-        Ok(s) => {
-            trace!("JSON representation of ParsedSP for get fees {:?}", &s);
-            unsafe { *parsed_sp = c_pointer_from_string(s); }
-            return ErrorCode::Success;
-        },
-        Err(_) => return ErrorCode::CommonInvalidStructure
-    }
+    serde_json::to_string(&sp)
+        .map_err(|err| SovtokenError::invalid_state("could not serialize the parsed state proof").with_source(err))
 }
 
 #[cfg(test)]
@@ -157,6 +193,29 @@ mod parse_fees_responses_test {
         assert_eq!(parsed_fees_json, expected_json, "The json objects don't match");
     }
 
+    #[test]
+    fn parse_fees_with_proof_timestamp() {
+        let get_fees_response = json!({
+            "op": "REPLY",
+            "result": {
+                "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+                "reqId": 47660,
+                "type": "20001",
+                "fees": {"1": 4, "10001": 8},
+                "state_proof": {
+                    "multi_signature": {"value": {"timestamp": 1530059419}},
+                    "root_hash": "5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms",
+                    "proof_nodes": "29qFIGZlZXOT0pF7IjEiOjQsIjEwMDAxIjo4fQ=="
+                }
+            }
+        });
+
+        let fees_json = parse_fees_from_get_txn_fees_response(get_fees_response.to_string()).unwrap();
+        let parsed_fees_json: Value = serde_json::from_str(&fees_json).unwrap();
+
+        assert_eq!(parsed_fees_json, json!({"1": 4, "10001": 8, "proofTimestamp": 1530059419}));
+    }
+
     #[test]
     fn failure_parse_fees_from_reply_response() {
         let invalid_json_response =
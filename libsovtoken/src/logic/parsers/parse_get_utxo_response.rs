@@ -4,11 +4,13 @@ use ErrorCode;
 use libc::c_char;
 use logic::parsers::common::{ResponseOperations, TXO, StateProof, ParsedSP, KeyValuesInSP,
                              KeyValueSimpleData, extract_result_and_state_proof_from_node_reply};
-use logic::parsers::error_code_parser;
+use logic::parsers::rejection_parser;
 use logic::type_aliases::{TokenAmount, TxnSeqNo, ProtocolVersion, ReqId};
 use logic::address;
+use logic::state_proof::freshness::{self, FreshnessSettings, StaleProof};
 use serde_json;
 use utils::constants::txn_fields::OUTPUTS;
+use utils::errors::{self, SovtokenError};
 use utils::ffi_support::c_pointer_from_string;
 use logic::parsers::common::KeyValueSimpleDataVerificationType;
 use utils::constants::txn_fields::{FROM, NEXT, ADDRESS};
@@ -16,7 +18,7 @@ use logic::parsers::common::NumericalSuffixAscendingNoGapsData;
 
 type UTXOs = Vec<UTXOInner>;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct UTXOInner {
     pub address: String,
@@ -50,6 +52,8 @@ pub struct ParseGetUtxoResponseResult {
     pub identifier: String,
     pub req_id : ReqId,
     pub outputs : UTXOs,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from : Option<u64>,
     pub next : Option<u64>,
     #[serde(rename = "state_proof", skip_serializing_if = "Option::is_none")]
     pub state_proof : Option<StateProof>
@@ -69,6 +73,8 @@ pub struct UTXO {
     pub source: String,
     pub amount: TokenAmount,
     pub extra: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof_timestamp: Option<u64>,
 }
 
 
@@ -86,13 +92,14 @@ pub fn from_response(base : ParseGetUtxoResponse) -> Result<ParseGetUtxoReply, E
     match base.op {
         ResponseOperations::REPLY => {
             let result = base.result.ok_or(ErrorCode::CommonInvalidStructure)?;
+            let proof_timestamp = result.state_proof.as_ref().and_then(freshness::proof_timestamp);
             let mut utxos: Vec<UTXO> = vec![];
 
             for unspent_output in result.outputs {
 
                 let payment_address = address::address_from_unqualified_address(&result.address.to_string())?;
                 let txo = (TXO { address: payment_address.clone(), seq_no: unspent_output.seq_no }).to_libindy_string()?;
-                let utxo: UTXO = UTXO { payment_address, source: txo, amount: unspent_output.amount, extra: "".to_string() };
+                let utxo: UTXO = UTXO { payment_address, source: txo, amount: unspent_output.amount, extra: "".to_string(), proof_timestamp };
 
                 utxos.push(utxo);
             }
@@ -101,66 +108,76 @@ pub fn from_response(base : ParseGetUtxoResponse) -> Result<ParseGetUtxoReply, E
         }
         ResponseOperations::REQNACK | ResponseOperations::REJECT => {
             let reason = base.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
-            Err(error_code_parser::parse_error_code_from_string(&reason))
+            Err(rejection_parser::error_code_from_reason(&reason))
         }
     }
 }
 
+/**
+    Checks the state proof of a reply against `settings`, returns the time it
+    was signed.
+*/
+pub fn check_freshness(base: &ParseGetUtxoResponse, settings: Option<&FreshnessSettings>) -> Result<Option<u64>, StaleProof> {
+    match (base.op, base.result.as_ref()) {
+        (ResponseOperations::REPLY, Some(result)) => freshness::check_proof_freshness(result.state_proof.as_ref(), settings),
+        _ => Ok(None)
+    }
+}
+
 // Assumes a valid address. The delimeter `:` has to be the same as used on ledger
 pub fn get_utxo_state_key(address: &str, seq_no: TxnSeqNo) -> String {
     format!("{}:{}", address, seq_no)
 }
 
 pub fn get_utxo_state_proof_extractor(reply_from_node: *const c_char, parsed_sp: *mut *const c_char) -> ErrorCode {
-    // TODO: The following errors should have logs
-    let (result, state_proof) = match extract_result_and_state_proof_from_node_reply(reply_from_node) {
-        Ok((r, s)) => (r, s),
-        Err(_) => return ErrorCode::CommonInvalidStructure
-    };
+    match get_utxo_parsed_state_proof(reply_from_node) {
+        Ok(s) => {
+            trace!("JSON representation of ParsedSP for get utxo {:?}", &s);
+            unsafe { *parsed_sp = c_pointer_from_string(s); }
+            ErrorCode::Success
+        },
+        Err(err) => {
+            error!("Could not extract the state proof of a get utxo reply: {}", err);
+            errors::to_error_code(err)
+        }
+    }
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn get_utxo_parsed_state_proof(reply_from_node: *const c_char) -> Result<String, SovtokenError> {
+    let (result, state_proof) = extract_result_and_state_proof_from_node_reply(reply_from_node)?;
     // TODO: No validation of outputs being done. This has to fixed by creating an `Address` with
     // a single private field called `address` and with implementation defining `new` and a getter.
     // The `new` method will do the validation.
 
     let outputs: UTXOs = match result.get(OUTPUTS) {
-        Some(outs) => {
-            let outputs: UTXOs = match serde_json::from_value(outs.to_owned()) {
-                Ok(o) => o,
-                Err(_) => return ErrorCode::CommonInvalidStructure
-            };
-            outputs
-        },
-        None => return ErrorCode::CommonInvalidStructure
+        Some(outs) => serde_json::from_value(outs.to_owned())
+            .map_err(|err| SovtokenError::invalid_structure(OUTPUTS).with_value(outs).with_source(err))?,
+        None => return Err(SovtokenError::invalid_structure(OUTPUTS).with_source("missing"))
     };
 
     let from: Option<u64> = match result.get(FROM) {
-        Some(from) => {
-            let from: u64 = match serde_json::from_value(from.to_owned()) {
-                Ok(o) => o,
-                Err(_) => return ErrorCode::CommonInvalidStructure
-            };
-            Some(from)
-        },
+        Some(from) => Some(serde_json::from_value(from.to_owned())
+            .map_err(|err| SovtokenError::invalid_structure(FROM).with_value(from).with_source(err))?),
         None => None
     };
     let next: Option<u64> = match result.get(NEXT) {
-        Some(next) => {
-            let next: u64 = match serde_json::from_value(next.to_owned()) {
-                Ok(o) => o,
-                Err(_) => return ErrorCode::CommonInvalidStructure
-            };
-            Some(next)
-        },
+        Some(next) => Some(serde_json::from_value(next.to_owned())
+            .map_err(|err| SovtokenError::invalid_structure(NEXT).with_value(next).with_source(err))?),
         None => None
     };
     let prefix: String = match result.get(ADDRESS) {
         Some(prefix) => {
-            let prefix: String = match serde_json::from_value(prefix.to_owned()) {
-                Ok(p) => p,
-                Err(_) => return ErrorCode::CommonInvalidStructure
-            };
+            let prefix: String = serde_json::from_value(prefix.to_owned())
+                .map_err(|err| SovtokenError::invalid_structure(ADDRESS).with_value(prefix).with_source(err))?;
             prefix + ":"
         },
-        None => return ErrorCode::CommonInvalidStructure
+        None => return Err(SovtokenError::invalid_structure(ADDRESS).with_source("missing"))
     };
 
     let mut kvs: Vec<(String, Option<String>)> = Vec::new();
@@ -174,18 +191,12 @@ pub fn get_utxo_state_proof_extractor(reply_from_node: *const c_char, parsed_sp:
         kvs,
         verification_type: KeyValueSimpleDataVerificationType::NumericalSuffixAscendingNoGaps(NumericalSuffixAscendingNoGapsData {from, next, prefix})
     });
-    let proof_nodes = match state_proof.proof_nodes {
-        Some(o) => o,
-        None => return ErrorCode::CommonInvalidStructure
-    };
-    let root_hash = match state_proof.root_hash {
-        Some(o) => o,
-        None => return ErrorCode::CommonInvalidStructure
-    };
-    let multi_signature = match state_proof.multi_signature {
-        Some(o) => o,
-        None => return ErrorCode::CommonInvalidStructure
-    };
+    let proof_nodes = state_proof.proof_nodes
+        .ok_or_else(|| SovtokenError::invalid_structure("state_proof.proof_nodes").with_source("missing"))?;
+    let root_hash = state_proof.root_hash
+        .ok_or_else(|| SovtokenError::invalid_structure("state_proof.root_hash").with_source("missing"))?;
+    let multi_signature = state_proof.multi_signature
+        .ok_or_else(|| SovtokenError::invalid_structure("state_proof.multi_signature").with_source("missing"))?;
 
     let sp = vec![ParsedSP {
         proof_nodes,
@@ -194,14 +205,8 @@ pub fn get_utxo_state_proof_extractor(reply_from_node: *const c_char, parsed_sp:
         multi_signature,
     }];
 
-    match serde_json::to_string(&sp) {
-        Ok(s) => {
-            trace!("JSON representation of ParsedSP for get utxo {:?}", &s);
-            unsafe { *parsed_sp = c_pointer_from_string(s); }
-            return ErrorCode::Success;
-        },
-        Err(_) => return ErrorCode::CommonInvalidStructure
-    }
+    serde_json::to_string(&sp)
+        .map_err(|err| SovtokenError::invalid_state("could not serialize the parsed state proof").with_source(err))
 }
 
 #[cfg(test)]
@@ -280,12 +285,12 @@ mod parse_get_utxo_responses_tests {
         outputs.push(UTXOInner {
             address: rand_string(32),
             seq_no: 1,
-            amount: 10
+            amount: TokenAmount::new(10)
         });
         outputs.push(UTXOInner {
             address: rand_string(32),
             seq_no: 2,
-            amount: 20
+            amount: TokenAmount::new(20)
         });
 
         let outputs_len: usize = outputs.len();
@@ -296,6 +301,7 @@ mod parse_get_utxo_responses_tests {
             identifier,
             req_id: 123457890,
             outputs,
+            from: None,
             next: None,
             state_proof: Some(state_proof)
         };
@@ -311,7 +317,7 @@ mod parse_get_utxo_responses_tests {
 
         assert!(next.is_none());
         assert_eq!(outputs_len, reply.len());
-
+        assert!(reply.iter().all(|utxo| utxo.proof_timestamp == Some(1529705683)));
     }
 
     #[test]
@@ -346,6 +352,7 @@ mod parse_get_utxo_responses_tests {
             identifier,
             req_id: 123457890,
             outputs,
+            from: None,
             next: None,
             state_proof: Some(state_proof)
         };
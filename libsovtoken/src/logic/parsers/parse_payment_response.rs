@@ -9,7 +9,7 @@ use logic::parsers::common::{ResponseOperations,
                              TXO,
                              TransactionMetaData,
                              RequireSignature};
-use logic::parsers::error_code_parser;
+use logic::parsers::rejection_parser;
 use logic::type_aliases::ProtocolVersion;
 use logic::xfer_payload::Extra;
 
@@ -107,7 +107,7 @@ pub fn from_response(base: ParsePaymentResponse) -> Result<ParsePaymentReply, Er
         }
         ResponseOperations::REJECT | ResponseOperations::REQNACK => {
             let reason = base.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
-            Err(error_code_parser::parse_error_code_from_string(&reason))
+            Err(rejection_parser::error_code_from_reason(&reason))
         }
     }
 }
@@ -8,7 +8,7 @@ use logic::parsers::common::{ResponseOperations,
                              TXO,
                              TransactionMetaData,
                              RequireSignature};
-use logic::parsers::error_code_parser;
+use logic::parsers::rejection_parser;
 use ErrorCode;
 use logic::type_aliases::{ProtocolVersion, TokenAmount, TxnSeqNo, TxnVersion};
 
@@ -136,7 +136,7 @@ pub fn from_response(base : ParseResponseWithFees) -> Result<Option<ParseRespons
         }
         ResponseOperations::REQNACK | ResponseOperations::REJECT => {
             let reason = base.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
-            Err(error_code_parser::parse_error_code_from_string(&reason))
+            Err(rejection_parser::error_code_from_reason(&reason))
         }
     }
 
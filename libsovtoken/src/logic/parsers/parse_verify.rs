@@ -33,19 +33,43 @@ pub struct ParseVerifyResponseResult {
 #[serde(rename_all = "camelCase")]
 pub struct ParseVerifyResponseResultData {
     pub txn: Option<ParseVerifyResponseResultDataTxn>,
-    pub txn_metadata: Option<TxnMetadata>
+    pub txn_metadata: Option<TxnMetadata>,
+    pub req_signature: Option<ReqSignature>
+}
+
+/**
+    Signatures of the request of a transaction, one per input for transfers
+*/
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReqSignature {
+    #[serde(rename = "type")]
+    pub sig_type: Option<String>,
+    #[serde(default)]
+    pub values: Vec<ReqSignatureValue>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReqSignatureValue {
+    pub from: String,
+    pub value: String
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TxnMetadata {
-    pub seq_no: TxnSeqNo
+    pub seq_no: TxnSeqNo,
+    pub txn_time: Option<u64>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ParseVerifyResponseResultDataTxn {
-    pub data: Option<ParseVerifyResponseResultDataTxnData>
+    #[serde(rename = "type")]
+    pub txn_type: Option<String>,
+    pub data: Option<ParseVerifyResponseResultDataTxnData>,
+    pub metadata: Option<serde_json::Value>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -53,7 +77,8 @@ pub struct ParseVerifyResponseResultDataTxn {
 pub struct ParseVerifyResponseResultDataTxnData {
     pub outputs: Option<Outputs>,
     pub inputs: Option<Inputs>,
-    pub extra: Option<Extra>
+    pub extra: Option<Extra>,
+    pub fees: Option<serde_json::Value>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -64,7 +89,7 @@ pub struct VerifyResult {
     pub extra: Option<String>
 }
 
-fn parse_verify(resp: &str) -> Result<VerifyResult, ErrorCode> {
+pub fn parse_verify(resp: &str) -> Result<VerifyResult, ErrorCode> {
     let p: ParseVerifyResponse = serde_json::from_str(resp)
         .map_err(map_err_err!())
         .map_err(|_| ErrorCode::CommonInvalidStructure)?;
@@ -0,0 +1,386 @@
+/*!
+    Parses the `reason` of REJECT and REQNACK replies
+
+    The token plugin and plenum reject requests with the message of the python
+    exception, e.g.
+    `InsufficientFundsError("Insufficient funds, sum of inputs is 10 but required amount: 12. sum of outputs: 12")`.
+    [`parse_rejection`] maps the reason to an `ErrorCode` and extracts what
+    went wrong into [`RejectionDetails`], which is kept as the current error,
+    see [`sovtoken_get_current_error`].
+
+    [`parse_rejection`]: fn.parse_rejection.html
+    [`RejectionDetails`]: struct.RejectionDetails.html
+    [`sovtoken_get_current_error`]: ../../../api/fn.sovtoken_get_current_error.html
+*/
+
+use ErrorCode;
+use logic::type_aliases::TokenAmount;
+use utils::errors::{self, SovtokenError};
+
+const INSUFFICIENT_FUNDS_ERROR: &str = "InsufficientFundsError";
+const EXTRA_FUNDS_ERROR: &str = "ExtraFundsError";
+const INVALID_FUNDS: &str = "InvalidFundsError";
+
+const TXO_PREFIX: &str = "txo:sov:";
+const TAA: &str = "txn author agreement";
+
+/**
+    Why the ledger rejected a request, as far as the reason tells
+
+    ### Fields
+    - `reason` the reason as received
+    - `error` name of the exception, e.g. `InsufficientFundsError`
+    - `spent_utxos` inputs which are spent or don't exist, as txos or `<address>:<seqNo>`
+    - `required_amount` tokens the outputs and fees require
+    - `provided_amount` tokens of the inputs
+    - `missing_signatures` when signatures are missing or invalid
+    - `taa` problem with the acceptance of the transaction author agreement
+    - `fee_alias` problem with a fee alias of the auth rules
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectionDetails {
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spent_utxos: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_amount: Option<TokenAmount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provided_amount: Option<TokenAmount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing_signatures: Option<MissingSignatures>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taa: Option<TaaProblem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_alias: Option<FeeAliasProblem>,
+}
+
+/**
+    Counts of signatures, when the reason has them
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingSignatures {
+    pub provided: Option<u64>,
+    pub required: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TaaProblem {
+    /// The ledger requires an acceptance, none was sent
+    Required,
+    /// The acceptance is for another text or version than the latest
+    DigestMismatch,
+    /// The acceptance mechanism is not in the acceptance mechanisms list
+    Mechanism,
+    /// The acceptance time is too precise or out of range
+    Time,
+    /// An acceptance was sent but the ledger has no agreement
+    NotExpected,
+    Other,
+}
+
+/**
+    A fee alias the ledger complained about, `alias` when the reason names it
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeAliasProblem {
+    pub alias: Option<String>,
+}
+
+/**
+    The `ErrorCode` and [`RejectionDetails`] of a rejection
+
+    [`RejectionDetails`]: struct.RejectionDetails.html
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    pub error_code: ErrorCode,
+    pub details: RejectionDetails,
+}
+
+/**
+    Parses the reason of a REJECT or REQNACK.
+
+    The `ErrorCode` is `PaymentInsufficientFundsError`, `PaymentExtraFundsError`,
+    `PaymentSourceDoesNotExistError` for spent or unknown inputs and
+    `CommonInvalidStructure` otherwise.
+*/
+pub fn parse_rejection(reason: &str) -> Rejection {
+    let spent_utxos = spent_utxos(reason);
+
+    let error_code = if reason.contains(INSUFFICIENT_FUNDS_ERROR) {
+        ErrorCode::PaymentInsufficientFundsError
+    } else if reason.contains(EXTRA_FUNDS_ERROR) {
+        ErrorCode::PaymentExtraFundsError
+    } else if reason.contains(INVALID_FUNDS) {
+        ErrorCode::PaymentSourceDoesNotExistError
+    } else {
+        ErrorCode::CommonInvalidStructure
+    };
+
+    let details = RejectionDetails {
+        reason: reason.to_string(),
+        error: error_name(reason),
+        spent_utxos,
+        required_amount: number_after(reason, &["required amount:", "required amount", "required"]),
+        provided_amount: number_after(reason, &["sum of inputs is", "sum of inputs:", "provided"]),
+        missing_signatures: missing_signatures(reason),
+        taa: taa_problem(reason),
+        fee_alias: fee_alias_problem(reason),
+    };
+
+    Rejection { error_code, details }
+}
+
+/**
+    Parses the reason of a REJECT or REQNACK, keeps the rejection as the
+    current error and returns its `ErrorCode`.
+*/
+pub fn error_code_from_reason(reason: &str) -> ErrorCode {
+    error!("{}", reason);
+    errors::to_error_code(SovtokenError::from(parse_rejection(reason)))
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    `InsufficientFundsError("...")` or `client request invalid: InsufficientFundsError(...)`
+*/
+fn error_name(reason: &str) -> Option<String> {
+    reason.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .find(|word| word.len() > "Error".len() && (word.ends_with("Error") || word.ends_with("Exception")))
+        .map(|word| word.to_string())
+}
+
+fn spent_utxos(reason: &str) -> Vec<String> {
+    let mut utxos: Vec<String> = reason
+        .split(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == ',' || c == '[' || c == ']' || c == '(' || c == ')')
+        .filter(|word| word.starts_with(TXO_PREFIX) && word.len() > TXO_PREFIX.len())
+        .map(|word| word.to_string())
+        .collect();
+
+    // sovtoken: "seq_nos {2, 3} are not found in list of seq_nos_amounts for address <address>"
+    if let (Some(seq_nos), Some(address)) = (between(reason, "seq_nos {", "}"), word_after(reason, "for address")) {
+        for seq_no in seq_nos.split(',').filter_map(|seq_no| seq_no.trim().parse::<u64>().ok()) {
+            utxos.push(format!("{}:{}", address, seq_no));
+        }
+    }
+
+    utxos
+}
+
+/*
+    plenum: "insufficient signatures, 1 provided but 2 required",
+    "MissingSignature()" and "InsufficientCorrectSignatures(0, 1)"
+*/
+fn missing_signatures(reason: &str) -> Option<MissingSignatures> {
+    let lower = reason.to_ascii_lowercase();
+    if !lower.contains("signature") || !(lower.contains("missing") || lower.contains("insufficient")) {
+        return None;
+    }
+
+    let mut signatures = MissingSignatures::default();
+    if let Some(counts) = between(reason, "InsufficientCorrectSignatures(", ")") {
+        let counts: Vec<u64> = counts.split(',').filter_map(|count| count.trim().parse().ok()).collect();
+        if counts.len() == 2 {
+            signatures.provided = Some(counts[0]);
+            signatures.required = Some(counts[1]);
+        }
+    } else {
+        signatures.provided = number_before(&lower, "provided");
+        signatures.required = number_before(&lower, "required");
+    }
+
+    Some(signatures)
+}
+
+fn taa_problem(reason: &str) -> Option<TaaProblem> {
+    let lower = reason.to_ascii_lowercase();
+    if !lower.contains(TAA) && !reason.contains("TAA") {
+        return None;
+    }
+
+    let problem = if lower.contains("digest") || lower.contains("does not match") || lower.contains("latest") {
+        TaaProblem::DigestMismatch
+    } else if lower.contains("mechanism") {
+        TaaProblem::Mechanism
+    } else if lower.contains("time") {
+        TaaProblem::Time
+    } else if lower.contains("not expected") || lower.contains("disabled") || lower.contains("no taa") {
+        TaaProblem::NotExpected
+    } else if lower.contains("required") || lower.contains("has not been set") || lower.contains("missing") {
+        TaaProblem::Required
+    } else {
+        TaaProblem::Other
+    };
+
+    Some(problem)
+}
+
+/*
+    sovtoken: "Fees alias add_nym is not set" or "fees_alias 'add_nym' not found"
+*/
+fn fee_alias_problem(reason: &str) -> Option<FeeAliasProblem> {
+    let lower = reason.to_ascii_lowercase();
+    if !lower.contains("alias") || !lower.contains("fee") {
+        return None;
+    }
+
+    let alias = ["fees alias", "fee alias", "fees_alias", "fee_alias", "alias"].iter()
+        .find_map(|key| lower.find(key).map(|start| start + key.len()))
+        .and_then(|start| word_after(&reason[start..], ""))
+        .filter(|alias| !["is", "not", "for", "of"].contains(&alias.to_ascii_lowercase().as_str()));
+
+    Some(FeeAliasProblem { alias })
+}
+
+/*
+    The first number following any of `keys`, the earlier keys are tried first.
+*/
+fn number_after(text: &str, keys: &[&str]) -> Option<TokenAmount> {
+    let lower = text.to_ascii_lowercase();
+    keys.iter()
+        .find_map(|key| {
+            let start = lower.find(key)? + key.len();
+            let digits: String = lower[start..].trim_start_matches(|c: char| c.is_whitespace() || c == ':' || c == '=')
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            digits.parse().ok()
+        })
+}
+
+/*
+    The number right before `key`, like the 1 of "1 provided".
+*/
+fn number_before(text: &str, key: &str) -> Option<u64> {
+    let end = text.find(key)?;
+    text[..end].split_whitespace().last()?.parse().ok()
+}
+
+/*
+    The word following `key`, without quotes and trailing punctuation.
+*/
+fn word_after(text: &str, key: &str) -> Option<String> {
+    let start = text.find(key)? + key.len();
+    text[start..].split_whitespace()
+        .next()
+        .map(|word| word.trim_matches(|c: char| c == '"' || c == '\'' || c == '`' || c == ',' || c == '.' || c == ')' || c == '(' || c == ':'))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_string())
+}
+
+fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let from = text.find(start)? + start.len();
+    let to = text[from..].find(end)? + from;
+    Some(&text[from..to])
+}
+
+
+#[cfg(test)]
+mod rejection_parser_tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn insufficient_funds() {
+        let reason = r#"client request invalid: InsufficientFundsError("Insufficient funds, sum of inputs is 10 but required amount: 12. sum of outputs: 12",)"#;
+
+        let rejection = parse_rejection(reason);
+
+        assert_eq!(rejection.error_code, ErrorCode::PaymentInsufficientFundsError);
+        assert_eq!(rejection.details.error, Some("InsufficientFundsError".to_string()));
+        assert_eq!(rejection.details.provided_amount, Some(TokenAmount::new(10)));
+        assert_eq!(rejection.details.required_amount, Some(TokenAmount::new(12)));
+        assert_eq!(rejection.details.taa, None);
+    }
+
+    #[test]
+    fn extra_funds() {
+        let reason = r#"ExtraFundsError("Extra funds, sum of inputs is 20 but required amount: 12. sum of outputs: 12")"#;
+
+        let rejection = parse_rejection(reason);
+
+        assert_eq!(rejection.error_code, ErrorCode::PaymentExtraFundsError);
+        assert_eq!(rejection.details.provided_amount, Some(TokenAmount::new(20)));
+        assert_eq!(rejection.details.required_amount, Some(TokenAmount::new(12)));
+    }
+
+    #[test]
+    fn spent_utxos_by_seq_no() {
+        let reason = r#"InvalidFundsError("seq_nos {2, 3} are not found in list of seq_nos_amounts for address 2oWxuFMbhPewEbCEeKnvjcpVq8qpHHrN5y4aU81MWG5dYfeM7V -- current list: [[4, 10]]")"#;
+
+        let rejection = parse_rejection(reason);
+
+        assert_eq!(rejection.error_code, ErrorCode::PaymentSourceDoesNotExistError);
+        assert_eq!(rejection.details.spent_utxos, vec![
+            "2oWxuFMbhPewEbCEeKnvjcpVq8qpHHrN5y4aU81MWG5dYfeM7V:2".to_string(),
+            "2oWxuFMbhPewEbCEeKnvjcpVq8qpHHrN5y4aU81MWG5dYfeM7V:3".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn spent_utxos_by_txo() {
+        let reason = r#"InvalidFundsError("txo:sov:3x42qH8UkJac1BuorqjSEvuVjvYkXk8sUAqoVPn1fGCwjLPquu4CndzBHBQ5hX6RSmDVnXGdMPrnWDUN5S1ty4YQP87hW8ubMSzu9M56z1FbAQV6aMSX5h is spent")"#;
+
+        let rejection = parse_rejection(reason);
+
+        assert_eq!(rejection.details.spent_utxos, vec![
+            "txo:sov:3x42qH8UkJac1BuorqjSEvuVjvYkXk8sUAqoVPn1fGCwjLPquu4CndzBHBQ5hX6RSmDVnXGdMPrnWDUN5S1ty4YQP87hW8ubMSzu9M56z1FbAQV6aMSX5h".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn missing_signatures_counts() {
+        let rejection = parse_rejection("client request invalid: InsufficientSignatures(insufficient signatures, 1 provided but 2 required)");
+        assert_eq!(rejection.error_code, ErrorCode::CommonInvalidStructure);
+        assert_eq!(rejection.details.missing_signatures, Some(MissingSignatures { provided: Some(1), required: Some(2) }));
+
+        let rejection = parse_rejection("client request invalid: InsufficientCorrectSignatures(0, 1)");
+        assert_eq!(rejection.details.missing_signatures, Some(MissingSignatures { provided: Some(0), required: Some(1) }));
+
+        let rejection = parse_rejection("client request invalid: MissingSignature()");
+        assert_eq!(rejection.details.missing_signatures, Some(MissingSignatures::default()));
+    }
+
+    #[test]
+    fn taa_problems() {
+        assert_eq!(parse_rejection("Txn Author Agreement acceptance is required for ledger with id 1001").details.taa, Some(TaaProblem::Required));
+        assert_eq!(parse_rejection("Txn Author Agreement acceptance digest does not match the latest TAA").details.taa, Some(TaaProblem::DigestMismatch));
+        assert_eq!(parse_rejection("Txn Author Agreement acceptance mechanism is inappropriate").details.taa, Some(TaaProblem::Mechanism));
+        assert_eq!(parse_rejection("Txn Author Agreement acceptance time 1560000001 is too precise").details.taa, Some(TaaProblem::Time));
+        assert_eq!(parse_rejection("Txn Author Agreement acceptance is not expected").details.taa, Some(TaaProblem::NotExpected));
+    }
+
+    #[test]
+    fn fee_alias() {
+        let rejection = parse_rejection("InvalidClientRequest(\"Fees alias 'add_nym' is not set\")");
+        assert_eq!(rejection.details.fee_alias, Some(FeeAliasProblem { alias: Some("add_nym".to_string()) }));
+    }
+
+    #[test]
+    fn unknown_reason() {
+        let rejection = parse_rejection("some reason");
+
+        assert_eq!(rejection.error_code, ErrorCode::CommonInvalidStructure);
+        assert_eq!(rejection.details, RejectionDetails { reason: "some reason".to_string(), ..Default::default() });
+        assert_eq!(serde_json::to_value(&rejection.details).unwrap(), json!({"reason": "some reason"}));
+    }
+
+    #[test]
+    fn parse_error_code_keeps_current_error() {
+        let error_code = error_code_from_reason(r#"InsufficientFundsError("sum of inputs is 1 but required amount: 2")"#);
+
+        assert_eq!(error_code, ErrorCode::PaymentInsufficientFundsError);
+        assert!(errors::current_error_json().unwrap().contains(r#""requiredAmount":2"#));
+    }
+}
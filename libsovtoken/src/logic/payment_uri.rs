@@ -0,0 +1,389 @@
+/*!
+    Payment request URIs
+
+    A payment request ("pay X tokens to address Y for reference Z") is written as
+    the payment address followed by a query, which fits in a QR code:
+
+    ```text
+    pay:sov:<address>?amount=<int>&ref=<percent-encoded str>&extra=<percent-encoded json>
+    ```
+
+    All the parameters are optional. `extra` is a json object or a plain string,
+    like the `extra` of a payment.
+
+    Parsing is strict: the address and its checksum are validated, unknown or
+    repeated parameters, amounts which aren't plain positive integers and
+    malformed percent-encoding are rejected.
+*/
+
+use serde_json;
+use std::str::FromStr;
+
+use ErrorCode;
+use logic::address;
+use logic::output::{Output, Outputs};
+use logic::type_aliases::TokenAmount;
+use logic::xfer_payload::Extra;
+
+pub const PARAM_AMOUNT: &str = "amount";
+pub const PARAM_REFERENCE: &str = "ref";
+pub const PARAM_EXTRA: &str = "extra";
+
+/**
+    A payment request
+
+    `reference` ends up in the `ref` field of the `extra` of the payment.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PaymentRequest {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<TokenAmount>,
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<Extra>,
+}
+
+impl PaymentRequest {
+    pub fn new(address: String, amount: Option<TokenAmount>, reference: Option<String>, extra: Option<Extra>) -> PaymentRequest {
+        PaymentRequest { address, amount, reference, extra }
+    }
+
+    /**
+        Writes the request as a URI.
+
+        Errors with `CommonInvalidStructure` when the address is invalid, the
+        amount is zero or the extra is neither an object nor a string.
+    */
+    pub fn to_uri(&self) -> Result<String, ErrorCode> {
+        self.validate()?;
+
+        let mut params: Vec<String> = Vec::new();
+        if let Some(amount) = self.amount {
+            params.push(format!("{}={}", PARAM_AMOUNT, amount));
+        }
+        if let Some(ref reference) = self.reference {
+            params.push(format!("{}={}", PARAM_REFERENCE, percent_encode(reference)));
+        }
+        if let Some(ref extra) = self.extra {
+            params.push(format!("{}={}", PARAM_EXTRA, percent_encode(&extra_to_string(extra)?)));
+        }
+
+        if params.is_empty() {
+            Ok(self.address.clone())
+        } else {
+            Ok(format!("{}?{}", self.address, params.join("&")))
+        }
+    }
+
+    /**
+        The `outputs` and `extra` of the payment fulfilling the request.
+
+        The reference is added to the extra as `ref`. Errors with
+        `CommonInvalidStructure` when the request has no amount, or has a
+        reference and an extra which isn't an object or already has a `ref`.
+    */
+    pub fn to_outputs(&self) -> Result<(Outputs, Option<Extra>), ErrorCode> {
+        self.validate()?;
+
+        let amount = self.amount
+            .ok_or(ErrorCode::CommonInvalidStructure)
+            .map_err(map_err_err!())?;
+        let outputs = vec![Output::new(self.address.clone(), amount)];
+
+        let reference = match self.reference {
+            Some(ref reference) => reference.clone(),
+            None => return Ok((outputs, self.extra.clone()))
+        };
+
+        let mut extra = match self.extra {
+            None => serde_json::Map::new(),
+            Some(Extra(serde_json::Value::Object(ref extra))) if !extra.contains_key(PARAM_REFERENCE) => extra.clone(),
+            Some(_) => {
+                error!("Payment request extra can't hold its reference");
+                return Err(ErrorCode::CommonInvalidStructure);
+            }
+        };
+        extra.insert(PARAM_REFERENCE.to_string(), serde_json::Value::String(reference));
+
+        Ok((outputs, Some(Extra(serde_json::Value::Object(extra)))))
+    }
+
+    fn validate(&self) -> Result<(), ErrorCode> {
+        address::validate_address(&self.address)?;
+
+        if self.amount.map_or(false, TokenAmount::is_zero) {
+            error!("Payment request amount has to be positive");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        if let Some(ref extra) = self.extra {
+            extra_to_string(extra)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = ErrorCode;
+
+    fn from_str(uri: &str) -> Result<PaymentRequest, ErrorCode> {
+        trace!("logic::payment_uri::from_str >> uri: {:?}", secret!(uri));
+        let (address, query) = match uri.find('?') {
+            Some(position) => (&uri[..position], Some(&uri[position + 1..])),
+            None => (uri, None)
+        };
+
+        address::validate_address(address)?;
+        let mut request = PaymentRequest::new(address.to_string(), None, None, None);
+
+        let query = match query {
+            Some(query) => query,
+            None => return Ok(request)
+        };
+
+        for param in query.split('&') {
+            let (name, value) = match param.find('=') {
+                Some(position) => (&param[..position], &param[position + 1..]),
+                None => {
+                    error!("Payment request parameter {:?} has no value", param);
+                    return Err(ErrorCode::CommonInvalidStructure);
+                }
+            };
+
+            let value = percent_decode(value)?;
+            let duplicate = match name {
+                PARAM_AMOUNT => request.amount.replace(parse_amount(&value)?).is_some(),
+                PARAM_REFERENCE => request.reference.replace(value).is_some(),
+                PARAM_EXTRA => request.extra.replace(parse_extra(value)).is_some(),
+                _ => {
+                    error!("Unknown payment request parameter {:?}", name);
+                    return Err(ErrorCode::CommonInvalidStructure);
+                }
+            };
+
+            if duplicate {
+                error!("Payment request parameter {:?} is repeated", name);
+                return Err(ErrorCode::CommonInvalidStructure);
+            }
+        }
+
+        request.validate()?;
+        trace!("logic::payment_uri::from_str << request: {:?}", secret!(&request));
+        Ok(request)
+    }
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn is_unreserved(byte: u8) -> bool {
+    match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => true,
+        _ => false
+    }
+}
+
+fn percent_encode(value: &str) -> String {
+    value.bytes()
+        .map(|byte| if is_unreserved(byte) { (byte as char).to_string() } else { format!("%{:02X}", byte) })
+        .collect()
+}
+
+/*
+    Only unreserved characters and `%XX` escapes are accepted, `+` is not a space.
+*/
+fn percent_decode(value: &str) -> Result<String, ErrorCode> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = value.get(i + 1..i + 3)
+                .and_then(|hex| if hex.bytes().all(|c| (c as char).is_digit(16)) { u8::from_str_radix(hex, 16).ok() } else { None })
+                .ok_or(ErrorCode::CommonInvalidStructure)
+                .map_err(map_err_err!())?;
+            decoded.push(byte);
+            i += 3;
+        } else if is_unreserved(bytes[i]) {
+            decoded.push(bytes[i]);
+            i += 1;
+        } else {
+            error!("Payment request has an unescaped character {:?}", bytes[i] as char);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    }
+
+    String::from_utf8(decoded)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)
+}
+
+fn parse_amount(value: &str) -> Result<TokenAmount, ErrorCode> {
+    if value.is_empty() || !value.bytes().all(|c| (c as char).is_digit(10)) {
+        error!("Payment request amount {:?} isn't an integer", value);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    value.parse::<TokenAmount>()
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)
+}
+
+/*
+    Like the `extra` of `build_payment_req_handler`: a json object, otherwise a string.
+*/
+fn parse_extra(value: String) -> Extra {
+    match serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&value) {
+        Ok(extra) => Extra(serde_json::Value::Object(extra)),
+        Err(_) => Extra(serde_json::Value::String(value))
+    }
+}
+
+fn extra_to_string(extra: &Extra) -> Result<String, ErrorCode> {
+    match extra.0 {
+        serde_json::Value::Object(_) | serde_json::Value::String(_) => Ok(extra.to_string()),
+        _ => {
+            error!("Payment request extra has to be an object or a string");
+            Err(ErrorCode::CommonInvalidStructure)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod payment_uri_tests {
+    use super::*;
+
+    fn payment_address() -> String {
+        address::address_from_unqualified_address(&address::address_tests::gen_random_base58_address()).unwrap()
+    }
+
+    fn parse(uri: &str) -> Result<PaymentRequest, ErrorCode> {
+        uri.parse::<PaymentRequest>()
+    }
+
+    #[test]
+    fn to_uri_with_all_parameters() {
+        let address = payment_address();
+        let request = PaymentRequest::new(
+            address.clone(),
+            Some(TokenAmount::new(15)),
+            Some("invoice #42".to_string()),
+            Some(Extra(json!({"note": "a&b"})))
+        );
+
+        let uri = request.to_uri().unwrap();
+
+        assert_eq!(uri, format!("{}?amount=15&ref=invoice%20%2342&extra=%7B%22note%22%3A%22a%26b%22%7D", address));
+        assert_eq!(parse(&uri).unwrap(), request);
+    }
+
+    #[test]
+    fn to_uri_address_only() {
+        let address = payment_address();
+        let request = PaymentRequest::new(address.clone(), None, None, None);
+
+        assert_eq!(request.to_uri().unwrap(), address);
+        assert_eq!(parse(&address).unwrap(), request);
+    }
+
+    #[test]
+    fn to_uri_invalid() {
+        let zero = PaymentRequest::new(payment_address(), Some(TokenAmount::new(0)), None, None);
+        let bad_address = PaymentRequest::new("pay:sov:notanaddress".to_string(), Some(TokenAmount::new(1)), None, None);
+        let bad_extra = PaymentRequest::new(payment_address(), Some(TokenAmount::new(1)), None, Some(Extra(json!(5))));
+
+        assert_eq!(zero.to_uri().unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(bad_address.to_uri().unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(bad_extra.to_uri().unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn parse_string_extra() {
+        let address = payment_address();
+        let request = parse(&format!("{}?extra=thanks%21", address)).unwrap();
+        assert_eq!(request.extra, Some(Extra(json!("thanks!"))));
+    }
+
+    #[test]
+    fn parse_rejects_bad_checksum() {
+        let address = payment_address();
+        let last = address.chars().last().unwrap();
+        let changed = if last == '1' { '2' } else { '1' };
+        let tampered = format!("{}{}", &address[..address.len() - 1], changed);
+
+        assert_eq!(parse(&format!("{}?amount=1", tampered)).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_queries() {
+        let address = payment_address();
+        let invalid = [
+            "amount=1&amount=2",
+            "amount=-1",
+            "amount=+1",
+            "amount=1.5",
+            "amount=0",
+            "amount=99999999999999999999999",
+            "amount",
+            "ref=a+b",
+            "ref=a b",
+            "ref=%4",
+            "ref=%zz",
+            "ref=%FF",
+            "unknown=1",
+            "",
+        ];
+
+        for query in invalid.iter() {
+            assert_eq!(parse(&format!("{}?{}", address, query)).unwrap_err(), ErrorCode::CommonInvalidStructure, "{}", query);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_unqualified_address() {
+        let unqualified = address::address_tests::gen_random_base58_address();
+        assert_eq!(parse(&format!("{}?amount=1", unqualified)).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn to_outputs_adds_reference_to_extra() {
+        let address = payment_address();
+        let request = parse(&format!("{}?amount=7&ref=r1&extra=%7B%22note%22%3A%22n%22%7D", address)).unwrap();
+
+        let (outputs, extra) = request.to_outputs().unwrap();
+
+        assert_eq!(outputs, vec![Output::new(address, 7)]);
+        assert_eq!(extra, Some(Extra(json!({"note": "n", "ref": "r1"}))));
+    }
+
+    #[test]
+    fn to_outputs_without_reference() {
+        let address = payment_address();
+        let request = PaymentRequest::new(address.clone(), Some(TokenAmount::new(3)), None, Some(Extra(json!("memo"))));
+
+        let (outputs, extra) = request.to_outputs().unwrap();
+
+        assert_eq!(outputs, vec![Output::new(address, 3)]);
+        assert_eq!(extra, Some(Extra(json!("memo"))));
+    }
+
+    #[test]
+    fn to_outputs_invalid() {
+        let no_amount = PaymentRequest::new(payment_address(), None, None, None);
+        let string_extra = PaymentRequest::new(payment_address(), Some(TokenAmount::new(1)), Some("r".to_string()), Some(Extra(json!("memo"))));
+        let ref_in_extra = PaymentRequest::new(payment_address(), Some(TokenAmount::new(1)), Some("r".to_string()), Some(Extra(json!({"ref": "other"}))));
+
+        assert_eq!(no_amount.to_outputs().unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(string_extra.to_outputs().unwrap_err(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(ref_in_extra.to_outputs().unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+}
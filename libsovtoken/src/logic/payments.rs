@@ -4,6 +4,8 @@ use ErrorCode;
 use logic::config::payment_address_config::PaymentAddressConfig;
 use logic::indy_sdk_api::crypto_api::{CryptoAPI};
 use logic::address;
+#[cfg(feature = "std-futures")]
+use utils::future::{self, CallbackFuture};
 
 
 // ------------------------------------------------------------------
@@ -71,6 +73,28 @@ impl<T: CryptoAPI> CreatePaymentHandler<T> {
 
         return result_code;
     }
+
+    /**
+        To get a sovrin address as a future, resolves to the address of
+        [`create_payment_address_async`] or its error.
+
+        [`create_payment_address_async`]: #method.create_payment_address_async
+    */
+    #[cfg(feature = "std-futures")]
+    pub fn create_payment_address_future(&self, wallet_id: i32, config: PaymentAddressConfig) -> CallbackFuture<Result<String, ErrorCode>> {
+        let (address, completer) = future::callback_future();
+        let cb_completer = completer.clone();
+
+        let result_code = self.create_payment_address_async(wallet_id, config, move |address: String, err: ErrorCode| {
+            cb_completer.complete(if ErrorCode::Success == err { Ok(address) } else { Err(err) });
+        });
+
+        if result_code != ErrorCode::Success {
+            completer.complete(Err(result_code));
+        }
+
+        address
+    }
 }
 
 
@@ -177,4 +201,50 @@ mod payments_tests {
         assert_eq!(got_good_result, true);
         assert_eq!(ErrorCode::Success, error_code);
     }
+
+    #[cfg(feature = "std-futures")]
+    mod future_tests {
+        use super::*;
+        use utils::future::future_tests::block_on;
+
+        // mock SDK api calls failing before the callback is called
+        struct FailingCreateKeySDKMockHandler {}
+
+        impl CryptoAPI for FailingCreateKeySDKMockHandler {
+            fn indy_create_key(&self, _wallet_id: i32, _config: PaymentAddressConfig) -> Result<String, ErrorCode> {
+                return Err(ErrorCode::WalletInvalidHandle);
+            }
+
+            fn indy_crypto_sign<F>(&self, _: i32, _: String, _: String, _: F) -> ErrorCode {
+                return ErrorCode::CommonInvalidState;
+            }
+
+            fn indy_create_key_async<F: 'static>(&self, _wallet_id: i32, _config: PaymentAddressConfig, _closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+                return ErrorCode::WalletInvalidHandle;
+            }
+        }
+
+
+        // The future resolves to the address sent to the callback
+        #[test]
+        fn success_create_payment_future() {
+            let config: PaymentAddressConfig = PaymentAddressConfig { seed: String::new() };
+
+            let handler = CreatePaymentHandler::new(CreatePaymentSDKMockHandler {});
+            let address = block_on(handler.create_payment_address_future(WALLET_ID, config)).unwrap();
+
+            validate_address(address);
+        }
+
+        // The future resolves to the error code when indy does not call the callback
+        #[test]
+        fn create_payment_future_fails_to_start() {
+            let config: PaymentAddressConfig = PaymentAddressConfig { seed: String::new() };
+
+            let handler = CreatePaymentHandler::new(FailingCreateKeySDKMockHandler {});
+            let result = block_on(handler.create_payment_address_future(WALLET_ID, config));
+
+            assert_eq!(Err(ErrorCode::WalletInvalidHandle), result);
+        }
+    }
 }
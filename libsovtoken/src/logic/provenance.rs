@@ -0,0 +1,484 @@
+/*!
+ * [`parse_verify_response_handler`] checks one transaction. [`verify_provenance`]
+ * follows the inputs of the transaction of a txo to the transactions which
+ * created them, and so on until every path ends at a MINT_PUBLIC or the depth
+ * bound is reached. For each transaction it checks that
+ * - the output being followed exists, with the amount the spender relies on
+ * - the inputs cover the outputs, and the fees when the transaction has them
+ * - every input of a XFER_PUBLIC is signed by the key of its address
+ *
+ * The signatures of fee transactions cover the digest of the request they paid
+ * for, which the transaction doesn't have, and MINT_PUBLIC is signed by trustees,
+ * so those are reported as [`SignatureCheck::Unverifiable`].
+ *
+ * [`parse_verify_response_handler`]: ../../api/fn.parse_verify_response_handler.html
+ * [`verify_provenance`]: fn.verify_provenance.html
+ * [`SignatureCheck::Unverifiable`]: enum.SignatureCheck.html
+ */
+
+use std::collections::{BTreeMap, VecDeque};
+
+use ErrorCode;
+use logic::address;
+use logic::history::TxnKind;
+use logic::in_memory_crypto::verify_signature;
+use logic::input::Inputs;
+use logic::output::Outputs;
+use logic::parsers::common::TXO;
+use logic::parsers::parse_verify::{ParseVerifyResponse, ReqSignature};
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use logic::xfer_payload::{Extra, signing_message};
+use utils::base58::FromBase58;
+use utils::constants::txn_types::{MINT_PUBLIC, XFER_PUBLIC};
+use utils::errors::SovtokenError;
+use utils::json_conversion::JsonDeserialize;
+use utils::txn_author_agreement::TaaAcceptance;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SignatureCheck {
+    /// Every input is signed by the key of its address
+    Verified,
+    /// The signed message can't be rebuilt from the transaction
+    Unverifiable,
+}
+
+/**
+ * A transaction of the provenance chain
+ *
+ * ### Fields
+ * - `seq_no` seqNo of the transaction on the payment ledger
+ * - `txn_type` type of the transaction, e.g. `10001` for XFER_PUBLIC
+ * - `kind` what the transaction was
+ * - `depth` transactions between it and the txo being verified, 0 for its own
+ * - `inputs` the txos it spends
+ * - `input_amount` sum of the inputs, none when an input is past the depth bound
+ * - `output_amount` sum of the outputs
+ * - `fees` tokens of the inputs not given to outputs
+ * - `signatures` whether the signatures of the inputs were verified
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifiedTxn {
+    pub seq_no: TxnSeqNo,
+    pub txn_type: Option<String>,
+    pub kind: TxnKind,
+    pub depth: usize,
+    pub inputs: Vec<String>,
+    pub input_amount: Option<TokenAmount>,
+    pub output_amount: TokenAmount,
+    pub fees: Option<TokenAmount>,
+    pub signatures: SignatureCheck,
+}
+
+/**
+ * The verified provenance of a txo
+ *
+ * ### Fields
+ * - `txo` the txo which was verified
+ * - `amount` its amount
+ * - `transactions` the transactions it comes from, ascending by seqNo
+ * - `mints` seqNos of the MINT_PUBLIC transactions reached
+ * - `complete` every path ends at a MINT_PUBLIC within the depth bound
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    pub txo: String,
+    pub amount: TokenAmount,
+    pub transactions: Vec<VerifiedTxn>,
+    pub mints: Vec<TxnSeqNo>,
+    pub complete: bool,
+}
+
+/**
+ * Verifies where the tokens of `txo` come from
+ *
+ * `fetch` returns the reply to a GET_TXN request of a seqNo on the payment
+ * ledger, see [`build_verify_req_handler`]. Transactions deeper than
+ * `max_depth` are not fetched and the provenance is not `complete`.
+ *
+ * Errors with `CommonInvalidStructure` and the failing txo as field when a
+ * transaction doesn't verify, with `PaymentSourceDoesNotExistError` when a
+ * transaction isn't on the ledger and with the errors of `fetch`.
+ *
+ * [`build_verify_req_handler`]: ../../api/fn.build_verify_req_handler.html
+ */
+pub fn verify_provenance<F>(txo: &str, max_depth: usize, mut fetch: F) -> Result<Provenance, SovtokenError>
+    where F: FnMut(TxnSeqNo) -> Result<String, ErrorCode>
+{
+    trace!("logic::provenance::verify_provenance >> txo: {:?}, max_depth: {}", secret!(txo), max_depth);
+    let root = TXO::from_libindy_string(txo)
+        .map_err(|err| SovtokenError::invalid_structure("txo").with_value(txo).with_source(err))?;
+    let root_address = address::unqualified_address_from_address(&root.address)?;
+
+    let mut txns: BTreeMap<TxnSeqNo, LedgerTxn> = BTreeMap::new();
+    let mut verified: BTreeMap<TxnSeqNo, VerifiedTxn> = BTreeMap::new();
+    let mut complete = true;
+
+    let mut to_follow: VecDeque<(String, TxnSeqNo, usize)> = VecDeque::new();
+    to_follow.push_back((root_address.clone(), root.seq_no, 0));
+
+    while let Some((output_address, seq_no, depth)) = to_follow.pop_front() {
+        if depth > max_depth {
+            complete = false;
+            continue;
+        }
+
+        if !txns.contains_key(&seq_no) {
+            let txn = parse_txn(&fetch(seq_no)?, seq_no)?;
+            let checked = check_txn(&txn, seq_no, depth)?;
+            debug!("Verified transaction {} >>> {:?}", seq_no, checked);
+
+            for input in &txn.inputs {
+                to_follow.push_back((input.address.clone(), input.seq_no, depth + 1));
+            }
+            verified.insert(seq_no, checked);
+            txns.insert(seq_no, txn);
+        }
+
+        if txns[&seq_no].amount_to(&output_address).is_none() {
+            return Err(txo_error(&output_address, seq_no, "the transaction has no output to the address"));
+        }
+    }
+
+    for (seq_no, checked) in verified.iter_mut() {
+        let txn = &txns[seq_no];
+        let mut input_amount = Some(TokenAmount::ZERO);
+        for input in &txn.inputs {
+            let amount = txns.get(&input.seq_no).and_then(|spent| spent.amount_to(&input.address));
+            input_amount = match (input_amount, amount) {
+                (Some(sum), Some(amount)) => Some(sum.try_add(amount)?),
+                _ => None
+            };
+        }
+
+        if checked.kind == TxnKind::Mint {
+            continue;
+        }
+
+        if let Some(input_amount) = input_amount {
+            let fees = input_amount.checked_sub(checked.output_amount)
+                .ok_or_else(|| txn_error(*seq_no, &format!("outputs of {} exceed inputs of {}", checked.output_amount, input_amount)))?;
+            if let Some(declared) = txn.fees {
+                if declared != fees {
+                    return Err(txn_error(*seq_no, &format!("fees of {} declared, inputs leave {}", declared, fees)));
+                }
+            }
+            checked.input_amount = Some(input_amount);
+            checked.fees = Some(fees);
+        }
+    }
+
+    let amount = txns[&root.seq_no].amount_to(&root_address).unwrap_or_default();
+    let mints = verified.values().filter(|txn| txn.kind == TxnKind::Mint).map(|txn| txn.seq_no).collect();
+    let res = Provenance {
+        txo: txo.to_string(),
+        amount,
+        transactions: verified.into_iter().map(|(_, txn)| txn).collect(),
+        mints,
+        complete,
+    };
+
+    trace!("logic::provenance::verify_provenance << result: {:?}", res);
+    Ok(res)
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    The parts of a GET_TXN reply the verification needs
+*/
+struct LedgerTxn {
+    txn_type: Option<String>,
+    inputs: Inputs,
+    outputs: Outputs,
+    extra: Option<Extra>,
+    taa_acceptance: Option<TaaAcceptance>,
+    fees: Option<TokenAmount>,
+    req_signature: Option<ReqSignature>,
+}
+
+impl LedgerTxn {
+    fn amount_to(&self, address: &str) -> Option<TokenAmount> {
+        let mut amounts = self.outputs.iter().filter(|output| output.recipient == address).map(|output| output.amount);
+        let first = amounts.next()?;
+        amounts.try_fold(first, |sum, amount| sum.checked_add(amount))
+    }
+}
+
+fn parse_txn(reply: &str, seq_no: TxnSeqNo) -> Result<LedgerTxn, SovtokenError> {
+    let reply = ParseVerifyResponse::from_json(reply)
+        .map_err(|err| txn_error(seq_no, "malformed GET_TXN reply").with_source(err))?;
+
+    let data = reply.result
+        .ok_or_else(|| txn_error(seq_no, "GET_TXN reply without result"))?
+        .data
+        .ok_or(ErrorCode::PaymentSourceDoesNotExistError)?;
+
+    let txn_seq_no = data.txn_metadata.map(|metadata| metadata.seq_no);
+    if txn_seq_no != Some(seq_no) {
+        return Err(txn_error(seq_no, &format!("GET_TXN reply for {:?}", txn_seq_no)));
+    }
+
+    let txn = data.txn.ok_or_else(|| txn_error(seq_no, "GET_TXN reply without txn"))?;
+    let txn_data = txn.data.ok_or_else(|| txn_error(seq_no, "transaction without data"))?;
+
+    Ok(LedgerTxn {
+        txn_type: txn.txn_type,
+        inputs: txn_data.inputs.unwrap_or_default(),
+        outputs: txn_data.outputs.unwrap_or_default(),
+        extra: txn_data.extra,
+        taa_acceptance: txn.metadata.and_then(|metadata| metadata.get("taaAcceptance").cloned()),
+        fees: txn_data.fees.and_then(|fees| fees.as_u64()).map(TokenAmount::new),
+        req_signature: data.req_signature,
+    })
+}
+
+fn check_txn(txn: &LedgerTxn, seq_no: TxnSeqNo, depth: usize) -> Result<VerifiedTxn, SovtokenError> {
+    let kind = match txn.txn_type.as_ref().map(String::as_str) {
+        Some(MINT_PUBLIC) => TxnKind::Mint,
+        Some(XFER_PUBLIC) => TxnKind::Transfer,
+        _ => TxnKind::Fees
+    };
+
+    match kind {
+        TxnKind::Mint if !txn.inputs.is_empty() => return Err(txn_error(seq_no, "MINT_PUBLIC with inputs")),
+        TxnKind::Transfer | TxnKind::Fees if txn.inputs.is_empty() => return Err(txn_error(seq_no, "transaction without inputs which is not a MINT_PUBLIC")),
+        _ => ()
+    }
+
+    if let Some(input) = txn.inputs.iter().find(|input| input.seq_no >= seq_no) {
+        return Err(txo_error(&input.address, input.seq_no, &format!("spent by the earlier transaction {}", seq_no)));
+    }
+
+    let output_amount = TokenAmount::try_sum(txn.outputs.iter().map(|output| output.amount))?;
+
+    let signatures = match kind {
+        TxnKind::Transfer => {
+            verify_input_signatures(txn, seq_no)?;
+            SignatureCheck::Verified
+        }
+        _ => SignatureCheck::Unverifiable
+    };
+
+    Ok(VerifiedTxn {
+        seq_no,
+        txn_type: txn.txn_type.clone(),
+        kind,
+        depth,
+        inputs: txn.inputs.iter()
+            .map(|input| TXO { address: address::address_from_unqualified_address(&input.address)?, seq_no: input.seq_no }.to_libindy_string())
+            .collect::<Result<Vec<String>, ErrorCode>>()?,
+        input_amount: None,
+        output_amount,
+        fees: None,
+        signatures,
+    })
+}
+
+fn verify_input_signatures(txn: &LedgerTxn, seq_no: TxnSeqNo) -> Result<(), SovtokenError> {
+    let values = txn.req_signature.as_ref().map(|signature| &signature.values[..]).unwrap_or(&[]);
+
+    for input in &txn.inputs {
+        let verkey = address::verkey_from_unqualified_address(&input.address)?;
+        let message = signing_message(input, &txn.outputs, &None, &txn.extra, &txn.taa_acceptance)?;
+
+        let mut signed = false;
+        for value in values.iter().filter(|value| value.from == input.address) {
+            let signature = match value.value.from_base58() {
+                Ok(signature) => signature,
+                Err(_) => continue
+            };
+            if verify_signature(&verkey, message.as_bytes(), &signature)? {
+                signed = true;
+                break;
+            }
+        }
+
+        if !signed {
+            return Err(txo_error(&input.address, input.seq_no, &format!("no valid signature in transaction {}", seq_no)));
+        }
+    }
+
+    Ok(())
+}
+
+fn txn_error(seq_no: TxnSeqNo, reason: &str) -> SovtokenError {
+    SovtokenError::invalid_structure(&format!("transaction {}", seq_no)).with_source(reason)
+}
+
+fn txo_error(unqualified_address: &str, seq_no: TxnSeqNo, reason: &str) -> SovtokenError {
+    let txo = address::address_from_unqualified_address(unqualified_address)
+        .and_then(|address| TXO { address, seq_no }.to_libindy_string())
+        .unwrap_or_else(|_| format!("{}:{}", unqualified_address, seq_no));
+    SovtokenError::invalid_structure("txo").with_value(txo).with_source(reason)
+}
+
+
+#[cfg(test)]
+mod provenance_tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use logic::in_memory_crypto::InMemoryCrypto;
+    use logic::input::Input;
+    use logic::output::Output;
+    use utils::base58::IntoBase58;
+
+    struct Wallet {
+        crypto: InMemoryCrypto,
+        address: String,
+        verkey: String,
+    }
+
+    fn wallet(seed: &str) -> Wallet {
+        let crypto = InMemoryCrypto::new();
+        let config = PaymentAddressConfig { seed: seed.to_string() };
+        let verkey = crypto.create_key(&config).unwrap();
+        let address = address::unqualified_address_from_address(&address::qualified_address_from_verkey(&verkey).unwrap()).unwrap();
+        Wallet { crypto, address, verkey }
+    }
+
+    fn mint_reply(seq_no: TxnSeqNo, outputs: Vec<(&str, u64)>) -> String {
+        let outputs: Vec<_> = outputs.into_iter().map(|(address, amount)| json!({"address": address, "amount": amount})).collect();
+        json!({
+            "op": "REPLY",
+            "result": {
+                "data": {
+                    "txn": {"type": MINT_PUBLIC, "data": {"outputs": outputs}, "metadata": {}},
+                    "txnMetadata": {"seqNo": seq_no, "txnTime": 1_560_000_000},
+                    "reqSignature": {"type": "ED25519", "values": [{"from": "V4SGRU86Z58d6TV7PBUe6f", "value": "2f"}]}
+                }
+            }
+        }).to_string()
+    }
+
+    fn xfer_reply(seq_no: TxnSeqNo, inputs: Vec<(&Wallet, TxnSeqNo)>, outputs: Vec<(&str, u64)>) -> String {
+        let outputs: Outputs = outputs.into_iter().map(|(address, amount)| Output::new(address.to_string(), amount)).collect();
+        let signatures: Vec<_> = inputs.iter().map(|&(wallet, input_seq_no)| {
+            let input = Input::new(wallet.address.clone(), input_seq_no);
+            let message = signing_message(&input, &outputs, &None, &None, &None).unwrap();
+            let signature = wallet.crypto.sign(&wallet.verkey, message.as_bytes()).unwrap();
+            json!({"from": wallet.address, "value": signature.into_base58()})
+        }).collect();
+        let inputs: Vec<_> = inputs.iter().map(|&(wallet, input_seq_no)| json!({"address": wallet.address, "seqNo": input_seq_no})).collect();
+
+        json!({
+            "op": "REPLY",
+            "result": {
+                "data": {
+                    "txn": {"type": XFER_PUBLIC, "data": {"inputs": inputs, "outputs": outputs}, "metadata": {}},
+                    "txnMetadata": {"seqNo": seq_no, "txnTime": 1_560_000_000 + seq_no},
+                    "reqSignature": {"type": "ED25519", "values": signatures}
+                }
+            }
+        }).to_string()
+    }
+
+    fn ledger(txns: HashMap<TxnSeqNo, String>) -> impl FnMut(TxnSeqNo) -> Result<String, ErrorCode> {
+        move |seq_no| Ok(txns.get(&seq_no).cloned().unwrap_or_else(|| json!({"op": "REPLY", "result": {"data": null}}).to_string()))
+    }
+
+    fn txo(unqualified_address: &str, seq_no: TxnSeqNo) -> String {
+        TXO { address: address::address_from_unqualified_address(unqualified_address).unwrap(), seq_no }.to_libindy_string().unwrap()
+    }
+
+    #[test]
+    fn provenance_down_to_mint() {
+        let alice = wallet("00000000000000000000000000Alice1");
+        let bob = wallet("000000000000000000000000000Bob01");
+        let mut txns = HashMap::new();
+        txns.insert(1, mint_reply(1, vec![(&alice.address, 30)]));
+        txns.insert(2, mint_reply(2, vec![(&alice.address, 5)]));
+        txns.insert(4, xfer_reply(4, vec![(&alice, 1), (&alice, 2)], vec![(&bob.address, 20), (&alice.address, 14)]));
+        txns.insert(6, xfer_reply(6, vec![(&bob, 4)], vec![(&alice.address, 20)]));
+
+        let provenance = verify_provenance(&txo(&alice.address, 6), 5, ledger(txns)).unwrap();
+
+        assert!(provenance.complete);
+        assert_eq!(provenance.amount, 20);
+        assert_eq!(provenance.mints, vec![1, 2]);
+        let transactions: Vec<_> = provenance.transactions.iter().map(|t| (t.seq_no, t.depth, t.input_amount.map(TokenAmount::sovatoms), t.fees.map(TokenAmount::sovatoms), t.signatures)).collect();
+        assert_eq!(transactions, vec![
+            (1, 2, None, None, SignatureCheck::Unverifiable),
+            (2, 2, None, None, SignatureCheck::Unverifiable),
+            (4, 1, Some(35), Some(1), SignatureCheck::Verified),
+            (6, 0, Some(20), Some(0), SignatureCheck::Verified),
+        ]);
+    }
+
+    #[test]
+    fn provenance_bounded_by_depth() {
+        let alice = wallet("00000000000000000000000000Alice1");
+        let bob = wallet("000000000000000000000000000Bob01");
+        let mut txns = HashMap::new();
+        txns.insert(1, mint_reply(1, vec![(&alice.address, 30)]));
+        txns.insert(4, xfer_reply(4, vec![(&alice, 1)], vec![(&bob.address, 30)]));
+
+        let provenance = verify_provenance(&txo(&bob.address, 4), 0, ledger(txns)).unwrap();
+
+        assert!(!provenance.complete);
+        assert!(provenance.mints.is_empty());
+        assert_eq!(provenance.transactions[0].input_amount, None);
+    }
+
+    #[test]
+    fn provenance_outputs_exceed_inputs() {
+        let alice = wallet("00000000000000000000000000Alice1");
+        let bob = wallet("000000000000000000000000000Bob01");
+        let mut txns = HashMap::new();
+        txns.insert(1, mint_reply(1, vec![(&alice.address, 30)]));
+        txns.insert(4, xfer_reply(4, vec![(&alice, 1)], vec![(&bob.address, 31)]));
+
+        let err = verify_provenance(&txo(&bob.address, 4), 5, ledger(txns)).unwrap_err();
+
+        assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure);
+        assert!(err.to_string().contains("exceed"), "{}", err);
+    }
+
+    #[test]
+    fn provenance_forged_signature() {
+        let alice = wallet("00000000000000000000000000Alice1");
+        let bob = wallet("000000000000000000000000000Bob01");
+        let mut txns = HashMap::new();
+        txns.insert(1, mint_reply(1, vec![(&alice.address, 30)]));
+        // bob signs for the input of alice
+        let forged = xfer_reply(4, vec![(&bob, 1)], vec![(&bob.address, 30)]).replace(&bob.address, &alice.address);
+        let forged = forged.replacen(&format!("\"address\":\"{}\",\"amount\"", alice.address), &format!("\"address\":\"{}\",\"amount\"", bob.address), 1);
+        txns.insert(4, forged);
+
+        let err = verify_provenance(&txo(&bob.address, 4), 5, ledger(txns)).unwrap_err();
+
+        assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure);
+        assert!(err.to_error_json().contains(&txo(&alice.address, 1)));
+    }
+
+    #[test]
+    fn provenance_missing_output() {
+        let alice = wallet("00000000000000000000000000Alice1");
+        let bob = wallet("000000000000000000000000000Bob01");
+        let mut txns = HashMap::new();
+        txns.insert(1, mint_reply(1, vec![(&bob.address, 30)]));
+        txns.insert(4, xfer_reply(4, vec![(&alice, 1)], vec![(&bob.address, 30)]));
+
+        let err = verify_provenance(&txo(&bob.address, 4), 5, ledger(txns)).unwrap_err();
+
+        assert!(err.to_string().contains("no output"), "{}", err);
+    }
+
+    #[test]
+    fn provenance_unknown_transaction() {
+        let alice = wallet("00000000000000000000000000Alice1");
+
+        let err = verify_provenance(&txo(&alice.address, 3), 5, ledger(HashMap::new())).unwrap_err();
+
+        assert_eq!(err.error_code(), ErrorCode::PaymentSourceDoesNotExistError);
+    }
+}
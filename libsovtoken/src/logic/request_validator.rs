@@ -0,0 +1,400 @@
+/*!
+ * The pool rejects a malformed or under-signed payment only once it is
+ * submitted. [`validate_request`] runs the same checks locally on a request of
+ * [`build_payment_req_handler`] or [`add_request_fees_handler`]:
+ * - the inputs are unique and in the UTXO set given
+ * - the amounts are not 0 and their sums don't overflow
+ * - the inputs are the outputs plus the fee
+ * - every input is signed by the key of its address, over the message
+ *   `XferPayload::sign_input` signs
+ * - the TAA acceptance is there when the ledger requires one
+ *
+ * [`validate_request`]: fn.validate_request.html
+ * [`build_payment_req_handler`]: ../../api/fn.build_payment_req_handler.html
+ * [`add_request_fees_handler`]: ../../api/fn.add_request_fees_handler.html
+ */
+
+use serde_json;
+use std::collections::{BTreeMap, BTreeSet};
+
+use ErrorCode;
+use logic::address;
+use logic::api_internals::add_request_fees;
+use logic::in_memory_crypto::verify_signature;
+use logic::input::Inputs;
+use logic::output::Outputs;
+use logic::parsers::common::{TXO, UTXO};
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use logic::xfer_payload::{Extra, XferPayload, signing_message};
+use utils::base58::FromBase58;
+use utils::constants::txn_types::XFER_PUBLIC;
+use utils::errors::SovtokenError;
+use utils::txn_author_agreement::{TaaAcceptance, extract_taa_acceptance_from_extra};
+
+type SerdeMap = serde_json::Map<String, serde_json::Value>;
+
+/**
+ * What the request is checked against
+ *
+ * ```JSON
+ * {
+ *      "utxos": [{"recipient": <str>, "receipt": <str: txo>, "amount": <int>, "extra": <str>}],
+ *      "fee": <optional int: fee of the request, 0 by default>,
+ *      "taaRequired": <optional bool: the ledger requires a TAA acceptance>,
+ *      "extra": <optional object: extra the fees of a request were signed with>
+ * }
+ * ```
+ *
+ * The `extra` of fees is not in the request, it has to be given to check
+ * fees signed with one.
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationConfig {
+    #[serde(default)]
+    pub utxos: Vec<UTXO>,
+    #[serde(default)]
+    pub fee: TokenAmount,
+    #[serde(default)]
+    pub taa_required: bool,
+    #[serde(default)]
+    pub extra: Option<Extra>,
+}
+
+/**
+ * Sums of a valid request
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatedRequest {
+    pub txn_type: String,
+    pub total_in: TokenAmount,
+    pub total_out: TokenAmount,
+    pub fee: TokenAmount,
+}
+
+/**
+ * Checks a XFER_PUBLIC request or a request with fees before it is submitted
+ *
+ * Errors with
+ * - `CommonInvalidStructure` and the failing field for duplicated inputs,
+ *   amounts of 0, overflows, invalid signatures or a missing TAA acceptance
+ * - `PaymentSourceDoesNotExistError` when an input is not in the UTXO set
+ * - `PaymentInsufficientFundsError` when the inputs don't cover the outputs and the fee
+ * - `PaymentExtraFundsError` when the inputs are more than the outputs and the fee
+ */
+pub fn validate_request(request_json: &str, config: &ValidationConfig) -> Result<ValidatedRequest, SovtokenError> {
+    trace!("logic::request_validator::validate_request >> request_json: {:?}, config: {:?}", secret!(request_json), secret!(config));
+    let request: SerdeMap = serde_json::from_str(request_json)
+        .map_err(|err| SovtokenError::invalid_structure("request_json").with_source(err))?;
+
+    let txn_type = request.get("operation").and_then(|operation| operation["type"].as_str())
+        .ok_or_else(|| SovtokenError::invalid_structure("operation.type"))?
+        .to_string();
+
+    let taa_acceptance = request.get("taaAcceptance").cloned();
+    validate_taa_acceptance(&taa_acceptance, config.taa_required)?;
+
+    let (payload, txn_digest, taa_acceptance) = if txn_type == XFER_PUBLIC {
+        if request.contains_key("fees") {
+            return Err(SovtokenError::invalid_structure("fees").with_source("a XFER_PUBLIC pays its fee with its inputs"));
+        }
+        let payload: XferPayload = serde_json::from_value(request["operation"].clone())
+            .map_err(|err| SovtokenError::invalid_structure("operation").with_source(err))?;
+        (payload, None, taa_acceptance)
+    } else {
+        let fees = request.get("fees")
+            .ok_or_else(|| SovtokenError::invalid_structure("fees").with_source(format!("a {} request without fees", txn_type)))?;
+        let (inputs, outputs, signatures): (Inputs, Outputs, Option<Vec<String>>) = serde_json::from_value(fees.clone())
+            .map_err(|err| SovtokenError::invalid_structure("fees").with_source(err))?;
+
+        let mut request_without_fees = request.clone();
+        request_without_fees.remove("fees");
+        let txn_digest = add_request_fees::txn_digest(&request_without_fees)?;
+
+        let (extra, fees_taa_acceptance) = extract_taa_acceptance_from_extra(config.extra.clone())?;
+        (XferPayload { inputs, outputs, extra, signatures }, Some(txn_digest), fees_taa_acceptance)
+    };
+
+    if payload.inputs.is_empty() {
+        return Err(SovtokenError::invalid_structure("inputs").with_source("no inputs"));
+    }
+
+    let total_in = sum_inputs(&payload.inputs, &config.utxos)?;
+
+    let mut total_out = TokenAmount::ZERO;
+    for output in &payload.outputs {
+        if output.amount.is_zero() {
+            return Err(SovtokenError::invalid_structure("outputs").with_value(&output.recipient).with_source("an output of 0"));
+        }
+        total_out = total_out.try_add(output.amount)?;
+    }
+
+    let expected_in = total_out.try_add(config.fee)?;
+    if total_in != expected_in {
+        error!("Inputs of {} for outputs of {} and a fee of {}", total_in, total_out, config.fee);
+        let error_code = if total_in < expected_in { ErrorCode::PaymentInsufficientFundsError } else { ErrorCode::PaymentExtraFundsError };
+        return Err(SovtokenError::from(error_code));
+    }
+
+    verify_input_signatures(&payload, &txn_digest, &taa_acceptance)?;
+
+    let res = ValidatedRequest { txn_type, total_in, total_out, fee: config.fee };
+    trace!("logic::request_validator::validate_request << result: {:?}", res);
+    Ok(res)
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn validate_taa_acceptance(taa_acceptance: &Option<TaaAcceptance>, taa_required: bool) -> Result<(), SovtokenError> {
+    let taa_acceptance = match *taa_acceptance {
+        Some(ref taa_acceptance) => taa_acceptance,
+        None if taa_required => return Err(SovtokenError::invalid_structure("taaAcceptance").with_source("the ledger requires a TAA acceptance")),
+        None => return Ok(())
+    };
+
+    for field in &["taaDigest", "mechanism"] {
+        if taa_acceptance[field].as_str().map_or(true, str::is_empty) {
+            return Err(SovtokenError::invalid_structure(&format!("taaAcceptance.{}", field)).with_value(&taa_acceptance[field]));
+        }
+    }
+    if taa_acceptance["time"].as_u64().is_none() {
+        return Err(SovtokenError::invalid_structure("taaAcceptance.time").with_value(&taa_acceptance["time"]));
+    }
+    Ok(())
+}
+
+fn sum_inputs(inputs: &Inputs, utxos: &[UTXO]) -> Result<TokenAmount, SovtokenError> {
+    let mut amounts: BTreeMap<(String, TxnSeqNo), TokenAmount> = BTreeMap::new();
+    for utxo in utxos {
+        let txo = TXO::from_libindy_string(&utxo.receipt)
+            .map_err(|err| SovtokenError::invalid_structure("utxos.receipt").with_value(&utxo.receipt).with_source(err))?;
+        amounts.insert((address::unqualified_address_from_address(&txo.address)?, txo.seq_no), utxo.amount);
+    }
+
+    let mut spent = BTreeSet::new();
+    let mut total_in = TokenAmount::ZERO;
+    for input in inputs {
+        let key = (input.address.clone(), input.seq_no);
+        if !spent.insert(key.clone()) {
+            return Err(SovtokenError::invalid_structure("inputs").with_value(txo_string(input.seq_no, &input.address)).with_source("the input is spent twice"));
+        }
+
+        let amount = match amounts.get(&key) {
+            Some(&amount) => amount,
+            None => {
+                error!("Input {} is not in the UTXO set", txo_string(input.seq_no, &input.address));
+                return Err(SovtokenError::from(ErrorCode::PaymentSourceDoesNotExistError));
+            }
+        };
+        if amount.is_zero() {
+            return Err(SovtokenError::invalid_structure("utxos.amount").with_value(txo_string(input.seq_no, &input.address)).with_source("an input of 0"));
+        }
+
+        total_in = total_in.try_add(amount)?;
+    }
+    Ok(total_in)
+}
+
+fn verify_input_signatures(payload: &XferPayload, txn_digest: &Option<String>, taa_acceptance: &Option<TaaAcceptance>) -> Result<(), SovtokenError> {
+    let signatures = payload.signatures.as_ref()
+        .ok_or_else(|| SovtokenError::invalid_structure("signatures").with_source("the inputs are not signed"))?;
+    if signatures.len() != payload.inputs.len() {
+        return Err(SovtokenError::invalid_structure("signatures").with_value(signatures.len())
+            .with_source(format!("{} inputs", payload.inputs.len())));
+    }
+
+    for (input, signature) in payload.inputs.iter().zip(signatures) {
+        let verkey = address::verkey_from_unqualified_address(&input.address)?;
+        let message = signing_message(input, &payload.outputs, txn_digest, &payload.extra, taa_acceptance)?;
+        let valid = match signature.from_base58() {
+            Ok(signature) => verify_signature(&verkey, message.as_bytes(), &signature)?,
+            Err(_) => false
+        };
+
+        if !valid {
+            return Err(SovtokenError::invalid_structure("signatures").with_value(txo_string(input.seq_no, &input.address))
+                .with_source("not signed by the key of the address"));
+        }
+    }
+    Ok(())
+}
+
+fn txo_string(seq_no: TxnSeqNo, unqualified_address: &str) -> String {
+    address::address_from_unqualified_address(unqualified_address)
+        .and_then(|address| TXO { address, seq_no }.to_libindy_string())
+        .unwrap_or_else(|_| format!("{}:{}", unqualified_address, seq_no))
+}
+
+
+#[cfg(test)]
+mod request_validator_tests {
+    use super::*;
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use logic::in_memory_crypto::InMemoryCrypto;
+    use logic::input::Input;
+    use logic::output::Output;
+    use utils::base58::IntoBase58;
+
+    struct Wallet {
+        crypto: InMemoryCrypto,
+        address: String,
+        verkey: String,
+    }
+
+    fn wallet() -> Wallet {
+        let crypto = InMemoryCrypto::new();
+        let verkey = crypto.create_key(&PaymentAddressConfig { seed: "00000000000000000000000000Alice1".to_string() }).unwrap();
+        let address = address::unqualified_address_from_verkey(&verkey).unwrap();
+        Wallet { crypto, address, verkey }
+    }
+
+    fn utxo(wallet: &Wallet, seq_no: TxnSeqNo, amount: u64) -> UTXO {
+        let qualified = address::address_from_unqualified_address(&wallet.address).unwrap();
+        UTXO {
+            receipt: TXO { address: qualified.clone(), seq_no }.to_libindy_string().unwrap(),
+            recipient: qualified,
+            amount: TokenAmount::new(amount),
+            extra: String::new(),
+        }
+    }
+
+    fn signed(wallet: &Wallet, inputs: &Inputs, outputs: &Outputs, txn_digest: &Option<String>, taa_acceptance: &Option<TaaAcceptance>) -> Vec<String> {
+        inputs.iter().map(|input| {
+            let message = signing_message(input, outputs, txn_digest, &None, taa_acceptance).unwrap();
+            wallet.crypto.sign(&wallet.verkey, message.as_bytes()).unwrap().into_base58()
+        }).collect()
+    }
+
+    fn payment(wallet: &Wallet, seq_nos: &[TxnSeqNo], amount: u64, taa_acceptance: Option<TaaAcceptance>) -> String {
+        let inputs: Inputs = seq_nos.iter().map(|&seq_no| Input::new(wallet.address.clone(), seq_no)).collect();
+        let outputs = vec![Output::new("2mVXsXyVADzSDw88RAojPpdgxLPQyC1oJUqkrLeU5AdfEq2PmC".to_string(), amount)];
+        let signatures = signed(wallet, &inputs, &outputs, &None, &taa_acceptance);
+
+        let mut request = json!({
+            "operation": {"type": XFER_PUBLIC, "inputs": inputs, "outputs": outputs, "signatures": signatures},
+            "reqId": 7,
+            "protocolVersion": 2,
+        });
+        if let Some(taa_acceptance) = taa_acceptance {
+            request["taaAcceptance"] = taa_acceptance;
+        }
+        request.to_string()
+    }
+
+    fn config(utxos: Vec<UTXO>, fee: u64) -> ValidationConfig {
+        ValidationConfig { utxos, fee: TokenAmount::new(fee), ..Default::default() }
+    }
+
+    #[test]
+    fn valid_payment() {
+        let wallet = wallet();
+        let request = payment(&wallet, &[1, 2], 25, None);
+
+        let validated = validate_request(&request, &config(vec![utxo(&wallet, 1, 20), utxo(&wallet, 2, 7)], 2)).unwrap();
+
+        assert_eq!(validated, ValidatedRequest { txn_type: XFER_PUBLIC.to_string(), total_in: TokenAmount::new(27), total_out: TokenAmount::new(25), fee: TokenAmount::new(2) });
+    }
+
+    #[test]
+    fn payment_with_wrong_sums() {
+        let wallet = wallet();
+        let request = payment(&wallet, &[1], 20, None);
+
+        let insufficient = validate_request(&request, &config(vec![utxo(&wallet, 1, 20)], 1)).unwrap_err();
+        let extra = validate_request(&request, &config(vec![utxo(&wallet, 1, 22)], 1)).unwrap_err();
+
+        assert_eq!(insufficient.error_code(), ErrorCode::PaymentInsufficientFundsError);
+        assert_eq!(extra.error_code(), ErrorCode::PaymentExtraFundsError);
+    }
+
+    #[test]
+    fn payment_with_unknown_or_duplicated_input() {
+        let wallet = wallet();
+
+        let unknown = validate_request(&payment(&wallet, &[1], 20, None), &config(vec![utxo(&wallet, 2, 20)], 0)).unwrap_err();
+        let duplicated = validate_request(&payment(&wallet, &[1, 1], 40, None), &config(vec![utxo(&wallet, 1, 20)], 0)).unwrap_err();
+
+        assert_eq!(unknown.error_code(), ErrorCode::PaymentSourceDoesNotExistError);
+        assert_eq!(duplicated.error_code(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn payment_with_output_of_zero() {
+        let wallet = wallet();
+
+        let err = validate_request(&payment(&wallet, &[1], 0, None), &config(vec![utxo(&wallet, 1, 20)], 20)).unwrap_err();
+
+        assert!(err.to_string().ends_with("an output of 0"), "{}", err);
+    }
+
+    #[test]
+    fn payment_with_overflowing_inputs() {
+        let wallet = wallet();
+
+        let err = validate_request(&payment(&wallet, &[1, 2], 20, None), &config(vec![utxo(&wallet, 1, ::std::u64::MAX), utxo(&wallet, 2, 1)], 0)).unwrap_err();
+
+        assert_eq!(err, SovtokenError::AmountOverflow(TokenAmount::MAX.try_add(TokenAmount::new(1)).unwrap_err()));
+    }
+
+    #[test]
+    fn payment_with_tampered_output() {
+        let wallet = wallet();
+        let request = payment(&wallet, &[1], 20, None).replace("\"amount\":20", "\"amount\":19");
+
+        let err = validate_request(&request, &config(vec![utxo(&wallet, 1, 20)], 1)).unwrap_err();
+
+        assert_eq!(err, SovtokenError::invalid_structure("signatures")
+            .with_value(txo_string(1, &wallet.address))
+            .with_source("not signed by the key of the address"));
+    }
+
+    #[test]
+    fn payment_with_taa_acceptance() {
+        let wallet = wallet();
+        let taa_acceptance = json!({"taaDigest": "abc", "mechanism": "on_file", "time": 1_560_000_000});
+        let utxos = vec![utxo(&wallet, 1, 20)];
+        let required = ValidationConfig { taa_required: true, ..config(utxos.clone(), 0) };
+
+        validate_request(&payment(&wallet, &[1], 20, Some(taa_acceptance)), &required).unwrap();
+
+        let err = validate_request(&payment(&wallet, &[1], 20, None), &required).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid structure: `taaAcceptance`: the ledger requires a TAA acceptance");
+        validate_request(&payment(&wallet, &[1], 20, None), &config(utxos, 0)).unwrap();
+    }
+
+    #[test]
+    fn valid_request_with_fees() {
+        let wallet = wallet();
+        let request_without_fees = json!({"operation": {"type": "1", "dest": "V4SGRU86Z58d6TV7PBUe6f"}, "reqId": 7, "identifier": "V4SGRU86Z58d6TV7PBUe6f"});
+        let txn_digest = Some(add_request_fees::txn_digest(request_without_fees.as_object().unwrap()).unwrap());
+
+        let inputs = vec![Input::new(wallet.address.clone(), 3)];
+        let outputs = vec![Output::new(wallet.address.clone(), 9)];
+        let signatures = signed(&wallet, &inputs, &outputs, &txn_digest, &None);
+        let mut request = request_without_fees.clone();
+        request["fees"] = json!([inputs, outputs, signatures]);
+
+        let validated = validate_request(&request.to_string(), &config(vec![utxo(&wallet, 3, 10)], 1)).unwrap();
+        assert_eq!(validated.fee, 1);
+
+        request["operation"]["dest"] = json!("Th7MpTaRZVRYnPiabds81Y");
+        let err = validate_request(&request.to_string(), &config(vec![utxo(&wallet, 3, 10)], 1)).unwrap_err();
+        assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn request_without_fees() {
+        let request = json!({"operation": {"type": "1"}, "reqId": 7}).to_string();
+
+        let err = validate_request(&request, &ValidationConfig::default()).unwrap_err();
+
+        assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure);
+    }
+
+}
@@ -45,6 +45,7 @@ mod test_deserialize_inputs {
     use std::ptr;
     use utils::test::default;
     use utils::ffi_support::{c_pointer_from_str};
+    use logic::type_aliases::TokenAmount;
 
     pub fn call_deserialize_inputs(
         did: Option<*const c_char>,
@@ -120,8 +121,8 @@ mod test_deserialize_inputs {
         let (_, fees, _) = call_deserialize_inputs(None, Some(invalid_fees), None).unwrap();
 
         assert_eq!(fees.fees.len(), 2);
-        assert_eq!(fees.fees.get("XFER_PUBLIC"), Some(&5));
-        assert_eq!(fees.fees.get("3"), Some(&1));
+        assert_eq!(fees.fees.get("XFER_PUBLIC"), Some(&TokenAmount::new(5)));
+        assert_eq!(fees.fees.get("3"), Some(&TokenAmount::new(1)));
     }
 
     #[test]
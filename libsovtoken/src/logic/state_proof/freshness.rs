@@ -0,0 +1,210 @@
+/*!
+    Freshness of state proofs
+
+    The `multi_signature.value.timestamp` of a state proof is when the nodes
+    signed the state. A lagging node still has a valid proof of an old state, so
+    the proofs of GET_UTXO and GET_FEES replies can be required to be recent.
+
+    The settings are given to each check. The libindy handlers have a fixed
+    signature, they use the process-wide default of [`set_freshness_settings`].
+    Not checked by default.
+
+    [`set_freshness_settings`]: fn.set_freshness_settings.html
+*/
+
+use std::sync::RwLock;
+use time;
+
+use ErrorCode;
+use logic::parsers::common::StateProof;
+
+lazy_static! {
+    static ref FRESHNESS_SETTINGS: RwLock<Option<FreshnessSettings>> = RwLock::new(None);
+}
+
+/**
+    How old a state proof can be
+
+    ### Fields
+    - `max_proof_age` seconds since the proof was signed
+    - `clock_skew_tolerance` seconds the clocks of the nodes and ours can differ
+      by, added to `max_proof_age` and allowed for proofs from the future
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FreshnessSettings {
+    pub max_proof_age: u64,
+    #[serde(default)]
+    pub clock_skew_tolerance: u64,
+}
+
+/**
+    A state proof which isn't fresh, or has no timestamp
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleProof {
+    pub proof_timestamp: Option<u64>,
+    pub now: u64,
+    pub max_proof_age: u64,
+    pub clock_skew_tolerance: u64,
+}
+
+impl From<StaleProof> for ErrorCode {
+    fn from(_: StaleProof) -> ErrorCode {
+        ErrorCode::LedgerSecurityError
+    }
+}
+
+/**
+    Sets how fresh the state proofs parsed by the libindy handlers have to be,
+    `None` to not check them.
+
+    The setting is process-wide: it applies to the replies parsed on every
+    thread, for every pool, until it is changed again. Rust callers give the
+    settings per call instead.
+*/
+pub fn set_freshness_settings(settings: Option<FreshnessSettings>) {
+    match FRESHNESS_SETTINGS.write() {
+        Ok(mut current) => *current = settings,
+        Err(poisoned) => *poisoned.into_inner() = settings,
+    }
+}
+
+/**
+    The process-wide default of [`set_freshness_settings`].
+
+    [`set_freshness_settings`]: fn.set_freshness_settings.html
+*/
+pub fn freshness_settings() -> Option<FreshnessSettings> {
+    match FRESHNESS_SETTINGS.read() {
+        Ok(settings) => *settings,
+        Err(poisoned) => *poisoned.into_inner(),
+    }
+}
+
+/**
+    The time the nodes signed a state proof.
+*/
+pub fn proof_timestamp(state_proof: &StateProof) -> Option<u64> {
+    state_proof.multi_signature.as_ref()
+        .and_then(|multi_signature| multi_signature["value"]["timestamp"].as_u64())
+}
+
+/**
+    Checks a state proof against `settings`, returns its timestamp.
+
+    Any proof passes when `settings` is `None`.
+*/
+pub fn check_proof_freshness(state_proof: Option<&StateProof>, settings: Option<&FreshnessSettings>) -> Result<Option<u64>, StaleProof> {
+    let timestamp = state_proof.and_then(proof_timestamp);
+    match settings {
+        Some(settings) => check_timestamp(timestamp, settings, time::get_time().sec as u64),
+        None => Ok(timestamp)
+    }
+}
+
+/**
+    Checks the timestamp of a state proof at the time `now`.
+*/
+pub fn check_timestamp(timestamp: Option<u64>, settings: &FreshnessSettings, now: u64) -> Result<Option<u64>, StaleProof> {
+    let fresh = match timestamp {
+        Some(timestamp) if timestamp > now => timestamp - now <= settings.clock_skew_tolerance,
+        Some(timestamp) => now - timestamp <= settings.max_proof_age.saturating_add(settings.clock_skew_tolerance),
+        None => false
+    };
+
+    if fresh {
+        return Ok(timestamp);
+    }
+
+    let stale = StaleProof {
+        proof_timestamp: timestamp,
+        now,
+        max_proof_age: settings.max_proof_age,
+        clock_skew_tolerance: settings.clock_skew_tolerance,
+    };
+    error!("State proof is stale: {:?}", stale);
+    Err(stale)
+}
+
+
+#[cfg(test)]
+mod freshness_tests {
+    use super::*;
+
+    const NOW: u64 = 1_560_000_000;
+
+    fn settings() -> FreshnessSettings {
+        FreshnessSettings { max_proof_age: 300, clock_skew_tolerance: 10 }
+    }
+
+    fn state_proof(timestamp: u64) -> StateProof {
+        StateProof {
+            multi_signature: Some(json!({
+                "participants": ["Node1", "Node2", "Node3"],
+                "signature": "QZ3S2tAmM8bR8EUdrLTuTz4T9kmQERJYJ4b9NvP1Mt8Aqcc9QMdsjY7GmNt3xJLfxWakuEALGDd2n8wxQvUUCV3EdWTUZYuWLYQNxM4E3A2P1ivs6FqS2KLzPEgfsJvdk7JWUi9Jf4hZeR6jJ6FZKr8Qq7sCE63ZcXm2Xkq3hn5xr7",
+                "value": {
+                    "ledger_id": 1001,
+                    "pool_state_root_hash": "9i3dHE9Q2Drq4TY8J8G7JZmRt6ZGn8E3n6ZN6djSG1Ce",
+                    "state_root_hash": "7fq8gU2iLJ6SbUcsrngLTxPTVZyLqxDvA4jHeNvGzTv4",
+                    "timestamp": timestamp,
+                    "txn_root_hash": "AKboMiJZJm247Sa7GsKQo5Ba8ukgxTQ3DsLc2pyVuDkU"
+                }
+            })),
+            root_hash: Some("7fq8gU2iLJ6SbUcsrngLTxPTVZyLqxDvA4jHeNvGzTv4".to_string()),
+            proof_nodes: Some("".to_string()),
+        }
+    }
+
+    #[test]
+    fn proof_timestamp_of_multi_signature() {
+        assert_eq!(proof_timestamp(&state_proof(NOW)), Some(NOW));
+
+        let mut without = state_proof(NOW);
+        without.multi_signature = None;
+        assert_eq!(proof_timestamp(&without), None);
+    }
+
+    #[test]
+    fn check_timestamp_fresh() {
+        assert_eq!(check_timestamp(Some(NOW), &settings(), NOW), Ok(Some(NOW)));
+        assert_eq!(check_timestamp(Some(NOW - 310), &settings(), NOW), Ok(Some(NOW - 310)));
+        assert_eq!(check_timestamp(Some(NOW + 10), &settings(), NOW), Ok(Some(NOW + 10)));
+    }
+
+    #[test]
+    fn check_timestamp_stale() {
+        assert_eq!(
+            check_timestamp(Some(NOW - 311), &settings(), NOW),
+            Err(StaleProof { proof_timestamp: Some(NOW - 311), now: NOW, max_proof_age: 300, clock_skew_tolerance: 10 })
+        );
+    }
+
+    #[test]
+    fn check_timestamp_from_future() {
+        assert!(check_timestamp(Some(NOW + 11), &settings(), NOW).is_err());
+    }
+
+    #[test]
+    fn check_timestamp_missing() {
+        let stale = check_timestamp(None, &settings(), NOW).unwrap_err();
+        assert_eq!(stale.proof_timestamp, None);
+        assert_eq!(ErrorCode::from(stale), ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn check_proof_freshness_with_settings() {
+        let old = state_proof(1);
+
+        assert_eq!(check_proof_freshness(Some(&old), None), Ok(Some(1)));
+        assert!(check_proof_freshness(Some(&old), Some(&settings())).is_err());
+        assert!(check_proof_freshness(None, Some(&settings())).is_err());
+    }
+
+    #[test]
+    fn deserialize_settings_with_default_skew() {
+        let settings: FreshnessSettings = ::serde_json::from_str(r#"{"maxProofAge": 600}"#).unwrap();
+        assert_eq!(settings, FreshnessSettings { max_proof_age: 600, clock_skew_tolerance: 0 });
+    }
+}
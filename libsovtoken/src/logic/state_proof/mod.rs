@@ -0,0 +1,370 @@
+/*!
+    Verification of state proofs without libindy
+
+    The nodes sign the roots of a ledger together with a BLS multi-signature. A
+    reply to a read request (GET_UTXO, GET_FEES) carries it in its `state_proof`:
+
+    ```JSON
+    {
+        "root_hash": <str: state root hash>,
+        "proof_nodes": <str>,
+        "multi_signature": {
+            "participants": [<str: node alias>],
+            "signature": <str: base58 multi-signature>,
+            "value": {
+                "ledger_id": <int>,
+                "pool_state_root_hash": <str>,
+                "state_root_hash": <str>,
+                "timestamp": <int>,
+                "txn_root_hash": <str>
+            }
+        }
+    }
+    ```
+
+    [`verify_multi_signature`] checks it against the BLS keys of the nodes (the
+    `blskey` of their NODE transactions), like libindy does when it registers
+    our state proof parsers.
+
+    The `proof_nodes` are checked by [`trie`], the age of the signature by
+    [`freshness`].
+
+    [`verify_multi_signature`]: fn.verify_multi_signature.html
+    [`trie`]: trie/index.html
+    [`freshness`]: freshness/index.html
+*/
+
+pub mod freshness;
+pub mod trie;
+
+use indy_crypto::bls::{Bls, Generator, MultiSignature as BlsMultiSignature, VerKey};
+use serde_json;
+use std::collections::{BTreeMap, HashSet};
+
+use ErrorCode;
+use utils::base58::FromBase58;
+use utils::constants::txn_fields::{RESULT, STATE_PROOF};
+
+/**
+    Generator of the BLS keys of indy-plenum nodes
+*/
+pub const DEFAULT_GENERATOR: &str = "3LHpUjiyFC2q2hD7MnwwNmVXiuaFbQx2XkAFJWzswCjgN1utjsCeLzHsKk1nJvFEaS4fcrUmVAkdhtPCYbrVyATZcmzwJReTcJqwqBCPTmTQ9uWPwz6rEncKb2pYYYFcdHa8N17HzVyTqKfgPi4X9pMetfT3A5xCHq54R2pDNYWVLDX";
+
+/**
+    The nodes trusted to sign state proofs
+
+    ### Fields
+    - `nodes` the base58 BLS key of each node alias
+    - `quorum` the number of nodes which have to sign, `n - f` by default
+    - `generator` the base58 BLS generator, [`DEFAULT_GENERATOR`] by default
+
+    [`DEFAULT_GENERATOR`]: constant.DEFAULT_GENERATOR.html
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeKeys {
+    pub nodes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quorum: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MultiSignatureValue {
+    pub ledger_id: u64,
+    pub pool_state_root_hash: String,
+    pub state_root_hash: String,
+    pub timestamp: u64,
+    pub txn_root_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MultiSignature {
+    pub participants: Vec<String>,
+    pub signature: String,
+    pub value: MultiSignatureValue,
+}
+
+impl NodeKeys {
+    /**
+        Signatures needed out of `n` nodes: `n - f`, `f` being the number of faulty
+        nodes the pool tolerates.
+    */
+    pub fn default_quorum(n: usize) -> usize {
+        n - n.saturating_sub(1) / 3
+    }
+
+    pub fn quorum(&self) -> usize {
+        self.quorum.unwrap_or_else(|| NodeKeys::default_quorum(self.nodes.len()))
+    }
+}
+
+impl MultiSignatureValue {
+    /**
+        The message the nodes sign: the values sorted by name joined with `:`,
+        like indy-plenum does.
+    */
+    pub fn as_single_value(&self) -> String {
+        format!("{}:{}:{}:{}:{}", self.ledger_id, self.pool_state_root_hash, self.state_root_hash, self.timestamp, self.txn_root_hash)
+    }
+}
+
+/**
+    Checks a BLS multi-signature against the keys of the nodes.
+
+    Errors with `CommonInvalidStructure` when `node_keys` is invalid, and with
+    `LedgerSecurityError` when fewer nodes than the quorum signed, a
+    participant is unknown or the signature is wrong.
+*/
+pub fn verify_multi_signature(multi_signature: &MultiSignature, node_keys: &NodeKeys) -> Result<(), ErrorCode> {
+    trace!("logic::state_proof::verify_multi_signature >> multi_signature: {:?}", multi_signature);
+    let quorum = node_keys.quorum();
+    if quorum == 0 || quorum > node_keys.nodes.len() {
+        error!("Invalid quorum {:?} for {:?} nodes", quorum, node_keys.nodes.len());
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let participants: HashSet<&String> = multi_signature.participants.iter().collect();
+    if participants.len() != multi_signature.participants.len() {
+        error!("Multi-signature has repeated participants");
+        return Err(ErrorCode::LedgerSecurityError);
+    }
+
+    if participants.len() < quorum {
+        error!("Multi-signature has {:?} participants, {:?} needed", participants.len(), quorum);
+        return Err(ErrorCode::LedgerSecurityError);
+    }
+
+    let mut ver_keys: Vec<VerKey> = Vec::with_capacity(participants.len());
+    for participant in &multi_signature.participants {
+        let key = match node_keys.nodes.get(participant) {
+            Some(key) => key,
+            None => {
+                error!("Multi-signature participant {:?} is not a known node", participant);
+                return Err(ErrorCode::LedgerSecurityError);
+            }
+        };
+
+        let key = decode_base58(key)?;
+        ver_keys.push(VerKey::from_bytes(&key).map_err(|_| ErrorCode::CommonInvalidStructure)?);
+    }
+
+    let generator = decode_base58(node_keys.generator.as_ref().map(String::as_str).unwrap_or(DEFAULT_GENERATOR))?;
+    let generator = Generator::from_bytes(&generator).map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    let signature = decode_base58(&multi_signature.signature).map_err(|_| ErrorCode::LedgerSecurityError)?;
+    let signature = BlsMultiSignature::from_bytes(&signature).map_err(|_| ErrorCode::LedgerSecurityError)?;
+
+    let ver_keys: Vec<&VerKey> = ver_keys.iter().collect();
+    let message = multi_signature.value.as_single_value();
+    let valid = Bls::verify_multi_sig(&signature, message.as_bytes(), &ver_keys, &generator).unwrap_or(false);
+
+    trace!("logic::state_proof::verify_multi_signature << valid: {:?}", valid);
+    if valid {
+        Ok(())
+    } else {
+        Err(ErrorCode::LedgerSecurityError)
+    }
+}
+
+/**
+    Checks the multi-signature of the state proof of a reply to a read request.
+
+    The signed `state_root_hash` has to be the `root_hash` of the state proof.
+    Returns the signed value, its `timestamp` tells how fresh the reply is.
+
+    Errors with `LedgerInvalidTransaction` when the reply has no state proof,
+    and with `LedgerSecurityError` when the signature doesn't verify or signs
+    another root hash.
+*/
+pub fn verify_state_proof_signature(reply_json: &str, node_keys: &NodeKeys) -> Result<MultiSignatureValue, ErrorCode> {
+    let reply: serde_json::Value = serde_json::from_str(reply_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    let state_proof = &reply[RESULT][STATE_PROOF];
+    let root_hash = state_proof["root_hash"].as_str()
+        .ok_or(ErrorCode::LedgerInvalidTransaction)
+        .map_err(map_err_err!())?;
+    let multi_signature: MultiSignature = serde_json::from_value(state_proof["multi_signature"].clone())
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::LedgerInvalidTransaction)?;
+
+    if multi_signature.value.state_root_hash != root_hash {
+        error!("State proof root hash {:?} is not the signed one {:?}", root_hash, multi_signature.value.state_root_hash);
+        return Err(ErrorCode::LedgerSecurityError);
+    }
+
+    verify_multi_signature(&multi_signature, node_keys)?;
+    Ok(multi_signature.value)
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn decode_base58(value: &str) -> Result<Vec<u8>, ErrorCode> {
+    value.from_base58()
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)
+}
+
+
+#[cfg(test)]
+mod state_proof_tests {
+    use super::*;
+    use indy_crypto::bls::{SignKey, Signature};
+    use utils::base58::IntoBase58;
+
+    struct Pool {
+        generator: Generator,
+        keys: Vec<(String, SignKey, VerKey)>,
+    }
+
+    impl Pool {
+        fn new(n: usize) -> Pool {
+            let generator = Generator::new().unwrap();
+            let keys = (0..n).map(|i| {
+                let sign_key = SignKey::new(None).unwrap();
+                let ver_key = VerKey::new(&generator, &sign_key).unwrap();
+                (format!("Node{}", i), sign_key, ver_key)
+            }).collect();
+            Pool { generator, keys }
+        }
+
+        fn node_keys(&self) -> NodeKeys {
+            NodeKeys {
+                nodes: self.keys.iter().map(|&(ref name, _, ref ver_key)| (name.clone(), ver_key.as_bytes().into_base58())).collect(),
+                quorum: None,
+                generator: Some(self.generator.as_bytes().into_base58()),
+            }
+        }
+
+        fn sign(&self, signers: &[usize], value: &MultiSignatureValue) -> MultiSignature {
+            let message = value.as_single_value();
+            let signatures: Vec<Signature> = signers.iter()
+                .map(|i| Bls::sign(message.as_bytes(), &self.keys[*i].1).unwrap())
+                .collect();
+            let signatures: Vec<&Signature> = signatures.iter().collect();
+
+            MultiSignature {
+                participants: signers.iter().map(|i| self.keys[*i].0.clone()).collect(),
+                signature: BlsMultiSignature::new(&signatures).unwrap().as_bytes().into_base58(),
+                value: value.clone(),
+            }
+        }
+    }
+
+    fn value() -> MultiSignatureValue {
+        MultiSignatureValue {
+            ledger_id: 1001,
+            pool_state_root_hash: "9i3dHE9Q2Drq4TY8J8G7JZmRt6ZGn8E3n6ZN6djSG1Ce".to_string(),
+            state_root_hash: "7fq8gU2iLJ6SbUcsrngLTxPTVZyLqxDvA4jHeNvGzTv4".to_string(),
+            timestamp: 1530059419,
+            txn_root_hash: "AKboMiJZJm247Sa7GsKQo5Ba8ukgxTQ3DsLc2pyVuDkU".to_string(),
+        }
+    }
+
+    #[test]
+    fn single_value_is_sorted_by_name() {
+        assert_eq!(
+            value().as_single_value(),
+            "1001:9i3dHE9Q2Drq4TY8J8G7JZmRt6ZGn8E3n6ZN6djSG1Ce:7fq8gU2iLJ6SbUcsrngLTxPTVZyLqxDvA4jHeNvGzTv4:1530059419:AKboMiJZJm247Sa7GsKQo5Ba8ukgxTQ3DsLc2pyVuDkU"
+        );
+    }
+
+    #[test]
+    fn default_quorum() {
+        assert_eq!(NodeKeys::default_quorum(1), 1);
+        assert_eq!(NodeKeys::default_quorum(4), 3);
+        assert_eq!(NodeKeys::default_quorum(7), 5);
+        assert_eq!(NodeKeys::default_quorum(25), 17);
+    }
+
+    #[test]
+    fn verify_multi_signature_of_quorum() {
+        let pool = Pool::new(4);
+        verify_multi_signature(&pool.sign(&[0, 1, 3], &value()), &pool.node_keys()).unwrap();
+    }
+
+    #[test]
+    fn verify_multi_signature_below_quorum() {
+        let pool = Pool::new(4);
+        let err = verify_multi_signature(&pool.sign(&[0, 1], &value()), &pool.node_keys()).unwrap_err();
+        assert_eq!(err, ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn verify_multi_signature_with_custom_quorum() {
+        let pool = Pool::new(4);
+        let mut node_keys = pool.node_keys();
+        node_keys.quorum = Some(2);
+        verify_multi_signature(&pool.sign(&[2, 3], &value()), &node_keys).unwrap();
+    }
+
+    #[test]
+    fn verify_multi_signature_of_other_value() {
+        let pool = Pool::new(4);
+        let mut multi_signature = pool.sign(&[0, 1, 2], &value());
+        multi_signature.value.timestamp += 1;
+
+        let err = verify_multi_signature(&multi_signature, &pool.node_keys()).unwrap_err();
+        assert_eq!(err, ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn verify_multi_signature_claimed_participant() {
+        let pool = Pool::new(4);
+        let mut multi_signature = pool.sign(&[0, 1], &value());
+        multi_signature.participants.push("Node2".to_string());
+
+        let err = verify_multi_signature(&multi_signature, &pool.node_keys()).unwrap_err();
+        assert_eq!(err, ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn verify_multi_signature_unknown_or_repeated_participant() {
+        let pool = Pool::new(4);
+
+        let mut unknown = pool.sign(&[0, 1, 2], &value());
+        unknown.participants[2] = "Mallory".to_string();
+        let mut repeated = pool.sign(&[0, 1, 2], &value());
+        repeated.participants[2] = "Node0".to_string();
+
+        assert_eq!(verify_multi_signature(&unknown, &pool.node_keys()).unwrap_err(), ErrorCode::LedgerSecurityError);
+        assert_eq!(verify_multi_signature(&repeated, &pool.node_keys()).unwrap_err(), ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn verify_state_proof_signature_of_reply() {
+        let pool = Pool::new(4);
+        let multi_signature = pool.sign(&[0, 1, 2, 3], &value());
+        let reply = json!({
+            "op": "REPLY",
+            "result": {
+                "type": "10002",
+                "state_proof": {
+                    "root_hash": value().state_root_hash,
+                    "proof_nodes": "",
+                    "multi_signature": multi_signature
+                }
+            }
+        });
+
+        assert_eq!(verify_state_proof_signature(&reply.to_string(), &pool.node_keys()).unwrap(), value());
+
+        let mut other_root = reply.clone();
+        other_root["result"]["state_proof"]["root_hash"] = json!("5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms");
+        assert_eq!(verify_state_proof_signature(&other_root.to_string(), &pool.node_keys()).unwrap_err(), ErrorCode::LedgerSecurityError);
+    }
+
+    #[test]
+    fn verify_state_proof_signature_without_proof() {
+        let pool = Pool::new(4);
+        let reply = json!({"op": "REPLY", "result": {"type": "10002"}}).to_string();
+        assert_eq!(verify_state_proof_signature(&reply, &pool.node_keys()).unwrap_err(), ErrorCode::LedgerInvalidTransaction);
+    }
+}
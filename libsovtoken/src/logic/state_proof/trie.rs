@@ -0,0 +1,726 @@
+/*!
+    Verification of the `proof_nodes` of a state proof
+
+    The state of the ledger is a Merkle-Patricia trie. The `proof_nodes` of a
+    reply are the base64 of the RLP list of the trie nodes on the paths to the
+    keys of the reply, and `root_hash` is the base58 SHA3-256 of the root node.
+
+    A node is a leaf `[path, value]`, an extension `[path, child]` or a branch
+    `[child * 16, value]`, paths being hex-prefix encoded nibbles. A child
+    shorter than 32 bytes is embedded in its parent, otherwise it is referenced
+    by its hash.
+
+    [`verify_parsed_state_proof`] checks the key values the extractors of
+    [`parse_get_utxo_response`] and [`parse_get_txn_fees`] give to libindy.
+
+    [`verify_parsed_state_proof`]: fn.verify_parsed_state_proof.html
+    [`parse_get_utxo_response`]: ../../parsers/parse_get_utxo_response/index.html
+    [`parse_get_txn_fees`]: ../../parsers/parse_get_txn_fees/index.html
+*/
+
+use base64;
+use serde_json;
+use sha3::{Digest, Sha3_256};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+
+use ErrorCode;
+use logic::parsers::common::{KeyValuesInSP, KeyValueSimpleDataVerificationType, ParsedSP};
+use logic::parsers::parse_get_txn_fees::get_fees_state_proof_extractor;
+use logic::parsers::parse_get_utxo_response::get_utxo_state_proof_extractor;
+use utils::base58::FromBase58;
+use utils::constants::txn_fields::RESULT;
+use utils::constants::txn_types::{GET_FEES, GET_UTXO};
+
+/**
+    A key of a state proof and its value in the trie, `None` when the proof
+    shows the key is absent.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedKeyValue {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+/**
+    The trie nodes of a state proof, indexed by hash
+*/
+pub struct ProofTrie {
+    nodes: HashMap<Vec<u8>, Vec<u8>>,
+    root_hash: Vec<u8>,
+}
+
+impl ProofTrie {
+    /**
+        Decodes the base64 `proof_nodes` of a state proof.
+
+        Errors with `LedgerInvalidTransaction` when the node of the base58
+        `root_hash` isn't part of the proof.
+    */
+    pub fn new(proof_nodes: &str, root_hash: &str) -> Result<ProofTrie, ErrorCode> {
+        let proof = base64::decode(proof_nodes)
+            .map_err(map_err_err!())
+            .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+        let root_hash = root_hash.from_base58()
+            .map_err(map_err_err!())
+            .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+        let nodes = match rlp_decode(&proof)? {
+            Rlp::List(nodes) => nodes,
+            Rlp::Bytes(_) => return Err(ErrorCode::CommonInvalidStructure),
+        };
+
+        let nodes: HashMap<Vec<u8>, Vec<u8>> = nodes.into_iter()
+            .map(|node| (sha3_256(node), node.to_vec()))
+            .collect();
+
+        if !nodes.contains_key(&root_hash) {
+            error!("State proof doesn't contain its root node");
+            return Err(ErrorCode::LedgerInvalidTransaction);
+        }
+
+        Ok(ProofTrie { nodes, root_hash })
+    }
+
+    /**
+        The value of `key`, `None` when the proof shows it isn't in the trie.
+
+        Errors with `LedgerInvalidTransaction` when the proof lacks a node on the
+        path of the key.
+    */
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, ErrorCode> {
+        let path = to_nibbles(key);
+        let mut path: &[u8] = &path;
+        let mut node = self.root()?;
+
+        loop {
+            match node {
+                Node::Empty => return Ok(None),
+                Node::Leaf(leaf_path, value) => {
+                    return Ok(if leaf_path.as_slice() == path { Some(value.to_vec()) } else { None });
+                },
+                Node::Extension(extension_path, child) => {
+                    if !path.starts_with(&extension_path) {
+                        return Ok(None);
+                    }
+                    path = &path[extension_path.len()..];
+                    node = self.resolve(child)?;
+                },
+                Node::Branch(children, value) => {
+                    if path.is_empty() {
+                        return Ok(if value.is_empty() { None } else { Some(value.to_vec()) });
+                    }
+                    node = self.resolve(children[path[0] as usize])?;
+                    path = &path[1..];
+                },
+            }
+        }
+    }
+
+    /**
+        All the keys starting with `prefix` and their values, in key order.
+
+        Errors with `LedgerInvalidTransaction` when the proof lacks a node of the
+        sub-trie, as it can't show there are no other keys.
+    */
+    pub fn get_all_with_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ErrorCode> {
+        let prefix = to_nibbles(prefix);
+        let mut consumed: Vec<u8> = Vec::new();
+        let mut node = self.root()?;
+
+        loop {
+            let remaining = &prefix[consumed.len()..];
+            if remaining.is_empty() {
+                break;
+            }
+
+            node = match node {
+                Node::Empty => return Ok(Vec::new()),
+                Node::Leaf(ref leaf_path, _) if leaf_path.starts_with(remaining) => break,
+                Node::Leaf(..) => return Ok(Vec::new()),
+                Node::Extension(ref extension_path, _) if extension_path.starts_with(remaining) => break,
+                Node::Extension(extension_path, child) => {
+                    if !remaining.starts_with(&extension_path) {
+                        return Ok(Vec::new());
+                    }
+                    consumed.extend(extension_path);
+                    self.resolve(child)?
+                },
+                Node::Branch(children, _) => {
+                    consumed.push(remaining[0]);
+                    self.resolve(children[remaining[0] as usize])?
+                },
+            };
+        }
+
+        let mut values = Vec::new();
+        self.collect(node, consumed, &mut values)?;
+
+        values.into_iter()
+            .map(|(path, value)| from_nibbles(&path).map(|key| (key, value)))
+            .collect()
+    }
+
+    fn root(&self) -> Result<Node, ErrorCode> {
+        decode_node(&self.nodes[&self.root_hash])
+    }
+
+    fn resolve<'a>(&'a self, reference: &'a [u8]) -> Result<Node<'a>, ErrorCode> {
+        match rlp_decode(reference)? {
+            Rlp::List(_) => decode_node(reference),
+            Rlp::Bytes(hash) if hash.is_empty() => Ok(Node::Empty),
+            Rlp::Bytes(hash) if hash.len() == 32 => {
+                match self.nodes.get(hash) {
+                    Some(node) => decode_node(node),
+                    None => {
+                        error!("State proof lacks the trie node {:?}", hash);
+                        Err(ErrorCode::LedgerInvalidTransaction)
+                    }
+                }
+            },
+            Rlp::Bytes(_) => Err(ErrorCode::CommonInvalidStructure),
+        }
+    }
+
+    fn collect(&self, node: Node, path: Vec<u8>, values: &mut Vec<(Vec<u8>, Vec<u8>)>) -> Result<(), ErrorCode> {
+        match node {
+            Node::Empty => {},
+            Node::Leaf(leaf_path, value) => {
+                values.push(([path, leaf_path].concat(), value.to_vec()));
+            },
+            Node::Extension(extension_path, child) => {
+                self.collect(self.resolve(child)?, [path, extension_path].concat(), values)?;
+            },
+            Node::Branch(children, value) => {
+                if !value.is_empty() {
+                    values.push((path.clone(), value.to_vec()));
+                }
+                for (nibble, child) in children.into_iter().enumerate() {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble as u8);
+                    self.collect(self.resolve(child)?, child_path, values)?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/**
+    Verifies the key values of a [`ParsedSP`] against its proof nodes.
+
+    `Simple` key values are base64 keys checked one by one.
+    `NumericalSuffixAscendingNoGaps` ones have to be all the keys of the trie
+    starting with the prefix whose numerical suffix is within `[from, next)`,
+    and `next`, when given, has to be in the trie.
+
+    Errors with `LedgerInvalidTransaction` when a value doesn't match the trie.
+    The BLS multi-signature of `root_hash` isn't checked here, see
+    [`verify_state_proof_signature`].
+
+    [`ParsedSP`]: ../../parsers/common/struct.ParsedSP.html
+    [`verify_state_proof_signature`]: ../fn.verify_state_proof_signature.html
+*/
+pub fn verify_parsed_state_proof(parsed_sp: &ParsedSP) -> Result<Vec<VerifiedKeyValue>, ErrorCode> {
+    trace!("logic::state_proof::trie::verify_parsed_state_proof >> root_hash: {:?}", parsed_sp.root_hash);
+    let trie = ProofTrie::new(&parsed_sp.proof_nodes, &parsed_sp.root_hash)?;
+
+    let data = match parsed_sp.kvs_to_verify {
+        KeyValuesInSP::Simple(ref data) => data,
+        KeyValuesInSP::SubTrie(_) => {
+            error!("Sub-trie state proofs are not supported");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+
+    let verified = match data.verification_type {
+        KeyValueSimpleDataVerificationType::Simple => {
+            let mut verified = Vec::with_capacity(data.kvs.len());
+            for &(ref key, ref expected) in &data.kvs {
+                let key = base64::decode(key)
+                    .map_err(map_err_err!())
+                    .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+                let value = match trie.get(&key)? {
+                    Some(value) => Some(decode_value(&value)?),
+                    None => None,
+                };
+
+                if !values_match(expected, &value) {
+                    error!("State proof value {:?} is not {:?}", value, expected);
+                    return Err(ErrorCode::LedgerInvalidTransaction);
+                }
+
+                let key = String::from_utf8(key).map_err(|_| ErrorCode::CommonInvalidStructure)?;
+                verified.push(VerifiedKeyValue { key, value });
+            }
+            verified
+        },
+        KeyValueSimpleDataVerificationType::NumericalSuffixAscendingNoGaps(ref range) => {
+            let mut expected = Vec::with_capacity(data.kvs.len());
+            for &(ref key, ref value) in &data.kvs {
+                let suffix = numerical_suffix(key, &range.prefix).ok_or(ErrorCode::CommonInvalidStructure)?;
+                expected.push((suffix, key, value));
+            }
+            expected.sort_by_key(|&(suffix, _, _)| suffix);
+
+            let mut in_range = Vec::new();
+            let mut next_found = false;
+            for (key, value) in trie.get_all_with_prefix(range.prefix.as_bytes())? {
+                let key = String::from_utf8(key).map_err(|_| ErrorCode::CommonInvalidStructure)?;
+                let suffix = match numerical_suffix(&key, &range.prefix) {
+                    Some(suffix) => suffix,
+                    None => continue
+                };
+
+                next_found |= range.next == Some(suffix);
+                if range.from.map(|from| suffix >= from).unwrap_or(true) && range.next.map(|next| suffix < next).unwrap_or(true) {
+                    in_range.push((suffix, VerifiedKeyValue { key, value: Some(decode_value(&value)?) }));
+                }
+            }
+            in_range.sort_by_key(|&(suffix, _)| suffix);
+
+            let matches = in_range.len() == expected.len()
+                && in_range.iter().zip(expected.iter())
+                    .all(|(&(_, ref actual), &(_, key, value))| actual.key == *key && values_match(value, &actual.value));
+
+            if !matches || (range.next.is_some() && !next_found) {
+                error!("State proof doesn't show all the keys of {:?} from {:?} to {:?}", range.prefix, range.from, range.next);
+                return Err(ErrorCode::LedgerInvalidTransaction);
+            }
+
+            in_range.into_iter().map(|(_, verified)| verified).collect()
+        },
+    };
+
+    trace!("logic::state_proof::trie::verify_parsed_state_proof << verified: {:?}", verified);
+    Ok(verified)
+}
+
+/**
+    Verifies the proof nodes of a GET_UTXO or GET_FEES reply.
+
+    The key values are the ones the state proof parsers registered with libindy
+    extract from the reply.
+*/
+pub fn verify_reply_state_proof(reply_json: &str) -> Result<Vec<VerifiedKeyValue>, ErrorCode> {
+    let reply: serde_json::Value = serde_json::from_str(reply_json)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    let extractor: fn(*const c_char, *mut *const c_char) -> ErrorCode = match reply[RESULT]["type"].as_str() {
+        Some(GET_UTXO) => get_utxo_state_proof_extractor,
+        Some(GET_FEES) => get_fees_state_proof_extractor,
+        txn_type => {
+            error!("No state proof to verify for transaction type {:?}", txn_type);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+
+    let reply = CString::new(reply_json).map_err(|_| ErrorCode::CommonInvalidStructure)?;
+    let mut parsed_sp: *const c_char = ptr::null();
+    let ec = extractor(reply.as_ptr(), &mut parsed_sp);
+    if ec != ErrorCode::Success {
+        return Err(ec);
+    }
+
+    let parsed_sp = unsafe { CString::from_raw(parsed_sp as *mut c_char) };
+    let parsed_sp: Vec<ParsedSP> = serde_json::from_slice(parsed_sp.as_bytes())
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidState)?;
+
+    let mut verified = Vec::new();
+    for parsed_sp in &parsed_sp {
+        verified.extend(verify_parsed_state_proof(parsed_sp)?);
+    }
+    Ok(verified)
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+enum Rlp<'a> {
+    Bytes(&'a [u8]),
+    /// the encoded items
+    List(Vec<&'a [u8]>),
+}
+
+enum Node<'a> {
+    Empty,
+    Leaf(Vec<u8>, &'a [u8]),
+    Extension(Vec<u8>, &'a [u8]),
+    Branch(Vec<&'a [u8]>, &'a [u8]),
+}
+
+fn sha3_256(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha3_256::default();
+    hasher.input(data);
+    hasher.result().to_vec()
+}
+
+/*
+    Splits the first item off `data`: whether it is a list, its payload and the
+    rest of `data`.
+*/
+fn rlp_split(data: &[u8]) -> Result<(bool, &[u8], &[u8]), ErrorCode> {
+    let first = *data.first().ok_or(ErrorCode::CommonInvalidStructure)?;
+    let (is_list, header_len, payload_len) = match first {
+        0x00..=0x7f => return Ok((false, &data[..1], &data[1..])),
+        0x80..=0xb7 => (false, 1, (first - 0x80) as usize),
+        0xb8..=0xbf => (false, 1 + (first - 0xb7) as usize, rlp_length(data, (first - 0xb7) as usize)?),
+        0xc0..=0xf7 => (true, 1, (first - 0xc0) as usize),
+        _ => (true, 1 + (first - 0xf7) as usize, rlp_length(data, (first - 0xf7) as usize)?),
+    };
+
+    let end = header_len.checked_add(payload_len).ok_or(ErrorCode::CommonInvalidStructure)?;
+    if end > data.len() {
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    Ok((is_list, &data[header_len..end], &data[end..]))
+}
+
+fn rlp_length(data: &[u8], length_len: usize) -> Result<usize, ErrorCode> {
+    if length_len > 8 || data.len() <= length_len {
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    Ok(data[1..=length_len].iter().fold(0usize, |length, byte| (length << 8) | *byte as usize))
+}
+
+fn rlp_decode(data: &[u8]) -> Result<Rlp, ErrorCode> {
+    let (is_list, payload, rest) = rlp_split(data)?;
+    if !rest.is_empty() {
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    if !is_list {
+        return Ok(Rlp::Bytes(payload));
+    }
+
+    let mut items = Vec::new();
+    let mut remaining = payload;
+    while !remaining.is_empty() {
+        let (_, _, rest) = rlp_split(remaining)?;
+        items.push(&remaining[..remaining.len() - rest.len()]);
+        remaining = rest;
+    }
+    Ok(Rlp::List(items))
+}
+
+fn rlp_bytes(data: &[u8]) -> Result<&[u8], ErrorCode> {
+    match rlp_decode(data)? {
+        Rlp::Bytes(bytes) => Ok(bytes),
+        Rlp::List(_) => Err(ErrorCode::CommonInvalidStructure),
+    }
+}
+
+fn decode_node(data: &[u8]) -> Result<Node, ErrorCode> {
+    match rlp_decode(data)? {
+        Rlp::Bytes(bytes) if bytes.is_empty() => Ok(Node::Empty),
+        Rlp::List(ref items) if items.len() == 17 => Ok(Node::Branch(items[..16].to_vec(), rlp_bytes(items[16])?)),
+        Rlp::List(ref items) if items.len() == 2 => {
+            let (path, is_leaf) = decode_hex_prefix(rlp_bytes(items[0])?)?;
+            if is_leaf {
+                Ok(Node::Leaf(path, rlp_bytes(items[1])?))
+            } else {
+                Ok(Node::Extension(path, items[1]))
+            }
+        },
+        _ => Err(ErrorCode::CommonInvalidStructure),
+    }
+}
+
+fn decode_hex_prefix(encoded: &[u8]) -> Result<(Vec<u8>, bool), ErrorCode> {
+    let first = *encoded.first().ok_or(ErrorCode::CommonInvalidStructure)?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let mut nibbles = Vec::with_capacity(encoded.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    }
+    nibbles.extend(to_nibbles(&encoded[1..]));
+    Ok((nibbles, flag & 2 == 2))
+}
+
+fn to_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| vec![byte >> 4, byte & 0x0f]).collect()
+}
+
+fn from_nibbles(nibbles: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+    if nibbles.len() % 2 != 0 {
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    Ok(nibbles.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect())
+}
+
+/*
+    Values of the state are stored as the RLP list of the value.
+*/
+fn decode_value(value: &[u8]) -> Result<String, ErrorCode> {
+    let value = match rlp_decode(value)? {
+        Rlp::List(ref items) if items.len() == 1 => rlp_bytes(items[0])?,
+        Rlp::Bytes(bytes) => bytes,
+        Rlp::List(_) => return Err(ErrorCode::CommonInvalidStructure),
+    };
+    String::from_utf8(value.to_vec()).map_err(|_| ErrorCode::CommonInvalidStructure)
+}
+
+/*
+    JSON values, like the fees, don't have to keep the order of their keys.
+*/
+fn values_match(expected: &Option<String>, actual: &Option<String>) -> bool {
+    match (expected, actual) {
+        (&Some(ref expected), &Some(ref actual)) => {
+            expected == actual || match (serde_json::from_str::<serde_json::Value>(expected), serde_json::from_str::<serde_json::Value>(actual)) {
+                (Ok(expected), Ok(actual)) => expected == actual,
+                _ => false
+            }
+        },
+        (&None, &None) => true,
+        _ => false
+    }
+}
+
+fn numerical_suffix(key: &str, prefix: &str) -> Option<u64> {
+    if key.starts_with(prefix) {
+        key[prefix.len()..].parse().ok()
+    } else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod trie_tests {
+    use super::*;
+    use logic::parsers::common::{KeyValueSimpleData, NumericalSuffixAscendingNoGapsData};
+    use utils::base58::IntoBase58;
+
+    fn encode_length(offset: u8, length: usize) -> Vec<u8> {
+        if length < 56 {
+            return vec![offset + length as u8];
+        }
+        let length_bytes: Vec<u8> = (length as u64).to_be_bytes().iter().cloned().skip_while(|byte| *byte == 0).collect();
+        [vec![offset + 55 + length_bytes.len() as u8], length_bytes].concat()
+    }
+
+    fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+        if bytes.len() == 1 && bytes[0] < 0x80 {
+            return bytes.to_vec();
+        }
+        [encode_length(0x80, bytes.len()), bytes.to_vec()].concat()
+    }
+
+    fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+        let payload = items.concat();
+        [encode_length(0xc0, payload.len()), payload].concat()
+    }
+
+    fn encode_hex_prefix(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+        let flag = if is_leaf { 2 } else { 0 };
+        let mut encoded = if nibbles.len() % 2 == 1 { vec![(flag + 1) << 4 | nibbles[0]] } else { vec![flag << 4] };
+        let even = &nibbles[nibbles.len() % 2..];
+        encoded.extend(even.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+        encode_bytes(&encoded)
+    }
+
+    fn reference(node: Vec<u8>, proof: &mut Vec<Vec<u8>>) -> Vec<u8> {
+        if node.len() < 32 {
+            return node;
+        }
+        let hash = sha3_256(&node);
+        proof.push(node);
+        encode_bytes(&hash)
+    }
+
+    fn build(pairs: &[(Vec<u8>, Vec<u8>)], proof: &mut Vec<Vec<u8>>) -> Vec<u8> {
+        if pairs.len() == 1 {
+            return encode_list(&[encode_hex_prefix(&pairs[0].0, true), encode_bytes(&pairs[0].1)]);
+        }
+
+        let common = (0..).take_while(|i| pairs.iter().all(|pair| pair.0.len() > *i && pair.0[*i] == pairs[0].0[*i])).count();
+        if common > 0 {
+            let stripped: Vec<(Vec<u8>, Vec<u8>)> = pairs.iter().map(|pair| (pair.0[common..].to_vec(), pair.1.clone())).collect();
+            let child = build(&stripped, proof);
+            return encode_list(&[encode_hex_prefix(&pairs[0].0[..common], false), reference(child, proof)]);
+        }
+
+        let mut items = Vec::new();
+        for nibble in 0..16 {
+            let group: Vec<(Vec<u8>, Vec<u8>)> = pairs.iter()
+                .filter(|pair| !pair.0.is_empty() && pair.0[0] == nibble)
+                .map(|pair| (pair.0[1..].to_vec(), pair.1.clone()))
+                .collect();
+            items.push(if group.is_empty() { encode_bytes(&[]) } else { let child = build(&group, proof); reference(child, proof) });
+        }
+        let value = pairs.iter().find(|pair| pair.0.is_empty()).map(|pair| pair.1.clone()).unwrap_or_default();
+        items.push(encode_bytes(&value));
+        encode_list(&items)
+    }
+
+    /*
+        Builds a trie of the values stored like the ledger does, returns the
+        `proof_nodes` and `root_hash`.
+    */
+    fn proof_of(state: &[(&str, &str)]) -> (String, String) {
+        let pairs: Vec<(Vec<u8>, Vec<u8>)> = state.iter()
+            .map(|&(key, value)| (to_nibbles(key.as_bytes()), encode_list(&[encode_bytes(value.as_bytes())])))
+            .collect();
+        let mut proof = Vec::new();
+        let root = build(&pairs, &mut proof);
+        let root_hash = sha3_256(&root).into_base58();
+        proof.push(root);
+        (base64::encode(&encode_list(&proof)), root_hash)
+    }
+
+    fn parsed_sp(state: &[(&str, &str)], kvs_to_verify: KeyValuesInSP) -> ParsedSP {
+        let (proof_nodes, root_hash) = proof_of(state);
+        ParsedSP { proof_nodes, root_hash, kvs_to_verify, multi_signature: json!({}) }
+    }
+
+    fn utxo_kvs(kvs: &[(&str, &str)], from: Option<u64>, next: Option<u64>) -> KeyValuesInSP {
+        KeyValuesInSP::Simple(KeyValueSimpleData {
+            kvs: kvs.iter().map(|&(key, value)| (key.to_string(), Some(value.to_string()))).collect(),
+            verification_type: KeyValueSimpleDataVerificationType::NumericalSuffixAscendingNoGaps(NumericalSuffixAscendingNoGapsData {
+                from,
+                next,
+                prefix: "addr:".to_string()
+            })
+        })
+    }
+
+    const UTXO_STATE: [(&str, &str); 5] = [("addr:4", "10"), ("addr:6", "20"), ("addr:10", "30"), ("addr:12", "40"), ("other:5", "50")];
+
+    #[test]
+    fn rlp_decode_long_items() {
+        let long = vec![7u8; 300];
+        let encoded = encode_list(&[encode_bytes(&long), encode_bytes(b"a"), encode_bytes(&[])]);
+        match rlp_decode(&encoded).unwrap() {
+            Rlp::List(items) => {
+                assert_eq!(items.len(), 3);
+                assert_eq!(rlp_bytes(items[0]).unwrap(), long.as_slice());
+                assert_eq!(rlp_bytes(items[1]).unwrap(), b"a");
+                assert!(rlp_bytes(items[2]).unwrap().is_empty());
+            },
+            Rlp::Bytes(_) => panic!("not a list"),
+        }
+    }
+
+    #[test]
+    fn rlp_decode_truncated() {
+        let encoded = encode_bytes(&[7u8; 40]);
+        assert!(rlp_decode(&encoded[..30]).is_err());
+        assert!(rlp_decode(&[0x81]).is_err());
+    }
+
+    #[test]
+    fn trie_get() {
+        let (proof_nodes, root_hash) = proof_of(&UTXO_STATE);
+        let trie = ProofTrie::new(&proof_nodes, &root_hash).unwrap();
+
+        assert_eq!(decode_value(&trie.get(b"addr:10").unwrap().unwrap()).unwrap(), "30");
+        assert_eq!(decode_value(&trie.get(b"other:5").unwrap().unwrap()).unwrap(), "50");
+        assert_eq!(trie.get(b"addr:11").unwrap(), None);
+        assert_eq!(trie.get(b"addr:").unwrap(), None);
+    }
+
+    #[test]
+    fn trie_without_root() {
+        let (proof_nodes, _) = proof_of(&UTXO_STATE);
+        let (_, other_root_hash) = proof_of(&[("addr:4", "10")]);
+        assert_eq!(ProofTrie::new(&proof_nodes, &other_root_hash).err(), Some(ErrorCode::LedgerInvalidTransaction));
+    }
+
+    #[test]
+    fn trie_get_all_with_prefix() {
+        let (proof_nodes, root_hash) = proof_of(&UTXO_STATE);
+        let trie = ProofTrie::new(&proof_nodes, &root_hash).unwrap();
+
+        let keys: Vec<Vec<u8>> = trie.get_all_with_prefix(b"addr:").unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![b"addr:10".to_vec(), b"addr:12".to_vec(), b"addr:4".to_vec(), b"addr:6".to_vec()]);
+        assert_eq!(trie.get_all_with_prefix(b"addr:1").unwrap().len(), 2);
+        assert!(trie.get_all_with_prefix(b"nobody:").unwrap().is_empty());
+    }
+
+    #[test]
+    fn verify_fees() {
+        let fees = r#"{"1":4,"10001":8}"#;
+        let kvs = KeyValuesInSP::Simple(KeyValueSimpleData {
+            kvs: vec![(base64::encode("200:fees"), Some(r#"{"10001":8,"1":4}"#.to_string()))],
+            verification_type: KeyValueSimpleDataVerificationType::Simple
+        });
+        let verified = verify_parsed_state_proof(&parsed_sp(&[("200:fees", fees)], kvs)).unwrap();
+        assert_eq!(verified, vec![VerifiedKeyValue { key: "200:fees".to_string(), value: Some(fees.to_string()) }]);
+    }
+
+    #[test]
+    fn verify_fees_with_other_value() {
+        let kvs = KeyValuesInSP::Simple(KeyValueSimpleData {
+            kvs: vec![(base64::encode("200:fees"), Some(r#"{"1":5}"#.to_string()))],
+            verification_type: KeyValueSimpleDataVerificationType::Simple
+        });
+        let err = verify_parsed_state_proof(&parsed_sp(&[("200:fees", r#"{"1":4}"#)], kvs)).unwrap_err();
+        assert_eq!(err, ErrorCode::LedgerInvalidTransaction);
+    }
+
+    #[test]
+    fn verify_utxos_no_gaps() {
+        let kvs = utxo_kvs(&[("addr:10", "30"), ("addr:4", "10"), ("addr:6", "20"), ("addr:12", "40")], None, None);
+        let verified = verify_parsed_state_proof(&parsed_sp(&UTXO_STATE, kvs)).unwrap();
+        let keys: Vec<&str> = verified.iter().map(|kv| kv.key.as_str()).collect();
+        assert_eq!(keys, vec!["addr:4", "addr:6", "addr:10", "addr:12"]);
+    }
+
+    #[test]
+    fn verify_utxos_page() {
+        let kvs = utxo_kvs(&[("addr:6", "20"), ("addr:10", "30")], Some(5), Some(12));
+        assert_eq!(verify_parsed_state_proof(&parsed_sp(&UTXO_STATE, kvs)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn verify_utxos_with_gap() {
+        let kvs = utxo_kvs(&[("addr:4", "10"), ("addr:10", "30"), ("addr:12", "40")], None, None);
+        let err = verify_parsed_state_proof(&parsed_sp(&UTXO_STATE, kvs)).unwrap_err();
+        assert_eq!(err, ErrorCode::LedgerInvalidTransaction);
+    }
+
+    #[test]
+    fn verify_utxos_with_missing_next() {
+        let kvs = utxo_kvs(&[("addr:6", "20"), ("addr:10", "30"), ("addr:12", "40")], Some(5), Some(13));
+        let err = verify_parsed_state_proof(&parsed_sp(&UTXO_STATE, kvs)).unwrap_err();
+        assert_eq!(err, ErrorCode::LedgerInvalidTransaction);
+    }
+
+    #[test]
+    fn verify_utxos_with_other_amount() {
+        let kvs = utxo_kvs(&[("addr:4", "10"), ("addr:6", "25"), ("addr:10", "30"), ("addr:12", "40")], None, None);
+        let err = verify_parsed_state_proof(&parsed_sp(&UTXO_STATE, kvs)).unwrap_err();
+        assert_eq!(err, ErrorCode::LedgerInvalidTransaction);
+    }
+
+    #[test]
+    fn verify_reply_of_get_fees() {
+        let (proof_nodes, root_hash) = proof_of(&[("200:fees", r#"{"1":4,"10001":8}"#)]);
+        let reply = json!({
+            "op": "REPLY",
+            "result": {
+                "type": "20001",
+                "fees": {"1": 4, "10001": 8},
+                "state_proof": {
+                    "multi_signature": {},
+                    "root_hash": root_hash,
+                    "proof_nodes": proof_nodes
+                }
+            }
+        });
+        let verified = verify_reply_state_proof(&reply.to_string()).unwrap();
+        assert_eq!(verified[0].key, "200:fees");
+    }
+}
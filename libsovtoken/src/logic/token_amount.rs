@@ -0,0 +1,309 @@
+/*!
+ * A [`TokenAmount`] is a number of sovatoms, the smallest unit of the token.
+ * Release builds don't check overflows, so `TokenAmount` has no `+` or `-`:
+ * sums go through [`checked_add`] or [`try_add`], the latter failing with an
+ * [`AmountOverflow`] instead of wrapping around.
+ *
+ * It is serialized as the json number of sovatoms, like the ledger does, and
+ * parsed from strings with `FromStr` or [`parse_decimal`]. [`decimal`] writes it
+ * with a precision, e.g. [`TOKEN_PRECISION`] to show tokens instead of sovatoms.
+ *
+ * [`TokenAmount`]: struct.TokenAmount.html
+ * [`checked_add`]: struct.TokenAmount.html#method.checked_add
+ * [`try_add`]: struct.TokenAmount.html#method.try_add
+ * [`decimal`]: struct.TokenAmount.html#method.decimal
+ * [`parse_decimal`]: struct.TokenAmount.html#method.parse_decimal
+ * [`AmountOverflow`]: struct.AmountOverflow.html
+ * [`TOKEN_PRECISION`]: constant.TOKEN_PRECISION.html
+ */
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use utils::errors::SovtokenError;
+
+/// Decimal places of an amount written in sovatoms
+pub const SOVATOM_PRECISION: u32 = 0;
+/// Decimal places of an amount written in tokens, a token is 10^8 sovatoms
+pub const TOKEN_PRECISION: u32 = 8;
+
+const MAX_PRECISION: u32 = 19;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TokenAmount(u64);
+
+/**
+ * A sum of amounts over `u64::MAX` sovatoms
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountOverflow {
+    pub left: TokenAmount,
+    pub right: TokenAmount,
+}
+
+/**
+ * An amount written with a precision, see [`TokenAmount::decimal`]
+ *
+ * [`TokenAmount::decimal`]: struct.TokenAmount.html#method.decimal
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalAmount {
+    amount: TokenAmount,
+    precision: u32,
+}
+
+impl TokenAmount {
+    pub const ZERO: TokenAmount = TokenAmount(0);
+    pub const MAX: TokenAmount = TokenAmount(::std::u64::MAX);
+
+    pub fn new(sovatoms: u64) -> TokenAmount {
+        TokenAmount(sovatoms)
+    }
+
+    pub fn sovatoms(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: TokenAmount) -> Option<TokenAmount> {
+        self.0.checked_add(other.0).map(TokenAmount)
+    }
+
+    pub fn checked_sub(self, other: TokenAmount) -> Option<TokenAmount> {
+        self.0.checked_sub(other.0).map(TokenAmount)
+    }
+
+    pub fn saturating_add(self, other: TokenAmount) -> TokenAmount {
+        TokenAmount(self.0.saturating_add(other.0))
+    }
+
+    pub fn try_add(self, other: TokenAmount) -> Result<TokenAmount, AmountOverflow> {
+        self.checked_add(other).ok_or(AmountOverflow { left: self, right: other })
+    }
+
+    /**
+     * Sums `amounts`, failing on the first overflow
+     */
+    pub fn try_sum<I: IntoIterator<Item = TokenAmount>>(amounts: I) -> Result<TokenAmount, AmountOverflow> {
+        amounts.into_iter().try_fold(TokenAmount::ZERO, TokenAmount::try_add)
+    }
+
+    /**
+     * Writes the amount with `precision` decimal places, without trailing zeros
+     *
+     * ```
+     * use sovtoken::logic::token_amount::{TokenAmount, TOKEN_PRECISION};
+     *
+     * assert_eq!(TokenAmount::new(150_000_000).decimal(TOKEN_PRECISION).to_string(), "1.5");
+     * assert_eq!(TokenAmount::new(7).decimal(TOKEN_PRECISION).to_string(), "0.00000007");
+     * ```
+     */
+    pub fn decimal(self, precision: u32) -> DecimalAmount {
+        DecimalAmount { amount: self, precision: precision.min(MAX_PRECISION) }
+    }
+
+    /**
+     * Parses an amount written with up to `precision` decimal places
+     *
+     * `"1.5"` with the [`TOKEN_PRECISION`] is 150000000 sovatoms. Errors with
+     * `CommonInvalidStructure` for more decimal places than `precision`, or
+     * an amount over `u64::MAX` sovatoms.
+     *
+     * [`TOKEN_PRECISION`]: constant.TOKEN_PRECISION.html
+     */
+    pub fn parse_decimal(amount: &str, precision: u32) -> Result<TokenAmount, SovtokenError> {
+        let invalid = |reason: &str| SovtokenError::invalid_structure("amount").with_value(amount).with_source(reason);
+        let precision = precision.min(MAX_PRECISION);
+
+        let (integer, fraction) = match amount.find('.') {
+            Some(dot) => (&amount[..dot], &amount[dot + 1..]),
+            None => (amount, "")
+        };
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(invalid("no digits"));
+        }
+        if !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(invalid("not a positive decimal number"));
+        }
+        if fraction.len() > precision as usize {
+            return Err(invalid(&format!("more than {} decimal places", precision)));
+        }
+
+        let scale = 10u64.pow(precision);
+        let fraction_scale = 10u64.pow(precision - fraction.len() as u32);
+        let integer: u64 = if integer.is_empty() { 0 } else { integer.parse().map_err(|_| invalid("too large"))? };
+        let fraction: u64 = if fraction.is_empty() { 0 } else { fraction.parse().map_err(|_| invalid("too large"))? };
+
+        integer.checked_mul(scale)
+            .and_then(|sovatoms| sovatoms.checked_add(fraction * fraction_scale))
+            .map(TokenAmount)
+            .ok_or_else(|| invalid("too large"))
+    }
+}
+
+impl From<u64> for TokenAmount {
+    fn from(sovatoms: u64) -> TokenAmount {
+        TokenAmount(sovatoms)
+    }
+}
+
+impl From<TokenAmount> for u64 {
+    fn from(amount: TokenAmount) -> u64 {
+        amount.0
+    }
+}
+
+impl PartialEq<u64> for TokenAmount {
+    fn eq(&self, other: &u64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialOrd<u64> for TokenAmount {
+    fn partial_cmp(&self, other: &u64) -> Option<Ordering> {
+        self.0.partial_cmp(other)
+    }
+}
+
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for TokenAmount {
+    type Err = SovtokenError;
+
+    /**
+     * Parses a number of sovatoms
+     */
+    fn from_str(sovatoms: &str) -> Result<TokenAmount, SovtokenError> {
+        TokenAmount::parse_decimal(sovatoms, SOVATOM_PRECISION)
+    }
+}
+
+impl fmt::Display for DecimalAmount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.precision == 0 {
+            return write!(f, "{}", self.amount.0);
+        }
+
+        let scale = 10u64.pow(self.precision);
+        let integer = self.amount.0 / scale;
+        let fraction = self.amount.0 % scale;
+        if fraction == 0 {
+            return write!(f, "{}", integer);
+        }
+
+        let fraction = format!("{:0width$}", fraction, width = self.precision as usize);
+        write!(f, "{}.{}", integer, fraction.trim_end_matches('0'))
+    }
+}
+
+impl fmt::Display for AmountOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} + {} sovatoms overflows", self.left, self.right)
+    }
+}
+
+impl Serialize for TokenAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TokenAmount, D::Error> {
+        struct TokenAmountVisitor;
+
+        impl<'de> de::Visitor<'de> for TokenAmountVisitor {
+            type Value = TokenAmount;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a positive number of sovatoms")
+            }
+
+            fn visit_u64<E: de::Error>(self, sovatoms: u64) -> Result<TokenAmount, E> {
+                Ok(TokenAmount(sovatoms))
+            }
+
+            fn visit_i64<E: de::Error>(self, sovatoms: i64) -> Result<TokenAmount, E> {
+                if sovatoms < 0 {
+                    return Err(E::invalid_value(de::Unexpected::Signed(sovatoms), &self));
+                }
+                Ok(TokenAmount(sovatoms as u64))
+            }
+        }
+
+        deserializer.deserialize_any(TokenAmountVisitor)
+    }
+}
+
+
+#[cfg(test)]
+mod token_amount_tests {
+    use super::*;
+    use serde_json;
+    use ErrorCode;
+
+    #[test]
+    fn checked_arithmetic() {
+        let max = TokenAmount::MAX;
+
+        assert_eq!(TokenAmount::new(2).checked_add(TokenAmount::new(3)), Some(TokenAmount::new(5)));
+        assert_eq!(max.checked_add(TokenAmount::new(1)), None);
+        assert_eq!(TokenAmount::new(2).checked_sub(TokenAmount::new(3)), None);
+        assert_eq!(max.saturating_add(max), max);
+        assert_eq!(max.try_add(TokenAmount::new(1)), Err(AmountOverflow { left: max, right: TokenAmount::new(1) }));
+    }
+
+    #[test]
+    fn try_sum() {
+        let amounts = vec![TokenAmount::new(1), TokenAmount::new(2), TokenAmount::new(3)];
+
+        assert_eq!(TokenAmount::try_sum(amounts), Ok(TokenAmount::new(6)));
+        assert_eq!(TokenAmount::try_sum(vec![]), Ok(TokenAmount::ZERO));
+        assert!(TokenAmount::try_sum(vec![TokenAmount::MAX, TokenAmount::new(1), TokenAmount::new(2)]).is_err());
+    }
+
+    #[test]
+    fn decimal_display() {
+        assert_eq!(TokenAmount::new(150_000_000).decimal(TOKEN_PRECISION).to_string(), "1.5");
+        assert_eq!(TokenAmount::new(200_000_000).decimal(TOKEN_PRECISION).to_string(), "2");
+        assert_eq!(TokenAmount::new(1).decimal(TOKEN_PRECISION).to_string(), "0.00000001");
+        assert_eq!(TokenAmount::new(1234).decimal(SOVATOM_PRECISION).to_string(), "1234");
+        assert_eq!(TokenAmount::MAX.decimal(TOKEN_PRECISION).to_string(), "184467440737.09551615");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("1234".parse::<TokenAmount>().unwrap(), 1234);
+        assert_eq!(TokenAmount::parse_decimal("1.5", TOKEN_PRECISION).unwrap(), 150_000_000);
+        assert_eq!(TokenAmount::parse_decimal(".25", TOKEN_PRECISION).unwrap(), 25_000_000);
+        assert_eq!(TokenAmount::parse_decimal("184467440737.09551615", TOKEN_PRECISION).unwrap(), TokenAmount::MAX);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for amount in &["", ".", "-1", "1.5", "1e3", "18446744073709551616"] {
+            let err = amount.parse::<TokenAmount>().unwrap_err();
+            assert_eq!(err.error_code(), ErrorCode::CommonInvalidStructure, "{}", amount);
+        }
+        assert!(TokenAmount::parse_decimal("0.000000001", TOKEN_PRECISION).is_err());
+        assert!(TokenAmount::parse_decimal("184467440737.09551616", TOKEN_PRECISION).is_err());
+    }
+
+    #[test]
+    fn serde() {
+        assert_eq!(serde_json::to_string(&TokenAmount::new(10)).unwrap(), "10");
+        assert_eq!(serde_json::from_str::<TokenAmount>("10").unwrap(), 10);
+        assert!(serde_json::from_str::<TokenAmount>("\"10\"").is_err());
+        assert!(serde_json::from_str::<TokenAmount>("-10").is_err());
+        assert!(serde_json::from_str::<TokenAmount>("1.5").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 //! Type aliases for various domain specific primitives like transaction seq no, amount, et
 
 pub type TxnSeqNo = u64;
-pub type TokenAmount = u64;
+pub use logic::token_amount::TokenAmount;
 pub type ReqId = u64;
 pub type ProtocolVersion = u32;
 pub type TxnVersion = String;
@@ -0,0 +1,233 @@
+/*!
+ * The ledger counts amounts in sovatoms, the smallest unit. People count them
+ * in SOV, a SOV being 10^8 sovatoms. This module converts between the two.
+ *
+ * Formatting and parsing are locale-neutral: `.` is the decimal separator,
+ * digits aren't grouped and trailing zeros are dropped, so `150000000`
+ * sovatoms are written `1.5 SOV` whatever the locale of the frontend.
+ */
+
+use serde::{de, Deserialize, Deserializer};
+use std::fmt;
+use std::str::FromStr;
+
+use logic::token_amount::{TokenAmount, SOVATOM_PRECISION, TOKEN_PRECISION};
+use utils::errors::SovtokenError;
+
+pub const SOV_SYMBOL: &str = "SOV";
+pub const SOVATOM_SYMBOL: &str = "sovatom";
+
+/**
+ * A unit amounts are written in
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denomination {
+    #[serde(rename = "sovatom")]
+    Sovatom,
+    #[serde(rename = "SOV")]
+    Sov,
+}
+
+impl Denomination {
+    /// Decimal places of an amount in this denomination
+    pub fn precision(self) -> u32 {
+        match self {
+            Denomination::Sovatom => SOVATOM_PRECISION,
+            Denomination::Sov => TOKEN_PRECISION,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Denomination::Sovatom => SOVATOM_SYMBOL,
+            Denomination::Sov => SOV_SYMBOL,
+        }
+    }
+
+    /**
+     * The denomination of a symbol, ignoring case: `SOV`, `sovatom` or `sovatoms`
+     */
+    pub fn from_symbol(symbol: &str) -> Option<Denomination> {
+        match symbol.to_ascii_lowercase().as_str() {
+            "sov" => Some(Denomination::Sov),
+            "sovatom" | "sovatoms" => Some(Denomination::Sovatom),
+            _ => None
+        }
+    }
+
+    /**
+     * Writes `amount` in this denomination, without the symbol
+     *
+     * ```
+     * use sovtoken::logic::token_amount::TokenAmount;
+     * use sovtoken::logic::units::Denomination;
+     *
+     * assert_eq!(Denomination::Sov.format(TokenAmount::new(150_000_000)), "1.5");
+     * assert_eq!(Denomination::Sovatom.format(TokenAmount::new(150_000_000)), "150000000");
+     * ```
+     */
+    pub fn format(self, amount: TokenAmount) -> String {
+        amount.decimal(self.precision()).to_string()
+    }
+
+    /**
+     * Parses a decimal number, without the symbol, in this denomination
+     *
+     * Errors with `CommonInvalidStructure` for a number with more decimal
+     * places than the denomination has, or over `u64::MAX` sovatoms.
+     */
+    pub fn parse(self, amount: &str) -> Result<TokenAmount, SovtokenError> {
+        TokenAmount::parse_decimal(amount, self.precision())
+    }
+}
+
+impl fmt::Display for Denomination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl FromStr for Denomination {
+    type Err = SovtokenError;
+
+    fn from_str(symbol: &str) -> Result<Denomination, SovtokenError> {
+        Denomination::from_symbol(symbol)
+            .ok_or_else(|| SovtokenError::invalid_structure("denomination").with_value(symbol))
+    }
+}
+
+/**
+ * Converts a decimal number from a denomination to another
+ *
+ * `convert("1.5", Denomination::Sov, Denomination::Sovatom)` is `"150000000"`.
+ */
+pub fn convert(amount: &str, from: Denomination, to: Denomination) -> Result<String, SovtokenError> {
+    from.parse(amount).map(|amount| to.format(amount))
+}
+
+/**
+ * Writes `amount` followed by the symbol of `denomination`, like `1.5 SOV`
+ */
+pub fn format_amount(amount: TokenAmount, denomination: Denomination) -> String {
+    format!("{} {}", denomination.format(amount), denomination)
+}
+
+/**
+ * Parses an amount written with its symbol, like `1.5 SOV` or `7 sovatoms`
+ *
+ * The space before the symbol is optional. A number without symbol is a
+ * number of sovatoms.
+ */
+pub fn parse_amount(amount: &str) -> Result<TokenAmount, SovtokenError> {
+    let amount = amount.trim();
+    let number_end = amount.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(amount.len());
+    let (number, symbol) = (&amount[..number_end], amount[number_end..].trim_start());
+
+    let denomination = if symbol.is_empty() { Denomination::Sovatom } else { symbol.parse()? };
+    denomination.parse(number)
+}
+
+/**
+ * An amount deserialized from a number of sovatoms or from a string with a
+ * symbol, like `"1.5 SOV"`, see [`parse_amount`]
+ *
+ * A string without symbol is rejected, `"1"` could be meant as a SOV.
+ *
+ * [`parse_amount`]: fn.parse_amount.html
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DenominatedAmount(pub TokenAmount);
+
+impl<'de> Deserialize<'de> for DenominatedAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DenominatedAmount, D::Error> {
+        struct DenominatedAmountVisitor;
+
+        impl<'de> de::Visitor<'de> for DenominatedAmountVisitor {
+            type Value = DenominatedAmount;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a positive number of sovatoms or a string like \"1.5 SOV\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, sovatoms: u64) -> Result<DenominatedAmount, E> {
+                Ok(DenominatedAmount(TokenAmount::new(sovatoms)))
+            }
+
+            fn visit_i64<E: de::Error>(self, sovatoms: i64) -> Result<DenominatedAmount, E> {
+                if sovatoms < 0 {
+                    return Err(E::invalid_value(de::Unexpected::Signed(sovatoms), &self));
+                }
+                Ok(DenominatedAmount(TokenAmount::new(sovatoms as u64)))
+            }
+
+            fn visit_str<E: de::Error>(self, amount: &str) -> Result<DenominatedAmount, E> {
+                if !amount.trim_end().ends_with(|c: char| c.is_ascii_alphabetic()) {
+                    return Err(E::invalid_value(de::Unexpected::Str(amount), &self));
+                }
+                parse_amount(amount).map(DenominatedAmount).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(DenominatedAmountVisitor)
+    }
+}
+
+
+#[cfg(test)]
+mod units_tests {
+    use super::*;
+    use serde_json;
+    use ErrorCode;
+
+    #[test]
+    fn format_in_denominations() {
+        let amount = TokenAmount::new(123_450_000);
+
+        assert_eq!(Denomination::Sov.format(amount), "1.2345");
+        assert_eq!(format_amount(amount, Denomination::Sov), "1.2345 SOV");
+        assert_eq!(format_amount(amount, Denomination::Sovatom), "123450000 sovatom");
+        assert_eq!(format_amount(TokenAmount::ZERO, Denomination::Sov), "0 SOV");
+    }
+
+    #[test]
+    fn convert_between_denominations() {
+        assert_eq!(convert("1.5", Denomination::Sov, Denomination::Sovatom).unwrap(), "150000000");
+        assert_eq!(convert("1", Denomination::Sovatom, Denomination::Sov).unwrap(), "0.00000001");
+        assert_eq!(convert("0.5", Denomination::Sovatom, Denomination::Sov).unwrap_err().error_code(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn parse_with_symbol() {
+        assert_eq!(parse_amount("1.5 SOV").unwrap(), 150_000_000);
+        assert_eq!(parse_amount("2sov").unwrap(), 200_000_000);
+        assert_eq!(parse_amount(" 7 sovatoms ").unwrap(), 7);
+        assert_eq!(parse_amount("42").unwrap(), 42);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for amount in &["", "SOV", "1.5", "1,5 SOV", "-1 SOV", "1.5 BTC", "0.000000001 SOV", "1 SOV SOV"] {
+            assert_eq!(parse_amount(amount).unwrap_err().error_code(), ErrorCode::CommonInvalidStructure, "{}", amount);
+        }
+    }
+
+    #[test]
+    fn deserialize_denominated_amount() {
+        let amount = |json: &str| serde_json::from_str::<DenominatedAmount>(json).map(|amount| amount.0);
+
+        assert_eq!(amount("10").unwrap(), 10);
+        assert_eq!(amount("\"1.5 SOV\"").unwrap(), 150_000_000);
+        assert_eq!(amount("\"3 sovatoms\"").unwrap(), 3);
+        assert!(amount("\"10\"").is_err());
+        assert!(amount("-10").is_err());
+        assert!(amount("\"1.5 XYZ\"").is_err());
+    }
+
+    #[test]
+    fn denomination_symbols() {
+        assert_eq!("sov".parse::<Denomination>().unwrap(), Denomination::Sov);
+        assert_eq!(Denomination::from_symbol("Sovatoms"), Some(Denomination::Sovatom));
+        assert_eq!(serde_json::to_string(&Denomination::Sov).unwrap(), "\"SOV\"");
+        assert!("atom".parse::<Denomination>().is_err());
+    }
+}
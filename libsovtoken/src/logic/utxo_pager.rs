@@ -0,0 +1,431 @@
+/*!
+ * A GET_UTXO reply holds a page of the outputs of an address. When there are
+ * more, `next` is the seq_no the following request has to start `from`.
+ *
+ * [`fetch_all_utxos`] builds the requests and gives them to a submit closure,
+ * so the caller decides how they reach the ledger, [`fetch_all_utxos_async`]
+ * does the same from libindy callbacks. Each page is checked like the
+ * `NumericalSuffixAscendingNoGaps` state proof verification does: outputs are
+ * ascending and within `[from, next)`, and the cursor moves forward.
+ *
+ * [`fetch_all_utxos`]: fn.fetch_all_utxos.html
+ * [`fetch_all_utxos_async`]: fn.fetch_all_utxos_async.html
+ */
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ErrorCode;
+use logic::address;
+use logic::config::get_utxo_config::GetUtxoOperationRequest;
+use logic::parsers::common::ResponseOperations;
+use logic::parsers::rejection_parser;
+use logic::parsers::parse_get_utxo_response::{ParseGetUtxoResponse, ParseGetUtxoResponseResult, UTXOInner};
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use utils::json_conversion::JsonDeserialize;
+
+/**
+ * All the UTXOs of an address
+ *
+ * ### Fields
+ * - `address` the qualified payment address
+ * - `utxos` the outputs, ascending by seq_no
+ * - `balance` the sum of the amounts
+ * - `pages` the number of GET_UTXO requests made
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressUtxos {
+    pub address: String,
+    pub utxos: Vec<UTXOInner>,
+    pub balance: TokenAmount,
+    pub pages: usize,
+}
+
+/**
+ * Sends a request json to the ledger, then calls the callback once with the
+ * reply or the error.
+ */
+pub type SubmitAsync = Fn(String, Box<FnMut(Result<String, ErrorCode>) + Send>) + Send + Sync;
+
+/**
+ * Callback of [`fetch_all_utxos_async`]
+ *
+ * [`fetch_all_utxos_async`]: fn.fetch_all_utxos_async.html
+ */
+pub type AddressUtxosCallback = Box<FnMut(Result<AddressUtxos, ErrorCode>) + Send>;
+
+/**
+ * Fetches all the UTXOs of `payment_address`
+ *
+ * `submit` sends a GET_UTXO request json and returns the reply of the ledger.
+ * Outputs returned by several pages are only counted once.
+ *
+ * Errors with `CommonInvalidStructure` when a reply is malformed, is for another
+ * address or breaks the ordering of the outputs, and with the error of the
+ * rejection when the ledger rejects a request.
+ */
+pub fn fetch_all_utxos<F>(payment_address: &str, mut submit: F) -> Result<AddressUtxos, ErrorCode>
+    where F: FnMut(String) -> Result<String, ErrorCode>
+{
+    trace!("logic::utxo_pager::fetch_all_utxos >> payment_address: {:?}", secret!(payment_address));
+    let mut pager = UtxoPager::new(payment_address)?;
+
+    loop {
+        let response = submit(pager.request()?)?;
+        if !pager.add_page(&response)? {
+            break;
+        }
+    }
+
+    let res = pager.finish();
+    trace!("logic::utxo_pager::fetch_all_utxos << result: {:?}", secret!(&res));
+    res
+}
+
+/**
+ * Fetches all the UTXOs of `payment_address`, like [`fetch_all_utxos`]
+ *
+ * Each page is requested from the callback of the previous one, so `submit`
+ * may answer from a libindy callback. `cb` is called once.
+ *
+ * [`fetch_all_utxos`]: fn.fetch_all_utxos.html
+ */
+pub fn fetch_all_utxos_async(payment_address: &str, submit: Arc<SubmitAsync>, mut cb: AddressUtxosCallback) {
+    trace!("logic::utxo_pager::fetch_all_utxos_async >> payment_address: {:?}", secret!(payment_address));
+    match UtxoPager::new(payment_address) {
+        Ok(pager) => fetch_page(pager, submit, cb),
+        Err(err) => cb(Err(err))
+    }
+}
+
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    The pages fetched so far, and where the next one starts.
+*/
+struct UtxoPager {
+    payment_address: String,
+    unqualified_address: String,
+    utxos: BTreeMap<TxnSeqNo, UTXOInner>,
+    from: Option<u64>,
+    pages: usize,
+}
+
+impl UtxoPager {
+    fn new(payment_address: &str) -> Result<UtxoPager, ErrorCode> {
+        Ok(UtxoPager {
+            payment_address: payment_address.to_string(),
+            unqualified_address: address::unqualified_address_from_address(payment_address)?,
+            utxos: BTreeMap::new(),
+            from: None,
+            pages: 0,
+        })
+    }
+
+    fn request(&self) -> Result<String, ErrorCode> {
+        let request = GetUtxoOperationRequest::new(self.payment_address.clone(), self.from.map(|from| from as i64))
+            .serialize_to_string()
+            .map_err(|_| ErrorCode::CommonInvalidState)?;
+        debug!("GET_UTXO request >>> {:?}", request);
+        Ok(request)
+    }
+
+    /*
+        Adds the page of `response`, returns whether there are more.
+    */
+    fn add_page(&mut self, response: &str) -> Result<bool, ErrorCode> {
+        self.pages += 1;
+
+        let result = parse_page(response)?;
+        let next = verify_page(&result, &self.unqualified_address, self.from)?;
+
+        for utxo in result.outputs {
+            let previous = self.utxos.insert(utxo.seq_no, utxo.clone());
+            match previous {
+                Some(ref previous) if *previous != utxo => {
+                    error!("UTXO {:?} returned with different amounts: {:?}, {:?}", utxo.seq_no, previous.amount, utxo.amount);
+                    return Err(ErrorCode::CommonInvalidStructure);
+                }
+                _ => ()
+            }
+        }
+
+        self.from = next;
+        Ok(next.is_some())
+    }
+
+    fn finish(self) -> Result<AddressUtxos, ErrorCode> {
+        let utxos: Vec<UTXOInner> = self.utxos.into_iter().map(|(_, utxo)| utxo).collect();
+
+        let balance = TokenAmount::try_sum(utxos.iter().map(|utxo| utxo.amount))?;
+
+        Ok(AddressUtxos { address: self.payment_address, utxos, balance, pages: self.pages })
+    }
+}
+
+fn fetch_page(pager: UtxoPager, submit: Arc<SubmitAsync>, mut cb: AddressUtxosCallback) {
+    let request = match pager.request() {
+        Ok(request) => request,
+        Err(err) => return cb(Err(err))
+    };
+
+    let next_submit = submit.clone();
+    let mut pending = Some((pager, cb));
+    submit(request, Box::new(move |response| {
+        let (mut pager, mut cb) = match pending.take() {
+            Some(pending) => pending,
+            None => return
+        };
+
+        match response.and_then(|response| pager.add_page(&response)) {
+            Ok(true) => fetch_page(pager, next_submit.clone(), cb),
+            Ok(false) => {
+                let res = pager.finish();
+                trace!("logic::utxo_pager::fetch_all_utxos_async << result: {:?}", secret!(&res));
+                cb(res)
+            }
+            Err(err) => cb(Err(err))
+        }
+    }));
+}
+
+fn parse_page(response: &str) -> Result<ParseGetUtxoResponseResult, ErrorCode> {
+    let response = ParseGetUtxoResponse::from_json(response)
+        .map_err(map_err_err!())
+        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+
+    match response.op {
+        ResponseOperations::REPLY => response.result.ok_or(ErrorCode::CommonInvalidStructure),
+        ResponseOperations::REQNACK | ResponseOperations::REJECT => {
+            let reason = response.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
+            Err(rejection_parser::error_code_from_reason(&reason))
+        }
+    }
+}
+
+/*
+    Checks a page against the request it replies to, returns its `next` cursor.
+*/
+fn verify_page(result: &ParseGetUtxoResponseResult, unqualified_address: &str, from: Option<u64>) -> Result<Option<u64>, ErrorCode> {
+    if result.address != unqualified_address {
+        error!("GET_UTXO reply for {:?}, expected {:?}", result.address, unqualified_address);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    if result.from.is_some() && result.from != from {
+        error!("GET_UTXO reply from {:?}, expected {:?}", result.from, from);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let mut last: Option<TxnSeqNo> = None;
+    for utxo in &result.outputs {
+        if utxo.address != unqualified_address {
+            error!("GET_UTXO reply contains an output of {:?}", utxo.address);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        let ascending = last.map(|last| utxo.seq_no > last).unwrap_or(true);
+        let after_from = from.map(|from| utxo.seq_no >= from).unwrap_or(true);
+        let before_next = result.next.map(|next| utxo.seq_no < next).unwrap_or(true);
+        if !ascending || !after_from || !before_next {
+            error!("GET_UTXO reply output {:?} out of order, from: {:?}, next: {:?}", utxo.seq_no, from, result.next);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        last = Some(utxo.seq_no);
+    }
+
+    if let (Some(next), Some(from)) = (result.next, from) {
+        if next <= from {
+            error!("GET_UTXO reply cursor does not move forward, from: {:?}, next: {:?}", from, next);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    }
+
+    Ok(result.next)
+}
+
+
+#[cfg(test)]
+mod utxo_pager_tests {
+    use super::*;
+    use serde_json;
+    use std::collections::HashMap;
+    use std::sync::mpsc::channel;
+
+    fn reply(address: &str, outputs: Vec<(u64, u64)>, from: Option<u64>, next: Option<u64>) -> String {
+        let outputs: Vec<serde_json::Value> = outputs.into_iter()
+            .map(|(seq_no, amount)| json!([address, seq_no, amount]))
+            .collect();
+
+        let mut result = json!({
+            "type": "10002",
+            "address": address,
+            "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+            "reqId": 15424,
+            "outputs": outputs,
+        });
+        if let Some(from) = from {
+            result["from"] = json!(from);
+        }
+        if let Some(next) = next {
+            result["next"] = json!(next);
+        }
+
+        json!({"op": "REPLY", "protocol_version": 2, "result": result}).to_string()
+    }
+
+    fn addresses() -> (String, String) {
+        let unqualified = address::address_tests::gen_random_base58_address();
+        let qualified = address::address_from_unqualified_address(&unqualified).unwrap();
+        (qualified, unqualified)
+    }
+
+    fn request_from(request: &str) -> Option<u64> {
+        let request: serde_json::Value = serde_json::from_str(request).unwrap();
+        request["operation"]["from"].as_u64()
+    }
+
+    fn ledger(pages: HashMap<Option<u64>, String>) -> impl FnMut(String) -> Result<String, ErrorCode> {
+        move |request| Ok(pages[&request_from(&request)].clone())
+    }
+
+    #[test]
+    fn fetch_all_utxos_single_page() {
+        let (qualified, unqualified) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&unqualified, vec![(1, 10), (3, 20)], None, None));
+
+        let utxos = fetch_all_utxos(&qualified, ledger(pages)).unwrap();
+
+        assert_eq!(utxos.address, qualified);
+        assert_eq!(utxos.balance, 30);
+        assert_eq!(utxos.pages, 1);
+        assert_eq!(utxos.utxos.iter().map(|u| u.seq_no).collect::<Vec<u64>>(), vec![1, 3]);
+    }
+
+    #[test]
+    fn fetch_all_utxos_follows_next() {
+        let (qualified, unqualified) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&unqualified, vec![(1, 10), (3, 20)], None, Some(5)));
+        pages.insert(Some(5), reply(&unqualified, vec![(5, 5), (8, 1)], Some(5), Some(9)));
+        pages.insert(Some(9), reply(&unqualified, vec![(12, 4)], Some(9), None));
+
+        let utxos = fetch_all_utxos(&qualified, ledger(pages)).unwrap();
+
+        assert_eq!(utxos.balance, 40);
+        assert_eq!(utxos.pages, 3);
+        assert_eq!(utxos.utxos.iter().map(|u| u.seq_no).collect::<Vec<u64>>(), vec![1, 3, 5, 8, 12]);
+    }
+
+    #[test]
+    fn fetch_all_utxos_empty_address() {
+        let (qualified, unqualified) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&unqualified, vec![], None, None));
+
+        let utxos = fetch_all_utxos(&qualified, ledger(pages)).unwrap();
+
+        assert_eq!(utxos.balance, 0);
+        assert!(utxos.utxos.is_empty());
+    }
+
+    #[test]
+    fn fetch_all_utxos_unordered_outputs() {
+        let (qualified, unqualified) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&unqualified, vec![(3, 20), (1, 10)], None, None));
+
+        let err = fetch_all_utxos(&qualified, ledger(pages)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn fetch_all_utxos_output_past_next() {
+        let (qualified, unqualified) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&unqualified, vec![(1, 10), (6, 20)], None, Some(5)));
+
+        let err = fetch_all_utxos(&qualified, ledger(pages)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn fetch_all_utxos_cursor_not_moving() {
+        let (qualified, unqualified) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&unqualified, vec![(1, 10)], None, Some(5)));
+        pages.insert(Some(5), reply(&unqualified, vec![], Some(5), Some(5)));
+
+        let err = fetch_all_utxos(&qualified, ledger(pages)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn fetch_all_utxos_other_address() {
+        let (qualified, _) = addresses();
+        let (_, other) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&other, vec![(1, 10)], None, None));
+
+        let err = fetch_all_utxos(&qualified, ledger(pages)).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    fn fetch_async(payment_address: &str, pages: HashMap<Option<u64>, String>) -> Result<AddressUtxos, ErrorCode> {
+        let submit: Arc<SubmitAsync> = Arc::new(move |request: String, mut cb: Box<FnMut(Result<String, ErrorCode>) + Send>| {
+            cb(Ok(pages[&request_from(&request)].clone()))
+        });
+
+        let (sender, receiver) = channel();
+        fetch_all_utxos_async(payment_address, submit, Box::new(move |res| sender.send(res).unwrap()));
+        receiver.recv().unwrap()
+    }
+
+    #[test]
+    fn fetch_all_utxos_async_follows_next() {
+        let (qualified, unqualified) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&unqualified, vec![(1, 10), (3, 20)], None, Some(5)));
+        pages.insert(Some(5), reply(&unqualified, vec![(5, 5)], Some(5), None));
+
+        let utxos = fetch_async(&qualified, pages).unwrap();
+
+        assert_eq!(utxos.balance, 35);
+        assert_eq!(utxos.pages, 2);
+        assert_eq!(utxos.utxos.iter().map(|u| u.seq_no).collect::<Vec<u64>>(), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn fetch_all_utxos_async_other_address() {
+        let (qualified, _) = addresses();
+        let (_, other) = addresses();
+        let mut pages = HashMap::new();
+        pages.insert(None, reply(&other, vec![(1, 10)], None, None));
+
+        assert_eq!(fetch_async(&qualified, pages).unwrap_err(), ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn fetch_all_utxos_rejected() {
+        let (qualified, _) = addresses();
+
+        let err = fetch_all_utxos(&qualified, |_| Ok(json!({"op": "REJECT", "reason": "some reason"}).to_string())).unwrap_err();
+        assert_eq!(err, ErrorCode::CommonInvalidStructure);
+    }
+
+    #[test]
+    fn fetch_all_utxos_submit_error() {
+        let (qualified, _) = addresses();
+
+        let err = fetch_all_utxos(&qualified, |_| Err(ErrorCode::PoolLedgerTimeout)).unwrap_err();
+        assert_eq!(err, ErrorCode::PoolLedgerTimeout);
+    }
+}
@@ -10,9 +10,15 @@
 
 use hex::ToHex;
 use serde_json;
+#[cfg(feature = "std-futures")]
+use std::future::Future;
+#[cfg(feature = "std-futures")]
+use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::collections::HashMap;
+#[cfg(feature = "std-futures")]
+use std::task::{Context, Poll};
 
 use IndyHandle;
 use ErrorCode;
@@ -23,6 +29,8 @@ use logic::input::{Input, Inputs};
 use logic::output::Outputs;
 use logic::hash::Hash;
 use utils::constants::txn_types::{ATTRIB, GET_ATTRIB};
+#[cfg(feature = "std-futures")]
+use utils::future::{self, CallbackFuture, JoinAll};
 use utils::txn_author_agreement::{TaaAcceptance, extract_taa_acceptance_from_extra};
 
 /**
@@ -89,7 +97,7 @@ impl XferPayload {
     }
 
     // TODO: Add request hash to include while signature
-    pub fn sign_fees<A: CryptoAPI>(self, crypto_api: &'static A, wallet_handle: IndyHandle, txn_digest: &Option<String>, cb: Box<Fn(Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
+    pub fn sign_fees<A: CryptoAPI>(self, crypto_api: &A, wallet_handle: IndyHandle, txn_digest: &Option<String>, cb: Box<Fn(Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
         trace!("logic::xfer_payload::xfer_payload::sign_fees >> wallet_handle: {:?}", wallet_handle);
         if self.inputs.len() < 1 {
             return Err(ErrorCode::CommonInvalidStructure);
@@ -107,7 +115,7 @@ impl XferPayload {
      * [`Input`]: Input
      * [`Inputs`]: Inputs
      */
-    pub fn sign_transfer<A: CryptoAPI>(self, crypto_api: &'static A, wallet_handle: IndyHandle, cb: Box<Fn(Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
+    pub fn sign_transfer<A: CryptoAPI>(self, crypto_api: &A, wallet_handle: IndyHandle, cb: Box<Fn(Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
         trace!("logic::xfer_payload::xfer_payload::sign >> wallet_handle: {:?}", wallet_handle);
         if self.outputs.len() < 1 || self.inputs.len() < 1 {
             return Err(ErrorCode::CommonInvalidStructure);
@@ -115,25 +123,13 @@ impl XferPayload {
         self.sign(crypto_api, wallet_handle, &None, cb)
     }
 
-    fn sign<A: CryptoAPI>(mut self, crypto_api: &'static A, wallet_handle: IndyHandle, txn_digest: &Option<String>, cb: Box<Fn(Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
-        for output in &mut self.outputs {
-            output.recipient = address::unqualified_address_from_address(&output.recipient)?;
-        }
-        debug!("Indicator stripped from outputs");
-
-        for input in &mut self.inputs {
-            input.address = address::unqualified_address_from_address(&input.address)?;
-        }
-
-        debug!("Indicator stripped from inputs");
+    fn sign<A: CryptoAPI>(self, crypto_api: &A, wallet_handle: IndyHandle, txn_digest: &Option<String>, cb: Box<Fn(Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
+        let (payload, taa_acceptance) = self.prepare()?;
 
-        let (extra, taa_acceptance) = extract_taa_acceptance_from_extra(self.extra)?;
-        self.extra = extra;
-
-        XferPayload::sign_inputs(crypto_api, wallet_handle, &self.inputs.clone(), &self.outputs.clone(), txn_digest, &self.extra.clone(), &taa_acceptance.clone(), Box::new(move |signatures| {
+        XferPayload::sign_inputs(crypto_api, wallet_handle, &payload.inputs.clone(), &payload.outputs.clone(), txn_digest, &payload.extra.clone(), &taa_acceptance.clone(), Box::new(move |signatures| {
             match signatures {
                 Ok(signatures) => {
-                    let payload = Self::clone_payload_add_signatures(&self, signatures);
+                    let payload = Self::clone_payload_add_signatures(&payload, signatures);
                     info!("Built XFER payload: {:?}", payload);
                     cb(Ok((payload, taa_acceptance.clone())));
                 }
@@ -149,6 +145,30 @@ impl XferPayload {
         res
     }
 
+    /**
+     * Strips the qualifiers from the addresses of the inputs and outputs and
+     * takes the TAA acceptance out of `extra`.
+     *
+     * The returned payload and acceptance are what the input signatures cover.
+     */
+    pub fn prepare(mut self) -> Result<(XferPayload, Option<TaaAcceptance>), ErrorCode> {
+        for output in &mut self.outputs {
+            output.recipient = address::unqualified_address_from_address(&output.recipient)?;
+        }
+        debug!("Indicator stripped from outputs");
+
+        for input in &mut self.inputs {
+            input.address = address::unqualified_address_from_address(&input.address)?;
+        }
+
+        debug!("Indicator stripped from inputs");
+
+        let (extra, taa_acceptance) = extract_taa_acceptance_from_extra(self.extra)?;
+        self.extra = extra;
+
+        Ok((self, taa_acceptance))
+    }
+
     fn clone_payload_add_signatures(prev: &Self, signatures: HashMap<String, String>) -> Self {
         let signatures = prev.inputs
             .iter()
@@ -166,8 +186,121 @@ impl XferPayload {
     }
 }
 
+#[cfg(feature = "std-futures")]
+impl XferPayload {
+    /**
+     * Signs [`Inputs`] like [`sign_transfer`], the signed payload is awaited
+     * instead of sent to a callback.
+     *
+     * [`Inputs`]: Inputs
+     * [`sign_transfer`]: XferPayload::sign_transfer
+     */
+    pub fn sign_transfer_future<A: CryptoAPI>(self, crypto_api: &A, wallet_handle: IndyHandle) -> SignedPayloadFuture {
+        trace!("logic::xfer_payload::xfer_payload::sign_transfer_future >> wallet_handle: {:?}", wallet_handle);
+        if self.outputs.len() < 1 || self.inputs.len() < 1 {
+            return SignedPayloadFuture::failed(ErrorCode::CommonInvalidStructure);
+        }
+        self.sign_future(crypto_api, wallet_handle, &None)
+    }
+
+    /**
+     * Signs [`Inputs`] like [`sign_fees`], the signed payload is awaited
+     * instead of sent to a callback.
+     *
+     * [`Inputs`]: Inputs
+     * [`sign_fees`]: XferPayload::sign_fees
+     */
+    pub fn sign_fees_future<A: CryptoAPI>(self, crypto_api: &A, wallet_handle: IndyHandle, txn_digest: &Option<String>) -> SignedPayloadFuture {
+        trace!("logic::xfer_payload::xfer_payload::sign_fees_future >> wallet_handle: {:?}", wallet_handle);
+        if self.inputs.len() < 1 {
+            return SignedPayloadFuture::failed(ErrorCode::CommonInvalidStructure);
+        }
+        self.sign_future(crypto_api, wallet_handle, txn_digest)
+    }
+
+    fn sign_future<A: CryptoAPI>(self, crypto_api: &A, wallet_handle: IndyHandle, txn_digest: &Option<String>) -> SignedPayloadFuture {
+        let (payload, taa_acceptance) = match self.prepare() {
+            Ok(prepared) => prepared,
+            Err(err) => return SignedPayloadFuture::failed(err)
+        };
+
+        let mut signatures = Vec::with_capacity(payload.inputs.len());
+        for input in &payload.inputs {
+            let (signature, completer) = future::callback_future();
+            let cb = move |signature: Result<String, ErrorCode>, _input: String| completer.complete(signature);
+            let res = <XferPayload as InputSigner<A>>::sign_input(crypto_api, wallet_handle, input, &payload.outputs, txn_digest, &payload.extra, &taa_acceptance, Box::new(Arc::new(cb)));
+            if let Err(err) = res {
+                return SignedPayloadFuture::failed(err);
+            }
+            signatures.push(signature);
+        }
+
+        SignedPayloadFuture {
+            payload: Some(Ok((payload, taa_acceptance))),
+            signatures: future::join_all(signatures),
+        }
+    }
+}
+
+/**
+ * Resolves to the [`XferPayload`] with the signatures of all its inputs, and
+ * the TAA acceptance taken out of its `extra`.
+ *
+ * Returned by [`sign_transfer_future`] and [`sign_fees_future`]; fails with
+ * the first error of signing an input.
+ *
+ * [`XferPayload`]: XferPayload
+ * [`sign_transfer_future`]: XferPayload::sign_transfer_future
+ * [`sign_fees_future`]: XferPayload::sign_fees_future
+ */
+#[cfg(feature = "std-futures")]
+pub struct SignedPayloadFuture {
+    payload: Option<Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>>,
+    signatures: JoinAll<Result<String, ErrorCode>>,
+}
+
+#[cfg(feature = "std-futures")]
+impl SignedPayloadFuture {
+    fn failed(err: ErrorCode) -> SignedPayloadFuture {
+        SignedPayloadFuture {
+            payload: Some(Err(err)),
+            signatures: future::join_all(Vec::<CallbackFuture<_>>::new()),
+        }
+    }
+}
+
+#[cfg(feature = "std-futures")]
+impl Future for SignedPayloadFuture {
+    type Output = Result<(XferPayload, Option<TaaAcceptance>), ErrorCode>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let signatures = match Pin::new(&mut this.signatures).poll(cx) {
+            Poll::Ready(signatures) => signatures,
+            Poll::Pending => return Poll::Pending
+        };
+
+        let (mut payload, taa_acceptance) = match this.payload.take().expect("SignedPayloadFuture polled after completion") {
+            Ok(prepared) => prepared,
+            Err(err) => return Poll::Ready(Err(err))
+        };
+
+        match signatures.into_iter().collect::<Result<Vec<String>, ErrorCode>>() {
+            Ok(signatures) => {
+                payload.signatures = Some(signatures);
+                info!("Built XFER payload: {:?}", payload);
+                Poll::Ready(Ok((payload, taa_acceptance)))
+            }
+            Err(err) => {
+                error!("Got an error while signing utxos: {:?}", err);
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
 trait InputSigner<A: CryptoAPI> {
-    fn sign_inputs(crypto_api: &'static A, wallet_handle: IndyHandle, inputs: &Inputs, outputs: &Outputs, txn_digest: &Option<String>, extra: &Option<Extra>, taa_acceptance: &Option<TaaAcceptance>, cb: Box<Fn(Result<HashMap<String, String>, ErrorCode>) + Send + Sync>)
+    fn sign_inputs(crypto_api: &A, wallet_handle: IndyHandle, inputs: &Inputs, outputs: &Outputs, txn_digest: &Option<String>, extra: &Option<Extra>, taa_acceptance: &Option<TaaAcceptance>, cb: Box<Fn(Result<HashMap<String, String>, ErrorCode>) + Send + Sync>)
                    -> Result<(), ErrorCode>
     {
         let inputs_result: Arc<Mutex<HashMap<String, String>>> = Default::default();
@@ -207,7 +340,7 @@ trait InputSigner<A: CryptoAPI> {
      * [`Input`]: Input
      */
     fn sign_input(
-        crypto_api: &'static A,
+        crypto_api: &A,
         wallet_handle: IndyHandle,
         input: &Input,
         outputs: &Outputs,
@@ -222,15 +355,7 @@ trait InputSigner<A: CryptoAPI> {
 
         debug!("Received verkey for payment address >>> {:?}", secret!(&verkey));
 
-        let vals: Vec<serde_json::Value> = vec![
-            Some(json!([input])),
-            Some(json!(outputs)),
-            txn_digest.clone().map(|e| json!(e)),
-            extra.clone().map(|e| json!(e)),
-            taa_acceptance.clone().map(|e| json!(e)),
-        ].into_iter().filter_map(|e| e).collect();
-
-        let message = serialize_signature(json!(vals))?;
+        let message = signing_message(input, outputs, txn_digest, extra, taa_acceptance)?;
 
         debug!("Message to sign >>> {:?}", secret!(&message));
 
@@ -258,6 +383,33 @@ trait InputSigner<A: CryptoAPI> {
     }
 }
 
+/**
+ * Builds the message the signature of an [`Input`] covers
+ *
+ * `[[<Input>], [<Output>, ...], <txn_digest>?, <extra>?, <taa_acceptance>?]`
+ * serialized with [`serialize_signature`]. Addresses are unqualified.
+ *
+ * [`Input`]: Input
+ * [`serialize_signature`]: serialize_signature
+ */
+pub fn signing_message(
+    input: &Input,
+    outputs: &Outputs,
+    txn_digest: &Option<String>,
+    extra: &Option<Extra>,
+    taa_acceptance: &Option<TaaAcceptance>
+) -> Result<String, ErrorCode> {
+    let vals: Vec<serde_json::Value> = vec![
+        Some(json!([input])),
+        Some(json!(outputs)),
+        txn_digest.clone().map(|e| json!(e)),
+        extra.clone().map(|e| json!(e)),
+        taa_acceptance.clone().map(|e| json!(e)),
+    ].into_iter().filter_map(|e| e).collect();
+
+    serialize_signature(json!(vals))
+}
+
 pub fn serialize_signature(v: serde_json::Value) -> Result<String, ErrorCode> {
     let _type = v["operation"]["type"].clone();
     do_serialize_signature(v, true, _type.as_str())
@@ -582,4 +734,54 @@ mod test_xfer_payload {
             assert_eq!(expected_signatures, signed_payload.signatures.unwrap());
         }
     }
+
+    #[cfg(feature = "std-futures")]
+    mod future_tests {
+        use super::*;
+        use utils::future::future_tests::block_on;
+
+        #[test]
+        fn sign_transfer_future_valid() {
+            let (inputs, outputs) = inputs_outputs_valid_qualified();
+
+            let expected_signatures = Some(vec![String::from("31VzUm5vZRfWPk38W3YJaNjrkUeD6tELmjxv42cp7Vnksigned"),
+                                                String::from("GyPZzuu8S1KMs5p6iE1wBzjQsFtaB7eigssW4YbdXdtesigned")]);
+
+            let future = XferPayload::new(inputs, outputs, None).sign_transfer_future(&CryptoApiHandler {}, 1);
+            let (signed_payload, taa_acceptance) = block_on(future).unwrap();
+
+            assert_eq!(inputs_outputs_valid().0, signed_payload.inputs);
+            assert_eq!(expected_signatures, signed_payload.signatures);
+            assert_eq!(None, taa_acceptance);
+        }
+
+        #[test]
+        fn sign_transfer_future_invalid_empty_outputs() {
+            let (inputs, _) = inputs_outputs_valid_qualified();
+
+            let future = XferPayload::new(inputs, Vec::new(), None).sign_transfer_future(&CryptoApiHandler {}, 1);
+
+            assert_eq!(ErrorCode::CommonInvalidStructure, block_on(future).unwrap_err());
+        }
+
+        #[test]
+        fn sign_transfer_future_invalid_input_address() {
+            let (mut inputs, outputs) = inputs_outputs_valid_qualified();
+            String::remove(&mut inputs[0].address, 13);
+
+            let future = XferPayload::new(inputs, outputs, None).sign_transfer_future(&CryptoApiHandler {}, 1);
+
+            assert_eq!(ErrorCode::CommonInvalidStructure, block_on(future).unwrap_err());
+        }
+
+        #[test]
+        fn sign_fees_future_valid_empty_outputs() {
+            let (inputs, _) = inputs_outputs_valid_qualified();
+
+            let future = XferPayload::new(inputs, Vec::new(), None).sign_fees_future(&CryptoApiHandler {}, 1, &None);
+            let (signed_payload, _) = block_on(future).unwrap();
+
+            assert_eq!(2, signed_payload.signatures.unwrap().len());
+        }
+    }
 }
@@ -9,6 +9,7 @@ use std::sync::Mutex;
 use std::sync::mpsc::{channel, Receiver};
 
 use indy_sys::{ResponseEmptyCB,
+               ResponseI32CB,
                ResponseStringCB,
                ResponseSliceCB};
 
@@ -39,8 +40,8 @@ impl ClosureHandler {
             static ref CALLBACKS: Mutex<HashMap<i32, Box<FnMut(ErrorCode) + Send>>> = Default::default();
         }
         extern "C" fn _callback(command_handle: IndyHandle, err: i32) {
-            let mut callbacks = CALLBACKS.lock().unwrap();
-            let mut cb = callbacks.remove(&command_handle).unwrap();
+            // released before calling the closure, which may register another callback
+            let mut cb = CALLBACKS.lock().unwrap().remove(&command_handle).unwrap();
             cb(ErrorCode::from(err))
         }
 
@@ -51,6 +52,35 @@ impl ClosureHandler {
         (command_handle, Some(_callback))
     }
 
+    pub fn cb_ec_i32() -> (Receiver<(ErrorCode, i32)>, IndyHandle, Option<ResponseI32CB>) {
+        let (sender, receiver) = channel();
+
+        let closure = Box::new(move |err, val| {
+            sender.send((err, val)).unwrap_or_else(log_error);
+        });
+
+        let (command_handle, cb) = ClosureHandler::convert_cb_ec_i32(closure);
+
+        (receiver, command_handle, cb)
+    }
+
+    pub fn convert_cb_ec_i32(closure: Box<FnMut(ErrorCode, i32) + Send>) -> (IndyHandle, Option<ResponseI32CB>) {
+        lazy_static! {
+            static ref CALLBACKS: Mutex<HashMap<i32, Box<FnMut(ErrorCode, i32) + Send>>> = Default::default();
+        }
+
+        extern "C" fn _callback(command_handle: IndyHandle, err: i32, val: i32) {
+            let mut cb = CALLBACKS.lock().unwrap().remove(&command_handle).unwrap();
+            cb(ErrorCode::from(err), val)
+        }
+
+        let mut callbacks = CALLBACKS.lock().unwrap();
+        let command_handle = SequenceUtils::get_next_id();
+        callbacks.insert(command_handle, closure);
+
+        (command_handle, Some(_callback))
+    }
+
     pub fn cb_ec_string() -> (Receiver<(ErrorCode, String)>, IndyHandle, Option<ResponseStringCB>) {
         let (sender, receiver) = channel();
 
@@ -69,8 +99,7 @@ impl ClosureHandler {
         }
 
         extern "C" fn _callback(command_handle: IndyHandle, err: i32, c_str: *const c_char) {
-            let mut callbacks = CALLBACKS.lock().unwrap();
-            let mut cb = callbacks.remove(&command_handle).unwrap();
+            let mut cb = CALLBACKS.lock().unwrap().remove(&command_handle).unwrap();
             let metadata = rust_str!(c_str);
             cb(ErrorCode::from(err), metadata)
         }
@@ -100,8 +129,7 @@ impl ClosureHandler {
         }
 
         extern "C" fn _callback(command_handle: IndyHandle, err: i32, raw: *const u8, len: u32) {
-            let mut callbacks = CALLBACKS.lock().unwrap();
-            let mut cb = callbacks.remove(&command_handle).unwrap();
+            let mut cb = CALLBACKS.lock().unwrap().remove(&command_handle).unwrap();
             let sig = rust_slice!(raw, len);
             cb(ErrorCode::from(err), sig.to_vec())
         }
@@ -40,4 +40,18 @@ pub const GET_ATTRIB: &'static str = "104";
     A transaction type submitted by anyone to get the current Fees costs of every transaction
 */
 
-pub const GET_FEES: &'static str = "20001";
\ No newline at end of file
+pub const GET_FEES: &'static str = "20001";
+
+pub const SCHEMA: &'static str = "101";
+
+pub const CRED_DEF: &'static str = "102";
+
+pub const REVOC_REG_DEF: &'static str = "113";
+
+/**
+    #description
+    A transaction type submitted by Trustees to change the auth constraint of an action
+*/
+pub const AUTH_RULE: &'static str = "120";
+
+pub const GET_AUTH_RULE: &'static str = "121";
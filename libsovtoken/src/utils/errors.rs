@@ -0,0 +1,321 @@
+/*!
+    Errors of libsovtoken with the context of why they happened
+
+    A [`SovtokenError`] carries the failing field, the offending value and the
+    error it came from. At the FFI boundary it becomes the libindy `ErrorCode`,
+    and is kept as the current error of the thread, see
+    [`sovtoken_get_current_error`].
+
+    The ledger rejections and stale proofs are boxed, they would make every
+    `Result<_, SovtokenError>` as large as them.
+
+    [`SovtokenError`]: enum.SovtokenError.html
+    [`sovtoken_get_current_error`]: ../../api/fn.sovtoken_get_current_error.html
+*/
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::c_char;
+use std::ptr;
+
+use serde_json;
+
+use ErrorCode;
+use logic::parsers::rejection_parser::Rejection;
+use logic::state_proof::freshness::StaleProof;
+use logic::token_amount::AmountOverflow;
+
+thread_local! {
+    static CURRENT_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/**
+    Error of libsovtoken
+
+    `value` is the offending value as it was received, secrets must not be
+    attached to an error.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum SovtokenError {
+    /// Nothing more than the `ErrorCode` is known
+    Code(ErrorCode),
+    /// A field is missing or has an invalid value
+    InvalidStructure { field: String, value: Option<String>, source: Option<String> },
+    /// A valid input which can't be handled in the current state
+    InvalidState { message: String, source: Option<String> },
+    /// The state proof of a reply isn't fresh enough
+    StaleProof(Box<StaleProof>),
+    /// The ledger rejected the request
+    Rejected(Box<Rejection>),
+    /// A sum of amounts doesn't fit in 64 bits
+    AmountOverflow(AmountOverflow),
+}
+
+impl SovtokenError {
+    pub fn invalid_structure(field: &str) -> SovtokenError {
+        SovtokenError::InvalidStructure { field: field.to_string(), value: None, source: None }
+    }
+
+    pub fn invalid_state(message: &str) -> SovtokenError {
+        SovtokenError::InvalidState { message: message.to_string(), source: None }
+    }
+
+    /**
+        Attaches the offending value, only kept by `InvalidStructure`.
+    */
+    pub fn with_value<V: fmt::Display>(mut self, value: V) -> SovtokenError {
+        if let SovtokenError::InvalidStructure { value: ref mut v, .. } = self {
+            *v = Some(value.to_string());
+        }
+        self
+    }
+
+    /**
+        Attaches the error which caused this one.
+    */
+    pub fn with_source<E: fmt::Display>(mut self, source: E) -> SovtokenError {
+        match self {
+            SovtokenError::InvalidStructure { source: ref mut s, .. } |
+            SovtokenError::InvalidState { source: ref mut s, .. } => *s = Some(source.to_string()),
+            _ => ()
+        }
+        self
+    }
+
+    pub fn error_code(&self) -> ErrorCode {
+        match *self {
+            SovtokenError::Code(error_code) => error_code,
+            SovtokenError::InvalidStructure { .. } => ErrorCode::CommonInvalidStructure,
+            SovtokenError::InvalidState { .. } => ErrorCode::CommonInvalidState,
+            SovtokenError::StaleProof(ref stale) => ErrorCode::from((**stale).clone()),
+            SovtokenError::Rejected(ref rejection) => rejection.error_code,
+            SovtokenError::AmountOverflow(_) => ErrorCode::CommonInvalidStructure,
+        }
+    }
+
+    /**
+        The error as json, what [`sovtoken_get_current_error`] returns.
+
+        ```text
+        {
+            "errorCode": <int>,
+            "message": <str>,
+            "field": <optional str>,
+            "value": <optional str>,
+            "source": <optional str>,
+            "staleProof": <optional object>,
+            "rejection": <optional object, see RejectionDetails>,
+            "amountOverflow": <optional object: {"left": <int>, "right": <int>}>
+        }
+        ```
+
+        [`sovtoken_get_current_error`]: ../../api/fn.sovtoken_get_current_error.html
+    */
+    pub fn to_error_json(&self) -> String {
+        let mut error = json!({
+            "errorCode": self.error_code() as i32,
+            "message": self.to_string(),
+        });
+
+        match *self {
+            SovtokenError::InvalidStructure { ref field, ref value, ref source } => {
+                error["field"] = json!(field);
+                error["value"] = json!(value);
+                error["source"] = json!(source);
+            }
+            SovtokenError::InvalidState { ref source, .. } => {
+                error["source"] = json!(source);
+            }
+            SovtokenError::StaleProof(ref stale) => {
+                error["staleProof"] = json!(stale);
+            }
+            SovtokenError::Rejected(ref rejection) => {
+                error["rejection"] = json!(rejection.details);
+            }
+            SovtokenError::AmountOverflow(ref overflow) => {
+                error["amountOverflow"] = json!(overflow);
+            }
+            SovtokenError::Code(_) => ()
+        }
+
+        serde_json::to_string(&error).unwrap_or_default()
+    }
+}
+
+impl From<ErrorCode> for SovtokenError {
+    fn from(error_code: ErrorCode) -> SovtokenError {
+        SovtokenError::Code(error_code)
+    }
+}
+
+impl From<StaleProof> for SovtokenError {
+    fn from(stale: StaleProof) -> SovtokenError {
+        SovtokenError::StaleProof(Box::new(stale))
+    }
+}
+
+impl From<Rejection> for SovtokenError {
+    fn from(rejection: Rejection) -> SovtokenError {
+        SovtokenError::Rejected(Box::new(rejection))
+    }
+}
+
+impl From<AmountOverflow> for SovtokenError {
+    fn from(overflow: AmountOverflow) -> SovtokenError {
+        SovtokenError::AmountOverflow(overflow)
+    }
+}
+
+impl From<AmountOverflow> for ErrorCode {
+    fn from(overflow: AmountOverflow) -> ErrorCode {
+        error!("Amount overflow: {}", overflow);
+        SovtokenError::from(overflow).error_code()
+    }
+}
+
+impl From<SovtokenError> for ErrorCode {
+    fn from(error: SovtokenError) -> ErrorCode {
+        error.error_code()
+    }
+}
+
+impl fmt::Display for SovtokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SovtokenError::Code(error_code) => write!(f, "{:?} ({})", error_code, error_code as i32)?,
+            SovtokenError::InvalidStructure { ref field, ref value, .. } => {
+                write!(f, "Invalid structure: `{}`", field)?;
+                if let Some(ref value) = *value {
+                    write!(f, " is {:?}", value)?;
+                }
+            }
+            SovtokenError::InvalidState { ref message, .. } => write!(f, "Invalid state: {}", message)?,
+            SovtokenError::StaleProof(ref stale) => match stale.proof_timestamp {
+                Some(timestamp) => write!(f, "State proof of {} is stale at {}", timestamp, stale.now)?,
+                None => write!(f, "State proof has no timestamp")?
+            },
+            SovtokenError::Rejected(ref rejection) => write!(f, "Rejected by the ledger: {}", rejection.details.reason)?,
+            SovtokenError::AmountOverflow(ref overflow) => write!(f, "Amount overflow: {}", overflow)?,
+        }
+
+        match *self {
+            SovtokenError::InvalidStructure { source: Some(ref source), .. } |
+            SovtokenError::InvalidState { source: Some(ref source), .. } => write!(f, ": {}", source),
+            _ => Ok(())
+        }
+    }
+}
+
+impl Error for SovtokenError {}
+
+/**
+    Keeps `error` as the current error of this thread.
+*/
+pub fn set_current_error(error: &SovtokenError) {
+    debug!("Current error: {}", error);
+    let error_json = CString::new(error.to_error_json()).ok();
+    CURRENT_ERROR.with(|current| *current.borrow_mut() = error_json);
+}
+
+/**
+    Forgets the current error of this thread, done when an exported function is
+    called so a success doesn't leave the error of an earlier call.
+*/
+pub fn clear_current_error() {
+    CURRENT_ERROR.with(|current| *current.borrow_mut() = None);
+}
+
+/**
+    Keeps `error` as the current error and returns its `ErrorCode`.
+*/
+pub fn to_error_code(error: SovtokenError) -> ErrorCode {
+    set_current_error(&error);
+    error.error_code()
+}
+
+pub fn current_error_json() -> Option<String> {
+    CURRENT_ERROR.with(|current| current.borrow().as_ref().map(|error| error.to_string_lossy().into_owned()))
+}
+
+/**
+    The current error of this thread as a C string, null without an error.
+
+    The pointer stays valid until the current error of the thread changes.
+*/
+pub fn current_error_c_json() -> *const c_char {
+    CURRENT_ERROR.with(|current| current.borrow().as_ref().map(|error| error.as_ptr()).unwrap_or(ptr::null()))
+}
+
+
+#[cfg(test)]
+mod errors_tests {
+    use super::*;
+    use logic::token_amount::TokenAmount;
+
+    #[test]
+    fn invalid_structure_to_error_json() {
+        let error = SovtokenError::invalid_structure("result.state_proof")
+            .with_value("{}")
+            .with_source("missing field `root_hash`");
+
+        let error_json: serde_json::Value = serde_json::from_str(&error.to_error_json()).unwrap();
+
+        assert_eq!(error.error_code(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(error_json["errorCode"], json!(ErrorCode::CommonInvalidStructure as i32));
+        assert_eq!(error_json["field"], json!("result.state_proof"));
+        assert_eq!(error_json["value"], json!("{}"));
+        assert_eq!(error_json["source"], json!("missing field `root_hash`"));
+        assert_eq!(error_json["message"], json!("Invalid structure: `result.state_proof` is \"{}\": missing field `root_hash`"));
+    }
+
+    #[test]
+    fn code_to_error_json() {
+        let error = SovtokenError::from(ErrorCode::WalletItemNotFound);
+        let error_json: serde_json::Value = serde_json::from_str(&error.to_error_json()).unwrap();
+
+        assert_eq!(error_json["errorCode"], json!(ErrorCode::WalletItemNotFound as i32));
+        assert!(error_json.get("field").is_none());
+    }
+
+    #[test]
+    fn stale_proof_error_code() {
+        let stale = StaleProof { proof_timestamp: None, now: 10, max_proof_age: 5, clock_skew_tolerance: 0 };
+        let error = SovtokenError::from(stale);
+
+        assert_eq!(ErrorCode::from(error.clone()), ErrorCode::LedgerSecurityError);
+        assert_eq!(error.to_string(), "State proof has no timestamp");
+    }
+
+    #[test]
+    fn amount_overflow_to_error_json() {
+        let overflow = TokenAmount::MAX.try_add(TokenAmount::new(2)).unwrap_err();
+        let error = SovtokenError::from(overflow);
+        let error_json: serde_json::Value = serde_json::from_str(&error.to_error_json()).unwrap();
+
+        assert_eq!(error.error_code(), ErrorCode::CommonInvalidStructure);
+        assert_eq!(error_json["amountOverflow"], json!({"left": ::std::u64::MAX, "right": 2}));
+    }
+
+    #[test]
+    fn with_value_ignored_by_invalid_state() {
+        let error = SovtokenError::invalid_state("no fees set").with_value("secret");
+        assert_eq!(error, SovtokenError::InvalidState { message: "no fees set".to_string(), source: None });
+    }
+
+    #[test]
+    fn current_error_of_thread() {
+        clear_current_error();
+        assert_eq!(current_error_json(), None);
+        assert!(current_error_c_json().is_null());
+
+        let error_code = to_error_code(SovtokenError::invalid_structure("outputs"));
+
+        assert_eq!(error_code, ErrorCode::CommonInvalidStructure);
+        assert!(current_error_json().unwrap().contains("\"field\":\"outputs\""));
+        assert!(!current_error_c_json().is_null());
+
+        ::std::thread::spawn(|| assert_eq!(current_error_json(), None)).join().unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+/*!
+    Futures completed from indy callbacks
+
+    A [`CallbackFuture`] is handed out to the caller while its [`Completer`] is
+    moved into the callback given to indy. Awaiting the future does not block a
+    thread, the task is woken when the callback completes it.
+
+    Built with the `std-futures` feature only, std futures need Rust 1.36.
+
+    [`CallbackFuture`]: struct.CallbackFuture.html
+    [`Completer`]: struct.Completer.html
+*/
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+struct Shared<T> {
+    result: Option<T>,
+    completed: bool,
+    waker: Option<Waker>,
+}
+
+/**
+    Resolves to the value its [`Completer`] was completed with.
+
+    [`Completer`]: struct.Completer.html
+*/
+pub struct CallbackFuture<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/**
+    Completes a [`CallbackFuture`], only the first value is kept.
+
+    [`CallbackFuture`]: struct.CallbackFuture.html
+*/
+pub struct Completer<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/**
+    Creates a [`CallbackFuture`] and the [`Completer`] of it.
+
+    [`CallbackFuture`]: struct.CallbackFuture.html
+    [`Completer`]: struct.Completer.html
+*/
+pub fn callback_future<T>() -> (CallbackFuture<T>, Completer<T>) {
+    let shared = Arc::new(Mutex::new(Shared { result: None, completed: false, waker: None }));
+    (CallbackFuture { shared: shared.clone() }, Completer { shared })
+}
+
+impl<T> CallbackFuture<T> {
+    /**
+        A future which is already completed with `value`.
+    */
+    pub fn ready(value: T) -> CallbackFuture<T> {
+        let (future, completer) = callback_future();
+        completer.complete(value);
+        future
+    }
+}
+
+impl<T> Future for CallbackFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.result.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Completer<T> {
+    pub fn complete(&self, value: T) {
+        let waker = {
+            let mut shared = self.shared.lock().unwrap();
+            if shared.completed {
+                return;
+            }
+            shared.completed = true;
+            shared.result = Some(value);
+            shared.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Clone for Completer<T> {
+    fn clone(&self) -> Self {
+        Completer { shared: self.shared.clone() }
+    }
+}
+
+/**
+    Resolves to the values of all its futures, in the order they were given.
+*/
+pub struct JoinAll<T> {
+    futures: Vec<Option<CallbackFuture<T>>>,
+    results: Vec<Option<T>>,
+}
+
+pub fn join_all<T>(futures: Vec<CallbackFuture<T>>) -> JoinAll<T> {
+    let results = futures.iter().map(|_| None).collect();
+    JoinAll { futures: futures.into_iter().map(Some).collect(), results }
+}
+
+// The results are never pinned.
+impl<T> Unpin for JoinAll<T> {}
+
+impl<T> Future for JoinAll<T> {
+    type Output = Vec<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Vec<T>> {
+        let this = self.get_mut();
+        let mut pending = false;
+
+        for (future, result) in this.futures.iter_mut().zip(this.results.iter_mut()) {
+            let ready = match *future {
+                Some(ref mut f) => match Pin::new(f).poll(cx) {
+                    Poll::Ready(value) => {
+                        *result = Some(value);
+                        true
+                    }
+                    Poll::Pending => {
+                        pending = true;
+                        false
+                    }
+                },
+                None => false
+            };
+            if ready {
+                *future = None;
+            }
+        }
+
+        if pending {
+            return Poll::Pending;
+        }
+
+        Poll::Ready(this.results.iter_mut().map(|result| result.take().unwrap()).collect())
+    }
+}
+
+#[cfg(test)]
+pub mod future_tests {
+    use super::*;
+    use std::mem;
+    use std::sync::mpsc::channel;
+    use std::task::{RawWaker, RawWakerVTable};
+    use std::thread;
+
+    // A waker unparking the thread, its data is an `Arc<thread::Thread>`.
+    static THREAD_WAKER: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+    fn raw_waker(thread: Arc<thread::Thread>) -> RawWaker {
+        RawWaker::new(Arc::into_raw(thread) as *const (), &THREAD_WAKER)
+    }
+
+    unsafe fn clone_waker(data: *const ()) -> RawWaker {
+        let thread = Arc::from_raw(data as *const thread::Thread);
+        let clone = thread.clone();
+        mem::forget(thread);
+        raw_waker(clone)
+    }
+
+    unsafe fn wake(data: *const ()) {
+        Arc::from_raw(data as *const thread::Thread).unpark();
+    }
+
+    unsafe fn wake_by_ref(data: *const ()) {
+        (*(data as *const thread::Thread)).unpark();
+    }
+
+    unsafe fn drop_waker(data: *const ()) {
+        mem::drop(Arc::from_raw(data as *const thread::Thread));
+    }
+
+    /**
+        Polls `future` on the current thread until it is ready.
+    */
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = unsafe { Waker::from_raw(raw_waker(Arc::new(thread::current()))) };
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park()
+            }
+        }
+    }
+
+    #[test]
+    fn ready_future() {
+        assert_eq!(block_on(CallbackFuture::ready(5)), 5);
+    }
+
+    #[test]
+    fn completed_from_another_thread() {
+        let (future, completer) = callback_future();
+        let (sender, receiver) = channel::<()>();
+        thread::spawn(move || {
+            receiver.recv().unwrap();
+            completer.complete("done");
+        });
+        sender.send(()).unwrap();
+        assert_eq!(block_on(future), "done");
+    }
+
+    #[test]
+    fn first_completion_wins() {
+        let (future, completer) = callback_future();
+        completer.clone().complete(1);
+        completer.complete(2);
+        assert_eq!(block_on(future), 1);
+    }
+
+    #[test]
+    fn join_all_keeps_order() {
+        let (first, first_completer) = callback_future();
+        let (second, second_completer) = callback_future();
+        thread::spawn(move || {
+            second_completer.complete(2);
+            first_completer.complete(1);
+        });
+        assert_eq!(block_on(join_all(vec![first, second, CallbackFuture::ready(3)])), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn join_all_empty() {
+        let empty: Vec<CallbackFuture<u8>> = Vec::new();
+        assert!(block_on(join_all(empty)).is_empty());
+    }
+}
@@ -19,6 +19,9 @@
 pub mod base58;
 pub mod callbacks;
 pub mod constants;
+pub mod errors;
+#[cfg(feature = "std-futures")]
+pub mod future;
 pub mod general;
 pub mod random;
 pub mod sequence;
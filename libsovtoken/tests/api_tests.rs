@@ -3,7 +3,10 @@
 
 extern crate sovtoken;
 
-use sovtoken::api::sovtoken_init;
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use sovtoken::api::{free_parsed_state_proof, get_fees_state_proof_parser, sovtoken_get_current_error, sovtoken_init};
 use sovtoken::ErrorCode;
 
 
@@ -14,4 +17,38 @@ fn sovtoken_init_executes_successfully() {
 
    assert_eq!(err, ErrorCode::Success as i32, "sovtoken_init did not return ErrorCode::Success");
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn sovtoken_get_current_error_after_failure() {
+   let reply = CString::new(r#"{"op": "REPLY", "result": {"type": "20001"}}"#).unwrap();
+   let mut parsed_sp = ptr::null();
+
+   let err = get_fees_state_proof_parser(reply.as_ptr(), &mut parsed_sp);
+   assert_eq!(err, ErrorCode::CommonInvalidStructure as i32);
+
+   let mut error_json_p = ptr::null();
+   sovtoken_get_current_error(&mut error_json_p);
+   assert!(!error_json_p.is_null());
+
+   let error_json = unsafe { CStr::from_ptr(error_json_p) }.to_str().unwrap();
+   assert!(error_json.contains(r#""field":"result.state_proof""#), "{}", error_json);
+}
+
+#[test]
+fn sovtoken_get_current_error_cleared_by_next_call() {
+   let reply = CString::new(r#"{"op": "REPLY", "result": {"type": "20001"}}"#).unwrap();
+   let mut parsed_sp = ptr::null();
+
+   let err = get_fees_state_proof_parser(reply.as_ptr(), &mut parsed_sp);
+   assert_eq!(err, ErrorCode::CommonInvalidStructure as i32);
+
+   let reply = CString::new(r#"{"op": "REPLY", "result": {"reqId": 83955, "type": "20001", "identifier": "6ouriXMZkLeHsuXrN1X1fd", "fees": {"1": 4, "10001": 8}, "state_proof": {"proof_nodes": "29qFIGZlZXOT0pF7IjEiOjQsIjEwMDAxIjo4fQ==", "root_hash": "5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms", "multi_signature": {"participants": ["Gamma", "Delta", "Beta"], "value": {"timestamp": 1530059419}, "signature": "Qk67ePVhxdjHivAf8H4Loy1hN5zfb1dq79VSJKYx485EAXmj44PASpp8gj2faysdN8CNzSoUVvXgd3U4P2CA7VkwD7FHKUuviAFJfRQ68FnpUS8hVuqn6PAuv9RGUobohcJnKJ8CVKxr5i3Zn2JNXbk7AqeYRZQ2egq8fdoP3woPW7"}}}}"#).unwrap();
+   let err = get_fees_state_proof_parser(reply.as_ptr(), &mut parsed_sp);
+   assert_eq!(err, ErrorCode::Success as i32);
+   free_parsed_state_proof(parsed_sp);
+
+   let mut error_json_p = ptr::null();
+   sovtoken_get_current_error(&mut error_json_p);
+   assert!(error_json_p.is_null());
+}